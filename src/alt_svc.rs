@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use hyper::header::Headers;
+use hyper::Url;
+use url::percent_encoding::percent_decode;
+
+/// How long an alternative is fresh for without an `ma` parameter.
+const DEFAULT_MAX_AGE: u64 = 24 * 60 * 60;
+
+/// The longest an alternative is remembered for, whatever its `ma`.
+const MAX_MAX_AGE: u64 = 365 * 24 * 60 * 60;
+
+/// The only protocol the client can use an alternative for.
+const HTTP_1_1: &'static str = "http/1.1";
+
+/// An alternative service for an origin, from its `Alt-Svc` header, as in
+/// RFC 7838.
+///
+/// See `Response::alt_svc`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AltService {
+    protocol: String,
+    host: Option<String>,
+    port: u16,
+    max_age: Duration,
+}
+
+impl AltService {
+    /// The ALPN protocol the alternative speaks, such as `h2` or `http/1.1`.
+    #[inline]
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    /// The host of the alternative, or `None` for the host of the origin.
+    #[inline]
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_ref().map(|host| &**host)
+    }
+
+    /// The port of the alternative.
+    #[inline]
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// How long the alternative may be used for, from the `ma` parameter,
+    /// or 24 hours without one.
+    #[inline]
+    pub fn max_age(&self) -> Duration {
+        self.max_age
+    }
+}
+
+/// Parses the values of `Alt-Svc` headers.
+///
+/// Returns `None` for `clear`, which invalidates all of the alternatives
+/// of the origin. Alternatives that can't be parsed are skipped.
+pub fn parse(values: &[Vec<u8>]) -> Option<Vec<AltService>> {
+    let mut services = Vec::new();
+    for value in values {
+        let value = match ::std::str::from_utf8(value) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        if value.trim() == "clear" {
+            return None;
+        }
+        services.extend(split_unquoted(value, ',').into_iter().filter_map(parse_alternative));
+    }
+    Some(services)
+}
+
+fn parse_alternative(value: &str) -> Option<AltService> {
+    let mut params = split_unquoted(value, ';').into_iter();
+    let alternative = match params.next() {
+        Some(alternative) => alternative,
+        None => return None,
+    };
+    // unlike a parameter, the authority has to be quoted
+    if !alternative.splitn(2, '=').nth(1).map_or(false, |v| v.trim().starts_with('"')) {
+        return None;
+    }
+    let (protocol, authority) = match parse_param(alternative) {
+        Some(alternative) => alternative,
+        None => return None,
+    };
+    let protocol = match percent_decode(protocol.as_bytes()).decode_utf8() {
+        Ok(protocol) => protocol.into_owned(),
+        Err(_) => return None,
+    };
+    // the port is required, and the host may be empty
+    let (host, port) = match authority.rfind(':') {
+        Some(at) => (&authority[..at], &authority[at + 1..]),
+        None => return None,
+    };
+    let port = match port.parse::<u16>() {
+        Ok(port) => port,
+        Err(_) => return None,
+    };
+    let host = if host.starts_with('[') && host.ends_with(']') {
+        &host[1..host.len() - 1]
+    } else {
+        host
+    };
+
+    let mut max_age = DEFAULT_MAX_AGE;
+    for (name, value) in params.filter_map(parse_param) {
+        if name.eq_ignore_ascii_case("ma") {
+            match value.parse::<u64>() {
+                Ok(seconds) => max_age = seconds,
+                Err(_) => return None,
+            }
+        }
+    }
+    Some(AltService {
+        protocol: protocol,
+        host: if host.is_empty() { None } else { Some(host.to_owned()) },
+        port: port,
+        max_age: Duration::from_secs(max_age),
+    })
+}
+
+/// Parses a `name=value` pair, where the value is a token or a quoted
+/// string, which is unquoted.
+pub fn parse_param(param: &str) -> Option<(&str, String)> {
+    let mut parts = param.splitn(2, '=');
+    let (name, value) = match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) => (name.trim(), value.trim()),
+        _ => return None,
+    };
+    if name.is_empty() {
+        return None;
+    }
+    if !value.starts_with('"') {
+        if value.is_empty() || value.contains('"') {
+            return None;
+        }
+        return Some((name, value.to_owned()));
+    }
+    let mut unquoted = String::new();
+    let mut chars = value[1..].chars();
+    loop {
+        match chars.next() {
+            Some('\\') => match chars.next() {
+                Some(c) => unquoted.push(c),
+                None => return None,
+            },
+            // nothing may follow the closing quote
+            Some('"') => return if chars.next().is_none() { Some((name, unquoted)) } else { None },
+            Some(c) => unquoted.push(c),
+            None => return None,
+        }
+    }
+}
+
+/// Splits `value` at each `sep` that isn't in a quoted string, leaving out
+/// empty parts.
+pub fn split_unquoted(value: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if quoted && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if !quoted && c == sep {
+            parts.push(&value[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&value[start..]);
+    parts.into_iter().filter(|part| !part.trim().is_empty()).collect()
+}
+
+/// The alternatives that responses advertised, by origin.
+///
+/// See `Client::honor_alt_svc`.
+pub struct AltSvcCache {
+    // the host and port to connect to instead, until they expire
+    routes: Mutex<HashMap<Origin, Vec<((String, u16), Instant)>>>,
+}
+
+type Origin = (String, String, u16);
+
+impl AltSvcCache {
+    pub fn new() -> AltSvcCache {
+        AltSvcCache {
+            routes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Remembers the alternatives in the `Alt-Svc` of a response from `url`,
+    /// replacing any from before.
+    pub fn update(&self, url: &Url, headers: &Headers) {
+        let values = match headers.get_raw("Alt-Svc") {
+            Some(values) => values,
+            None => return,
+        };
+        let origin = match origin(url) {
+            Some(origin) => origin,
+            None => return,
+        };
+        let mut routes = self.routes.lock().unwrap();
+        let now = Instant::now();
+        prune(&mut routes, now);
+        let services = match parse(values) {
+            Some(services) => services,
+            None => {
+                debug!("alternative services of {}:{} cleared", origin.1, origin.2);
+                routes.remove(&origin);
+                return;
+            },
+        };
+        let max_age = Duration::from_secs(MAX_MAX_AGE);
+        let usable = services.into_iter()
+            .filter(|service| service.protocol == HTTP_1_1)
+            .filter_map(|service| {
+                let host = service.host.unwrap_or_else(|| origin.1.clone());
+                // `ma` comes from the server, so it is capped
+                let expires = now.checked_add(::std::cmp::min(service.max_age, max_age));
+                expires.map(|expires| ((host, service.port), expires))
+            })
+            .filter(|&((ref host, port), _)| *host != origin.1 || port != origin.2)
+            .collect::<Vec<_>>();
+        if usable.is_empty() {
+            routes.remove(&origin);
+        } else {
+            routes.insert(origin, usable);
+        }
+    }
+
+    /// Gets the host and port to connect to for `url`, if it has an
+    /// alternative that hasn't expired.
+    pub fn route(&self, url: &Url) -> Option<(String, u16)> {
+        let origin = match origin(url) {
+            Some(origin) => origin,
+            None => return None,
+        };
+        let mut routes = self.routes.lock().unwrap();
+        let now = Instant::now();
+        let route = match routes.get_mut(&origin) {
+            Some(alternatives) => {
+                alternatives.retain(|&(_, expires)| expires > now);
+                alternatives.first().map(|&(ref route, _)| route.clone())
+            },
+            None => return None,
+        };
+        if route.is_none() {
+            routes.remove(&origin);
+        }
+        route
+    }
+
+    /// Forgets the alternative `route` of `url`, such as after failing to
+    /// connect to it.
+    pub fn remove(&self, url: &Url, route: &(String, u16)) {
+        if let Some(origin) = origin(url) {
+            let mut routes = self.routes.lock().unwrap();
+            let empty = match routes.get_mut(&origin) {
+                Some(alternatives) => {
+                    alternatives.retain(|&(ref r, _)| r != route);
+                    alternatives.is_empty()
+                },
+                None => false,
+            };
+            if empty {
+                routes.remove(&origin);
+            }
+        }
+    }
+}
+
+/// Forgets the alternatives that expired, and the origins left without any.
+fn prune(routes: &mut HashMap<Origin, Vec<((String, u16), Instant)>>, now: Instant) {
+    for alternatives in routes.values_mut() {
+        alternatives.retain(|&(_, expires)| expires > now);
+    }
+    let empty = routes.iter()
+        .filter(|&(_, alternatives)| alternatives.is_empty())
+        .map(|(origin, _)| origin.clone())
+        .collect::<Vec<_>>();
+    for origin in empty {
+        routes.remove(&origin);
+    }
+}
+
+fn origin(url: &Url) -> Option<Origin> {
+    match (url.host_str(), url.port_or_known_default()) {
+        (Some(host), Some(port)) => Some((url.scheme().to_owned(), host.to_owned(), port)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+fn service(protocol: &str, host: Option<&str>, port: u16, max_age: u64) -> AltService {
+    AltService {
+        protocol: protocol.to_owned(),
+        host: host.map(|host| host.to_owned()),
+        port: port,
+        max_age: Duration::from_secs(max_age),
+    }
+}
+
+#[test]
+fn test_parse() {
+    let parsed = [
+        ("h2=\":443\"", vec![service("h2", None, 443, DEFAULT_MAX_AGE)]),
+        ("h3=\":443\"; ma=86400, h3-29=\":443\"; ma=86400", vec![
+            service("h3", None, 443, 86400),
+            service("h3-29", None, 443, 86400),
+        ]),
+        // a quoted parameter with a comma in it
+        ("quic=\":443\"; ma=2592000; v=\"46,43\"", vec![service("quic", None, 443, 2592000)]),
+        ("h2=\"alt.example.com:8443\";MA=60;persist=1 ,  http%2F1.1=\"[::1]:8080\"", vec![
+            service("h2", Some("alt.example.com"), 8443, 60),
+            service("http/1.1", Some("::1"), 8080, DEFAULT_MAX_AGE),
+        ]),
+        // the example of a percent-encoded protocol in RFC 7838
+        ("w%3Dx%3Ay=\"new.example.org:80\"", vec![service("w=x:y", Some("new.example.org"), 80,
+                                                           DEFAULT_MAX_AGE)]),
+        ("h2=\"a\\\"b:443\"", vec![service("h2", Some("a\"b"), 443, DEFAULT_MAX_AGE)]),
+        // the bad ones are skipped
+        ("h2=\":99999\", h2=:443, h2=\":443\"; ma=soon, =\":80\", h2=\"noport\", http/1.1=\":80\"",
+         vec![service("http/1.1", None, 80, DEFAULT_MAX_AGE)]),
+        ("", vec![]),
+    ];
+    for &(value, ref services) in &parsed {
+        assert_eq!(parse(&[value.as_bytes().to_vec()]), Some(services.clone()), "{:?}", value);
+    }
+
+    assert_eq!(parse(&[b"clear".to_vec()]), None);
+    assert_eq!(parse(&[b"h2=\":443\"".to_vec(), b" clear ".to_vec()]), None);
+    assert_eq!(parse(&[b"h2=\":443\"".to_vec(), b"h3=\":443\"".to_vec()]), Some(vec![
+        service("h2", None, 443, DEFAULT_MAX_AGE),
+        service("h3", None, 443, DEFAULT_MAX_AGE),
+    ]));
+}
+
+#[test]
+fn test_cache() {
+    let cache = AltSvcCache::new();
+    let url = Url::parse("http://example.com/").unwrap();
+    let mut headers = Headers::new();
+    headers.set_raw("Alt-Svc", vec![
+        b"h2=\":443\", http/1.1=\":80\", http/1.1=\"alt.example.com:8080\"".to_vec(),
+    ]);
+    cache.update(&url, &headers);
+    // only HTTP/1.1 can be used, and not the origin itself
+    let alt = ("alt.example.com".to_owned(), 8080);
+    assert_eq!(cache.route(&url), Some(alt.clone()));
+    assert_eq!(cache.route(&Url::parse("https://example.com/").unwrap()), None);
+    cache.remove(&url, &alt);
+    assert_eq!(cache.route(&url), None);
+
+    headers.set_raw("Alt-Svc", vec![b"http/1.1=\":81\"; ma=0".to_vec()]);
+    cache.update(&url, &headers);
+    assert_eq!(cache.route(&url), None);
+
+    headers.set_raw("Alt-Svc", vec![b"http/1.1=\":81\"".to_vec()]);
+    cache.update(&url, &headers);
+    assert_eq!(cache.route(&url), Some(("example.com".to_owned(), 81)));
+    headers.set_raw("Alt-Svc", vec![b"clear".to_vec()]);
+    cache.update(&url, &headers);
+    assert_eq!(cache.route(&url), None);
+
+    // a huge `ma` is capped, rather than overflowing
+    headers.set_raw("Alt-Svc", vec![b"http/1.1=\":82\"; ma=18446744073709551615".to_vec()]);
+    cache.update(&url, &headers);
+    assert_eq!(cache.route(&url), Some(("example.com".to_owned(), 82)));
+}
+
+#[test]
+fn test_cache_prune() {
+    let cache = AltSvcCache::new();
+    let mut headers = Headers::new();
+    headers.set_raw("Alt-Svc", vec![b"http/1.1=\":81\"; ma=0".to_vec()]);
+    for i in 0..10 {
+        let url = Url::parse(&format!("http://host{}.example.com/", i)).unwrap();
+        cache.update(&url, &headers);
+    }
+    // each update forgets those that expired before it
+    assert!(cache.routes.lock().unwrap().len() <= 1);
+
+    let url = Url::parse("http://example.com/").unwrap();
+    headers.set_raw("Alt-Svc", vec![b"http/1.1=\":81\"".to_vec()]);
+    cache.update(&url, &headers);
+    cache.remove(&url, &("example.com".to_owned(), 81));
+    assert!(!cache.routes.lock().unwrap().contains_key(&origin(&url).unwrap()));
+}
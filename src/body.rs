@@ -1,6 +1,10 @@
-use std::io::Read;
+use std::error::Error as StdError;
+use std::io::{self, Read, Write};
 use std::fs::File;
 use std::fmt;
+use std::sync::mpsc;
+
+use ::connect::Upload;
 
 /// Body type for a request.
 #[derive(Debug)]
@@ -20,7 +24,8 @@ impl Body {
     /// the redirect status code set.
     ///
     /// A `Body` constructed from a set of bytes, like `String` or `Vec<u8>`,
-    /// are stored differently and can be reused.
+    /// are stored differently and can be reused, and so can one from
+    /// `Body::resettable`.
     pub fn new<R: Read + Send + 'static>(reader: R) -> Body {
         Body {
             reader: Kind::Reader(Box::new(reader), None),
@@ -37,11 +42,171 @@ impl Body {
         }
     }
 
-    /*
-    pub fn chunked(reader: ()) -> Body {
-        unimplemented!()
+    /// Create a `Body` from a reader that can start over from the
+    /// beginning, such as one of a file, or of an object store.
+    ///
+    /// Unlike one from `Body::new`, this body is sent again when a `307`
+    /// or `308` redirect is followed, and `ResetableReader::reset` is
+    /// called before each time it is sent again. If that fails, the
+    /// request fails with `Error::BodyReset`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use std::io::{self, Seek, SeekFrom};
+    ///
+    /// struct Upload(File);
+    ///
+    /// impl io::Read for Upload {
+    ///     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    ///         self.0.read(buf)
+    ///     }
+    /// }
+    ///
+    /// impl reqwest::ResetableReader for Upload {
+    ///     fn reset(&mut self) -> io::Result<()> {
+    ///         self.0.seek(SeekFrom::Start(0)).map(|_| ())
+    ///     }
+    /// }
+    ///
+    /// let body = reqwest::Body::resettable(Upload(File::open("backup.tar").unwrap()));
+    /// let client = reqwest::Client::new().unwrap();
+    /// let res = client.put("http://example.com/backups/latest")
+    ///     .body(body)
+    ///     .send();
+    /// ```
+    pub fn resettable<R: Read + ResetableReader + Send + 'static>(reader: R) -> Body {
+        Body {
+            reader: Kind::Resettable(Box::new(reader)),
+        }
+    }
+
+    /// Create a `Body` that is streamed from a `BodySender`.
+    ///
+    /// The body is sent with chunked encoding, as the sender provides them.
+    /// At most `capacity` chunks are buffered while waiting to be sent, after
+    /// which `BodySender::send` will block until the connection has caught
+    /// up.
+    ///
+    /// The body is complete once `BodySender::finish` is called. If the
+    /// sender is dropped without finishing, the request is aborted with
+    /// `Error::BodyAborted`, instead of sending a truncated body.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::thread;
+    ///
+    /// let (mut tx, body) = reqwest::Body::channel(16);
+    /// thread::spawn(move || {
+    ///     for i in 0..100 {
+    ///         if tx.send(format!("line {}\n", i).into_bytes()).is_err() {
+    ///             // the request failed, so stop producing
+    ///             return;
+    ///         }
+    ///     }
+    ///     let _ = tx.finish();
+    /// });
+    ///
+    /// let client = reqwest::Client::new().unwrap();
+    /// let res = client.post("http://httpbin.org/post")
+    ///     .body(body)
+    ///     .send();
+    /// ```
+    pub fn channel(capacity: usize) -> (BodySender, Body) {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let reader = ChannelReader {
+            rx: rx,
+            chunk: io::Cursor::new(Vec::new()),
+            finished: false,
+        };
+        (BodySender { tx: tx }, Body::new(reader))
+    }
+}
+
+/// A reader, for `Body::resettable`, that can be started over.
+pub trait ResetableReader {
+    /// Go back to the beginning, so that the next read returns the first
+    /// bytes again.
+    fn reset(&mut self) -> io::Result<()>;
+}
+
+trait ResetableRead: Read + ResetableReader + Send {}
+
+impl<R: Read + ResetableReader + Send> ResetableRead for R {}
+
+/// The sending half of a `Body::channel`.
+#[derive(Debug)]
+pub struct BodySender {
+    tx: mpsc::SyncSender<Chunk>,
+}
+
+impl BodySender {
+    /// Send a chunk of the body.
+    ///
+    /// Blocks if the channel is full. Returns an error of kind `BrokenPipe`
+    /// if the `Body` was dropped, such as when the request failed.
+    pub fn send(&mut self, chunk: Vec<u8>) -> io::Result<()> {
+        if chunk.is_empty() {
+            // an empty chunk would end the chunked encoding
+            return Ok(());
+        }
+        self.tx.send(Chunk::Data(chunk)).map_err(|_| receiver_dropped())
+    }
+
+    /// Finish the body, allowing the request to complete.
+    pub fn finish(self) -> io::Result<()> {
+        self.tx.send(Chunk::Finish).map_err(|_| receiver_dropped())
+    }
+}
+
+fn receiver_dropped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "request body was dropped")
+}
+
+#[derive(Debug)]
+enum Chunk {
+    Data(Vec<u8>),
+    Finish,
+}
+
+struct ChannelReader {
+    rx: mpsc::Receiver<Chunk>,
+    chunk: io::Cursor<Vec<u8>>,
+    finished: bool,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = try!(self.chunk.read(buf));
+            if n > 0 || buf.is_empty() || self.finished {
+                return Ok(n);
+            }
+            match self.rx.recv() {
+                Ok(Chunk::Data(data)) => self.chunk = io::Cursor::new(data),
+                Ok(Chunk::Finish) => self.finished = true,
+                Err(_) => return Err(io::Error::new(io::ErrorKind::Other, Aborted)),
+            }
+        }
+    }
+}
+
+/// The `BodySender` was dropped without finishing the body.
+#[derive(Debug)]
+struct Aborted;
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl StdError for Aborted {
+    fn description(&self) -> &str {
+        "body sender dropped without finishing"
     }
-    */
 }
 
 // useful for tests, but not publicly exposed
@@ -52,6 +217,9 @@ pub fn read_to_string(mut body: Body) -> ::std::io::Result<String> {
         Kind::Reader(ref mut reader, _) => {
             reader.read_to_string(&mut s)
         }
+        Kind::Resettable(ref mut reader) => {
+            reader.read_to_string(&mut s)
+        }
         Kind::Bytes(ref mut bytes) => {
             (&**bytes).read_to_string(&mut s)
         }
@@ -60,6 +228,7 @@ pub fn read_to_string(mut body: Body) -> ::std::io::Result<String> {
 
 enum Kind {
     Reader(Box<Read + Send>, Option<u64>),
+    Resettable(Box<ResetableRead>),
     Bytes(Vec<u8>),
 }
 
@@ -108,6 +277,7 @@ impl fmt::Debug for Kind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Kind::Reader(_, ref v) => f.debug_tuple("Kind::Reader").field(&"_").field(v).finish(),
+            Kind::Resettable(_) => f.debug_tuple("Kind::Resettable").field(&"_").finish(),
             Kind::Bytes(ref v) => f.debug_tuple("Kind::Bytes").field(v).finish(),
         }
     }
@@ -118,24 +288,111 @@ impl fmt::Debug for Kind {
 //pub struct Pipe(Kind);
 
 
-pub fn as_hyper_body(body: &mut Body) -> ::hyper::client::Body {
+pub fn len(body: &Body) -> Option<u64> {
     match body.reader {
-        Kind::Bytes(ref bytes) => {
-            let len = bytes.len();
-            ::hyper::client::Body::BufBody(bytes, len)
-        }
-        Kind::Reader(ref mut reader, len_opt) => {
-            match len_opt {
-                Some(len) => ::hyper::client::Body::SizedBody(reader, len),
-                None => ::hyper::client::Body::ChunkedBody(reader),
-            }
-        }
+        Kind::Bytes(ref bytes) => Some(bytes.len() as u64),
+        Kind::Reader(_, len) => len,
+        Kind::Resettable(_) => None,
+    }
+}
+
+/// The bytes of a body that is kept in memory.
+pub fn bytes(body: &Body) -> Option<&[u8]> {
+    match body.reader {
+        Kind::Bytes(ref bytes) => Some(bytes),
+        Kind::Reader(..) |
+        Kind::Resettable(_) => None,
     }
 }
 
+/// Writes the body to the request, until it is done, or the connection
+/// stopped sending it.
+pub fn write_to<W: Write>(body: &mut Body, dst: &mut W, upload: &Upload) -> ::Result<()> {
+    let reader: &mut Read = match body.reader {
+        Kind::Bytes(ref bytes) => return dst.write_all(bytes).map_err(::Error::from),
+        Kind::Reader(ref mut reader, _) => reader,
+        Kind::Resettable(ref mut reader) => reader,
+    };
+    let mut buf = [0; 8192];
+    while !upload.is_aborted() {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(ref e) if e.get_ref().map_or(false, |e| e.is::<Aborted>()) => {
+                return Err(::Error::BodyAborted);
+            },
+            Err(e) => return Err(::Error::from(e)),
+        };
+        try!(dst.write_all(&buf[..n]));
+    }
+    Ok(())
+}
+
 pub fn can_reset(body: &Body) -> bool {
     match body.reader {
-        Kind::Bytes(_) => true,
+        Kind::Bytes(_) |
+        Kind::Resettable(_) => true,
         Kind::Reader(..) => false,
     }
 }
+
+/// Gets the body ready to be sent again.
+///
+/// A reader that can't be reset is only sent again when nothing of it was
+/// read yet, such as when connecting failed, so it is left as it is.
+pub fn reset(body: &mut Body) -> ::Result<()> {
+    match body.reader {
+        Kind::Resettable(ref mut reader) => reader.reset().map_err(::Error::BodyReset),
+        Kind::Bytes(_) |
+        Kind::Reader(..) => Ok(()),
+    }
+}
+
+#[test]
+fn test_channel_applies_back_pressure() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    let (mut tx, body) = Body::channel(2);
+    let sent = Arc::new(AtomicUsize::new(0));
+    let counter = sent.clone();
+    let producer = thread::spawn(move || {
+        for i in 0..5 {
+            tx.send(vec![b'0' + i]).unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+        }
+        tx.finish().unwrap();
+    });
+
+    thread::sleep(Duration::from_millis(100));
+    // 2 chunks buffered, and the producer blocked on the 3rd
+    assert_eq!(sent.load(Ordering::SeqCst), 2);
+
+    assert_eq!(read_to_string(body).unwrap(), "01234");
+    producer.join().unwrap();
+}
+
+#[test]
+fn test_channel_sender_dropped() {
+    let (mut tx, mut body) = Body::channel(1);
+    tx.send(b"partial".to_vec()).unwrap();
+    drop(tx);
+
+    let mut buf = Vec::new();
+    match write_to(&mut body, &mut buf, &Upload::new()) {
+        Err(::Error::BodyAborted) => (),
+        other => panic!("expected BodyAborted, got: {:?}", other),
+    }
+}
+
+#[test]
+fn test_channel_body_dropped() {
+    let (mut tx, body) = Body::channel(1);
+    drop(body);
+
+    let err = tx.send(b"nobody listening".to_vec()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+}
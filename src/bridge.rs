@@ -0,0 +1,87 @@
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use ::client::RequestBuilder;
+use ::response::Response;
+
+pub const DEFAULT_THREADS: usize = 4;
+
+/// The worker threads that send requests for `Client::send_in_background`.
+///
+/// Threads are started as requests come in, up to the configured number.
+/// They stop once the `Bridge` is dropped and the queued requests are done.
+pub struct Bridge {
+    state: Mutex<State>,
+}
+
+struct State {
+    size: usize,
+    threads: usize,
+    jobs: Sender<Job>,
+    queue: Arc<Mutex<Receiver<Job>>>,
+}
+
+struct Job {
+    req: RequestBuilder,
+    tx: Sender<::Result<Response>>,
+}
+
+impl Bridge {
+    pub fn new() -> Bridge {
+        let (jobs, queue) = mpsc::channel();
+        Bridge {
+            state: Mutex::new(State {
+                size: DEFAULT_THREADS,
+                threads: 0,
+                jobs: jobs,
+                queue: Arc::new(Mutex::new(queue)),
+            }),
+        }
+    }
+
+    /// Sets the most threads to run. Threads already started keep running.
+    pub fn set_size(&self, size: usize) {
+        assert!(size > 0, "a bridge needs at least one thread");
+        self.state.lock().unwrap().size = size;
+    }
+
+    pub fn send(&self, req: RequestBuilder) -> Receiver<::Result<Response>> {
+        let (tx, rx) = mpsc::channel();
+        let mut state = self.state.lock().unwrap();
+        if state.threads < state.size {
+            let queue = state.queue.clone();
+            let spawned = thread::Builder::new()
+                .name(format!("reqwest-bridge-{}", state.threads + 1))
+                .spawn(move || work(queue));
+            match spawned {
+                Ok(_) => state.threads += 1,
+                Err(e) => debug!("failed to start bridge thread: {}", e),
+            }
+        }
+        if state.threads == 0 {
+            // without any thread, the request has to be sent right here
+            drop(state);
+            let _ = tx.send(req.send());
+        } else {
+            // the queue is kept by the state, so this can't fail
+            let _ = state.jobs.send(Job {
+                req: req,
+                tx: tx,
+            });
+        }
+        rx
+    }
+}
+
+fn work(queue: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        let job = match queue.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => break,
+        };
+        // the caller may have stopped waiting, which is fine
+        let _ = job.tx.send(job.req.send());
+    }
+    trace!("bridge thread stopping");
+}
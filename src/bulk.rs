@@ -0,0 +1,149 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use ::response::Response;
+
+/// Options for `Client::get_many`.
+#[derive(Clone)]
+pub struct BulkOptions {
+    /// How many requests are sent at once, each on its own thread.
+    ///
+    /// Fewer threads are started when there are fewer URLs. It has to be at
+    /// least 1, or every request fails with `Error::InvalidThreadCount`.
+    pub workers: usize,
+    /// How many of the requests are sent to the same host and port at
+    /// once, at most, or `None`, the default, for as many as `workers`.
+    ///
+    /// The client itself doesn't limit how many connections it opens to a
+    /// host, so this keeps a batch from flooding one of them while others
+    /// wait. It has to be at least 1, or every request fails with
+    /// `Error::InvalidThreadCount`.
+    pub per_host: Option<usize>,
+    /// The read and write timeout of each request, instead of the client's.
+    ///
+    /// It has to be at least a millisecond, or every request fails with
+    /// `Error::InvalidTimeout`.
+    pub timeout: Option<Duration>,
+    /// Called on the thread of `get_many` after each request is done, with
+    /// how many are done, and how many there are in all.
+    pub progress: Option<Arc<Fn(usize, usize) + Send + Sync>>,
+}
+
+impl Default for BulkOptions {
+    fn default() -> BulkOptions {
+        BulkOptions {
+            workers: 8,
+            per_host: None,
+            timeout: None,
+            progress: None,
+        }
+    }
+}
+
+impl fmt::Debug for BulkOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BulkOptions")
+            .field("workers", &self.workers)
+            .field("per_host", &self.per_host)
+            .field("timeout", &self.timeout)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+/// The outcome of one of the requests of `Client::get_many`.
+#[derive(Debug)]
+pub struct BulkResult {
+    /// The position of the URL in those given to `get_many`.
+    pub index: usize,
+    /// How long the request took, until the response or the error.
+    pub elapsed: Duration,
+    /// The response, whose body is still to be read, or why there is none.
+    pub result: ::Result<Response>,
+}
+
+impl BulkResult {
+    /// Whether the request got a response, whatever its status.
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// The requests of `Client::get_many` that are still to be sent, shared by
+/// its workers, with the host and port of each, if it has one.
+pub struct Queue<T> {
+    // the requests, and how many are being sent to each host
+    state: Mutex<(VecDeque<(usize, Option<String>, T)>, HashMap<String, usize>)>,
+    done: Condvar,
+    per_host: Option<usize>,
+}
+
+impl<T> Queue<T> {
+    pub fn new(jobs: VecDeque<(usize, Option<String>, T)>, per_host: Option<usize>) -> Queue<T> {
+        Queue {
+            state: Mutex::new((jobs, HashMap::new())),
+            done: Condvar::new(),
+            per_host: per_host,
+        }
+    }
+
+    /// Takes the first request whose host has room for it, with its index,
+    /// waiting for one to be done while none has, or `None` once there are
+    /// none left.
+    ///
+    /// The request counts towards its host until the `Sending` is dropped.
+    pub fn next(&self) -> Option<(usize, T, Sending<T>)> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.0.is_empty() {
+                return None;
+            }
+            let per_host = self.per_host;
+            let next = {
+                let (ref jobs, ref sending) = *state;
+                jobs.iter().position(|&(_, ref host, _)| match (per_host, host) {
+                    (Some(max), &Some(ref host)) => sending.get(host).map_or(0, |&n| n) < max,
+                    _ => true,
+                })
+            };
+            if let Some(next) = next {
+                let (index, host, job) = state.0.remove(next).unwrap();
+                if let Some(ref host) = host {
+                    *state.1.entry(host.clone()).or_insert(0) += 1;
+                }
+                return Some((index, job, Sending {
+                    queue: self,
+                    host: host,
+                }));
+            }
+            state = self.done.wait(state).unwrap();
+        }
+    }
+}
+
+/// A request of a `Queue` that is being sent, even if its worker panics.
+pub struct Sending<'a, T: 'a> {
+    queue: &'a Queue<T>,
+    host: Option<String>,
+}
+
+impl<'a, T> Drop for Sending<'a, T> {
+    fn drop(&mut self) {
+        if let Some(ref host) = self.host {
+            let mut state = self.queue.state.lock().unwrap();
+            let left = match state.1.get_mut(host) {
+                Some(sending) => {
+                    *sending -= 1;
+                    *sending
+                },
+                None => 0,
+            };
+            if left == 0 {
+                state.1.remove(host);
+            }
+        }
+        self.queue.done.notify_all();
+    }
+}
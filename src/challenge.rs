@@ -0,0 +1,266 @@
+/// An authentication challenge, from a `WWW-Authenticate` header, as in
+/// RFC 7235.
+///
+/// See `Response::auth_challenges`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Challenge {
+    scheme: String,
+    token68: Option<String>,
+    params: Vec<(String, String)>,
+}
+
+impl Challenge {
+    /// The authentication scheme, such as `Basic` or `Digest`, as the
+    /// server wrote it. Schemes are case-insensitive.
+    #[inline]
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    /// The parameters of the challenge, in order, with quoted values
+    /// unquoted.
+    #[inline]
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+
+    /// The value of the first parameter named `name`, ignoring case.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter()
+            .find(|&&(ref param, _)| param.eq_ignore_ascii_case(name))
+            .map(|&(_, ref value)| &**value)
+    }
+
+    /// The single token a challenge can have instead of parameters, such as
+    /// the one of `Negotiate`.
+    #[inline]
+    pub fn token68(&self) -> Option<&str> {
+        self.token68.as_ref().map(|token| &**token)
+    }
+}
+
+/// Parses the values of `WWW-Authenticate` headers.
+///
+/// A value can have several challenges, separated by commas like their
+/// parameters are. Values that aren't UTF-8 are skipped, and so are the
+/// parts of a value that aren't a challenge.
+pub fn parse(values: &[Vec<u8>]) -> Vec<Challenge> {
+    let mut challenges = Vec::new();
+    for value in values {
+        if let Ok(value) = ::std::str::from_utf8(value) {
+            Parser { s: value, pos: 0 }.challenges(&mut challenges);
+        }
+    }
+    challenges
+}
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn challenges(&mut self, challenges: &mut Vec<Challenge>) {
+        loop {
+            self.skip(|c| c == ',' || is_space(c));
+            if self.pos == self.s.len() {
+                return;
+            }
+            let scheme = self.take(is_tchar);
+            if scheme.is_empty() {
+                // not a challenge, so on to whatever is after the next comma
+                self.skip(|c| c != ',');
+                continue;
+            }
+            let mut challenge = Challenge {
+                scheme: scheme.to_owned(),
+                token68: None,
+                params: Vec::new(),
+            };
+            self.skip(is_space);
+            if let Some(token) = self.token68() {
+                challenge.token68 = Some(token.to_owned());
+            } else {
+                self.params(&mut challenge.params);
+            }
+            challenges.push(challenge);
+        }
+    }
+
+    /// Takes a token68, if it is all there is until the next comma.
+    fn token68(&mut self) -> Option<&'a str> {
+        let start = self.pos;
+        let token = self.take(is_token68_char);
+        self.skip(|c| c == '=');
+        let end = self.pos;
+        self.skip(is_space);
+        if !token.is_empty() && (self.pos == self.s.len() || self.peek() == Some(',')) {
+            return Some(&self.s[start..end]);
+        }
+        self.pos = start;
+        None
+    }
+
+    /// Takes the parameters of a challenge, stopping before the scheme of
+    /// the next one.
+    fn params(&mut self, params: &mut Vec<(String, String)>) {
+        loop {
+            self.skip(|c| c == ',' || is_space(c));
+            let start = self.pos;
+            let name = self.take(is_tchar);
+            self.skip(is_space);
+            if name.is_empty() || self.peek() != Some('=') {
+                // the next challenge, or the end
+                self.pos = start;
+                return;
+            }
+            self.pos += 1;
+            self.skip(is_space);
+            let value = if self.peek() == Some('"') {
+                self.quoted()
+            } else {
+                // commonly left unquoted, even with spaces in it
+                self.take(|c| c != ',').trim().to_owned()
+            };
+            params.push((name.to_owned(), value));
+        }
+    }
+
+    /// Takes a quoted string, without the quotes and escapes. One that is
+    /// never closed goes on to the end.
+    fn quoted(&mut self) -> String {
+        let start = self.pos + 1;
+        let mut value = String::new();
+        let mut chars = self.s[start..].char_indices();
+        self.pos = self.s.len();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos = start + i + 1;
+                    break;
+                },
+                '\\' => {
+                    if let Some((_, escaped)) = chars.next() {
+                        value.push(escaped);
+                    }
+                },
+                c => value.push(c),
+            }
+        }
+        value
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.s[self.pos..].chars().next()
+    }
+
+    fn take<F: Fn(char) -> bool>(&mut self, pred: F) -> &'a str {
+        let start = self.pos;
+        self.skip(pred);
+        &self.s[start..self.pos]
+    }
+
+    fn skip<F: Fn(char) -> bool>(&mut self, pred: F) {
+        let rest = &self.s[self.pos..];
+        self.pos += rest.find(|c| !pred(c)).unwrap_or(rest.len());
+    }
+}
+
+fn is_space(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+fn is_tchar(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+fn is_token68_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-._~+/".contains(c)
+}
+
+#[cfg(test)]
+fn challenge(scheme: &str, token68: Option<&str>, params: &[(&str, &str)]) -> Challenge {
+    Challenge {
+        scheme: scheme.to_owned(),
+        token68: token68.map(|token| token.to_owned()),
+        params: params.iter().map(|&(name, value)| (name.to_owned(), value.to_owned())).collect(),
+    }
+}
+
+#[test]
+fn test_parse() {
+    let parsed = [
+        ("Basic realm=\"simple\"", vec![challenge("Basic", None, &[("realm", "simple")])]),
+        ("Basic realm=\"api\", Bearer realm=\"api\", error=\"invalid_token\"", vec![
+            challenge("Basic", None, &[("realm", "api")]),
+            challenge("Bearer", None, &[("realm", "api"), ("error", "invalid_token")]),
+        ]),
+        // the example in RFC 7235, with a comma and escaped quotes in a value
+        ("Newauth realm=\"apps\", type=1, title=\"Login to \\\"apps\\\", now\", \
+          Basic realm=\"simple\"", vec![
+            challenge("Newauth", None, &[("realm", "apps"), ("type", "1"),
+                                         ("title", "Login to \"apps\", now")]),
+            challenge("Basic", None, &[("realm", "simple")]),
+        ]),
+        ("Negotiate, NTLM", vec![challenge("Negotiate", None, &[]), challenge("NTLM", None, &[])]),
+        ("Negotiate YIIFyQYGKwYBBQUCoII=, Basic realm=x", vec![
+            challenge("Negotiate", Some("YIIFyQYGKwYBBQUCoII="), &[]),
+            challenge("Basic", None, &[("realm", "x")]),
+        ]),
+        // unquoted realms, even with spaces, and spaces around `=`
+        ("basic realm=My Realm ,bearer scope = read", vec![
+            challenge("basic", None, &[("realm", "My Realm")]),
+            challenge("bearer", None, &[("scope", "read")]),
+        ]),
+        ("", vec![]),
+    ];
+    for &(value, ref challenges) in &parsed {
+        assert_eq!(parse(&[value.as_bytes().to_vec()]), *challenges, "{:?}", value);
+    }
+
+    assert_eq!(parse(&[b"Basic realm=a".to_vec(), b"Bearer".to_vec()]), vec![
+        challenge("Basic", None, &[("realm", "a")]),
+        challenge("Bearer", None, &[]),
+    ]);
+}
+
+#[test]
+fn test_parse_digest() {
+    let value = "Digest realm=\"http-auth@example.org\", qop=\"auth, auth-int\", \
+                 algorithm=SHA-256, nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+                 opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\", stale=FALSE, \
+                 domain=\"/ /admin\", charset=UTF-8, userhash=true, Realm=\"ignored\", \
+                 nc=00000001, cnonce=\"f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ\"";
+    let challenges = parse(&[value.as_bytes().to_vec()]);
+    assert_eq!(challenges.len(), 1);
+    let digest = &challenges[0];
+    assert_eq!(digest.scheme(), "Digest");
+    assert_eq!(digest.token68(), None);
+    assert_eq!(digest.params().len(), 12);
+    assert_eq!(digest.param("realm"), Some("http-auth@example.org"));
+    assert_eq!(digest.param("QOP"), Some("auth, auth-int"));
+    assert_eq!(digest.param("algorithm"), Some("SHA-256"));
+    assert_eq!(digest.param("nonce"), Some("7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v"));
+    assert_eq!(digest.param("stale"), Some("FALSE"));
+    assert_eq!(digest.param("domain"), Some("/ /admin"));
+    assert_eq!(digest.param("userhash"), Some("true"));
+    assert_eq!(digest.param("cnonce"), Some("f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ"));
+    assert_eq!(digest.param("missing"), None);
+}
+
+#[test]
+fn test_parse_malformed() {
+    // what can't be parsed is skipped, and the rest is kept
+    assert_eq!(parse(&[b"=oops, \"stray\", Basic realm=\"ok\"".to_vec()]), vec![
+        challenge("Basic", None, &[("realm", "ok")]),
+    ]);
+    // a quoted value that is never closed goes on to the end
+    assert_eq!(parse(&[b"Digest realm=\"unterminated, nonce=1".to_vec()]), vec![
+        challenge("Digest", None, &[("realm", "unterminated, nonce=1")]),
+    ]);
+    assert_eq!(parse(&[b"Basic realm=\"a\" junk".to_vec()]), vec![
+        challenge("Basic", None, &[("realm", "a")]),
+        challenge("junk", None, &[]),
+    ]);
+    assert_eq!(parse(&[b"Basic realm=\xff".to_vec(), b", ,".to_vec()]), vec![]);
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+
+use ::response::{self, Response, Shared};
+
+/// The largest body that is shared by coalesced requests.
+pub const MAX_BODY: usize = 1024 * 1024;
+
+/// The identical requests in flight, for `Client::coalesce_identical_gets`.
+///
+/// The first request of a key is sent, and the others wait for it.
+pub struct Flights {
+    flights: Mutex<HashMap<String, Arc<Flight>>>,
+}
+
+struct Flight {
+    waiters: Mutex<usize>,
+    landed: Mutex<Option<Landed>>,
+    cond: Condvar,
+}
+
+enum Landed {
+    Shared(Shared),
+    Failed(Arc<::Error>),
+    // the response couldn't be shared, so each waiter sends its own request
+    Skipped,
+}
+
+pub enum Join<'a> {
+    /// Sends the request, and lands the flight with the result.
+    Leader(Leader<'a>),
+    /// Waits for the leader.
+    Waiter(Waiter),
+}
+
+impl Flights {
+    pub fn new() -> Flights {
+        Flights {
+            flights: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Joins the flight of `key`, or starts it when there is none.
+    pub fn join<'a>(&'a self, key: String) -> Join<'a> {
+        let mut flights = self.flights.lock().unwrap();
+        if let Some(flight) = flights.get(&key) {
+            *flight.waiters.lock().unwrap() += 1;
+            return Join::Waiter(Waiter {
+                flight: flight.clone(),
+            });
+        }
+        let flight = Arc::new(Flight {
+            waiters: Mutex::new(0),
+            landed: Mutex::new(None),
+            cond: Condvar::new(),
+        });
+        flights.insert(key.clone(), flight.clone());
+        Join::Leader(Leader {
+            flights: self,
+            key: key,
+            flight: flight,
+            landed: false,
+        })
+    }
+
+    /// Removes the flight of `key`, so that no more waiters join it,
+    /// returning how many did.
+    fn take_off(&self, key: &str, flight: &Flight) -> usize {
+        self.flights.lock().unwrap().remove(key);
+        *flight.waiters.lock().unwrap()
+    }
+}
+
+pub struct Leader<'a> {
+    flights: &'a Flights,
+    key: String,
+    flight: Arc<Flight>,
+    landed: bool,
+}
+
+impl<'a> Leader<'a> {
+    /// Shares the result of the request with the waiters.
+    ///
+    /// When there are any, an error is returned to everyone as
+    /// `Error::Coalesced`, and the response only if its body could be read
+    /// into memory.
+    pub fn land(mut self, result: ::Result<Response>) -> ::Result<Response> {
+        self.landed = true;
+        let waiters = self.flights.take_off(&self.key, &self.flight);
+        if waiters == 0 {
+            self.set(Landed::Skipped);
+            return result;
+        }
+        debug!("sharing response with {} identical requests", waiters);
+        match result {
+            Ok(mut res) => {
+                match response::share(&mut res, MAX_BODY) {
+                    Some(shared) => self.set(Landed::Shared(shared)),
+                    None => {
+                        debug!("response body too large to share");
+                        self.set(Landed::Skipped);
+                    },
+                }
+                Ok(res)
+            },
+            Err(err) => {
+                let err = Arc::new(err);
+                self.set(Landed::Failed(err.clone()));
+                Err(::Error::Coalesced(err))
+            },
+        }
+    }
+
+    fn set(&self, landed: Landed) {
+        *self.flight.landed.lock().unwrap() = Some(landed);
+        self.flight.cond.notify_all();
+    }
+}
+
+impl<'a> Drop for Leader<'a> {
+    fn drop(&mut self) {
+        // such as when sending panicked, so the waiters don't wait forever
+        if !self.landed {
+            self.flights.take_off(&self.key, &self.flight);
+            self.set(Landed::Skipped);
+        }
+    }
+}
+
+pub struct Waiter {
+    flight: Arc<Flight>,
+}
+
+impl Waiter {
+    /// Waits for the leader's result, which is `None` when the request has
+    /// to be sent after all.
+    pub fn wait(self) -> Option<::Result<Response>> {
+        let mut landed = self.flight.landed.lock().unwrap();
+        while landed.is_none() {
+            landed = self.flight.cond.wait(landed).unwrap();
+        }
+        match *landed {
+            Some(Landed::Shared(ref shared)) => Some(Ok(shared.response())),
+            Some(Landed::Failed(ref err)) => Some(Err(::Error::Coalesced(err.clone()))),
+            Some(Landed::Skipped) | None => None,
+        }
+    }
+}
@@ -0,0 +1,1832 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(feature = "test-util")]
+use std::sync::atomic::ATOMIC_USIZE_INIT;
+use std::time::{Duration, Instant};
+
+use hyper::header::Headers;
+use hyper::net::{HttpStream, HttpsStream, NetworkConnector, NetworkStream, SslClient};
+use hyper::version::HttpVersion;
+#[cfg(feature = "default-tls")]
+use hyper_native_tls::{NativeTlsClient, TlsStream};
+#[cfg(feature = "default-tls")]
+use native_tls::{TlsConnector, TlsConnectorBuilder};
+#[cfg(feature = "rustls-tls")]
+use ::rustls_tls::{self, RustlsClient};
+
+use ::dns::{Overrides, Resolve};
+use ::error::{TimeoutKind, is_io_timeout};
+use ::partition::Partitions;
+use ::socket::{self, SocketOptions};
+use ::tls::{Certificate, Identity};
+
+/// The name of the native-tls backend, for `reqwest::features` and
+/// `Error::TlsInit`.
+pub const TLS_BACKEND: &'static str = "native-tls";
+/// The name of the rustls backend, with the `rustls-tls` feature.
+pub const RUSTLS_BACKEND: &'static str = "rustls";
+
+/// How many `test::NetworkGuard`s are alive. While any are, nothing is
+/// connected to.
+#[cfg(feature = "test-util")]
+pub static NETWORK_GUARDS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// How the TLS backend verifies servers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TlsOptions {
+    /// Don't verify certificates at all.
+    pub accept_invalid_certs: bool,
+    /// Verify certificates, but not that they are for the host.
+    pub accept_invalid_hostnames: bool,
+    /// Certificates to trust, besides the system's.
+    pub root_certificates: Vec<Certificate>,
+    /// Trust only `root_certificates`, and not the system's, or those
+    /// built into the backend.
+    pub no_built_in_roots: bool,
+    /// What to authenticate as, to servers that ask for it.
+    pub identity: Option<Identity>,
+    /// Use rustls, rather than native-tls, when both are built.
+    pub use_rustls: bool,
+}
+
+impl TlsOptions {
+    /// The name of the backend that these options initialize, if reqwest
+    /// was built with one.
+    pub fn backend(&self) -> Option<&'static str> {
+        if self.use_rustls && cfg!(feature = "rustls-tls") {
+            Some(RUSTLS_BACKEND)
+        } else if cfg!(feature = "default-tls") {
+            Some(TLS_BACKEND)
+        } else if cfg!(feature = "rustls-tls") {
+            Some(RUSTLS_BACKEND)
+        } else {
+            None
+        }
+    }
+}
+
+/// Initializes the TLS backend chosen by `options`, if there is one.
+fn new_tls(options: &TlsOptions) -> ::Result<Option<Tls>> {
+    if let Some(backend) = options.backend() {
+        if options.no_built_in_roots && options.root_certificates.is_empty() {
+            let msg = "no root certificates to trust, the built-in ones are disabled, \
+                       and none were added";
+            return Err(::Error::TlsInit(backend, msg.into()));
+        }
+    }
+    match options.backend() {
+        Some(RUSTLS_BACKEND) => new_rustls(options),
+        Some(_) => new_native_tls(options),
+        None => Ok(None),
+    }
+}
+
+#[cfg(feature = "default-tls")]
+fn new_native_tls(options: &TlsOptions) -> ::Result<Option<Tls>> {
+    let init = |e| ::Error::TlsInit(TLS_BACKEND, Box::new(e));
+    let mut builder = try!(TlsConnector::builder().map_err(&init));
+    // the system's roots are replaced before any are added
+    if options.no_built_in_roots {
+        try!(clear_root_certificates(&mut builder));
+    }
+    for cert in &options.root_certificates {
+        let cert = try!(::native_tls::Certificate::from_der(cert.to_der()).map_err(&init));
+        try!(builder.add_root_certificate(cert).map_err(&init));
+    }
+    if let Some(ref identity) = options.identity {
+        let pkcs12 = try!(::tls::to_pkcs12(identity));
+        try!(builder.identity(pkcs12).map_err(|e| ::Error::InvalidIdentity(Box::new(e))));
+    }
+    if options.accept_invalid_certs {
+        try!(disable_cert_verification(&mut builder));
+    }
+    let mut tls = NativeTlsClient::from(try!(builder.build().map_err(&init)));
+    // a certificate that isn't verified can't vouch for the host either,
+    // and checking it anyway would undo the above
+    tls.danger_disable_hostname_verification(options.accept_invalid_certs ||
+                                             options.accept_invalid_hostnames);
+    Ok(Some(Tls::Native(tls)))
+}
+
+#[cfg(not(feature = "default-tls"))]
+fn new_native_tls(_options: &TlsOptions) -> ::Result<Option<Tls>> {
+    Ok(None)
+}
+
+#[cfg(feature = "rustls-tls")]
+fn new_rustls(options: &TlsOptions) -> ::Result<Option<Tls>> {
+    Ok(Some(Tls::Rustls(try!(RustlsClient::new(options)))))
+}
+
+#[cfg(not(feature = "rustls-tls"))]
+fn new_rustls(_options: &TlsOptions) -> ::Result<Option<Tls>> {
+    Ok(None)
+}
+
+/// Makes the connections of `builder` accept any certificate, when they
+/// are made without a host to verify.
+#[cfg(all(feature = "default-tls",
+          not(any(target_os = "windows", target_os = "macos", target_os = "ios"))))]
+fn disable_cert_verification(builder: &mut TlsConnectorBuilder) -> ::Result<()> {
+    use native_tls::backend::openssl::TlsConnectorBuilderExt;
+
+    builder.builder_mut().builder_mut().set_verify(::openssl::ssl::SSL_VERIFY_NONE);
+    Ok(())
+}
+
+#[cfg(all(feature = "default-tls",
+          any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+fn disable_cert_verification(_builder: &mut TlsConnectorBuilder) -> ::Result<()> {
+    Err(::Error::TlsInit(TLS_BACKEND,
+                         "accepting invalid certificates isn't supported on this platform".into()))
+}
+
+/// Replaces the system's trust store of `builder` with an empty one.
+#[cfg(all(feature = "default-tls",
+          not(any(target_os = "windows", target_os = "macos", target_os = "ios"))))]
+fn clear_root_certificates(builder: &mut TlsConnectorBuilder) -> ::Result<()> {
+    use native_tls::backend::openssl::TlsConnectorBuilderExt;
+    use openssl::x509::store::X509StoreBuilder;
+
+    let store = try!(X509StoreBuilder::new()
+        .map_err(|e| ::Error::TlsInit(TLS_BACKEND, Box::new(e))));
+    builder.builder_mut().builder_mut().set_cert_store(store.build());
+    Ok(())
+}
+
+#[cfg(all(feature = "default-tls",
+          any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+fn clear_root_certificates(_builder: &mut TlsConnectorBuilder) -> ::Result<()> {
+    Err(::Error::TlsInit(TLS_BACKEND,
+                         "disabling the system's root certificates isn't supported on this \
+                          platform".into()))
+}
+
+/// What a `Connector` is made with, shared with the `Client` it connects
+/// for.
+pub struct ConnectorConfig {
+    pub negative: Arc<NegativeCache>,
+    pub open: Arc<AtomicUsize>,
+    pub partitions: Arc<Partitions>,
+    pub timeout: Arc<RwLock<Option<Duration>>>,
+    pub resolver: Arc<RwLock<Arc<Resolve>>>,
+    pub overrides: Arc<Overrides>,
+    /// How many responses had more body than their `Content-Length`.
+    pub overruns: Arc<AtomicUsize>,
+    /// Whether to initialize the TLS backend, to connect to `https` URLs.
+    pub tls: bool,
+    pub tls_options: TlsOptions,
+}
+
+/// The `NetworkConnector` used by a `Client`'s connection pool.
+pub struct Connector {
+    // `None` for a client that only speaks `http`
+    tls: Option<Tls>,
+    negative: Arc<NegativeCache>,
+    // how many streams are open, shared with each `Stream`
+    open: Arc<AtomicUsize>,
+    partitions: Arc<Partitions>,
+    // how long connecting, including the TLS handshake, may take
+    timeout: Arc<RwLock<Option<Duration>>>,
+    resolver: Arc<RwLock<Arc<Resolve>>>,
+    overrides: Arc<Overrides>,
+    overruns: Arc<AtomicUsize>,
+}
+
+impl Connector {
+    pub fn new(config: ConnectorConfig) -> ::Result<Connector> {
+        let tls = if config.tls {
+            try!(new_tls(&config.tls_options))
+        } else {
+            None
+        };
+        Ok(Connector {
+            tls: tls,
+            negative: config.negative,
+            open: config.open,
+            partitions: config.partitions,
+            timeout: config.timeout,
+            resolver: config.resolver,
+            overrides: config.overrides,
+            overruns: config.overruns,
+        })
+    }
+
+    /// Gets the addresses of `host`, from the overrides if it is pinned to
+    /// some, or else from the resolver.
+    ///
+    /// Also returns whether they are from the overrides.
+    fn resolve(&self, host: &str, port: u16) -> io::Result<(Vec<SocketAddr>, bool)> {
+        if let Some(addrs) = self.overrides.get(host, port) {
+            trace!("{}:{} is pinned to {:?}", host, port, addrs);
+            return Ok((addrs, true));
+        }
+        let resolver = self.resolver.read().unwrap().clone();
+        let resolved = try!(resolver.resolve(host, port));
+        trace!("resolved {}:{} to {:?}", host, port, resolved);
+        Ok((resolved, false))
+    }
+}
+
+impl NetworkConnector for Connector {
+    type Stream = Stream;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> ::hyper::Result<Stream> {
+        let (partition, partition_open) = match self.partitions.get(scheme) {
+            Some(partition) => partition,
+            None => {
+                return Err(::hyper::Error::Io(io::Error::new(io::ErrorKind::InvalidInput,
+                                                             "connecting outside a partition")));
+            },
+        };
+        let tls = if partition.scheme == "https" {
+            match self.tls {
+                Some(ref tls) => Some(tls),
+                // the client refuses to send these, before connecting
+                None => {
+                    return Err(::hyper::Error::Io(io::Error::new(io::ErrorKind::InvalidInput,
+                                                                 "https without TLS")));
+                },
+            }
+        } else {
+            None
+        };
+        // a proxy or an alternative service is connected to instead, but
+        // TLS is still for the origin's host
+        let (remote_host, remote_port) = match (&partition.proxy, &partition.route) {
+            (&Some((ref host, port)), _) |
+            (&None, &Some((ref host, port))) => (&**host, port),
+            (&None, &None) => (host, port),
+        };
+        #[cfg(feature = "test-util")]
+        {
+            if NETWORK_GUARDS.load(Ordering::SeqCst) > 0 {
+                let err = ::Error::NetworkDenied(remote_host.to_owned(), remote_port);
+                debug!("{}", err);
+                return Err(::hyper::Error::Io(io::Error::new(io::ErrorKind::PermissionDenied,
+                                                             err)));
+            }
+        }
+        if let Some(err) = self.negative.get(remote_host, remote_port) {
+            debug!("connect to {}:{} failed recently: {}", remote_host, remote_port, err);
+            return Err(::hyper::Error::Io(err));
+        }
+        let timeout = *self.timeout.read().unwrap();
+        // a timeout too long to add is never reached, so it has no deadline
+        let deadline = timeout.and_then(|timeout| {
+            Instant::now().checked_add(timeout).map(|deadline| (timeout, deadline))
+        });
+        // the deadline of the whole request may come first
+        let request_deadline = DEADLINE.with(|d| d.get());
+        let deadline = match (deadline, request_deadline) {
+            (Some(connect), Some(request)) if request.1 < connect.1 => Some(request),
+            (None, request) => request,
+            (connect, _) => connect,
+        };
+        let connected = self.resolve(remote_host, remote_port).and_then(|(resolved, pinned)| {
+            connect_tcp(resolved, remote_host, remote_port, &partition.socket, deadline)
+                .map(|(tcp, mut addrs)| {
+                    addrs.pinned = pinned;
+                    // what the proxy resolved the host to isn't known
+                    if partition.proxy.is_some() {
+                        addrs.resolved = None;
+                    }
+                    (tcp, addrs)
+                })
+        });
+        let (tcp, addrs) = match connected {
+            Ok(connected) => {
+                self.negative.remove(remote_host, remote_port);
+                connected
+            },
+            Err(err) => {
+                // running out of the request's own time says nothing about
+                // the server
+                if request_deadline.is_none() || !is_io_timeout(&err) {
+                    self.negative.insert(remote_host, remote_port, &err);
+                }
+                return Err(::hyper::Error::Io(err));
+            },
+        };
+        let socket = try!(tcp.try_clone());
+        let mut tcp = HttpStream(tcp);
+        let inner = if let Some(tls) = tls {
+            // the tunnel and the handshake get what is left of the connect
+            // timeout
+            if let Some((_, deadline)) = deadline {
+                // a zero timeout would be no timeout at all
+                let left = ::std::cmp::max(time_left(deadline), Duration::from_millis(1));
+                try!(socket.set_read_timeout(Some(left)));
+                try!(socket.set_write_timeout(Some(left)));
+            }
+            if partition.proxy.is_some() {
+                let auth = partition.proxy_auth.as_ref().map(|auth| &auth[..]);
+                if let Err(err) = proxy_connect(&mut tcp, host, port, auth) {
+                    let err = match deadline {
+                        Some((timeout, _)) if is_io_timeout(&err) => {
+                            let during = format!("while the proxy connected to {}:{}", host, port);
+                            io::Error::new(io::ErrorKind::TimedOut,
+                                           ::Error::Timeout(TimeoutKind::Connect, timeout, during))
+                        },
+                        _ => err,
+                    };
+                    return Err(::hyper::Error::Io(err));
+                }
+            }
+            let transport = Transport::new(tcp);
+            let error = transport.error.clone();
+            let wrapped = tls.wrap_client(transport, host);
+            if deadline.is_some() {
+                // each request sets its own timeouts
+                try!(socket.set_read_timeout(None));
+                try!(socket.set_write_timeout(None));
+            }
+            match wrapped {
+                Ok(tls) => HttpsStream::Https(tls),
+                Err(::hyper::Error::Ssl(e)) => {
+                    // the TLS error only describes a failed read or write,
+                    // so its kind is put back for `Error::io_kind`
+                    let kind = *error.lock().unwrap();
+                    if let (Some((timeout, _)), Some(kind)) = (deadline, kind) {
+                        if is_io_timeout(&kind.into()) {
+                            let during = format!("during the TLS handshake with {}", host);
+                            let err = ::Error::Timeout(TimeoutKind::Connect, timeout, during);
+                            return Err(::hyper::Error::Io(io::Error::new(kind, err)));
+                        }
+                    }
+                    let e: Box<StdError + Send + Sync> = match kind {
+                        Some(kind) => Box::new(io::Error::new(kind, e)),
+                        None => e,
+                    };
+                    return Err(::hyper::Error::Ssl(e));
+                },
+                Err(e) => return Err(e),
+            }
+        } else {
+            HttpsStream::Http(tcp)
+        };
+        self.open.fetch_add(1, Ordering::Relaxed);
+        partition_open.fetch_add(1, Ordering::Relaxed);
+        Ok(Stream {
+            inner: inner,
+            socket: socket,
+            upload: Upload::new(),
+            interim: Interim::new(),
+            line: Mutex::new(None),
+            http10: false,
+            closing: false,
+            read_timeout: Mutex::new(None),
+            head_timeout: Mutex::new(None),
+            framing: Mutex::new(Framing::new(false)),
+            limit: None,
+            chunked: None,
+            overrun: false,
+            fresh: AtomicBool::new(true),
+            addrs: addrs,
+            open: self.open.clone(),
+            overruns: self.overruns.clone(),
+            partition_open: partition_open,
+        })
+    }
+}
+
+/// Connects to the first address of `resolved` that works, which are those
+/// of `host`.
+///
+/// With a `deadline`, each address gets an equal share of the time that is
+/// left, so a host that doesn't respond can't use up all of it.
+///
+/// Returns the connected stream, and the addresses that were resolved.
+fn connect_tcp(resolved: Vec<SocketAddr>, host: &str, port: u16, options: &SocketOptions,
+               deadline: Option<(Duration, Instant)>)
+               -> io::Result<(TcpStream, Addrs)> {
+    let mut last_err = None;
+    // a socket bound to a local address can only connect to its family
+    let candidates: Vec<SocketAddr> = match options.local_address {
+        Some(local) => {
+            let candidates: Vec<_> = resolved.iter()
+                .filter(|addr| socket::same_family(&local, addr))
+                .cloned()
+                .collect();
+            if candidates.is_empty() && !resolved.is_empty() {
+                last_err = Some(io::Error::new(io::ErrorKind::InvalidInput,
+                    format!("{} has no address of the family of local address {}", host, local)));
+            }
+            candidates
+        },
+        None => resolved.clone(),
+    };
+    for (i, addr) in candidates.iter().enumerate() {
+        let timeout = match deadline {
+            Some((_, deadline)) => {
+                let share = time_left(deadline) / (candidates.len() - i) as u32;
+                if share == Duration::from_secs(0) {
+                    last_err = Some(io::Error::new(io::ErrorKind::TimedOut, "connect timed out"));
+                    break;
+                }
+                Some(share)
+            },
+            None => None,
+        };
+        match socket::connect(addr, options, timeout) {
+            Ok(tcp) => {
+                return Ok((tcp, Addrs {
+                    resolved: Some(resolved.clone()),
+                    remote: *addr,
+                    pinned: false,
+                }));
+            },
+            Err(e) => {
+                debug!("connect to {} failed: {}", addr, e);
+                last_err = Some(e);
+            },
+        }
+    }
+    let err = last_err.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput,
+                       format!("{} did not resolve to any address", host))
+    });
+    match deadline {
+        Some((timeout, _)) if is_io_timeout(&err) => {
+            let during = format!("while connecting to {}:{}", host, port);
+            Err(io::Error::new(io::ErrorKind::TimedOut,
+                               ::Error::Timeout(TimeoutKind::Connect, timeout, during)))
+        },
+        _ => Err(err),
+    }
+}
+
+/// Asks the proxy that `stream` is connected to for a tunnel to
+/// `host:port`, with `CONNECT`, authenticating with `auth` as the value of
+/// `Proxy-Authorization`.
+///
+/// The response is read a byte at a time, so that nothing after its head,
+/// which would be from the server, is taken from the stream.
+fn proxy_connect<S: Read + Write>(stream: &mut S, host: &str, port: u16, auth: Option<&[u8]>)
+                                  -> io::Result<()> {
+    let authority = format!("{}:{}", host, port);
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority).into_bytes();
+    if let Some(auth) = auth {
+        request.extend_from_slice(b"Proxy-Authorization: ");
+        request.extend_from_slice(auth);
+        request.extend_from_slice(b"\r\n");
+    }
+    request.extend_from_slice(b"\r\n");
+    try!(stream.write_all(&request));
+    try!(stream.flush());
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while head_end(&head).is_none() {
+        if head.len() >= MAX_CONNECT_HEAD {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "proxy response to CONNECT is too large"));
+        }
+        match stream.read(&mut byte) {
+            Ok(0) => {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                          "proxy closed the connection before responding"));
+            },
+            Ok(_) => head.push(byte[0]),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    // any 2xx status means the tunnel is open
+    if head.len() >= 12 && head.starts_with(b"HTTP/1.") && head[8] == b' ' && head[9] == b'2' {
+        trace!("proxy opened a tunnel to {}", authority);
+        return Ok(());
+    }
+    let status = String::from_utf8_lossy(&head).lines().next().unwrap_or("").to_owned();
+    Err(io::Error::new(io::ErrorKind::Other,
+                       format!("proxy refused to connect to {}: {}", authority, status)))
+}
+
+const MAX_CONNECT_HEAD: usize = 8192;
+
+thread_local! {
+    // the deadline of the request being sent on this thread, with the
+    // timeout it is from, set by `with_deadline`
+    static DEADLINE: Cell<Option<(Duration, Instant)>> = Cell::new(None);
+}
+
+/// Calls `f`, with connecting on this thread limited to `deadline`, as well
+/// as to the connect timeout, for a request that has to be done by then.
+///
+/// The `Connector` is only called through the pool, so the deadline can't
+/// be passed to it.
+pub fn with_deadline<T, F>(deadline: Option<(Duration, Instant)>, f: F) -> T
+where F: FnOnce() -> T {
+    struct Restore(Option<(Duration, Instant)>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0;
+            DEADLINE.with(|d| d.set(previous));
+        }
+    }
+
+    let _restore = Restore(DEADLINE.with(|d| {
+        let previous = d.get();
+        d.set(deadline);
+        previous
+    }));
+    f()
+}
+
+/// How long is left until the deadline of `with_deadline`, if there is
+/// one.
+pub fn deadline_left() -> Option<Duration> {
+    DEADLINE.with(|d| d.get()).map(|(_, deadline)| time_left(deadline))
+}
+
+/// How long until `deadline`, or zero once it passed.
+pub fn time_left(deadline: Instant) -> Duration {
+    let now = Instant::now();
+    if now < deadline {
+        deadline - now
+    } else {
+        Duration::from_secs(0)
+    }
+}
+
+/// The addresses of a connection.
+#[derive(Clone, Debug)]
+pub struct Addrs {
+    /// Everything the host resolved to, when connecting, or `None` if it
+    /// wasn't resolved here, such as through a proxy.
+    pub resolved: Option<Vec<SocketAddr>>,
+    /// The address that was connected to.
+    pub remote: SocketAddr,
+    /// Whether `resolved` is what the host is pinned to with
+    /// `Client::resolve`, rather than what the resolver returned.
+    pub pinned: bool,
+}
+
+/// A connection made by the `Connector`.
+///
+/// Besides passing through to the underlying stream, this watches for a
+/// server that responds before the request body was completely sent. When
+/// that happens, the remaining writes are discarded, so that the response
+/// can still be read.
+///
+/// It also skips any interim (1xx) responses the server sends before the
+/// final response, whether the request asked for them or not. hyper would
+/// otherwise return a `100 Continue` as if it were the response.
+pub struct Stream {
+    inner: HttpsStream<TlsConnection>,
+    // a handle to the same socket as `inner`, to peek for an early response
+    socket: TcpStream,
+    upload: Upload,
+    interim: Interim,
+    // replaces parts of the next request line that is written
+    line: Mutex<Option<RequestLine>>,
+    // the request being sent or answered is HTTP/1.0
+    http10: bool,
+    // a response to an HTTP/1.0 request didn't keep the connection alive
+    closing: bool,
+    // as set by hyper, to go back to after the response head
+    read_timeout: Mutex<Option<Duration>>,
+    // how long the response head may take, once the request was sent
+    head_timeout: Mutex<Option<Duration>>,
+    // about the response to the next request
+    framing: Mutex<Framing>,
+    // how much more of the response may be read, if its length is known
+    limit: Option<u64>,
+    // where a chunked response is, to take its trailers
+    chunked: Option<Chunked>,
+    // a response had more body than its `Content-Length`
+    overrun: bool,
+    // hasn't been checked out of the pool before
+    fresh: AtomicBool,
+    addrs: Addrs,
+    open: Arc<AtomicUsize>,
+    overruns: Arc<AtomicUsize>,
+    partition_open: Arc<AtomicUsize>,
+}
+
+impl Stream {
+    /// Gets a handle to check if writing to this stream was aborted.
+    pub fn upload(&self) -> Upload {
+        self.upload.clone()
+    }
+
+    /// Marks the stream as checked out, returning whether it was already
+    /// checked out before.
+    ///
+    /// A stream is new right after connecting, and reused once it comes
+    /// back out of the pool.
+    pub fn check_out(&self) -> bool {
+        !self.fresh.swap(false, Ordering::Relaxed)
+    }
+
+    /// Gets the addresses this stream was connected with.
+    pub fn addrs(&self) -> &Addrs {
+        &self.addrs
+    }
+
+    /// Sets what to write in the request line instead of what hyper writes,
+    /// for the next request only.
+    pub fn set_request_line(&self, line: RequestLine) {
+        *self.line.lock().unwrap() = Some(line);
+    }
+
+    /// Sets how long the head of the response to the next request may take
+    /// to arrive, from when the request was sent, or with `None`, only
+    /// limits each read by the read timeout.
+    pub fn set_head_timeout(&self, timeout: Option<Duration>) {
+        *self.head_timeout.lock().unwrap() = timeout;
+    }
+
+    /// Gets a handle to the framing of the response to the next request,
+    /// which has no body if it is to a `HEAD` request.
+    ///
+    /// The response is never read past the end of its `Content-Length`, so
+    /// a body that is longer doesn't become the start of the next response.
+    pub fn framing(&self, head_request: bool) -> Framing {
+        let framing = Framing::new(head_request);
+        *self.framing.lock().unwrap() = framing.clone();
+        framing
+    }
+
+    /// Whether a response on this stream had more body than it should have,
+    /// so the connection can't be reused.
+    pub fn overran(&self) -> bool {
+        self.overrun
+    }
+
+    /// Whether the response to an HTTP/1.0 request didn't ask to keep the
+    /// connection alive, so it can't be reused.
+    pub fn is_closing(&self) -> bool {
+        self.closing
+    }
+
+    /// Writes the start of a request, with its request line rewritten.
+    fn write_with_line(&mut self, buf: &[u8], line: &RequestLine) -> io::Result<()> {
+        match rewrite_line(buf, line) {
+            Some(start) => self.inner.write_all(&start),
+            None => self.inner.write_all(buf),
+        }
+    }
+
+    /// Whether the server already sent something that hasn't been read yet.
+    ///
+    /// Only done for plain text connections, since TLS may have sent
+    /// session data that isn't part of the response.
+    ///
+    /// Interim responses don't count, they are read and skipped here.
+    fn has_pending_response(&mut self) -> bool {
+        if let HttpsStream::Https(..) = self.inner {
+            return false;
+        }
+        if !self.interim.awaiting {
+            return !self.interim.buf.is_empty();
+        }
+        if self.socket.set_nonblocking(true).is_err() {
+            return false;
+        }
+        if let Ok(n) = self.socket.peek(&mut [0]) {
+            if n > 0 {
+                // stops with `WouldBlock` in the middle of an interim response
+                let _ = self.skip_interim(None);
+            }
+        }
+        let _ = self.socket.set_nonblocking(false);
+        !self.interim.awaiting && !self.interim.buf.is_empty()
+    }
+
+    /// Whether there are bytes left after the last response.
+    ///
+    /// An idle connection should have nothing to read. When it does, such
+    /// as from a server sending a body with a `204 No Content`, those bytes
+    /// would be taken as the start of the next response.
+    ///
+    /// For TLS, the socket is peeked below the TLS stream, so any record
+    /// that arrived counts, even one that isn't data, such as an alert.
+    /// Such a connection isn't reused either, which at worst costs a new
+    /// one.
+    pub fn has_unread_data(&self) -> bool {
+        if !self.interim.buf.is_empty() {
+            return true;
+        }
+        if self.socket.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let unread = match self.socket.peek(&mut [0]) {
+            Ok(n) => n > 0,
+            Err(_) => false,
+        };
+        let _ = self.socket.set_nonblocking(false);
+        unread
+    }
+
+    /// Reads response heads until one that isn't an interim response
+    /// starts.
+    ///
+    /// Whatever was read of the final response stays in the buffer.
+    ///
+    /// With a `head_timeout`, the final response has to start within it,
+    /// each read waiting for no longer than what is left of it.
+    fn skip_interim(&mut self, head_timeout: Option<Duration>) -> io::Result<()> {
+        let mut chunk = [0; 1024];
+        // a timeout too long to add is never reached
+        let deadline = head_timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        let read_timeout = *self.read_timeout.lock().unwrap();
+        while self.interim.awaiting {
+            if let Some(end) = head_end(&self.interim.buf) {
+                if is_interim(&self.interim.buf[..end]) {
+                    debug!("skipping interim response: {:?}",
+                           String::from_utf8_lossy(&self.interim.buf[..end]).lines().next());
+                    self.interim.buf.drain(..end);
+                    continue;
+                }
+                let head_request = self.framing.lock().unwrap().head_request;
+                self.limit = body_len(&self.interim.buf[..end], head_request)
+                    .map(|len| end as u64 + len);
+                self.chunked = if self.limit.is_none() && is_chunked(&self.interim.buf[..end]) {
+                    Some(Chunked::new(end as u64))
+                } else {
+                    None
+                };
+                if self.http10 && !keeps_alive(&self.interim.buf[..end]) {
+                    self.closing = true;
+                }
+                self.interim.awaiting = false;
+            } else if self.interim.buf.len() >= MAX_INTERIM_HEAD {
+                // let hyper deal with whatever this is
+                self.limit = None;
+                self.chunked = None;
+                self.interim.awaiting = false;
+            } else {
+                // whether the head timeout is shorter than the read timeout
+                let mut head_bound = false;
+                if let (Some(deadline), Some(timeout)) = (deadline, head_timeout) {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(head_timed_out(timeout));
+                    }
+                    let left = deadline - now;
+                    head_bound = read_timeout.map_or(true, |read| left < read);
+                    try!(self.inner.set_read_timeout(if head_bound {
+                        Some(left)
+                    } else {
+                        read_timeout
+                    }));
+                }
+                match self.inner.read(&mut chunk) {
+                    Ok(0) => {
+                        self.limit = None;
+                        self.chunked = None;
+                        self.interim.awaiting = false;
+                    },
+                    Ok(n) => {
+                        self.framing.lock().unwrap().set_received();
+                        self.interim.buf.extend_from_slice(&chunk[..n]);
+                    },
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                    Err(ref e) if head_bound && is_io_timeout(e) => {
+                        return Err(head_timed_out(head_timeout.unwrap()));
+                    },
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        if deadline.is_some() {
+            // the body is only limited by the read timeout
+            try!(self.inner.set_read_timeout(read_timeout));
+        }
+        Ok(())
+    }
+
+    /// Reads the trailers of a chunked body, which start with `section`,
+    /// and keeps them in the framing.
+    ///
+    /// hyper fails on trailers, so in their place, it is given the empty
+    /// line that ends a chunked body without any, and then the response
+    /// ends, as if it had a length.
+    fn read_trailers(&mut self, mut section: Vec<u8>) -> io::Result<()> {
+        let mut chunk = [0; 1024];
+        loop {
+            if let Some(end) = trailers_end(&section) {
+                self.framing.lock().unwrap().set_trailers(parse_trailers(&section[..end]));
+                // what came after is counted as excess at the end
+                let mut rest = b"\r\n".to_vec();
+                rest.extend_from_slice(&section[end..]);
+                rest.extend(self.interim.buf.drain(..));
+                self.interim.buf = rest;
+                self.limit = Some(2);
+                return Ok(());
+            }
+            if section.len() >= MAX_INTERIM_HEAD {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "trailers of chunked body too large"));
+            }
+            if !self.interim.buf.is_empty() {
+                section.extend(self.interim.buf.drain(..));
+                continue;
+            }
+            match self.inner.read(&mut chunk) {
+                Ok(0) => {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                              "connection closed in trailers of chunked body"));
+                },
+                Ok(n) => section.extend_from_slice(&chunk[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Discards what was received after the end of a response, counting it
+    /// as excess body.
+    ///
+    /// Only what already arrived is found, and for TLS, only what was
+    /// already decrypted.
+    fn end_of_response(&mut self) {
+        let mut excess = self.interim.buf.len() as u64;
+        self.interim.buf.clear();
+        if let HttpsStream::Http(..) = self.inner {
+            if self.socket.set_nonblocking(true).is_ok() {
+                let mut chunk = [0; 4096];
+                loop {
+                    match (&self.socket).read(&mut chunk) {
+                        Ok(0) => break,
+                        Ok(n) => excess += n as u64,
+                        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                        Err(_) => break,
+                    }
+                }
+                let _ = self.socket.set_nonblocking(false);
+            }
+        }
+        if excess > 0 {
+            debug!("discarding {} bytes after the end of the response body", excess);
+            self.overrun = true;
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+            self.framing.lock().unwrap().set_excess(excess);
+        }
+    }
+}
+
+const MAX_INTERIM_HEAD: usize = 8192;
+
+fn head_timed_out(timeout: Duration) -> io::Error {
+    let during = "while waiting for the response head".to_owned();
+    io::Error::new(io::ErrorKind::TimedOut,
+                   ::Error::Timeout(TimeoutKind::ResponseHeader, timeout, during))
+}
+
+/// Gets the length of the body of a response from its head, if it is
+/// known up front: from its `Content-Length`, or none at all for a
+/// response that can't have one.
+///
+/// `None` for a chunked body, or one that ends when the connection is
+/// closed, and for any `Content-Length` that isn't valid, which hyper
+/// fails on anyway.
+fn body_len(head: &[u8], head_request: bool) -> Option<u64> {
+    let mut lines = head.split(|&b| b == b'\n');
+    let status = match lines.next() {
+        Some(line) if line.len() >= 12 && line.starts_with(b"HTTP/1.") => &line[9..12],
+        _ => return None,
+    };
+    if status == b"101" {
+        return None;
+    }
+    if head_request || status[0] == b'1' || status == b"204" || status == b"304" {
+        return Some(0);
+    }
+    let mut len = None;
+    for line in lines {
+        let colon = match line.iter().position(|&b| b == b':') {
+            Some(colon) => colon,
+            None => continue,
+        };
+        let name = String::from_utf8_lossy(&line[..colon]);
+        let value = String::from_utf8_lossy(&line[colon + 1..]);
+        if name.trim().eq_ignore_ascii_case("Transfer-Encoding") {
+            return None;
+        }
+        if name.trim().eq_ignore_ascii_case("Content-Length") {
+            match (len, value.trim().parse::<u64>()) {
+                (None, Ok(n)) => len = Some(n),
+                (Some(l), Ok(n)) if l == n => (),
+                _ => return None,
+            }
+        }
+    }
+    len
+}
+
+/// Whether the last transfer coding of a response head is `chunked`.
+fn is_chunked(head: &[u8]) -> bool {
+    let mut chunked = false;
+    for line in head.split(|&b| b == b'\n').skip(1) {
+        let colon = match line.iter().position(|&b| b == b':') {
+            Some(colon) => colon,
+            None => continue,
+        };
+        let name = String::from_utf8_lossy(&line[..colon]);
+        if name.trim().eq_ignore_ascii_case("Transfer-Encoding") {
+            let value = String::from_utf8_lossy(&line[colon + 1..]);
+            chunked = value.split(',').last()
+                .map_or(false, |coding| coding.trim().eq_ignore_ascii_case("chunked"));
+        }
+    }
+    chunked
+}
+
+/// Follows a chunked body as it is read, to find where its trailers start.
+struct Chunked {
+    // how much of the head is left before the body
+    head: u64,
+    state: ChunkState,
+}
+
+#[derive(Clone, Copy)]
+enum ChunkState {
+    // in the size line of a chunk, with the size so far, and whether its
+    // digits ended, such as at an extension
+    Size(u64, bool),
+    // in the data of a chunk, with how much is left of it and its CRLF
+    Data(u64),
+    // past the size line of the last chunk
+    Trailers,
+}
+
+impl Chunked {
+    fn new(head: u64) -> Chunked {
+        Chunked {
+            head: head,
+            state: ChunkState::Size(0, false),
+        }
+    }
+
+    /// Follows `buf`, the next bytes of the response, returning where the
+    /// trailers start in it, if they do.
+    fn advance(&mut self, buf: &[u8]) -> Option<usize> {
+        let mut i = 0;
+        while i < buf.len() {
+            let left = (buf.len() - i) as u64;
+            if self.head > 0 {
+                let n = ::std::cmp::min(self.head, left);
+                self.head -= n;
+                i += n as usize;
+                continue;
+            }
+            let state = self.state;
+            self.state = match state {
+                ChunkState::Size(size, _) if buf[i] == b'\n' => {
+                    i += 1;
+                    if size == 0 {
+                        self.state = ChunkState::Trailers;
+                        return Some(i);
+                    }
+                    // an invalid size fails in hyper anyway
+                    ChunkState::Data(size.saturating_add(2))
+                },
+                ChunkState::Size(size, false) => {
+                    let digit = (buf[i] as char).to_digit(16);
+                    i += 1;
+                    match digit {
+                        Some(digit) => {
+                            ChunkState::Size(size.saturating_mul(16).saturating_add(digit as u64),
+                                             false)
+                        },
+                        None => ChunkState::Size(size, true),
+                    }
+                },
+                ChunkState::Size(size, true) => {
+                    i += 1;
+                    ChunkState::Size(size, true)
+                },
+                ChunkState::Data(data) => {
+                    let n = ::std::cmp::min(data, left);
+                    i += n as usize;
+                    if n == data {
+                        ChunkState::Size(0, false)
+                    } else {
+                        ChunkState::Data(data - n)
+                    }
+                },
+                ChunkState::Trailers => return Some(i),
+            };
+        }
+        None
+    }
+}
+
+/// Finds the end of the trailers of a chunked body, after the empty line
+/// that ends them.
+fn trailers_end(section: &[u8]) -> Option<usize> {
+    if section.starts_with(b"\r\n") {
+        Some(2)
+    } else {
+        head_end(section)
+    }
+}
+
+/// Parses the trailer fields of a chunked body, skipping any that aren't
+/// valid.
+fn parse_trailers(section: &[u8]) -> Headers {
+    let mut trailers = Headers::new();
+    for line in section.split(|&b| b == b'\n') {
+        let line = if line.ends_with(b"\r") { &line[..line.len() - 1] } else { line };
+        let colon = match line.iter().position(|&b| b == b':') {
+            Some(colon) => colon,
+            None => continue,
+        };
+        let name = match ::std::str::from_utf8(&line[..colon]) {
+            Ok(name) if !name.is_empty() && !name.contains(|c: char| c.is_whitespace()) => name,
+            _ => continue,
+        };
+        let value = trim_bytes(&line[colon + 1..]).to_vec();
+        let mut values = trailers.get_raw(name).map_or_else(Vec::new, |values| values.to_vec());
+        values.push(value);
+        trailers.set_raw(name.to_owned(), values);
+    }
+    trailers
+}
+
+fn trim_bytes(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|&b| b != b' ' && b != b'\t').unwrap_or(bytes.len());
+    let end = bytes.iter().rposition(|&b| b != b' ' && b != b'\t').map_or(start, |end| end + 1);
+    &bytes[start..end]
+}
+
+/// Tracks the start of a response, to skip interim responses.
+struct Interim {
+    // no final response head has been seen since the request was written
+    awaiting: bool,
+    // the response has started being read by hyper
+    reading: bool,
+    buf: Vec<u8>,
+}
+
+impl Interim {
+    fn new() -> Interim {
+        Interim {
+            awaiting: true,
+            reading: false,
+            buf: Vec::new(),
+        }
+    }
+}
+
+fn head_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|pos| pos + 4)
+}
+
+/// Whether the head has a 1xx status, other than `101 Switching Protocols`.
+fn is_interim(head: &[u8]) -> bool {
+    if head.len() < 12 || !head.starts_with(b"HTTP/1.") || head[8] != b' ' {
+        return false;
+    }
+    let code = &head[9..12];
+    code[0] == b'1' && code != b"101" && code.iter().all(|b| b.is_ascii_digit())
+}
+
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Stream")
+            .field("socket", &self.socket)
+            .field("upload", &self.upload)
+            .finish()
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.interim.reading = true;
+        if self.interim.awaiting {
+            let head_timeout = *self.head_timeout.lock().unwrap();
+            try!(self.skip_interim(head_timeout));
+        }
+        let len = match self.limit {
+            Some(0) => return Ok(0),
+            Some(limit) => ::std::cmp::min(buf.len() as u64, limit) as usize,
+            None => buf.len(),
+        };
+        let n = if self.interim.buf.is_empty() {
+            let n = try!(self.inner.read(&mut buf[..len]));
+            if n > 0 {
+                self.framing.lock().unwrap().set_received();
+            }
+            n
+        } else {
+            let n = ::std::cmp::min(len, self.interim.buf.len());
+            buf[..n].copy_from_slice(&self.interim.buf[..n]);
+            self.interim.buf.drain(..n);
+            n
+        };
+        if let Some(limit) = self.limit {
+            self.limit = Some(limit - n as u64);
+            if limit == n as u64 {
+                self.end_of_response();
+            }
+        }
+        let trailers = match self.chunked {
+            Some(ref mut chunked) => chunked.advance(&buf[..n]),
+            None => None,
+        };
+        if let Some(start) = trailers {
+            self.chunked = None;
+            let section = buf[start..n].to_vec();
+            try!(self.read_trailers(section));
+            if start == 0 {
+                return self.read(buf);
+            }
+            return Ok(start);
+        }
+        Ok(n)
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.interim.reading {
+            // a new request on a kept-alive connection
+            self.interim.reading = false;
+            self.interim.awaiting = true;
+            self.http10 = false;
+        }
+        if self.upload.is_aborted() {
+            return Ok(buf.len());
+        }
+        if self.has_pending_response() {
+            debug!("response received before request was completely sent");
+            self.upload.abort(None);
+            return Ok(buf.len());
+        }
+        let line = self.line.lock().unwrap().take();
+        let res = match line {
+            Some(line) => {
+                self.http10 = line.version == Some(HttpVersion::Http10);
+                self.write_with_line(buf, &line).map(|_| buf.len())
+            },
+            None => self.inner.write(buf),
+        };
+        match res {
+            Err(ref e) if is_disconnect(e.kind()) => {
+                debug!("connection closed while sending request: {}", e);
+                self.upload.abort(Some(io::Error::new(e.kind(), e.to_string())));
+                Ok(buf.len())
+            },
+            other => other,
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.upload.is_aborted() {
+            return Ok(());
+        }
+        match self.inner.flush() {
+            Err(ref e) if is_disconnect(e.kind()) => {
+                debug!("connection closed while sending request: {}", e);
+                self.upload.abort(Some(io::Error::new(e.kind(), e.to_string())));
+                Ok(())
+            },
+            other => other,
+        }
+    }
+}
+
+impl NetworkStream for Stream {
+    #[inline]
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    #[inline]
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        *self.read_timeout.lock().unwrap() = dur;
+        self.inner.set_read_timeout(dur)
+    }
+
+    #[inline]
+    fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.inner.set_write_timeout(dur)
+    }
+
+    #[inline]
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        self.inner.close(how)
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.open.fetch_sub(1, Ordering::Relaxed);
+        self.partition_open.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The TCP stream under a TLS stream, which remembers the kind of the last
+/// error reading or writing it.
+#[derive(Clone, Debug)]
+struct Transport {
+    stream: HttpStream,
+    error: Arc<Mutex<Option<io::ErrorKind>>>,
+}
+
+impl Transport {
+    fn new(stream: HttpStream) -> Transport {
+        Transport {
+            stream: stream,
+            error: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn record<T>(&self, result: io::Result<T>) -> io::Result<T> {
+        if let Err(ref e) = result {
+            *self.error.lock().unwrap() = Some(e.kind());
+        }
+        result
+    }
+}
+
+impl Read for Transport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = self.stream.read(buf);
+        self.record(result)
+    }
+}
+
+impl Write for Transport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.stream.write(buf);
+        self.record(result)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.stream.flush();
+        self.record(result)
+    }
+}
+
+impl NetworkStream for Transport {
+    #[inline]
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    #[inline]
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(dur)
+    }
+
+    #[inline]
+    fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.stream.set_write_timeout(dur)
+    }
+
+    #[inline]
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        self.stream.close(how)
+    }
+}
+
+/// The TLS backend of a `Connector`, one of those reqwest was built with.
+/// Without any, there are never any.
+#[derive(Clone)]
+pub enum Tls {
+    #[cfg(feature = "default-tls")]
+    Native(NativeTlsClient),
+    #[cfg(feature = "rustls-tls")]
+    Rustls(RustlsClient),
+}
+
+#[cfg_attr(not(any(feature = "default-tls", feature = "rustls-tls")), allow(unused_variables))]
+impl SslClient<Transport> for Tls {
+    type Stream = TlsConnection;
+
+    fn wrap_client(&self, stream: Transport, host: &str) -> ::hyper::Result<TlsConnection> {
+        match *self {
+            #[cfg(feature = "default-tls")]
+            Tls::Native(ref tls) => tls.wrap_client(stream, host).map(TlsConnection::Native),
+            #[cfg(feature = "rustls-tls")]
+            Tls::Rustls(ref tls) => tls.wrap_client(stream, host).map(TlsConnection::Rustls),
+        }
+    }
+}
+
+/// A TLS stream made by a `Tls`.
+#[derive(Clone)]
+pub enum TlsConnection {
+    #[cfg(feature = "default-tls")]
+    Native(TlsStream<Transport>),
+    #[cfg(feature = "rustls-tls")]
+    Rustls(rustls_tls::TlsStream<Transport>),
+}
+
+#[cfg_attr(not(any(feature = "default-tls", feature = "rustls-tls")), allow(unused_variables))]
+impl Read for TlsConnection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            #[cfg(feature = "default-tls")]
+            TlsConnection::Native(ref mut s) => s.read(buf),
+            #[cfg(feature = "rustls-tls")]
+            TlsConnection::Rustls(ref mut s) => s.read(buf),
+        }
+    }
+}
+
+#[cfg_attr(not(any(feature = "default-tls", feature = "rustls-tls")), allow(unused_variables))]
+impl Write for TlsConnection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            #[cfg(feature = "default-tls")]
+            TlsConnection::Native(ref mut s) => s.write(buf),
+            #[cfg(feature = "rustls-tls")]
+            TlsConnection::Rustls(ref mut s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            #[cfg(feature = "default-tls")]
+            TlsConnection::Native(ref mut s) => s.flush(),
+            #[cfg(feature = "rustls-tls")]
+            TlsConnection::Rustls(ref mut s) => s.flush(),
+        }
+    }
+}
+
+#[cfg_attr(not(any(feature = "default-tls", feature = "rustls-tls")), allow(unused_variables))]
+impl NetworkStream for TlsConnection {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        match *self {
+            #[cfg(feature = "default-tls")]
+            TlsConnection::Native(ref mut s) => s.peer_addr(),
+            #[cfg(feature = "rustls-tls")]
+            TlsConnection::Rustls(ref mut s) => s.peer_addr(),
+        }
+    }
+
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        match *self {
+            #[cfg(feature = "default-tls")]
+            TlsConnection::Native(ref s) => s.set_read_timeout(dur),
+            #[cfg(feature = "rustls-tls")]
+            TlsConnection::Rustls(ref s) => s.set_read_timeout(dur),
+        }
+    }
+
+    fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        match *self {
+            #[cfg(feature = "default-tls")]
+            TlsConnection::Native(ref s) => s.set_write_timeout(dur),
+            #[cfg(feature = "rustls-tls")]
+            TlsConnection::Rustls(ref s) => s.set_write_timeout(dur),
+        }
+    }
+
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        match *self {
+            #[cfg(feature = "default-tls")]
+            TlsConnection::Native(ref mut s) => s.close(how),
+            #[cfg(feature = "rustls-tls")]
+            TlsConnection::Rustls(ref mut s) => s.close(how),
+        }
+    }
+}
+
+/// What to write in a request line instead of what hyper writes, which is
+/// always the target it chose and `HTTP/1.1`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RequestLine {
+    /// The request target, such as `*`.
+    pub target: Option<Vec<u8>>,
+    pub version: Option<HttpVersion>,
+}
+
+impl RequestLine {
+    /// Whether nothing is replaced.
+    pub fn is_unchanged(&self) -> bool {
+        self.target.is_none() && self.version.is_none()
+    }
+}
+
+/// Rewrites the request line at the start of `buf`.
+fn rewrite_line(buf: &[u8], rewrite: &RequestLine) -> Option<Vec<u8>> {
+    let line_end = buf.windows(2).position(|w| w == b"\r\n").unwrap_or(0);
+    let line = &buf[..line_end];
+    if let Some(method_end) = line.iter().position(|&b| b == b' ') {
+        if let Some(target_len) = line[method_end + 1..].iter().position(|&b| b == b' ') {
+            let target_end = method_end + 1 + target_len;
+            let target = match rewrite.target {
+                Some(ref target) => &target[..],
+                None => &line[method_end + 1..target_end],
+            };
+            let version = match rewrite.version {
+                Some(version) => version.to_string().into_bytes(),
+                None => line[target_end + 1..].to_vec(),
+            };
+            let mut start = Vec::with_capacity(buf.len() + target.len());
+            start.extend_from_slice(&buf[..method_end + 1]);
+            start.extend_from_slice(target);
+            start.push(b' ');
+            start.extend_from_slice(&version);
+            start.extend_from_slice(&buf[line_end..]);
+            return Some(start);
+        }
+    }
+    // hyper always writes at least the whole request line at once
+    debug!("request line not found, not rewritten");
+    None
+}
+
+/// Whether a response head has `Connection: keep-alive`.
+fn keeps_alive(head: &[u8]) -> bool {
+    head.split(|&b| b == b'\n').skip(1).any(|line| {
+        let colon = match line.iter().position(|&b| b == b':') {
+            Some(colon) => colon,
+            None => return false,
+        };
+        let name = String::from_utf8_lossy(&line[..colon]);
+        let value = String::from_utf8_lossy(&line[colon + 1..]);
+        name.trim().eq_ignore_ascii_case("Connection") &&
+            value.split(',').any(|token| token.trim().eq_ignore_ascii_case("keep-alive"))
+    })
+}
+
+/// A `NetworkStream` that keeps everything written to it, to see what a
+/// request looks like on the wire.
+///
+/// Reading from it always reaches the end right away.
+pub struct Capture {
+    written: Vec<u8>,
+    line: Option<RequestLine>,
+}
+
+impl Capture {
+    /// Creates a `Capture`, rewriting the request line like
+    /// `Stream::set_request_line`.
+    pub fn new(line: Option<RequestLine>) -> Capture {
+        Capture {
+            written: Vec::new(),
+            line: line,
+        }
+    }
+
+    pub fn into_written(self) -> Vec<u8> {
+        self.written
+    }
+}
+
+impl Read for Capture {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Ok(0)
+    }
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.line.take().and_then(|line| rewrite_line(buf, &line)) {
+            Some(start) => self.written.extend_from_slice(&start),
+            None => self.written.extend_from_slice(buf),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl NetworkStream for Capture {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        Err(io::Error::new(io::ErrorKind::NotConnected, "not a connection"))
+    }
+
+    fn set_read_timeout(&self, _dur: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&self, _dur: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A stream given to `Client::request_over`, shared by the requests of a
+/// redirect chain.
+///
+/// The stream is used as is, so the client's timeouts don't apply to it.
+#[derive(Clone)]
+pub struct Tunnel {
+    inner: Arc<Mutex<Box<ReadWrite + Send>>>,
+    // hyper closed the connection, after a response that didn't keep it alive
+    closed: Arc<AtomicBool>,
+    used: Arc<AtomicBool>,
+    line: Option<RequestLine>,
+}
+
+pub trait ReadWrite: Read + Write {}
+
+impl<T: Read + Write> ReadWrite for T {}
+
+impl Tunnel {
+    pub fn new<S: Read + Write + Send + 'static>(stream: S) -> Tunnel {
+        Tunnel {
+            inner: Arc::new(Mutex::new(Box::new(stream))),
+            closed: Arc::new(AtomicBool::new(false)),
+            used: Arc::new(AtomicBool::new(false)),
+            line: None,
+        }
+    }
+
+    /// Gets a handle to the same stream, rewriting the request line like
+    /// `Stream::set_request_line`.
+    pub fn with_request_line(&self, line: Option<RequestLine>) -> Tunnel {
+        Tunnel {
+            line: line,
+            ..self.clone()
+        }
+    }
+
+    /// Marks the stream as used, returning whether it was already used
+    /// for a request before.
+    pub fn check_out(&self) -> bool {
+        self.used.swap(true, Ordering::Relaxed)
+    }
+
+    /// Whether a response closed the connection, so no more requests can
+    /// be sent on it.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// The addresses of the stream, which aren't known.
+    ///
+    /// Nothing was resolved, and the remote address is unspecified.
+    pub fn addrs(port: u16) -> Addrs {
+        Addrs {
+            resolved: None,
+            remote: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), port),
+            pinned: false,
+        }
+    }
+}
+
+impl fmt::Debug for Tunnel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Tunnel")
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+impl Read for Tunnel {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.lock().unwrap().read(buf)
+    }
+}
+
+impl Write for Tunnel {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut inner = self.inner.lock().unwrap();
+        match self.line.take().and_then(|line| rewrite_line(buf, &line)) {
+            Some(start) => inner.write_all(&start).map(|_| buf.len()),
+            None => inner.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.lock().unwrap().flush()
+    }
+}
+
+impl NetworkStream for Tunnel {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        Err(io::Error::new(io::ErrorKind::NotConnected, "address of stream is unknown"))
+    }
+
+    fn set_read_timeout(&self, _dur: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn set_write_timeout(&self, _dur: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn close(&mut self, _how: Shutdown) -> io::Result<()> {
+        // the stream itself is closed when it's dropped
+        self.closed.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Whether an error is from the connection being closed or reset by the
+/// other side.
+pub fn is_disconnect(kind: io::ErrorKind) -> bool {
+    match kind {
+        io::ErrorKind::BrokenPipe |
+        io::ErrorKind::ConnectionReset |
+        io::ErrorKind::ConnectionAborted => true,
+        _ => false,
+    }
+}
+
+/// Shared state about how a response on a `Stream` ended.
+#[derive(Clone, Debug)]
+pub struct Framing {
+    head_request: bool,
+    // anything of the response was read
+    received: Arc<AtomicBool>,
+    // how many bytes were discarded after the end of the body
+    excess: Arc<Mutex<Option<u64>>>,
+    // the trailers of a chunked body, once it ended
+    trailers: Arc<Mutex<Option<Headers>>>,
+}
+
+impl Framing {
+    pub fn new(head_request: bool) -> Framing {
+        Framing {
+            head_request: head_request,
+            received: Arc::new(AtomicBool::new(false)),
+            excess: Arc::new(Mutex::new(None)),
+            trailers: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn set_trailers(&self, trailers: Headers) {
+        *self.trailers.lock().unwrap() = Some(trailers);
+    }
+
+    /// The trailers of a chunked body, once it was read to its end, which
+    /// may be empty.
+    pub fn trailers(&self) -> Option<Headers> {
+        self.trailers.lock().unwrap().clone()
+    }
+
+    fn set_received(&self) {
+        self.received.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether any of the response was received, even just the start of
+    /// an interim response.
+    pub fn received(&self) -> bool {
+        self.received.load(Ordering::Relaxed)
+    }
+
+    fn set_excess(&self, excess: u64) {
+        *self.excess.lock().unwrap() = Some(excess);
+    }
+
+    /// How many bytes the server sent after the end of the body it said it
+    /// would send, if any.
+    pub fn excess(&self) -> Option<u64> {
+        *self.excess.lock().unwrap()
+    }
+}
+
+/// Shared state about sending a request on a `Stream`.
+#[derive(Clone, Debug)]
+pub struct Upload {
+    aborted: Arc<Mutex<Option<Aborted>>>,
+}
+
+#[derive(Debug)]
+struct Aborted {
+    err: Option<io::Error>,
+}
+
+impl Upload {
+    pub fn new() -> Upload {
+        Upload {
+            aborted: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Marks that the request will not be completely sent.
+    pub fn abort(&self, err: Option<io::Error>) {
+        *self.aborted.lock().unwrap() = Some(Aborted {
+            err: err,
+        });
+    }
+
+    /// Whether the stream stopped sending the request.
+    ///
+    /// A stream that was aborted can not be used for another request.
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.lock().unwrap().is_some()
+    }
+
+    /// Takes the write error that caused the abort, if there was one.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.aborted.lock().unwrap().as_mut().and_then(|aborted| aborted.err.take())
+    }
+}
+
+const MAX_NEGATIVE_ENTRIES: usize = 256;
+
+/// Remembers hosts that recently failed to resolve or connect.
+///
+/// While an entry is fresh, connecting to the same host and port fails
+/// right away with a copy of the original error.
+#[derive(Debug)]
+pub struct NegativeCache {
+    ttl: RwLock<Option<Duration>>,
+    failures: Mutex<HashMap<(String, u16), Failure>>,
+}
+
+#[derive(Debug)]
+struct Failure {
+    at: Instant,
+    kind: io::ErrorKind,
+    message: String,
+}
+
+impl NegativeCache {
+    pub fn new() -> NegativeCache {
+        NegativeCache {
+            ttl: RwLock::new(None),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets how long a failure is remembered. `None` disables the cache.
+    pub fn set_ttl(&self, ttl: Option<Duration>) {
+        *self.ttl.write().unwrap() = ttl;
+        if ttl.is_none() {
+            self.failures.lock().unwrap().clear();
+        }
+    }
+
+    fn get(&self, host: &str, port: u16) -> Option<io::Error> {
+        let ttl = match *self.ttl.read().unwrap() {
+            Some(ttl) => ttl,
+            None => return None,
+        };
+        let mut failures = self.failures.lock().unwrap();
+        let key = (host.to_owned(), port);
+        let expired = match failures.get(&key) {
+            Some(failure) if failure.at.elapsed() < ttl => {
+                return Some(io::Error::new(failure.kind, Cached {
+                    message: failure.message.clone(),
+                }));
+            },
+            Some(_) => true,
+            None => false,
+        };
+        if expired {
+            failures.remove(&key);
+        }
+        None
+    }
+
+    fn insert(&self, host: &str, port: u16, err: &io::Error) {
+        let ttl = match *self.ttl.read().unwrap() {
+            Some(ttl) => ttl,
+            None => return,
+        };
+        let mut failures = self.failures.lock().unwrap();
+        if failures.len() >= MAX_NEGATIVE_ENTRIES {
+            failures.retain(|_, failure| failure.at.elapsed() < ttl);
+        }
+        if failures.len() >= MAX_NEGATIVE_ENTRIES {
+            let oldest = failures.iter()
+                .min_by_key(|&(_, failure)| failure.at)
+                .map(|(key, _)| key.clone());
+            if let Some(key) = oldest {
+                failures.remove(&key);
+            }
+        }
+        failures.insert((host.to_owned(), port), Failure {
+            at: Instant::now(),
+            kind: err.kind(),
+            message: err.to_string(),
+        });
+    }
+
+    fn remove(&self, host: &str, port: u16) {
+        if self.ttl.read().unwrap().is_some() {
+            self.failures.lock().unwrap().remove(&(host.to_owned(), port));
+        }
+    }
+}
+
+/// The error of a connect that failed before, returned from the
+/// `NegativeCache` instead of trying again.
+#[derive(Debug)]
+pub struct Cached {
+    message: String,
+}
+
+impl fmt::Display for Cached {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (cached connect failure)", self.message)
+    }
+}
+
+impl StdError for Cached {
+    fn description(&self) -> &str {
+        "cached connect failure"
+    }
+}
@@ -0,0 +1,342 @@
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hyper::header::{Headers, HttpDate};
+use hyper::Url;
+use url::Host;
+
+/// A cookie that a response set with `Set-Cookie`, as in RFC 6265.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Cookie {
+    name: String,
+    value: String,
+    // lowercase, without a leading dot
+    domain: String,
+    // sent only to the host that set it, without a `Domain` attribute
+    host_only: bool,
+    path: String,
+    secure: bool,
+    // `None` for a session cookie, which lasts as long as the jar
+    expires: Option<SystemTime>,
+    // to send the older of cookies with paths of the same length first
+    created: u64,
+}
+
+impl Cookie {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+
+    fn matches(&self, url: &Url, host: &str) -> bool {
+        let domain = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(url, host, &self.domain)
+        };
+        domain && path_match(url.path(), &self.path) &&
+            (!self.secure || url.scheme() == "https")
+    }
+}
+
+/// The cookies that responses set, by domain and path.
+///
+/// See `Client::cookie_store`.
+pub struct CookieJar {
+    cookies: Mutex<(Vec<Cookie>, u64)>,
+}
+
+impl CookieJar {
+    pub fn new() -> CookieJar {
+        CookieJar {
+            cookies: Mutex::new((Vec::new(), 0)),
+        }
+    }
+
+    /// Remembers the cookies in the `Set-Cookie` of a response from `url`,
+    /// replacing those of the same name, domain and path, and removing
+    /// those it expires.
+    pub fn store(&self, url: &Url, headers: &Headers) {
+        let values = match headers.get_raw("Set-Cookie") {
+            Some(values) => values,
+            None => return,
+        };
+        let now = SystemTime::now();
+        let mut jar = self.cookies.lock().unwrap();
+        let (ref mut cookies, ref mut created) = *jar;
+        for value in values {
+            let value = String::from_utf8_lossy(value);
+            let cookie = match parse(url, &value, now, *created) {
+                Some(cookie) => cookie,
+                None => {
+                    debug!("ignoring Set-Cookie from '{}': {:?}", url, value);
+                    continue;
+                },
+            };
+            *created += 1;
+            let old = cookies.iter().position(|old| {
+                old.name == cookie.name && old.domain == cookie.domain && old.path == cookie.path
+            });
+            let cookie = match old {
+                Some(i) => Cookie {
+                    created: cookies[i].created,
+                    ..cookie
+                },
+                None => cookie,
+            };
+            cookies.retain(|old| {
+                old.name != cookie.name || old.domain != cookie.domain || old.path != cookie.path
+            });
+            if !cookie.is_expired(now) {
+                cookies.push(cookie);
+            }
+        }
+    }
+
+    /// Gets the value of the `Cookie` header to send to `url`, if any of
+    /// the cookies match it.
+    ///
+    /// Cookies with longer paths come first, and otherwise those that were
+    /// set first.
+    pub fn header(&self, url: &Url) -> Option<String> {
+        let host = match url.host_str() {
+            Some(host) => host,
+            None => return None,
+        };
+        let now = SystemTime::now();
+        let mut jar = self.cookies.lock().unwrap();
+        let cookies = &mut jar.0;
+        cookies.retain(|cookie| !cookie.is_expired(now));
+        let mut matching = cookies.iter()
+            .filter(|cookie| cookie.matches(url, host))
+            .collect::<Vec<_>>();
+        if matching.is_empty() {
+            return None;
+        }
+        matching.sort_by(|a, b| (b.path.len(), a.created).cmp(&(a.path.len(), b.created)));
+        let pairs = matching.iter()
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect::<Vec<_>>();
+        Some(pairs.join("; "))
+    }
+}
+
+/// Parses a `Set-Cookie` value from a response from `url`.
+///
+/// Returns `None` for a cookie that has to be ignored: one without a name,
+/// one for a `Domain` that `url` isn't in, or a `Secure` one that wasn't
+/// set over `https`.
+fn parse(url: &Url, value: &str, now: SystemTime, created: u64) -> Option<Cookie> {
+    let host = match url.host_str() {
+        Some(host) => host,
+        None => return None,
+    };
+    let mut parts = value.split(';');
+    let (name, value) = {
+        let pair = parts.next().unwrap_or("");
+        let mut pair = pair.splitn(2, '=');
+        match (pair.next(), pair.next()) {
+            (Some(name), Some(value)) if !name.trim().is_empty() => (name.trim(), value.trim()),
+            _ => return None,
+        }
+    };
+
+    let mut domain = None;
+    let mut path = None;
+    let mut secure = false;
+    let mut expires = None;
+    let mut max_age = None;
+    for attribute in parts {
+        let mut attribute = attribute.splitn(2, '=');
+        let name = attribute.next().unwrap_or("").trim();
+        let value = attribute.next().unwrap_or("").trim();
+        if name.eq_ignore_ascii_case("Domain") {
+            let value = value.trim_left_matches('.');
+            if !value.is_empty() {
+                domain = Some(value.to_lowercase());
+            }
+        } else if name.eq_ignore_ascii_case("Path") {
+            path = if value.starts_with('/') { Some(value.to_owned()) } else { None };
+        } else if name.eq_ignore_ascii_case("Secure") {
+            secure = true;
+        } else if name.eq_ignore_ascii_case("Expires") {
+            if let Ok(date) = value.parse::<HttpDate>() {
+                expires = to_system_time(date.0.to_timespec().sec);
+            }
+        } else if name.eq_ignore_ascii_case("Max-Age") {
+            if let Ok(secs) = value.parse::<i64>() {
+                // zero or less expires the cookie at once, and one too far
+                // off to tell never does
+                max_age = Some(if secs <= 0 {
+                    Some(UNIX_EPOCH)
+                } else {
+                    now.checked_add(Duration::from_secs(secs as u64))
+                });
+            }
+        }
+    }
+
+    if secure && url.scheme() != "https" {
+        return None;
+    }
+    // without a list of public suffixes, at least a top-level domain is
+    // only allowed for itself, such as `localhost`
+    let (domain, host_only) = match domain {
+        Some(domain) => {
+            let top_level = !domain.contains('.');
+            if domain == host {
+                (domain, top_level)
+            } else if top_level || !domain_match(url, host, &domain) {
+                return None;
+            } else {
+                (domain, false)
+            }
+        },
+        None => (host.to_owned(), true),
+    };
+    Some(Cookie {
+        name: name.to_owned(),
+        value: value.to_owned(),
+        domain: domain,
+        host_only: host_only,
+        path: path.unwrap_or_else(|| default_path(url.path()).to_owned()),
+        secure: secure,
+        // `Max-Age` wins over `Expires`
+        expires: max_age.unwrap_or(expires),
+        created: created,
+    })
+}
+
+fn to_system_time(secs: i64) -> Option<SystemTime> {
+    if secs <= 0 {
+        Some(UNIX_EPOCH)
+    } else {
+        UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))
+    }
+}
+
+/// Whether `host` is `domain`, or a subdomain of it. An IP address only
+/// matches itself.
+fn domain_match(url: &Url, host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    match url.host() {
+        Some(Host::Domain(_)) => {
+            host.ends_with(domain) && host[..host.len() - domain.len()].ends_with('.')
+        },
+        _ => false,
+    }
+}
+
+/// Whether a request for `path` is in the `cookie_path`.
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path ||
+        path.starts_with(cookie_path) &&
+            (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/'))
+}
+
+/// The path of a cookie without a `Path`, the directory of the request.
+fn default_path(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(i) => &path[..i],
+    }
+}
+
+#[cfg(test)]
+fn store(jar: &CookieJar, url: &str, set_cookies: &[&str]) {
+    let mut headers = Headers::new();
+    headers.set_raw("Set-Cookie", set_cookies.iter().map(|v| v.as_bytes().to_vec()).collect());
+    jar.store(&Url::parse(url).unwrap(), &headers);
+}
+
+#[cfg(test)]
+fn header(jar: &CookieJar, url: &str) -> Option<String> {
+    jar.header(&Url::parse(url).unwrap())
+}
+
+#[test]
+fn test_domain() {
+    let jar = CookieJar::new();
+    store(&jar, "http://www.example.com/", &[
+        "host=1",
+        "domain=2; Domain=.Example.COM",
+        // not a domain that www.example.com is in
+        "other=3; Domain=example.org",
+        "sub=4; Domain=api.www.example.com",
+        "tld=5; Domain=com",
+    ]);
+    assert_eq!(header(&jar, "http://www.example.com/").unwrap(), "host=1; domain=2");
+    assert_eq!(header(&jar, "http://api.example.com/").unwrap(), "domain=2");
+    assert_eq!(header(&jar, "http://example.com/").unwrap(), "domain=2");
+    assert_eq!(header(&jar, "http://badexample.com/"), None);
+    assert_eq!(header(&jar, "http://example.org/"), None);
+
+    let jar = CookieJar::new();
+    store(&jar, "http://example.com/", &["domain=1; Domain=example.com"]);
+    store(&jar, "http://localhost/", &["local=2; Domain=localhost"]);
+    assert_eq!(header(&jar, "http://www.example.com/").unwrap(), "domain=1");
+    assert_eq!(header(&jar, "http://localhost/").unwrap(), "local=2");
+
+    let jar = CookieJar::new();
+    store(&jar, "http://127.0.0.1/", &["ip=1; Domain=0.0.1"]);
+    assert_eq!(header(&jar, "http://127.0.0.1/"), None);
+}
+
+#[test]
+fn test_path() {
+    let jar = CookieJar::new();
+    store(&jar, "http://example.com/api/v1/users", &[
+        "default=1",
+        "root=2; Path=/",
+        "deep=3; Path=/api/v1/users",
+        "relative=4; Path=api",
+    ]);
+    // longer paths first
+    assert_eq!(header(&jar, "http://example.com/api/v1/users/7").unwrap(),
+               "deep=3; default=1; relative=4; root=2");
+    assert_eq!(header(&jar, "http://example.com/api/v1").unwrap(),
+               "default=1; relative=4; root=2");
+    assert_eq!(header(&jar, "http://example.com/api/v10").unwrap(), "root=2");
+
+    assert_eq!(default_path(""), "/");
+    assert_eq!(default_path("/"), "/");
+    assert_eq!(default_path("/login"), "/");
+    assert_eq!(default_path("/api/login"), "/api");
+}
+
+#[test]
+fn test_secure() {
+    let jar = CookieJar::new();
+    store(&jar, "http://example.com/", &["insecure=1; Secure"]);
+    assert_eq!(header(&jar, "https://example.com/"), None);
+
+    store(&jar, "https://example.com/", &["secure=2; Secure; HttpOnly", "plain=3"]);
+    assert_eq!(header(&jar, "https://example.com/").unwrap(), "secure=2; plain=3");
+    assert_eq!(header(&jar, "http://example.com/").unwrap(), "plain=3");
+}
+
+#[test]
+fn test_expiry() {
+    let jar = CookieJar::new();
+    store(&jar, "http://example.com/", &[
+        "session=1",
+        "old=2; Expires=Sun, 06 Nov 1994 08:49:37 GMT",
+        "later=3; Expires=Thu, 01 Jan 2099 00:00:00 GMT",
+        // Max-Age wins over Expires
+        "gone=4; Max-Age=0; Expires=Thu, 01 Jan 2099 00:00:00 GMT",
+        "aged=5; Max-Age=3600",
+    ]);
+    assert_eq!(header(&jar, "http://example.com/").unwrap(), "session=1; later=3; aged=5");
+
+    // replacing keeps the order, and an expired one is removed
+    store(&jar, "http://example.com/", &["session=6", "later=7; Max-Age=-1"]);
+    assert_eq!(header(&jar, "http://example.com/").unwrap(), "session=6; aged=5");
+
+    store(&jar, "http://example.com/", &["=nameless", "noequals"]);
+    assert_eq!(header(&jar, "http://example.com/").unwrap(), "session=6; aged=5");
+
+    // too far off to tell, so it never expires
+    store(&jar, "http://example.com/", &["forever=8; Max-Age=9223372036854775807"]);
+    assert_eq!(header(&jar, "http://example.com/").unwrap(), "session=6; aged=5; forever=8");
+}
@@ -0,0 +1,225 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hyper::header::{Headers, HttpDate};
+use hyper::Url;
+
+use ::alt_svc::{parse_param, split_unquoted};
+
+/// What a response said about its endpoint being deprecated, from its
+/// `Deprecation`, `Sunset` and `Link` headers.
+///
+/// See `Response::deprecation`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeprecationInfo {
+    deprecated: bool,
+    deprecated_at: Option<SystemTime>,
+    sunset: Option<SystemTime>,
+    links: Vec<Url>,
+}
+
+impl DeprecationInfo {
+    /// Whether the `Deprecation` header says the endpoint is deprecated.
+    ///
+    /// A response with only a `Sunset` header isn't.
+    pub fn is_deprecated(&self) -> bool {
+        self.deprecated
+    }
+
+    /// When the endpoint was, or will be, deprecated, if the `Deprecation`
+    /// header has a date instead of just `true`.
+    pub fn deprecated_at(&self) -> Option<SystemTime> {
+        self.deprecated_at
+    }
+
+    /// When the endpoint is expected to stop responding, from the `Sunset`
+    /// header.
+    pub fn sunset(&self) -> Option<SystemTime> {
+        self.sunset
+    }
+
+    /// The links with a `rel="deprecation"`, such as to a migration guide,
+    /// relative to the URL of the response.
+    pub fn links(&self) -> &[Url] {
+        &self.links
+    }
+}
+
+/// Parses the deprecation headers of a response from `url`.
+///
+/// `None` unless the `Deprecation` header says the endpoint is deprecated,
+/// or there is a `Sunset` with a valid date.
+pub fn parse(url: &Url, headers: &Headers) -> Option<DeprecationInfo> {
+    let (deprecated, deprecated_at) = match first(headers, "Deprecation") {
+        Some(value) => parse_deprecation(&value),
+        None => (false, None),
+    };
+    let sunset = first(headers, "Sunset").and_then(|value| parse_date(&value));
+    if !deprecated && sunset.is_none() {
+        return None;
+    }
+    Some(DeprecationInfo {
+        deprecated: deprecated,
+        deprecated_at: deprecated_at,
+        sunset: sunset,
+        links: links(url, headers),
+    })
+}
+
+fn first(headers: &Headers, name: &str) -> Option<String> {
+    headers.get_raw(name)
+        .and_then(|values| values.first())
+        .map(|value| String::from_utf8_lossy(value).trim().trim_matches('"').trim().to_owned())
+}
+
+/// Parses a `Deprecation`, in any of the forms its drafts had: `true`, a
+/// structured field boolean or date like `?1` and `@1688169599`, or an
+/// HTTP-date.
+///
+/// Any other value still means deprecated, such as the `version="v1"` of
+/// the first draft, just without a date.
+fn parse_deprecation(value: &str) -> (bool, Option<SystemTime>) {
+    if value.is_empty() || value.eq_ignore_ascii_case("false") || value == "?0" {
+        return (false, None);
+    }
+    if value.eq_ignore_ascii_case("true") || value == "?1" {
+        return (true, None);
+    }
+    (true, parse_date(value))
+}
+
+/// Parses an HTTP-date, or a structured field date like `@1688169599`.
+fn parse_date(value: &str) -> Option<SystemTime> {
+    let secs = if value.starts_with('@') {
+        match value[1..].parse::<i64>() {
+            Ok(secs) => secs,
+            Err(_) => return None,
+        }
+    } else {
+        match value.parse::<HttpDate>() {
+            Ok(date) => date.0.to_timespec().sec,
+            Err(_) => return None,
+        }
+    };
+    if secs < 0 {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+}
+
+/// Gets the targets of the `Link`s with a `rel` of `deprecation`.
+fn links(url: &Url, headers: &Headers) -> Vec<Url> {
+    let values = match headers.get_raw("Link") {
+        Some(values) => values,
+        None => return Vec::new(),
+    };
+    let mut links = Vec::new();
+    for value in values {
+        let value = String::from_utf8_lossy(value);
+        for link in split_unquoted(&value, ',') {
+            let mut parts = split_unquoted(link, ';').into_iter();
+            let target = parts.next().unwrap_or("").trim();
+            if !target.starts_with('<') || !target.ends_with('>') {
+                continue;
+            }
+            let deprecation = parts.any(|param| match parse_param(param.trim()) {
+                Some((name, ref rels)) if name.eq_ignore_ascii_case("rel") => {
+                    rels.split_whitespace().any(|rel| rel.eq_ignore_ascii_case("deprecation"))
+                },
+                _ => false,
+            });
+            if deprecation {
+                if let Ok(link) = url.join(&target[1..target.len() - 1]) {
+                    links.push(link);
+                }
+            }
+        }
+    }
+    links
+}
+
+/// Calls the function of `ClientBuilder::on_deprecated_endpoint`, once for
+/// each host and path.
+pub struct Reporter {
+    hook: Box<Fn(&Url, &DeprecationInfo) + Send + Sync + 'static>,
+    // the hosts and paths it was called for
+    seen: Mutex<HashSet<(String, String)>>,
+}
+
+impl Reporter {
+    pub fn new<F>(hook: F) -> Reporter
+    where F: Fn(&Url, &DeprecationInfo) + Send + Sync + 'static {
+        Reporter {
+            hook: Box::new(hook),
+            seen: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Calls the hook if a response from `url` is deprecated, unless it
+    /// already was for the same host and path.
+    pub fn report(&self, url: &Url, headers: &Headers) {
+        let info = match parse(url, headers) {
+            Some(info) => info,
+            None => return,
+        };
+        let key = (url.host_str().unwrap_or("").to_lowercase(), url.path().to_owned());
+        if self.seen.lock().unwrap().insert(key) {
+            (self.hook)(url, &info);
+        }
+    }
+}
+
+impl fmt::Debug for Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Reporter")
+            .field("seen", &self.seen.lock().unwrap().len())
+            .finish()
+    }
+}
+
+#[test]
+fn test_parse_deprecation() {
+    let date = Some(UNIX_EPOCH + Duration::from_secs(1688169599));
+    assert_eq!(parse_deprecation("true"), (true, None));
+    assert_eq!(parse_deprecation("?1"), (true, None));
+    assert_eq!(parse_deprecation("@1688169599"), (true, date));
+    assert_eq!(parse_deprecation("Fri, 30 Jun 2023 23:59:59 GMT"), (true, date));
+    // from the first draft
+    assert_eq!(parse_deprecation("version=\"v1\""), (true, None));
+
+    assert_eq!(parse_deprecation("false"), (false, None));
+    assert_eq!(parse_deprecation("?0"), (false, None));
+    assert_eq!(parse_deprecation(""), (false, None));
+}
+
+#[test]
+fn test_parse() {
+    let url = Url::parse("https://api.example.com/v1/users").unwrap();
+    let mut headers = Headers::new();
+    assert_eq!(parse(&url, &headers), None);
+
+    headers.set_raw("Deprecation", vec![b"false".to_vec()]);
+    headers.set_raw("Link", vec![b"</docs/v2>; rel=\"deprecation\"".to_vec()]);
+    assert_eq!(parse(&url, &headers), None);
+
+    // a sunset alone is still reported
+    headers.set_raw("Sunset", vec![b"\"Sat, 01 Jun 2030 00:00:00 GMT\"".to_vec()]);
+    let info = parse(&url, &headers).unwrap();
+    assert!(!info.is_deprecated());
+    assert_eq!(info.sunset(), Some(UNIX_EPOCH + Duration::from_secs(1906502400)));
+
+    headers.set_raw("Deprecation", vec![b" TRUE ".to_vec()]);
+    headers.set_raw("Link", vec![
+        b"<https://example.com/next>; rel=next, </docs/v2>; REL=\"alternate deprecation\"".to_vec(),
+        b"<guide>; type=\"text/html\"; rel=deprecation, <; rel=deprecation".to_vec(),
+    ]);
+    let info = parse(&url, &headers).unwrap();
+    assert!(info.is_deprecated());
+    assert_eq!(info.deprecated_at(), None);
+    assert_eq!(info.links().iter().map(|url| url.as_str()).collect::<Vec<_>>(), vec![
+        "https://api.example.com/docs/v2",
+        "https://api.example.com/v1/guide",
+    ]);
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::RwLock;
+
+/// Resolves the host names that a `Client` connects to.
+///
+/// Set with `ClientBuilder::dns_resolver`. The addresses are tried in the
+/// order they are returned, until one can be connected to.
+///
+/// ```
+/// use std::io;
+/// use std::net::SocketAddr;
+///
+/// // sends the green deployment's hosts to the staging servers
+/// struct Green;
+///
+/// impl reqwest::Resolve for Green {
+///     fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+///         if host.ends_with(".example.com") {
+///             Ok(vec![SocketAddr::from(([10, 0, 2, 1], port))])
+///         } else {
+///             reqwest::SystemResolver.resolve(host, port)
+///         }
+///     }
+/// }
+/// ```
+pub trait Resolve: Send + Sync {
+    /// Gets the addresses of `host`, with `port` as their port.
+    ///
+    /// `host` is as in the URL, so it can also be an IP address.
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+}
+
+/// Resolves host names with the system's resolver, as a `Client` does by
+/// default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemResolver;
+
+impl Resolve for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        (host, port).to_socket_addrs().map(|addrs| addrs.collect())
+    }
+}
+
+/// The hosts pinned to addresses with `ClientBuilder::resolve`, which are
+/// used instead of asking the resolver.
+#[derive(Debug)]
+pub struct Overrides {
+    // by host, in lowercase
+    hosts: RwLock<HashMap<String, Vec<SocketAddr>>>,
+}
+
+impl Overrides {
+    pub fn new(hosts: HashMap<String, Vec<SocketAddr>>) -> Overrides {
+        Overrides {
+            hosts: RwLock::new(hosts),
+        }
+    }
+
+    /// Pins `host` to `addrs`, replacing what it was pinned to, or unpins
+    /// it if `addrs` is empty.
+    pub fn set(&self, host: &str, addrs: &[SocketAddr]) {
+        set(&mut self.hosts.write().unwrap(), host, addrs);
+    }
+
+    /// Gets the addresses that `host` is pinned to, with `port` as the port
+    /// of those that have none.
+    pub fn get(&self, host: &str, port: u16) -> Option<Vec<SocketAddr>> {
+        let hosts = self.hosts.read().unwrap();
+        hosts.get(&host.to_lowercase()).map(|addrs| {
+            addrs.iter()
+                .map(|addr| {
+                    let mut addr = *addr;
+                    if addr.port() == 0 {
+                        addr.set_port(port);
+                    }
+                    addr
+                })
+                .collect()
+        })
+    }
+}
+
+/// Pins `host` to `addrs` in `hosts`, as in `Overrides::set`.
+pub fn set(hosts: &mut HashMap<String, Vec<SocketAddr>>, host: &str, addrs: &[SocketAddr]) {
+    if addrs.is_empty() {
+        hosts.remove(&host.to_lowercase());
+    } else {
+        hosts.insert(host.to_lowercase(), addrs.to_vec());
+    }
+}
+
+#[test]
+fn test_overrides() {
+    let overrides = Overrides::new(HashMap::new());
+    assert_eq!(overrides.get("api.example.com", 443), None);
+
+    let local = SocketAddr::from(([127, 0, 0, 1], 8443));
+    overrides.set("API.example.com", &[local]);
+    assert_eq!(overrides.get("api.example.com", 443), Some(vec![local]));
+
+    // the last one wins, and a port of 0 is the one of the URL
+    let any_port = SocketAddr::from(([10, 0, 0, 1], 0));
+    overrides.set("api.example.com", &[any_port, local]);
+    assert_eq!(overrides.get("api.EXAMPLE.com", 443),
+               Some(vec![SocketAddr::from(([10, 0, 0, 1], 443)), local]));
+
+    overrides.set("api.example.com", &[]);
+    assert_eq!(overrides.get("api.example.com", 443), None);
+}
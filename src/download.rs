@@ -0,0 +1,71 @@
+use hyper::header::{ETag, EntityTag, Headers, HttpDate, LastModified};
+use hyper::status::StatusCode;
+
+/// What is known about the local copy of a resource, for
+/// `Client::download_if_changed`.
+///
+/// The `Default` is no local copy at all.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LocalMeta {
+    /// The `ETag` the copy was downloaded with.
+    pub etag: Option<EntityTag>,
+    /// The `Last-Modified` the copy was downloaded with.
+    pub last_modified: Option<HttpDate>,
+    /// The size of the copy, in bytes.
+    pub size: Option<u64>,
+}
+
+impl LocalMeta {
+    /// Whether there is an `ETag` or `Last-Modified` to send a conditional
+    /// request with.
+    pub fn has_validators(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+}
+
+/// The result of `Client::download_if_changed`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DownloadOutcome {
+    /// The local copy is up to date, so nothing was written.
+    NotModified,
+    /// The resource was written to the destination.
+    Downloaded {
+        /// How many bytes were written.
+        bytes: u64,
+        /// What to keep about the new copy, for the next call.
+        new_meta: LocalMeta,
+    },
+    /// There was nothing to tell whether the local copy is up to date, so
+    /// nothing was written: it has only a size, and the server didn't
+    /// answer a `HEAD` request with a `Content-Length`, or doesn't allow
+    /// `HEAD` at all.
+    ValidatorsUnavailable,
+}
+
+/// Whether a `HEAD` request got a status saying that the server doesn't
+/// support the method.
+pub fn head_rejected(status: &StatusCode) -> bool {
+    match *status {
+        StatusCode::MethodNotAllowed | StatusCode::NotImplemented => true,
+        _ => false,
+    }
+}
+
+/// Whether a response to a conditional request says that the resource is
+/// still `local`, even without a `304 Not Modified`, as when a server
+/// ignores `If-None-Match`.
+pub fn unchanged(local: &LocalMeta, headers: &Headers) -> bool {
+    match (local.etag.as_ref(), headers.get::<ETag>()) {
+        (Some(local), Some(&ETag(ref remote))) => local.weak_eq(remote),
+        _ => false,
+    }
+}
+
+/// What to keep of a downloaded response of `bytes`.
+pub fn new_meta(headers: &Headers, bytes: u64) -> LocalMeta {
+    LocalMeta {
+        etag: headers.get::<ETag>().map(|etag| etag.0.clone()),
+        last_modified: headers.get::<LastModified>().map(|date| date.0),
+        size: Some(bytes),
+    }
+}
@@ -12,32 +12,486 @@ pub enum Error {
     /// form-url-encoded bodies.
     Serialize(Box<StdError + Send + Sync>),
     /// A request tried to redirect too many times.
-    TooManyRedirects,
+    ///
+    /// Has the URLs of the chain, from the first request to the redirect
+    /// that wasn't followed.
+    TooManyRedirects(Vec<::Url>),
     /// An infinite redirect loop was detected.
-    RedirectLoop,
+    ///
+    /// Has the URLs of the chain, ending with the one that was already
+    /// visited.
+    RedirectLoop(Vec<::Url>),
+    /// A `BodySender` was dropped before finishing the body.
+    BodyAborted,
+    /// A body from `Body::resettable` could not be reset, to be sent again
+    /// for a redirect.
+    ///
+    /// Has the error that `ResetableReader::reset` failed with.
+    BodyReset(::std::io::Error),
+    /// A response had a client (4xx) or server (5xx) error status.
+    ///
+    /// Returned from `Response::error_for_status`, and in an `Unhealthy`
+    /// result of `Client::health_check`, with the `Url` of the response.
+    Status(::hyper::status::StatusCode, ::hyper::Url),
+    /// Making a connection in `Client::warm_up` failed.
+    ///
+    /// Has the number of the connection attempt, starting at 1, and the
+    /// error it failed with.
+    WarmUp(usize, Box<Error>),
+    /// No server set with `Client::fallback_base_urls` could be connected to.
+    ///
+    /// Has each `Url` that was tried, in order, with its error.
+    Fallback(Vec<(::hyper::Url, Error)>),
+    /// A timeout was too short to use, such as zero.
+    ///
+    /// Has the name of the setting, and the rejected value.
+    InvalidTimeout(&'static str, ::std::time::Duration),
+    /// A socket option could not be set, or is not supported on this
+    /// platform.
+    ///
+    /// Has the name of the option, such as `SO_MARK`, and the cause. See
+    /// `Client::socket_mark` and `Client::dscp`.
+    SocketOption(&'static str, ::std::io::Error),
+    /// A q-value given to `RequestBuilder::accept_encoding` was not between
+    /// 0 and 1.
+    InvalidQuality(::hyper::header::Encoding, f32),
+    /// A redirect of a request sent with `Client::request_over` could not
+    /// be followed on the same stream, because it was to another origin,
+    /// or the connection was closed.
+    ///
+    /// Has the `Url` that was redirected to.
+    RedirectOverStream(::Url),
+    /// A request that was coalesced with identical ones, by
+    /// `Client::coalesce_identical_gets`, failed.
+    ///
+    /// Has the error, which all of them share.
+    Coalesced(::std::sync::Arc<Error>),
+    /// A `path` or `from` given to `RequestBuilder::json_patch` was not a
+    /// valid JSON Pointer.
+    InvalidPatchPath(String),
+    /// A redirect had more than one `Location` header.
+    ///
+    /// Has all of the values. See `Client::lenient_redirects`.
+    AmbiguousRedirect(Vec<String>),
+    /// A timeout fired.
+    ///
+    /// Has which one, its configured value, and what the request was doing
+    /// then, such as `"while waiting for the response"`. See
+    /// `Error::timeout_kind`.
+    Timeout(TimeoutKind, ::std::time::Duration, String),
+    /// A response body read with `Response::text_strict` was not valid in
+    /// its charset.
+    ///
+    /// Has the charset, and the offset of the first invalid byte.
+    InvalidText(String, usize),
+    /// A response body read with `Response::text_strict` had a charset
+    /// that can't be decoded.
+    ///
+    /// Has the name of the charset.
+    UnsupportedCharset(String),
+    /// Every attempt of `Client::update_with_retry` got `412 Precondition
+    /// Failed`, because the resource kept being modified.
+    ///
+    /// Has the number of attempts, the `Url`, and the body of the last
+    /// `412` response.
+    PreconditionFailed(usize, ::hyper::Url, Vec<u8>),
+    /// A response in `Client::update_with_retry` had no `ETag` to update
+    /// the resource with.
+    ///
+    /// Has the `Url` of the response.
+    MissingETag(::hyper::Url),
+    /// An operation polled with `Client::poll_until_complete` was still
+    /// pending when the next poll would have been after `max_duration`.
+    ///
+    /// Has the `max_duration` of the `PollOptions`, and the status and
+    /// `Url` of the last poll.
+    PollTimeout(::std::time::Duration, ::hyper::status::StatusCode, ::hyper::Url),
+    /// A pending response in `Client::poll_until_complete` had no
+    /// `Operation-Location` or `Location` header to poll.
+    ///
+    /// Has the `Url` of the response.
+    MissingPollUrl(::hyper::Url),
+    /// The TLS backend could not be initialized when building a `Client`,
+    /// such as when the system's certificates can't be loaded.
+    ///
+    /// Has the name of the backend, `native-tls` or `rustls`, and the
+    /// cause.
+    TlsInit(&'static str, Box<StdError + Send + Sync>),
+    /// A `ClientBuilder`, or the `BulkOptions` of `Client::get_many`, were
+    /// given a number of threads or requests at once it can't use, such as
+    /// zero.
+    ///
+    /// Has the name of the setting, and the rejected value.
+    InvalidThreadCount(&'static str, usize),
+    /// A request of `Client::get_many` wasn't done, because the thread that
+    /// was sending it, or that would have, panicked, such as in a hook of
+    /// the client.
+    WorkerPanicked,
+    /// Copying a body with `Response::copy_to_rotating` failed.
+    ///
+    /// Has the index of the chunk, starting at 0, how many bytes it had
+    /// been given, and the error, which is from reading the body, or from
+    /// opening, writing or flushing the chunk.
+    RotatedChunk(usize, u64, Box<Error>),
+    /// `Response::copy_to_rotating` was given a `chunk_size` of 0, which
+    /// has no room for any of the body.
+    ZeroChunkSize,
+    /// Writing a body to the sink of `Response::tee` failed, while it was
+    /// being read.
+    ///
+    /// Has the error from the sink.
+    TeeSink(::std::io::Error),
+    /// Connecting through a proxy failed, such as when the proxy couldn't
+    /// be connected to, or it refused to open a tunnel.
+    ///
+    /// Has the `Url` of the proxy, and the error.
+    Proxy(::hyper::Url, Box<Error>),
+    /// A `Proxy` was given a `Url` that it can't connect with, because it
+    /// isn't an `http` one with a host.
+    UnsupportedProxy(::hyper::Url),
+    /// A request, or a redirect, was to an `http` URL while
+    /// `ClientBuilder::https_only` was on, and its host wasn't allowed with
+    /// `ClientBuilder::allow_http_host`.
+    ///
+    /// Has the `Url`.
+    HttpsRequired(::hyper::Url),
+    /// A request, or a redirect, was to an `https` URL, but the client
+    /// can't make TLS connections, because of `ClientBuilder::http_only`,
+    /// or reqwest being built without its `default-tls` and `rustls-tls`
+    /// features.
+    ///
+    /// Has the `Url`.
+    UnsupportedScheme(::hyper::Url),
+    /// A request failed, with `ClientBuilder::capture_diagnostics` on.
+    ///
+    /// Has the error, and the snapshot of the request. See
+    /// `Error::diagnostics`.
+    Diagnosed(Box<Error>, Box<::diagnostics::Diagnostics>),
+    /// The `window_bits` of `DeflateOptions` were not between 8 and 15.
+    ///
+    /// Has the name of the setting, and the rejected value.
+    InvalidWindowBits(&'static str, u8),
+    /// An entry of the list given to `NoProxy::new` could not be parsed,
+    /// such as a CIDR range with too long a prefix, or a port that isn't a
+    /// number.
+    ///
+    /// Has the entry.
+    InvalidNoProxy(String),
+    /// A request was given an `HttpVersion` it can't be sent with, by
+    /// `RequestBuilder::version`: one other than HTTP/1.0 or HTTP/1.1.
+    UnsupportedVersion(::hyper::version::HttpVersion),
+    /// An HTTP/1.0 request had a body of unknown length, which could only
+    /// be sent chunked, and HTTP/1.0 has no chunked encoding.
+    ///
+    /// See `RequestBuilder::version`.
+    UnsizedBody,
+    /// The request target of a request, or a redirect, was longer than
+    /// `ClientBuilder::max_request_line_length` allows.
+    ///
+    /// Has the length of the target, and the limit, in bytes.
+    UriTooLong(usize, usize),
+    /// A request had an `Asterisk` target, from `RequestBuilder::target_form`,
+    /// and a method other than `OPTIONS`, the only one that may use `*`.
+    ///
+    /// Has the method.
+    AsteriskTarget(::hyper::method::Method),
+    /// A `Certificate` could not be parsed from PEM or DER.
+    ///
+    /// Has the cause.
+    InvalidCertificate(Box<StdError + Send + Sync>),
+    /// The `Identity` given to `ClientBuilder::identity` could not be
+    /// used, such as when its archive is malformed, or its password is
+    /// wrong.
+    ///
+    /// Has the cause.
+    InvalidIdentity(Box<StdError + Send + Sync>),
+    /// A request, or a redirect, had a method that isn't allowed for its
+    /// host, by `ClientBuilder::allowed_methods_for_host`.
+    ///
+    /// Has the method, the host, and the methods that are allowed for it.
+    MethodNotAllowed(::hyper::method::Method, String, Vec<::hyper::method::Method>),
+    /// A connection was about to be made while a guard from
+    /// `test::deny_network` was alive, with the `test-util` feature.
+    ///
+    /// Has the host and port that would have been connected to.
+    NetworkDenied(String, u16),
+    /// Decoding a `gzip`, `deflate` or `br` response body failed, while
+    /// reading it.
+    ///
+    /// Has why, and the error of the decoder. See `Error::decompress_reason`.
+    Decompress(DecompressReason, ::std::io::Error),
+    /// A relative URL, such as `/v1/users`, was given to a `Client` that
+    /// has no `ClientBuilder::base_url` to join it to.
+    ///
+    /// Has the relative URL.
+    RelativeUrlWithoutBase(String),
+    /// A request that had an `Idempotency-Key`, such as one generated with
+    /// `ClientBuilder::idempotency_keys`, failed.
+    ///
+    /// Has the error, and the key. See `Error::idempotency_key`.
+    WithIdempotencyKey(Box<Error>, String),
     #[doc(hidden)]
     __DontMatchMe,
 }
 
+/// Which timeout fired, from `Error::timeout_kind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeoutKind {
+    /// The read timeout, while waiting for the response, or reading its
+    /// body.
+    Read,
+    /// The write timeout, while sending the request.
+    Write,
+    /// The connect timeout, while connecting to the server, including the
+    /// TLS handshake.
+    Connect,
+    /// The response header timeout, while waiting for the head of the
+    /// response after sending the request.
+    ResponseHeader,
+    /// The total timeout, for sending the request and receiving the head of
+    /// its response, including any redirects.
+    Total,
+    #[doc(hidden)]
+    __DontMatchMe,
+}
+
+impl fmt::Display for TimeoutKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match *self {
+            TimeoutKind::Read => "read",
+            TimeoutKind::Write => "write",
+            TimeoutKind::Connect => "connect",
+            TimeoutKind::ResponseHeader => "response header",
+            TimeoutKind::Total => "total",
+            TimeoutKind::__DontMatchMe => unreachable!(),
+        })
+    }
+}
+
+/// Why decoding a response body failed, from `Error::decompress_reason`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecompressReason {
+    /// The body ended in the middle of the compressed stream, such as when
+    /// the connection was closed early. Sending the request again may
+    /// succeed.
+    UnexpectedEof,
+    /// The body was whole, but didn't match its checksum, so it was
+    /// corrupted before it was sent. `br` has no checksum, so its corrupted
+    /// bodies are `InvalidData`.
+    CrcMismatch,
+    /// The body was not valid `gzip`, `deflate` or `br`.
+    InvalidData,
+    #[doc(hidden)]
+    __DontMatchMe,
+}
+
+impl fmt::Display for DecompressReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match *self {
+            DecompressReason::UnexpectedEof => "truncated",
+            DecompressReason::CrcMismatch => "checksum mismatch",
+            DecompressReason::InvalidData => "invalid data",
+            DecompressReason::__DontMatchMe => unreachable!(),
+        })
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::Http(ref e) => fmt::Display::fmt(e, f),
             Error::Serialize(ref e) => fmt::Display::fmt(e, f),
-            Error::TooManyRedirects => f.pad("Too many redirects"),
-            Error::RedirectLoop => f.pad("Infinite redirect loop"),
+            Error::TooManyRedirects(ref urls) => {
+                try!(f.write_str("Too many redirects"));
+                fmt_chain(urls, f)
+            },
+            Error::RedirectLoop(ref urls) => {
+                try!(f.write_str("Infinite redirect loop"));
+                fmt_chain(urls, f)
+            },
+            Error::BodyAborted => f.pad("Request body aborted"),
+            Error::BodyReset(ref e) => write!(f, "Resetting the request body failed: {}", e),
+            Error::Status(ref status, ref url) => {
+                let kind = if status.is_client_error() {
+                    "Client Error"
+                } else {
+                    "Server Error"
+                };
+                write!(f, "{}: {} for url ({})", kind, status, url)
+            },
+            Error::WarmUp(attempt, ref e) => {
+                write!(f, "Warming up connection {} failed: {}", attempt, e)
+            },
+            Error::Fallback(ref attempts) => {
+                try!(f.write_str("All servers failed"));
+                for (i, &(ref url, ref e)) in attempts.iter().enumerate() {
+                    let sep = if i == 0 { ": " } else { "; " };
+                    try!(write!(f, "{}{} ({})", sep, url, e));
+                }
+                Ok(())
+            },
+            Error::InvalidTimeout(setting, ref timeout) => {
+                write!(f, "Invalid timeout for {}: {:?}, must be at least 1ms", setting, timeout)
+            },
+            Error::SocketOption(option, ref e) => write!(f, "Setting {} failed: {}", option, e),
+            Error::Coalesced(ref e) => fmt::Display::fmt(e, f),
+            Error::Diagnosed(ref e, _) => fmt::Display::fmt(e, f),
+            Error::WithIdempotencyKey(ref e, _) => fmt::Display::fmt(e, f),
+            Error::AmbiguousRedirect(ref locations) => {
+                write!(f, "Ambiguous redirect, to any of: {}", locations.join(", "))
+            },
+            Error::InvalidPatchPath(ref path) => {
+                write!(f, "Invalid JSON Pointer in patch: {:?}", path)
+            },
+            Error::RedirectOverStream(ref url) => {
+                write!(f, "Can't follow redirect to {} on the same stream", url)
+            },
+            Error::InvalidQuality(ref encoding, q) => {
+                write!(f, "Invalid q-value for {}: {}, must be between 0 and 1", encoding, q)
+            },
+            Error::Timeout(kind, timeout, ref during) => {
+                write!(f, "{} timeout of {} {}", kind, Seconds(timeout), during)
+            },
+            Error::InvalidText(ref charset, offset) => {
+                write!(f, "Response body is not valid {} at byte {}", charset, offset)
+            },
+            Error::UnsupportedCharset(ref charset) => {
+                write!(f, "Unsupported charset: {:?}", charset)
+            },
+            Error::PreconditionFailed(attempts, ref url, _) => {
+                write!(f, "Update of {} conflicted on all {} attempts", url, attempts)
+            },
+            Error::MissingETag(ref url) => write!(f, "No ETag in response from {}", url),
+            Error::PollTimeout(max_duration, ref status, ref url) => {
+                write!(f, "Operation still pending after polling for {:?}, last status {} from {}",
+                       max_duration, status, url)
+            },
+            Error::MissingPollUrl(ref url) => {
+                write!(f, "No Location to poll in pending response from {}", url)
+            },
+            Error::TlsInit(backend, ref e) => {
+                write!(f, "Initializing TLS with {} failed: {}", backend, e)
+            },
+            Error::RotatedChunk(index, written, ref e) => {
+                write!(f, "Copying chunk {} failed after {} bytes: {}", index, written, e)
+            },
+            Error::ZeroChunkSize => f.pad("A chunk size of 0 can't hold any of the body"),
+            Error::TeeSink(ref e) => write!(f, "Copying the response body failed: {}", e),
+            Error::Proxy(ref url, ref e) => write!(f, "Proxy {} failed: {}", url, e),
+            Error::UnsupportedProxy(ref url) => write!(f, "Unsupported proxy: {}", url),
+            Error::HttpsRequired(ref url) => write!(f, "HTTPS is required, not sending to {}", url),
+            Error::UnsupportedScheme(ref url) => {
+                write!(f, "Unsupported scheme, no TLS to send to {}", url)
+            },
+            Error::InvalidWindowBits(setting, bits) => {
+                write!(f, "Invalid window bits for {}: {}, must be from 8 to 15", setting, bits)
+            },
+            Error::InvalidNoProxy(ref entry) => write!(f, "Invalid no-proxy entry: {:?}", entry),
+            Error::UnsupportedVersion(ref version) => {
+                write!(f, "Unsupported HTTP version for a request: {}", version)
+            },
+            Error::UnsizedBody => f.pad("A body of unknown length can't be sent with HTTP/1.0"),
+            Error::UriTooLong(len, max) => {
+                write!(f, "Request target too long: {} bytes, the limit is {}", len, max)
+            },
+            Error::AsteriskTarget(ref method) => {
+                write!(f, "Only OPTIONS may have the request target *, not {}", method)
+            },
+            Error::InvalidCertificate(ref e) => write!(f, "Invalid certificate: {}", e),
+            Error::InvalidIdentity(ref e) => write!(f, "Invalid TLS identity: {}", e),
+            Error::MethodNotAllowed(ref method, ref host, ref allowed) => {
+                let allowed = allowed.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+                write!(f, "Method {} is not allowed for {}, only: {}",
+                       method, host, allowed.join(", "))
+            },
+            Error::NetworkDenied(ref host, port) => {
+                write!(f, "network access denied by test guard: tried {}:{}", host, port)
+            },
+            Error::Decompress(reason, ref e) => {
+                write!(f, "Decompressing the response body failed, {}: {}", reason, e)
+            },
+            Error::RelativeUrlWithoutBase(ref url) => {
+                write!(f, "Relative URL {:?} without a base URL set on the client", url)
+            },
+            Error::InvalidThreadCount(setting, threads) => {
+                write!(f, "Invalid thread count for {}: {}, must be at least 1", setting, threads)
+            },
+            Error::WorkerPanicked => f.pad("The thread sending the request panicked"),
             Error::__DontMatchMe => unreachable!()
         }
     }
 }
 
+/// Formats a timeout like `30s`, or `1500ms` if it isn't whole seconds.
+struct Seconds(::std::time::Duration);
+
+impl fmt::Display for Seconds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (secs, nanos) = (self.0.as_secs(), self.0.subsec_nanos());
+        if nanos == 0 {
+            write!(f, "{}s", secs)
+        } else {
+            write!(f, "{}ms", secs * 1000 + (nanos / 1_000_000) as u64)
+        }
+    }
+}
+
 impl StdError for Error {
     fn description(&self) -> &str {
         match *self {
             Error::Http(ref e) => e.description(),
             Error::Serialize(ref e) => e.description(),
-            Error::TooManyRedirects => "Too many redirects",
-            Error::RedirectLoop => "Infinite redirect loop",
+            Error::TooManyRedirects(..) => "Too many redirects",
+            Error::RedirectLoop(..) => "Infinite redirect loop",
+            Error::BodyAborted => "Request body aborted",
+            Error::BodyReset(..) => "Resetting the request body failed",
+            Error::Status(ref status, _) => {
+                if status.is_client_error() {
+                    "Client Error"
+                } else {
+                    "Server Error"
+                }
+            },
+            Error::WarmUp(..) => "Warming up a connection failed",
+            Error::Fallback(..) => "All servers failed",
+            Error::InvalidTimeout(..) => "Invalid timeout",
+            Error::SocketOption(..) => "Setting a socket option failed",
+            Error::InvalidQuality(..) => "Invalid q-value",
+            Error::RedirectOverStream(..) => "Can't follow redirect on the same stream",
+            Error::Coalesced(ref e) => e.description(),
+            Error::Diagnosed(ref e, _) => e.description(),
+            Error::WithIdempotencyKey(ref e, _) => e.description(),
+            Error::InvalidPatchPath(..) => "Invalid JSON Pointer in patch",
+            Error::AmbiguousRedirect(..) => "Ambiguous redirect",
+            Error::Timeout(..) => "Timed out",
+            Error::InvalidText(..) => "Invalid text in response body",
+            Error::UnsupportedCharset(..) => "Unsupported charset",
+            Error::PreconditionFailed(..) => "Update conflicted on all attempts",
+            Error::MissingETag(..) => "No ETag in response",
+            Error::PollTimeout(..) => "Operation still pending after polling",
+            Error::MissingPollUrl(..) => "No Location to poll in pending response",
+            Error::TlsInit(..) => "Initializing TLS failed",
+            Error::InvalidThreadCount(..) => "Invalid thread count",
+            Error::WorkerPanicked => "Worker panicked",
+            Error::InvalidWindowBits(..) => "Invalid window bits",
+            Error::InvalidNoProxy(..) => "Invalid no-proxy entry",
+            Error::RotatedChunk(..) => "Copying a chunk failed",
+            Error::ZeroChunkSize => "Chunk size of 0",
+            Error::TeeSink(..) => "Copying the response body failed",
+            Error::Proxy(..) => "Proxy failed",
+            Error::UnsupportedProxy(..) => "Unsupported proxy",
+            Error::HttpsRequired(..) => "HTTPS is required",
+            Error::UnsupportedScheme(..) => "Unsupported scheme",
+            Error::UnsupportedVersion(..) => "Unsupported HTTP version",
+            Error::UnsizedBody => "Body of unknown length with HTTP/1.0",
+            Error::UriTooLong(..) => "Request target too long",
+            Error::AsteriskTarget(..) => "Request target * with a method other than OPTIONS",
+            Error::InvalidCertificate(..) => "Invalid certificate",
+            Error::InvalidIdentity(..) => "Invalid TLS identity",
+            Error::MethodNotAllowed(..) => "Method not allowed for host",
+            Error::NetworkDenied(..) => "Network access denied by test guard",
+            Error::Decompress(..) => "Decompressing the response body failed",
+            Error::RelativeUrlWithoutBase(..) => "Relative URL without a base URL",
             Error::__DontMatchMe => unreachable!()
         }
     }
@@ -46,13 +500,281 @@ impl StdError for Error {
         match *self {
             Error::Http(ref e) => Some(e),
             Error::Serialize(ref e) => Some(&**e),
-            Error::TooManyRedirects |
-            Error::RedirectLoop => None,
+            Error::WarmUp(_, ref e) => Some(&**e),
+            Error::RotatedChunk(_, _, ref e) => Some(&**e),
+            Error::Proxy(_, ref e) => Some(&**e),
+            Error::SocketOption(_, ref e) => Some(e),
+            Error::BodyReset(ref e) |
+            Error::TeeSink(ref e) |
+            Error::Decompress(_, ref e) => Some(e),
+            Error::TlsInit(_, ref e) |
+            Error::InvalidCertificate(ref e) |
+            Error::InvalidIdentity(ref e) => Some(&**e),
+            Error::Coalesced(ref e) => Some(&**e),
+            Error::Diagnosed(ref e, _) => Some(&**e),
+            Error::WithIdempotencyKey(ref e, _) => Some(&**e),
+            Error::Fallback(ref attempts) => attempts.last().map(|&(_, ref e)| e as &StdError),
+            Error::TooManyRedirects(..) |
+            Error::RedirectLoop(..) |
+            Error::BodyAborted |
+            Error::Status(..) |
+            Error::InvalidTimeout(..) |
+            Error::InvalidQuality(..) |
+            Error::RedirectOverStream(..) |
+            Error::InvalidPatchPath(..) |
+            Error::AmbiguousRedirect(..) |
+            Error::Timeout(..) |
+            Error::InvalidText(..) |
+            Error::UnsupportedCharset(..) |
+            Error::PreconditionFailed(..) |
+            Error::MissingETag(..) |
+            Error::PollTimeout(..) |
+            Error::MissingPollUrl(..) |
+            Error::InvalidThreadCount(..) |
+            Error::WorkerPanicked |
+            Error::ZeroChunkSize |
+            Error::InvalidWindowBits(..) |
+            Error::InvalidNoProxy(..) |
+            Error::UnsupportedProxy(..) |
+            Error::HttpsRequired(..) |
+            Error::UnsupportedScheme(..) |
+            Error::UnsupportedVersion(..) |
+            Error::UnsizedBody |
+            Error::UriTooLong(..) |
+            Error::AsteriskTarget(..) |
+            Error::MethodNotAllowed(..) |
+            Error::NetworkDenied(..) |
+            Error::RelativeUrlWithoutBase(..) => None,
             Error::__DontMatchMe => unreachable!()
         }
     }
 }
 
+impl Error {
+    /// Whether this error is from a read or write timing out, or from
+    /// `Client::poll_until_complete` running out of time.
+    ///
+    /// See `Client::timeout`.
+    pub fn is_timeout(&self) -> bool {
+        match *self {
+            Error::Http(::hyper::Error::Io(ref e)) => is_io_timeout(e),
+            Error::Timeout(..) |
+            Error::PollTimeout(..) => true,
+            Error::WarmUp(_, ref e) |
+            Error::RotatedChunk(_, _, ref e) |
+            Error::Proxy(_, ref e) |
+            Error::Diagnosed(ref e, _) |
+            Error::WithIdempotencyKey(ref e, _) => e.is_timeout(),
+            Error::Coalesced(ref e) => e.is_timeout(),
+            _ => false,
+        }
+    }
+
+    /// Gets which timeout this error is from, if any.
+    ///
+    /// The `Display` of such an error also has the timeout's value, and
+    /// what the request was doing.
+    pub fn timeout_kind(&self) -> Option<TimeoutKind> {
+        match *self {
+            Error::Timeout(kind, ..) => Some(kind),
+            Error::WarmUp(_, ref e) |
+            Error::RotatedChunk(_, _, ref e) |
+            Error::Proxy(_, ref e) |
+            Error::Diagnosed(ref e, _) |
+            Error::WithIdempotencyKey(ref e, _) => e.timeout_kind(),
+            Error::Coalesced(ref e) => e.timeout_kind(),
+            _ => None,
+        }
+    }
+
+    /// Whether this error is from decoding a `gzip` or `deflate` response
+    /// body.
+    pub fn is_decompress(&self) -> bool {
+        self.decompress_reason().is_some()
+    }
+
+    /// Gets why decoding the response body failed, if that's what this
+    /// error is from.
+    ///
+    /// A body that was cut short is `DecompressReason::UnexpectedEof`, and
+    /// may be worth retrying, unlike a corrupt one.
+    pub fn decompress_reason(&self) -> Option<DecompressReason> {
+        match *self {
+            Error::Decompress(reason, _) => Some(reason),
+            Error::RotatedChunk(_, _, ref e) |
+            Error::Diagnosed(ref e, _) |
+            Error::WithIdempotencyKey(ref e, _) => e.decompress_reason(),
+            Error::Coalesced(ref e) => e.decompress_reason(),
+            _ => None,
+        }
+    }
+
+    /// Gets the kind of the I/O error that caused this error, if any.
+    ///
+    /// This is found wherever the I/O error is wrapped, such as by a failed
+    /// TLS handshake, or in `Error::WarmUp` or `Error::Proxy`.
+    ///
+    /// After the handshake, an I/O error of an `https` connection may be
+    /// wrapped by the TLS backend in an error of its own, which isn't looked
+    /// into: with native-tls, a connection reset while the response is read
+    /// can be `ErrorKind::Other` rather than `ErrorKind::ConnectionReset`,
+    /// and `is_connection_reset` is then `false`.
+    pub fn io_kind(&self) -> Option<::std::io::ErrorKind> {
+        self.io_error().map(|e| e.kind())
+    }
+
+    /// Whether this error is from not being able to connect to the server,
+    /// such as because nothing was listening, or it was unreachable.
+    pub fn is_connect(&self) -> bool {
+        let e = match self.io_error() {
+            Some(e) => e,
+            None => return false,
+        };
+        match e.kind() {
+            ::std::io::ErrorKind::ConnectionRefused |
+            ::std::io::ErrorKind::AddrNotAvailable => true,
+            _ => is_unreachable(e),
+        }
+    }
+
+    /// Whether this error is from the server resetting the connection,
+    /// which may have been after the request was already sent.
+    pub fn is_connection_reset(&self) -> bool {
+        self.io_kind() == Some(::std::io::ErrorKind::ConnectionReset)
+    }
+
+    fn io_error(&self) -> Option<&::std::io::Error> {
+        match *self {
+            Error::Http(::hyper::Error::Io(ref e)) |
+            Error::SocketOption(_, ref e) => Some(innermost(e)),
+            Error::Http(::hyper::Error::Ssl(ref e)) => {
+                e.downcast_ref::<::std::io::Error>().map(innermost)
+            },
+            Error::WarmUp(_, ref e) |
+            Error::RotatedChunk(_, _, ref e) |
+            Error::Proxy(_, ref e) |
+            Error::Diagnosed(ref e, _) |
+            Error::WithIdempotencyKey(ref e, _) => e.io_error(),
+            Error::Coalesced(ref e) => e.io_error(),
+            Error::Fallback(ref attempts) => attempts.last().and_then(|&(_, ref e)| e.io_error()),
+            _ => None,
+        }
+    }
+
+    /// Whether this error is a copy of a recent connect failure, returned
+    /// without trying to connect again.
+    ///
+    /// See `Client::negative_cache_ttl`.
+    pub fn from_negative_cache(&self) -> bool {
+        match *self {
+            Error::Http(::hyper::Error::Io(ref e)) => {
+                e.get_ref().map_or(false, |inner| inner.is::<::connect::Cached>())
+            },
+            Error::Proxy(_, ref e) |
+            Error::Diagnosed(ref e, _) |
+            Error::WithIdempotencyKey(ref e, _) => e.from_negative_cache(),
+            Error::Coalesced(ref e) => e.from_negative_cache(),
+            _ => false,
+        }
+    }
+
+    /// Gets the snapshot of the request that failed, if it was taken with
+    /// `ClientBuilder::capture_diagnostics`.
+    pub fn diagnostics(&self) -> Option<&::diagnostics::Diagnostics> {
+        match *self {
+            Error::Diagnosed(_, ref diagnostics) => Some(diagnostics),
+            Error::WithIdempotencyKey(ref e, _) => e.diagnostics(),
+            Error::Coalesced(ref e) => e.diagnostics(),
+            _ => None,
+        }
+    }
+
+    /// Gets the URLs of the redirect chain, if this is an
+    /// `Error::TooManyRedirects` or `Error::RedirectLoop`.
+    pub fn redirect_chain(&self) -> Option<&[::Url]> {
+        match *self {
+            Error::TooManyRedirects(ref urls) |
+            Error::RedirectLoop(ref urls) => Some(urls),
+            Error::Diagnosed(ref e, _) |
+            Error::WithIdempotencyKey(ref e, _) => e.redirect_chain(),
+            Error::Coalesced(ref e) => e.redirect_chain(),
+            _ => None,
+        }
+    }
+
+    /// Gets the `Idempotency-Key` of the request that failed, if it had
+    /// one, for logging.
+    ///
+    /// Like `Response::idempotency_key`, this is the key generated by
+    /// `Client::idempotency_keys`, or the one that was set explicitly.
+    pub fn idempotency_key(&self) -> Option<&str> {
+        match *self {
+            Error::WithIdempotencyKey(_, ref key) => Some(key),
+            Error::RotatedChunk(_, _, ref e) |
+            Error::Diagnosed(ref e, _) => e.idempotency_key(),
+            Error::Coalesced(ref e) => e.idempotency_key(),
+            _ => None,
+        }
+    }
+}
+
+/// How many URLs of a redirect chain are shown, half from its start and
+/// half from its end.
+const SHOWN_REDIRECTS: usize = 4;
+
+/// Writes a redirect chain as `": a -> b -> (3 more) -> y -> z"`.
+fn fmt_chain(urls: &[::Url], f: &mut fmt::Formatter) -> fmt::Result {
+    if urls.is_empty() {
+        return Ok(());
+    }
+    try!(f.write_str(":"));
+    let half = SHOWN_REDIRECTS / 2;
+    for (i, url) in urls.iter().enumerate() {
+        if urls.len() > SHOWN_REDIRECTS && i == half {
+            try!(write!(f, " -> ({} more)", urls.len() - SHOWN_REDIRECTS));
+        }
+        if urls.len() > SHOWN_REDIRECTS && i >= half && i < urls.len() - half {
+            continue;
+        }
+        if i > 0 {
+            try!(f.write_str(" ->"));
+        }
+        try!(write!(f, " {}", url));
+    }
+    Ok(())
+}
+
+/// Whether `e` is from a socket's read or write timeout.
+pub fn is_io_timeout(e: &::std::io::Error) -> bool {
+    match e.kind() {
+        // a socket timeout is `WouldBlock` on some platforms
+        ::std::io::ErrorKind::TimedOut |
+        ::std::io::ErrorKind::WouldBlock => true,
+        _ => false,
+    }
+}
+
+/// Unwraps an I/O error made from yet another one.
+fn innermost(e: &::std::io::Error) -> &::std::io::Error {
+    match e.get_ref().and_then(|inner| inner.downcast_ref::<::std::io::Error>()) {
+        Some(inner) => innermost(inner),
+        None => e,
+    }
+}
+
+#[cfg(unix)]
+fn is_unreachable(e: &::std::io::Error) -> bool {
+    match e.raw_os_error() {
+        Some(code) => code == ::libc::EHOSTUNREACH || code == ::libc::ENETUNREACH,
+        None => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_unreachable(_e: &::std::io::Error) -> bool {
+    false
+}
+
 fn _assert_types() {
     fn _assert_send<T: Send>() {
     }
@@ -61,7 +783,31 @@ fn _assert_types() {
 
 impl From<::hyper::Error> for Error {
     fn from(err: ::hyper::Error) -> Error {
-        Error::Http(err)
+        match err {
+            ::hyper::Error::Io(err) => Error::from(err),
+            err => Error::Http(err),
+        }
+    }
+}
+
+impl From<::std::io::Error> for Error {
+    fn from(err: ::std::io::Error) -> Error {
+        // such as a timeout reading a `Response` body
+        if err.get_ref().map_or(false, |inner| inner.is::<Error>()) {
+            match err.into_inner().map(|inner| inner.downcast::<Error>()) {
+                Some(Ok(err)) => return *err,
+                _ => unreachable!(),
+            }
+        }
+        if !err.get_ref().map_or(false, |inner| inner.is::<::socket::OptionFailed>()) {
+            return Error::Http(::hyper::Error::Io(err));
+        }
+        let kind = err.kind();
+        match err.into_inner().map(|inner| inner.downcast::<::socket::OptionFailed>()) {
+            Some(Ok(failed)) => Error::SocketOption(failed.option, failed.cause),
+            Some(Err(inner)) => Error::Http(::hyper::Error::Io(::std::io::Error::new(kind, inner))),
+            None => Error::Http(::hyper::Error::Io(kind.into())),
+        }
     }
 }
 
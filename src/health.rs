@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use hyper::status::{StatusClass, StatusCode};
+
+/// The result of `Client::health_check`.
+#[derive(Debug)]
+pub enum HealthStatus {
+    /// The server responded with a success (2xx) status within the budget.
+    Healthy {
+        /// How long the check took.
+        latency: Duration,
+        /// The status of the response.
+        status: StatusCode,
+    },
+    /// The server responded, but took longer than the budget, or with a
+    /// redirect (3xx) or `429 Too Many Requests` status.
+    Degraded {
+        /// How long the check took.
+        latency: Duration,
+        /// The status of the response.
+        status: StatusCode,
+    },
+    /// The check failed, such as when connecting or reading the response
+    /// failed or timed out, or the server responded with any other status.
+    ///
+    /// For a response, the error is `Error::Status`.
+    Unhealthy {
+        /// How long the check took.
+        latency: Duration,
+        /// Why the check failed.
+        error: ::Error,
+    },
+}
+
+impl HealthStatus {
+    /// How long the check took, until the response or the error.
+    pub fn latency(&self) -> Duration {
+        match *self {
+            HealthStatus::Healthy { latency, .. } |
+            HealthStatus::Degraded { latency, .. } |
+            HealthStatus::Unhealthy { latency, .. } => latency,
+        }
+    }
+
+    /// Whether the status is `Healthy`.
+    pub fn is_healthy(&self) -> bool {
+        match *self {
+            HealthStatus::Healthy { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the status is `Degraded`.
+    pub fn is_degraded(&self) -> bool {
+        match *self {
+            HealthStatus::Degraded { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the status is `Unhealthy`.
+    pub fn is_unhealthy(&self) -> bool {
+        match *self {
+            HealthStatus::Unhealthy { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// Classifies the response to a health check.
+pub fn classify(status: StatusCode, url: ::hyper::Url, latency: Duration, budget: Duration)
+                -> HealthStatus {
+    match status.class() {
+        StatusClass::Success if latency <= budget => HealthStatus::Healthy {
+            latency: latency,
+            status: status,
+        },
+        StatusClass::Success | StatusClass::Redirection => HealthStatus::Degraded {
+            latency: latency,
+            status: status,
+        },
+        _ if status == StatusCode::TooManyRequests => HealthStatus::Degraded {
+            latency: latency,
+            status: status,
+        },
+        _ => HealthStatus::Unhealthy {
+            latency: latency,
+            error: ::Error::Status(status, url),
+        },
+    }
+}
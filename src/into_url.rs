@@ -0,0 +1,73 @@
+use hyper::Url;
+use url::ParseError;
+
+/// A trait to try to convert some type into a `Url`.
+///
+/// This is implemented for `Url`, and for strings, which may also be a path
+/// relative to the `ClientBuilder::base_url` of a `Client`.
+///
+/// This is not hyper's `IntoUrl`, which a type has to be converted from,
+/// with its `into_url`, to be passed to reqwest.
+pub trait IntoUrl {
+    /// Consumes the object, trying to return a `Url`.
+    fn into_url(self) -> Result<Url, ParseError>;
+
+    /// The string to join to a base URL, if this is one.
+    #[doc(hidden)]
+    fn as_relative(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl IntoUrl for Url {
+    fn into_url(self) -> Result<Url, ParseError> {
+        Ok(self)
+    }
+}
+
+impl<'a> IntoUrl for &'a str {
+    fn into_url(self) -> Result<Url, ParseError> {
+        Url::parse(self)
+    }
+
+    fn as_relative(&self) -> Option<&str> {
+        Some(*self)
+    }
+}
+
+impl<'a> IntoUrl for &'a String {
+    fn into_url(self) -> Result<Url, ParseError> {
+        Url::parse(self)
+    }
+
+    fn as_relative(&self) -> Option<&str> {
+        Some(self.as_str())
+    }
+}
+
+impl IntoUrl for String {
+    fn into_url(self) -> Result<Url, ParseError> {
+        Url::parse(&self)
+    }
+
+    fn as_relative(&self) -> Option<&str> {
+        Some(self.as_str())
+    }
+}
+
+/// Parses `url`, joining it to `base` if it is relative.
+///
+/// Without a `base`, a relative `url` fails with
+/// `ParseError::RelativeUrlWithoutBase`, and is also returned, for the
+/// error to name it.
+pub fn resolve<U: IntoUrl>(url: U, base: Option<&Url>)
+                           -> Result<Url, (ParseError, Option<String>)> {
+    let relative = url.as_relative().map(|url| url.to_owned());
+    match (url.into_url(), relative) {
+        (Err(ParseError::RelativeUrlWithoutBase), Some(relative)) => match base {
+            Some(base) => base.join(&relative).map_err(|e| (e, None)),
+            None => Err((ParseError::RelativeUrlWithoutBase, Some(relative))),
+        },
+        (result, _) => result.map_err(|e| (e, None)),
+    }
+}
@@ -8,10 +8,11 @@
 //! It handles many of the things that most people just expect an HTTP client
 //! to do for them.
 //!
-//! - Uses system-native TLS
+//! - Uses system-native TLS, with the default `default-tls` feature, or
+//!   rustls, with the `rustls-tls` feature
 //! - Plain bodies, JSON, urlencoded, (TODO: multipart)
 //! - Customizable redirect policy
-//! - (TODO: Cookies)
+//! - Cookies, with `ClientBuilder::cookie_store`
 //!
 //! The `reqwest::Client` is synchronous, making it a great fit for
 //! applications that only require a few HTTP requests, and wish to handle
@@ -101,38 +102,101 @@
 extern crate hyper;
 
 #[macro_use] extern crate log;
+extern crate libc;
 extern crate libflate;
+#[cfg(feature = "default-tls")]
 extern crate hyper_native_tls;
+#[cfg(feature = "default-tls")]
+extern crate native_tls;
+#[cfg(all(feature = "default-tls",
+          not(any(target_os = "windows", target_os = "macos", target_os = "ios"))))]
+extern crate openssl;
+#[cfg(feature = "rustls-tls")]
+extern crate rustls;
+#[cfg(feature = "rustls-tls")]
+extern crate webpki_roots;
+#[cfg(feature = "brotli")]
+extern crate brotli_decompressor;
 extern crate serde;
 extern crate serde_json;
 extern crate serde_urlencoded;
 extern crate url;
 
-pub use hyper::client::IntoUrl;
 pub use hyper::Error as HyperError;
 pub use hyper::header;
 pub use hyper::mime;
 pub use hyper::method::Method;
-pub use hyper::status::StatusCode;
+pub use hyper::status::{StatusClass, StatusCode};
 pub use hyper::version::HttpVersion;
 pub use hyper::Url;
+pub use log::LogLevel;
 pub use url::ParseError as UrlError;
 
-pub use self::client::{Client, RequestBuilder};
-pub use self::error::{Error, Result};
-pub use self::body::Body;
-pub use self::redirect::RedirectPolicy;
-pub use self::response::Response;
+pub use self::alt_svc::AltService;
+pub use self::challenge::Challenge;
+pub use self::diagnostics::Diagnostics;
+pub use self::download::{DownloadOutcome, LocalMeta};
+pub use self::dns::{Resolve, SystemResolver};
+pub use self::client::{Client, ClientBuilder, PathPolicy, PoolStats, RequestBuilder, TargetForm,
+    TraceContextProvider};
+pub use self::deprecation::DeprecationInfo;
+pub use self::error::{DecompressReason, Error, Result, TimeoutKind};
+pub use self::body::{Body, BodySender, ResetableReader};
+pub use self::bulk::{BulkOptions, BulkResult};
+pub use self::health::HealthStatus;
+pub use self::into_url::IntoUrl;
+pub use self::metrics::{DURATION_BUCKETS, InMemoryMetrics, Labels, MetricsSink, NoopMetrics,
+    Outcome, Snapshot, Totals};
+pub use self::partition::PartitionStats;
+pub use self::patch::PatchOp;
+pub use self::poll::PollOptions;
+pub use self::prefer::Preference;
+pub use self::proxy::{NoProxy, Proxy};
+pub use self::redirect::{RedirectAttempt, RedirectPolicy};
+pub use self::request_log::LogOptions;
+pub use self::tls::{Certificate, Identity};
+pub use self::response::{ContentRange, CorsInfo, DeflateOptions, RequestStats, Response,
+    ResponseHead, RotationSummary};
 
+mod alt_svc;
 mod body;
+mod challenge;
+#[cfg(feature = "bridge")]
+mod bridge;
+mod bulk;
 mod client;
+mod coalesce;
+mod connect;
+mod cookie;
+mod deprecation;
+mod diagnostics;
+mod dns;
+mod download;
 mod error;
+mod health;
+mod into_url;
+mod metrics;
+mod partition;
+mod patch;
+mod poll;
+mod prefer;
+mod proxy;
 mod redirect;
+mod request_log;
 mod response;
+#[cfg(feature = "rustls-tls")]
+mod rustls_tls;
+mod socket;
+#[cfg(feature = "test-util")]
+pub mod test;
+mod tls;
 
 
 /// Shortcut method to quickly make a `GET` request.
 ///
+/// The request is made with the shared [`default_client`](./fn.default_client.html),
+/// so repeated calls can reuse connections.
+///
 /// See also the methods on the [`reqwest::Response`](./struct.Response.html)
 /// type.
 ///
@@ -146,10 +210,97 @@ mod response;
 ///     .read_to_string(&mut result);
 /// ```
 pub fn get<T: IntoUrl>(url: T) -> ::Result<Response> {
-    let client = try!(Client::new());
+    let client = try!(default_client());
     client.get(url).send()
 }
 
+/// Get the `Client` shared by the shortcut functions, like [`get`](./fn.get.html).
+///
+/// The client is created on first use. If creating it fails, the error is
+/// returned, and the next call will try again.
+///
+/// # Examples
+///
+/// ```no_run
+/// let client = reqwest::default_client().unwrap();
+/// let res = client.post("http://httpbin.org/post")
+///     .body("shared")
+///     .send();
+/// ```
+pub fn default_client() -> ::Result<&'static Client> {
+    use std::ptr;
+    use std::sync::atomic::{AtomicPtr, Ordering};
+
+    static DEFAULT: AtomicPtr<Client> = AtomicPtr::new(0 as *mut Client);
+
+    let existing = DEFAULT.load(Ordering::Acquire);
+    if !existing.is_null() {
+        return Ok(unsafe { &*existing });
+    }
+
+    // the client lives for the rest of the program, so it is never freed
+    let client = Box::into_raw(Box::new(try!(Client::new())));
+    match DEFAULT.compare_exchange(ptr::null_mut(), client, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => Ok(unsafe { &*client }),
+        Err(existing) => {
+            // another thread was first, so use theirs
+            drop(unsafe { Box::from_raw(client) });
+            Ok(unsafe { &*existing })
+        }
+    }
+}
+
+/// The version of the reqwest crate in use.
+///
+/// # Examples
+///
+/// ```
+/// println!("using reqwest {}", reqwest::version());
+/// ```
+pub fn version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
+
+/// The features that reqwest was compiled with.
+///
+/// This includes the names of the TLS backends it was built with,
+/// `native-tls` with the default `default-tls` feature, and `rustls` with
+/// the `rustls-tls` feature, and the content encodings that can be
+/// automatically decoded, `gzip` and `deflate`, and `br` with the `brotli`
+/// feature.
+///
+/// # Examples
+///
+/// ```
+/// assert!(reqwest::features().contains(&"gzip"));
+/// ```
+pub fn features() -> &'static [&'static str] {
+    FEATURES
+}
+
+// the TLS backends, and then the content codings that are decoded
+#[cfg(not(feature = "brotli"))]
+macro_rules! features {
+    ($($backend:expr),*) => (&[$($backend,)* "gzip", "deflate"])
+}
+
+#[cfg(feature = "brotli")]
+macro_rules! features {
+    ($($backend:expr),*) => (&[$($backend,)* "gzip", "deflate", "br"])
+}
+
+#[cfg(all(feature = "default-tls", not(feature = "rustls-tls")))]
+static FEATURES: &'static [&'static str] = features![connect::TLS_BACKEND];
+
+#[cfg(all(feature = "default-tls", feature = "rustls-tls"))]
+static FEATURES: &'static [&'static str] = features![connect::TLS_BACKEND, connect::RUSTLS_BACKEND];
+
+#[cfg(all(not(feature = "default-tls"), feature = "rustls-tls"))]
+static FEATURES: &'static [&'static str] = features![connect::RUSTLS_BACKEND];
+
+#[cfg(not(any(feature = "default-tls", feature = "rustls-tls")))]
+static FEATURES: &'static [&'static str] = features![];
+
 fn _assert_impls() {
     fn assert_send<T: Send>() {}
     fn assert_sync<T: Sync>() {}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use hyper::method::Method;
+use hyper::status::StatusClass;
+
+/// Receives metrics about the requests of a `Client`.
+///
+/// See `Client::metrics_sink`.
+pub trait MetricsSink: Send + Sync {
+    /// Called once for each request, when its response was received, or
+    /// when it failed.
+    ///
+    /// The `duration` is until the response headers were received. The
+    /// sizes are of the bodies, when they are known up front, from the
+    /// length of the request body and the `Content-Length` of the response.
+    fn record_request(&self, labels: &Labels, duration: Duration, request_bytes: Option<u64>,
+                      response_bytes: Option<u64>, outcome: Outcome);
+
+    /// Called after each request, with the number of idle connections of
+    /// the client, and the number of requests in flight.
+    ///
+    /// A request is in flight until its `Response` is dropped.
+    fn gauge_pool(&self, idle: usize, in_flight: usize) {
+        let _ = (idle, in_flight);
+    }
+}
+
+/// How a request ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    /// A response was received, with any status.
+    Response,
+    /// The request failed with an error.
+    Error,
+}
+
+/// The labels of a request, for `MetricsSink::record_request`.
+///
+/// Only a few labels are used, so the number of different labels stays
+/// small: the method, the host, and the class of the status.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Labels {
+    method: Method,
+    host: String,
+    status_class: Option<StatusClass>,
+}
+
+impl Labels {
+    /// Create the labels of a request, such as to look up its `Totals`.
+    pub fn new(method: Method, host: String, status_class: Option<StatusClass>) -> Labels {
+        Labels {
+            method: method,
+            host: host,
+            status_class: status_class,
+        }
+    }
+
+    /// The method of the request.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// The host of the request URL.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// The class of the response status, such as `StatusClass::Success`.
+    ///
+    /// This is `None` when the request failed.
+    pub fn status_class(&self) -> Option<StatusClass> {
+        self.status_class
+    }
+}
+
+impl Hash for Labels {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.method.hash(state);
+        self.host.hash(state);
+        self.status_class.map(|class| class as u8).hash(state);
+    }
+}
+
+/// A `MetricsSink` that ignores everything.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopMetrics;
+
+impl MetricsSink for NoopMetrics {
+    fn record_request(&self, _: &Labels, _: Duration, _: Option<u64>, _: Option<u64>, _: Outcome) {
+    }
+}
+
+/// The upper bounds of the duration buckets of `InMemoryMetrics`, in
+/// milliseconds. The last bucket has everything slower.
+pub const DURATION_BUCKETS: [u64; 6] = [10, 50, 100, 500, 1000, 5000];
+
+/// A `MetricsSink` that adds up the requests in memory.
+///
+/// Use `snapshot` to read the totals, such as to export them.
+///
+/// ```
+/// use std::sync::Arc;
+/// use reqwest::InMemoryMetrics;
+///
+/// let metrics = Arc::new(InMemoryMetrics::new());
+/// let mut client = reqwest::Client::new().unwrap();
+/// client.metrics_sink(metrics.clone());
+///
+/// // ... make requests, and later
+/// for (labels, totals) in metrics.snapshot().requests {
+///     println!("{} {}: {} requests", labels.method(), labels.host(), totals.count);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct InMemoryMetrics {
+    inner: Mutex<Snapshot>,
+}
+
+/// The totals of an `InMemoryMetrics`.
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot {
+    /// The totals of the requests with each set of labels.
+    pub requests: HashMap<Labels, Totals>,
+    /// The idle connections, the last time the pool was measured.
+    pub idle: usize,
+    /// The requests in flight, the last time the pool was measured.
+    pub in_flight: usize,
+}
+
+/// The totals of the requests with the same `Labels`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Totals {
+    /// How many requests there were.
+    pub count: u64,
+    /// How many of them failed with an error.
+    pub errors: u64,
+    /// The sum of their durations.
+    pub duration_sum: Duration,
+    /// How many requests were in each duration bucket.
+    ///
+    /// A request is counted in the first bucket whose bound in
+    /// `DURATION_BUCKETS` it is below, or else the last one.
+    pub duration_buckets: [u64; 7],
+    /// The sum of the known request body sizes.
+    pub request_bytes: u64,
+    /// The sum of the known response body sizes.
+    pub response_bytes: u64,
+}
+
+impl InMemoryMetrics {
+    /// Create an empty `InMemoryMetrics`.
+    pub fn new() -> InMemoryMetrics {
+        InMemoryMetrics::default()
+    }
+
+    /// Get a copy of the totals so far.
+    pub fn snapshot(&self) -> Snapshot {
+        self.inner.lock().unwrap().clone()
+    }
+}
+
+impl MetricsSink for InMemoryMetrics {
+    fn record_request(&self, labels: &Labels, duration: Duration, request_bytes: Option<u64>,
+                      response_bytes: Option<u64>, outcome: Outcome) {
+        let millis = duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64;
+        let bucket = DURATION_BUCKETS.iter()
+            .position(|&bound| millis < bound)
+            .unwrap_or(DURATION_BUCKETS.len());
+
+        let mut inner = self.inner.lock().unwrap();
+        let totals = inner.requests.entry(labels.clone()).or_insert_with(Totals::default);
+        totals.count += 1;
+        if outcome == Outcome::Error {
+            totals.errors += 1;
+        }
+        totals.duration_sum += duration;
+        totals.duration_buckets[bucket] += 1;
+        totals.request_bytes += request_bytes.unwrap_or(0);
+        totals.response_bytes += response_bytes.unwrap_or(0);
+    }
+
+    fn gauge_pool(&self, idle: usize, in_flight: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.idle = idle;
+        inner.in_flight = in_flight;
+    }
+}
+
+#[test]
+fn test_in_memory_metrics() {
+    let metrics = InMemoryMetrics::new();
+    let ok = Labels::new(Method::Get, "example.com".to_owned(), Some(StatusClass::Success));
+    let failed = Labels::new(Method::Get, "example.com".to_owned(), None);
+
+    metrics.record_request(&ok, Duration::from_millis(5), Some(0), Some(100), Outcome::Response);
+    metrics.record_request(&ok, Duration::from_millis(70), Some(10), None, Outcome::Response);
+    metrics.record_request(&failed, Duration::from_secs(10), None, None, Outcome::Error);
+    metrics.gauge_pool(2, 1);
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.requests.len(), 2);
+    assert_eq!(snapshot.requests[&ok], Totals {
+        count: 2,
+        errors: 0,
+        duration_sum: Duration::from_millis(75),
+        duration_buckets: [1, 0, 1, 0, 0, 0, 0],
+        request_bytes: 10,
+        response_bytes: 100,
+    });
+    assert_eq!(snapshot.requests[&failed].errors, 1);
+    assert_eq!(snapshot.requests[&failed].duration_buckets[6], 1);
+    assert_eq!((snapshot.idle, snapshot.in_flight), (2, 1));
+}
@@ -0,0 +1,188 @@
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ::socket::SocketOptions;
+
+/// What a connection is made with, besides its host and port.
+///
+/// The pool only hands a connection to a request of the same partition, so
+/// one made with other settings is never reused for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Partition {
+    pub scheme: String,
+    pub socket: SocketOptions,
+    // the host and port to connect to instead, from an `Alt-Svc`
+    pub route: Option<(String, u16)>,
+    // the host and port of the proxy to connect through
+    pub proxy: Option<(String, u16)>,
+    // the `Proxy-Authorization` to open a tunnel through the proxy with
+    pub proxy_auth: Option<Vec<u8>>,
+}
+
+/// The partitions of a `Client`'s connections.
+///
+/// The pool is only keyed by the scheme, host and port of a connection, so
+/// the partition is passed through it in the scheme, as its number, such
+/// as `https#1`, and taken back out by the `Connector`.
+pub struct Partitions {
+    // each partition, with how many of its connections are open
+    partitions: RwLock<Vec<(Partition, Arc<AtomicUsize>)>>,
+}
+
+impl Partitions {
+    pub fn new() -> Partitions {
+        Partitions {
+            partitions: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Gets the scheme to connect through the pool with, for a connection
+    /// of `partition`.
+    pub fn scheme(&self, partition: Partition) -> String {
+        if let Some(scheme) = self.find(&partition) {
+            return scheme;
+        }
+        let mut partitions = self.partitions.write().unwrap();
+        // another thread may have added it in the meantime
+        let index = match partitions.iter().position(|&(ref p, _)| *p == partition) {
+            Some(index) => index,
+            None => {
+                partitions.push((partition.clone(), Arc::new(AtomicUsize::new(0))));
+                partitions.len() - 1
+            },
+        };
+        format!("{}#{}", partition.scheme, index)
+    }
+
+    fn find(&self, partition: &Partition) -> Option<String> {
+        self.partitions.read().unwrap()
+            .iter()
+            .position(|&(ref p, _)| p == partition)
+            .map(|index| format!("{}#{}", partition.scheme, index))
+    }
+
+    /// Gets the partition of a scheme from `scheme`, with the count of its
+    /// open connections.
+    pub fn get(&self, scheme: &str) -> Option<(Partition, Arc<AtomicUsize>)> {
+        let index = scheme.rfind('#').and_then(|at| scheme[at + 1..].parse::<usize>().ok());
+        let index = match index {
+            Some(index) => index,
+            None => return None,
+        };
+        self.partitions.read().unwrap().get(index).cloned()
+    }
+
+    /// Gets how many connections are open in each partition.
+    pub fn stats(&self) -> Vec<PartitionStats> {
+        self.partitions.read().unwrap()
+            .iter()
+            .map(|&(ref partition, ref open)| {
+                PartitionStats {
+                    scheme: partition.scheme.clone(),
+                    socket_mark: partition.socket.mark,
+                    dscp: partition.socket.dscp,
+                    local_address: partition.socket.local_address,
+                    alternative: partition.route.clone(),
+                    proxy: partition.proxy.clone(),
+                    open: open.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The connections of a `Client` that were made with the same settings.
+///
+/// A connection is only reused by a request that would have made it with
+/// the same settings. See `PoolStats::partitions`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartitionStats {
+    scheme: String,
+    socket_mark: Option<u32>,
+    dscp: Option<u8>,
+    local_address: Option<IpAddr>,
+    alternative: Option<(String, u16)>,
+    proxy: Option<(String, u16)>,
+    open: usize,
+}
+
+impl PartitionStats {
+    /// The scheme of the connections, `http` or `https`.
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
+    /// The `SO_MARK` the connections were made with.
+    ///
+    /// See `Client::socket_mark`.
+    pub fn socket_mark(&self) -> Option<u32> {
+        self.socket_mark
+    }
+
+    /// The DSCP the connections were made with.
+    ///
+    /// See `Client::dscp`.
+    pub fn dscp(&self) -> Option<u8> {
+        self.dscp
+    }
+
+    /// The local address the connections were bound to.
+    ///
+    /// See `Client::local_address`.
+    pub fn local_address(&self) -> Option<IpAddr> {
+        self.local_address
+    }
+
+    /// The host and port of the alternative service the connections were
+    /// made to, instead of the origin.
+    ///
+    /// See `ClientBuilder::honor_alt_svc`.
+    pub fn alternative(&self) -> Option<(&str, u16)> {
+        self.alternative.as_ref().map(|&(ref host, port)| (&**host, port))
+    }
+
+    /// The host and port of the proxy the connections were made through.
+    ///
+    /// See `ClientBuilder::proxy`.
+    pub fn proxy(&self) -> Option<(&str, u16)> {
+        self.proxy.as_ref().map(|&(ref host, port)| (&**host, port))
+    }
+
+    /// How many of the connections are open, whether idle or in use.
+    pub fn open(&self) -> usize {
+        self.open
+    }
+}
+
+#[test]
+fn test_scheme() {
+    let partitions = Partitions::new();
+    let plain = Partition {
+        scheme: "http".to_owned(),
+        socket: SocketOptions::default(),
+        route: None,
+        proxy: None,
+        proxy_auth: None,
+    };
+    let marked = Partition {
+        scheme: "http".to_owned(),
+        socket: SocketOptions {
+            mark: Some(7),
+            dscp: None,
+            local_address: None,
+            keepalive: None,
+            nodelay: true,
+        },
+        route: None,
+        proxy: None,
+        proxy_auth: None,
+    };
+    assert_eq!(partitions.scheme(plain.clone()), "http#0");
+    assert_eq!(partitions.scheme(marked.clone()), "http#1");
+    assert_eq!(partitions.scheme(plain.clone()), "http#0");
+
+    assert_eq!(partitions.get("http#1").map(|(p, _)| p), Some(marked));
+    assert_eq!(partitions.get("http#2").map(|(p, _)| p), None);
+    assert_eq!(partitions.get("http").map(|(p, _)| p), None);
+}
@@ -0,0 +1,177 @@
+use serde::{Serialize, Serializer};
+use serde::ser::SerializeStruct;
+use serde_json::Value;
+
+/// An operation of a JSON Patch, as in RFC 6902.
+///
+/// A list of these is sent with `RequestBuilder::json_patch`. Each `path`
+/// and `from` is a JSON Pointer (RFC 6901), such as `/a/b/0`, or `""` for
+/// the whole document.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchOp {
+    /// Adds the `value` at `path`.
+    Add {
+        /// Where the value is added.
+        path: String,
+        /// The value added.
+        value: Value,
+    },
+    /// Removes the value at `path`.
+    Remove {
+        /// The value removed.
+        path: String,
+    },
+    /// Replaces the value at `path` with `value`.
+    Replace {
+        /// The value replaced.
+        path: String,
+        /// The new value.
+        value: Value,
+    },
+    /// Moves the value at `from` to `path`.
+    Move {
+        /// The value moved.
+        from: String,
+        /// Where the value is moved to.
+        path: String,
+    },
+    /// Copies the value at `from` to `path`.
+    Copy {
+        /// The value copied.
+        from: String,
+        /// Where the value is copied to.
+        path: String,
+    },
+    /// Tests that the value at `path` is equal to `value`, failing the
+    /// whole patch otherwise.
+    Test {
+        /// The value tested.
+        path: String,
+        /// The value it has to be equal to.
+        value: Value,
+    },
+}
+
+impl PatchOp {
+    fn name(&self) -> &'static str {
+        match *self {
+            PatchOp::Add { .. } => "add",
+            PatchOp::Remove { .. } => "remove",
+            PatchOp::Replace { .. } => "replace",
+            PatchOp::Move { .. } => "move",
+            PatchOp::Copy { .. } => "copy",
+            PatchOp::Test { .. } => "test",
+        }
+    }
+
+    /// The `path` and `from` pointers of this operation.
+    fn pointers(&self) -> (&str, Option<&str>) {
+        match *self {
+            PatchOp::Add { ref path, .. } |
+            PatchOp::Remove { ref path } |
+            PatchOp::Replace { ref path, .. } |
+            PatchOp::Test { ref path, .. } => (path, None),
+            PatchOp::Move { ref from, ref path } |
+            PatchOp::Copy { ref from, ref path } => (path, Some(from)),
+        }
+    }
+}
+
+impl Serialize for PatchOp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = match *self {
+            PatchOp::Remove { .. } => 2,
+            _ => 3,
+        };
+        let mut op = try!(serializer.serialize_struct("PatchOp", len));
+        try!(op.serialize_field("op", self.name()));
+        match *self {
+            PatchOp::Add { ref path, ref value } |
+            PatchOp::Replace { ref path, ref value } |
+            PatchOp::Test { ref path, ref value } => {
+                try!(op.serialize_field("path", path));
+                try!(op.serialize_field("value", value));
+            },
+            PatchOp::Remove { ref path } => {
+                try!(op.serialize_field("path", path));
+            },
+            PatchOp::Move { ref from, ref path } |
+            PatchOp::Copy { ref from, ref path } => {
+                try!(op.serialize_field("from", from));
+                try!(op.serialize_field("path", path));
+            },
+        }
+        op.end()
+    }
+}
+
+/// Checks the `path` and `from` of each operation, returning the first
+/// one that isn't a valid JSON Pointer.
+pub fn check_pointers(ops: &[PatchOp]) -> Result<(), String> {
+    for op in ops {
+        let (path, from) = op.pointers();
+        if !is_pointer(path) {
+            return Err(path.to_owned());
+        }
+        if let Some(from) = from {
+            if !is_pointer(from) {
+                return Err(from.to_owned());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether `pointer` is empty, or starts with a `/`, and each `~` is
+/// escaping a `~` or a `/`, as `~0` and `~1`.
+fn is_pointer(pointer: &str) -> bool {
+    if !pointer.is_empty() && !pointer.starts_with('/') {
+        return false;
+    }
+    let mut chars = pointer.chars();
+    while let Some(c) = chars.next() {
+        if c == '~' {
+            match chars.next() {
+                Some('0') | Some('1') => (),
+                _ => return false,
+            }
+        }
+    }
+    true
+}
+
+#[test]
+fn test_is_pointer() {
+    for valid in &["", "/", "/foo", "/foo/0", "/a~1b", "/m~0n", "/c%d", "/ "] {
+        assert!(is_pointer(valid), "{:?} should be valid", valid);
+    }
+    for invalid in &["foo", "foo/bar", "/a~", "/a~2", "/~b", "#/foo"] {
+        assert!(!is_pointer(invalid), "{:?} should be invalid", invalid);
+    }
+}
+
+#[test]
+fn test_serialize_rfc_example() {
+    use serde_json;
+
+    // RFC 6902, section 3
+    let ops = vec![
+        PatchOp::Test { path: "/a/b/c".to_owned(), value: Value::String("foo".to_owned()) },
+        PatchOp::Remove { path: "/a/b/c".to_owned() },
+        PatchOp::Add {
+            path: "/a/b/c".to_owned(),
+            value: serde_json::from_str(r#"["foo", "bar"]"#).unwrap(),
+        },
+        PatchOp::Replace { path: "/a/b/c".to_owned(), value: serde_json::to_value(42).unwrap() },
+        PatchOp::Move { from: "/a/b/c".to_owned(), path: "/a/b/d".to_owned() },
+        PatchOp::Copy { from: "/a/b/d".to_owned(), path: "/a/b/e".to_owned() },
+    ];
+    assert_eq!(serde_json::to_string(&ops).unwrap(), concat!(
+        r#"[{"op":"test","path":"/a/b/c","value":"foo"},"#,
+        r#"{"op":"remove","path":"/a/b/c"},"#,
+        r#"{"op":"add","path":"/a/b/c","value":["foo","bar"]},"#,
+        r#"{"op":"replace","path":"/a/b/c","value":42},"#,
+        r#"{"op":"move","from":"/a/b/c","path":"/a/b/d"},"#,
+        r#"{"op":"copy","from":"/a/b/d","path":"/a/b/e"}]"#,
+    ));
+}
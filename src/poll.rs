@@ -0,0 +1,139 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hyper::header::{Headers, HttpDate};
+use hyper::status::StatusCode;
+use hyper::Url;
+use serde_json::{self, Value};
+
+/// The most of the body of a poll that is read, for `PollOptions::pending`.
+pub const BODY_LIMIT: usize = 64 * 1024;
+
+/// Options for `Client::poll_until_complete`.
+#[derive(Clone)]
+pub struct PollOptions {
+    /// How long to wait before each poll, unless the last response has a
+    /// `Retry-After` header.
+    pub interval: Duration,
+    /// How long to poll for in all, from the start.
+    ///
+    /// Polling stops with `Error::PollTimeout` instead of waiting past it.
+    pub max_duration: Duration,
+    /// The headers of every poll, such as the `Authorization` of the
+    /// request that started the operation. The client's default headers
+    /// are sent too.
+    pub headers: Headers,
+    /// Whether a response, with its status and the start of its body, means
+    /// the operation is still running.
+    ///
+    /// Bodies of more than 64KB are given to it as empty. By default, this is
+    /// `PollOptions::is_running`.
+    pub pending: Arc<Fn(&StatusCode, &[u8]) -> bool + Send + Sync>,
+}
+
+impl PollOptions {
+    /// Whether a response is for a running operation: it is `202 Accepted`,
+    /// or has a JSON body with a `"status"` of `"running"`, in any case.
+    pub fn is_running(status: &StatusCode, body: &[u8]) -> bool {
+        if *status == StatusCode::Accepted {
+            return true;
+        }
+        match serde_json::from_slice::<Value>(body) {
+            Ok(Value::Object(ref object)) => match object.get("status") {
+                Some(&Value::String(ref status)) => status.eq_ignore_ascii_case("running"),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+impl Default for PollOptions {
+    fn default() -> PollOptions {
+        PollOptions {
+            interval: Duration::from_secs(1),
+            max_duration: Duration::from_secs(5 * 60),
+            headers: Headers::new(),
+            pending: Arc::new(PollOptions::is_running),
+        }
+    }
+}
+
+impl fmt::Debug for PollOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PollOptions")
+            .field("interval", &self.interval)
+            .field("max_duration", &self.max_duration)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+/// Gets the URL to poll from a response, in its `Operation-Location` or
+/// else its `Location` header, relative to `base`.
+pub fn location(base: &Url, headers: &Headers) -> Option<::Result<Url>> {
+    let raw = headers.get_raw("Operation-Location").or_else(|| headers.get_raw("Location"));
+    raw.and_then(|values| values.first()).map(|value| {
+        let value = String::from_utf8_lossy(value);
+        base.join(value.trim()).map_err(::Error::from)
+    })
+}
+
+/// Gets how long a `Retry-After` header says to wait, in seconds or until
+/// a date.
+///
+/// A date that already passed is no wait at all.
+pub fn retry_after(headers: &Headers) -> Option<Duration> {
+    let value = match headers.get_raw("Retry-After").and_then(|values| values.first()) {
+        Some(value) => String::from_utf8_lossy(value).trim().to_owned(),
+        None => return None,
+    };
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = match value.parse::<HttpDate>() {
+        Ok(date) => date.0.to_timespec().sec,
+        Err(_) => return None,
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+    Some(Duration::from_secs((date - now.as_secs() as i64).max(0) as u64))
+}
+
+#[test]
+fn test_is_running() {
+    assert!(PollOptions::is_running(&StatusCode::Accepted, b""));
+    assert!(PollOptions::is_running(&StatusCode::Ok, b"{\"id\":1,\"status\":\"Running\"}"));
+    assert!(!PollOptions::is_running(&StatusCode::Ok, b"{\"status\":\"succeeded\"}"));
+    assert!(!PollOptions::is_running(&StatusCode::Created, b"running"));
+    assert!(!PollOptions::is_running(&StatusCode::Ok, b"[\"running\"]"));
+}
+
+#[test]
+fn test_location() {
+    let base = Url::parse("http://example.com/jobs").unwrap();
+    let mut headers = Headers::new();
+    assert!(location(&base, &headers).is_none());
+
+    headers.set_raw("Location", vec![b"/jobs/1".to_vec()]);
+    assert_eq!(location(&base, &headers).unwrap().unwrap().as_str(),
+               "http://example.com/jobs/1");
+    headers.set_raw("Operation-Location", vec![b"http://ops.example.com/1".to_vec()]);
+    assert_eq!(location(&base, &headers).unwrap().unwrap().as_str(),
+               "http://ops.example.com/1");
+}
+
+#[test]
+fn test_retry_after() {
+    let mut headers = Headers::new();
+    assert_eq!(retry_after(&headers), None);
+
+    headers.set_raw("Retry-After", vec![b" 120 ".to_vec()]);
+    assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+    headers.set_raw("Retry-After", vec![b"Sun, 06 Nov 1994 08:49:37 GMT".to_vec()]);
+    assert_eq!(retry_after(&headers), Some(Duration::from_secs(0)));
+    headers.set_raw("Retry-After", vec![b"Wed, 21 Oct 2099 07:28:00 GMT".to_vec()]);
+    assert!(retry_after(&headers).unwrap() > Duration::from_secs(365 * 24 * 60 * 60));
+    headers.set_raw("Retry-After", vec![b"soon".to_vec()]);
+    assert_eq!(retry_after(&headers), None);
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+
+use ::alt_svc::{parse_param, split_unquoted};
+
+/// A preference for how a server handles a request, from the `Prefer`
+/// header of RFC 7240.
+///
+/// Sent with `RequestBuilder::prefer`. The server tells which ones it
+/// applied in its `Preference-Applied` header, see
+/// `Response::preferences_applied`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Preference {
+    /// `respond-async`, to be answered with `202 Accepted` while the
+    /// request is still being processed.
+    RespondAsync,
+    /// `return=minimal`, to leave the resource out of the response.
+    ReturnMinimal,
+    /// `return=representation`, to have the resource in the response.
+    ReturnRepresentation,
+    /// `wait`, the number of seconds to wait for a response, such as
+    /// before responding asynchronously.
+    Wait(u64),
+    /// `handling=strict`, to reject a request with any error in it.
+    HandlingStrict,
+    /// `handling=lenient`, to process what can be of a request with
+    /// errors in it.
+    HandlingLenient,
+    /// Any other preference, sent as is, such as `odata.maxpagesize=50`,
+    /// or `include="a b"; strict` with parameters.
+    Raw(String),
+}
+
+impl fmt::Display for Preference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Preference::RespondAsync => f.write_str("respond-async"),
+            Preference::ReturnMinimal => f.write_str("return=minimal"),
+            Preference::ReturnRepresentation => f.write_str("return=representation"),
+            Preference::Wait(seconds) => write!(f, "wait={}", seconds),
+            Preference::HandlingStrict => f.write_str("handling=strict"),
+            Preference::HandlingLenient => f.write_str("handling=lenient"),
+            Preference::Raw(ref preference) => f.write_str(preference.trim()),
+        }
+    }
+}
+
+/// Formats `preferences` as the value of a `Prefer` header.
+pub fn format(preferences: &[Preference]) -> String {
+    preferences.iter()
+        .map(|preference| preference.to_string())
+        .filter(|preference| !preference.is_empty())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parses the values of `Preference-Applied` headers.
+///
+/// Preferences that aren't one of the others, or have a value they can't
+/// have, are `Preference::Raw`.
+pub fn parse(values: &[Vec<u8>]) -> Vec<Preference> {
+    let mut preferences = Vec::new();
+    for value in values {
+        let value = String::from_utf8_lossy(value);
+        preferences.extend(split_unquoted(&value, ',').into_iter().map(parse_preference));
+    }
+    preferences
+}
+
+fn parse_preference(value: &str) -> Preference {
+    let value = value.trim();
+    // only `Prefer` has parameters, but they can be ignored anyway
+    let applied = split_unquoted(value, ';').into_iter().next().unwrap_or("").trim();
+    let known = if applied.contains('=') {
+        match parse_param(applied) {
+            Some((name, ref word)) if name.eq_ignore_ascii_case("return") => {
+                if word.eq_ignore_ascii_case("minimal") {
+                    Some(Preference::ReturnMinimal)
+                } else if word.eq_ignore_ascii_case("representation") {
+                    Some(Preference::ReturnRepresentation)
+                } else {
+                    None
+                }
+            },
+            Some((name, ref word)) if name.eq_ignore_ascii_case("handling") => {
+                if word.eq_ignore_ascii_case("strict") {
+                    Some(Preference::HandlingStrict)
+                } else if word.eq_ignore_ascii_case("lenient") {
+                    Some(Preference::HandlingLenient)
+                } else {
+                    None
+                }
+            },
+            Some((name, ref word)) if name.eq_ignore_ascii_case("wait") => {
+                word.parse().ok().map(Preference::Wait)
+            },
+            _ => None,
+        }
+    } else if applied.eq_ignore_ascii_case("respond-async") {
+        Some(Preference::RespondAsync)
+    } else {
+        None
+    };
+    known.unwrap_or_else(|| Preference::Raw(value.to_owned()))
+}
+
+#[test]
+fn test_format() {
+    assert_eq!(format(&[Preference::ReturnMinimal]), "return=minimal");
+    assert_eq!(format(&[
+        Preference::RespondAsync,
+        Preference::Wait(10),
+        Preference::HandlingLenient,
+    ]), "respond-async, wait=10, handling=lenient");
+    // raw ones are sent as is, parameters and all
+    assert_eq!(format(&[
+        Preference::ReturnRepresentation,
+        Preference::Raw("odata.maxpagesize=50".to_owned()),
+        Preference::Raw(" include=\"a, b\"; strict ".to_owned()),
+        Preference::Raw("".to_owned()),
+    ]), "return=representation, odata.maxpagesize=50, include=\"a, b\"; strict");
+    assert_eq!(format(&[]), "");
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(parse(&[b"return=minimal".to_vec()]), vec![Preference::ReturnMinimal]);
+    assert_eq!(parse(&[b"Respond-Async, wait=\"30\",handling=STRICT".to_vec()]), vec![
+        Preference::RespondAsync,
+        Preference::Wait(30),
+        Preference::HandlingStrict,
+    ]);
+    // every header, in order, and what isn't known is kept
+    assert_eq!(parse(&[
+        b"return=representation, odata.maxpagesize=50".to_vec(),
+        b"include=\"a, b\", wait=soon, , return=none".to_vec(),
+    ]), vec![
+        Preference::ReturnRepresentation,
+        Preference::Raw("odata.maxpagesize=50".to_owned()),
+        Preference::Raw("include=\"a, b\"".to_owned()),
+        Preference::Raw("wait=soon".to_owned()),
+        Preference::Raw("return=none".to_owned()),
+    ]);
+    assert_eq!(parse(&[b"return = minimal; charset=utf-8".to_vec()]), vec![
+        Preference::ReturnMinimal,
+    ]);
+    assert_eq!(parse(&[]), vec![]);
+}
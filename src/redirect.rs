@@ -1,5 +1,8 @@
 use std::fmt;
 
+use hyper::header::Headers;
+use hyper::method::Method;
+
 use ::Url;
 
 /// A type that controls the policy on how to handle the following of redirects.
@@ -28,6 +31,25 @@ impl RedirectPolicy {
         }
     }
 
+    /// Create a RedirectPolicy that only follows redirects of safe methods,
+    /// `GET`, `HEAD`, `OPTIONS` and `TRACE`, with a maximum number of
+    /// redirects.
+    ///
+    /// A redirect of any other method, such as `POST` or `DELETE`, is
+    /// returned as the `Response`, for the application to decide. The
+    /// method checked is the one the next request would be sent with, so a
+    /// `POST` answered with `303 See Other` is followed, as it becomes a
+    /// `GET`, and so is one answered with `301` or `302`. A `POST` answered
+    /// with `307` or `308` keeps its method, and is not followed.
+    ///
+    /// Like `limited`, a `Error::TooManyRedirects` will be returned if the
+    /// max is reached, and a `Error::RedirectLoop` for a loop.
+    pub fn safe_methods_only(max: usize) -> RedirectPolicy {
+        RedirectPolicy {
+            inner: Policy::SafeMethods(max),
+        }
+    }
+
     /// Create a custom RedirectPolicy using the passed function.
     ///
     /// # Note
@@ -46,7 +68,7 @@ impl RedirectPolicy {
     /// # let mut client = reqwest::Client::new().unwrap();
     /// client.redirect(RedirectPolicy::custom(|next, previous| {
     ///     if previous.len() > 5 {
-    ///         Err(reqwest::Error::TooManyRedirects)
+    ///         Err(reqwest::Error::TooManyRedirects(previous.to_vec()))
     ///     } else if next.host_str() == Some("example.domain") {
     ///         // prevent redirects to 'example.domain'
     ///         Ok(false)
@@ -62,16 +84,50 @@ impl RedirectPolicy {
         }
     }
 
-    fn redirect(&self, next: &Url, previous: &[Url]) -> ::Result<bool> {
+    /// Create a custom RedirectPolicy using the passed function, which is
+    /// given the whole `RedirectAttempt`, including the headers of the
+    /// redirect response.
+    ///
+    /// Like with `custom`, redirect loops and long chains are left to the
+    /// function.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use reqwest::RedirectPolicy;
+    /// # let mut client = reqwest::Client::new().unwrap();
+    /// client.redirect(RedirectPolicy::custom_attempt(|attempt| {
+    ///     if attempt.previous().len() > 5 {
+    ///         return Err(reqwest::Error::TooManyRedirects(attempt.previous().to_vec()));
+    ///     }
+    ///     // the response is returned, instead of going to the maintenance page
+    ///     let reason = attempt.headers().get_raw("X-Redirect-Reason");
+    ///     Ok(reason.map_or(true, |values| values.iter().all(|v| v != b"maintenance")))
+    /// }));
+    /// ```
+    pub fn custom_attempt<T>(policy: T) -> RedirectPolicy
+    where T: Fn(&RedirectAttempt) -> ::Result<bool> + Send + Sync + 'static {
+        RedirectPolicy {
+            inner: Policy::Attempt(Box::new(policy)),
+        }
+    }
+
+    fn redirect(&self, method: &Method, next: &Url, previous: &[Url], headers: &Headers)
+                -> ::Result<bool> {
         match self.inner {
             Policy::Custom(ref custom) => custom(next, previous),
-            Policy::Limit(max) => {
-                if previous.len() == max {
-                    Err(::Error::TooManyRedirects)
-                } else if previous.contains(next) {
-                    Err(::Error::RedirectLoop)
-                } else {
-                    Ok(true)
+            Policy::Attempt(ref custom) => custom(&RedirectAttempt {
+                next: next,
+                previous: previous,
+                headers: headers,
+            }),
+            Policy::Limit(max) => limit(max, next, previous),
+            Policy::SafeMethods(max) => {
+                match *method {
+                    Method::Get | Method::Head | Method::Options | Method::Trace => {
+                        limit(max, next, previous)
+                    },
+                    _ => Ok(false),
                 }
             },
             Policy::None => Ok(false),
@@ -79,6 +135,53 @@ impl RedirectPolicy {
     }
 }
 
+fn limit(max: usize, next: &Url, previous: &[Url]) -> ::Result<bool> {
+    if previous.len() == max {
+        Err(::Error::TooManyRedirects(chain(next, previous)))
+    } else if previous.contains(next) {
+        Err(::Error::RedirectLoop(chain(next, previous)))
+    } else {
+        Ok(true)
+    }
+}
+
+fn chain(next: &Url, previous: &[Url]) -> Vec<Url> {
+    let mut urls = previous.to_vec();
+    urls.push(next.clone());
+    urls
+}
+
+/// A redirect that a `RedirectPolicy::custom_attempt` policy decides
+/// whether to follow.
+#[derive(Debug)]
+pub struct RedirectAttempt<'a> {
+    next: &'a Url,
+    previous: &'a [Url],
+    headers: &'a Headers,
+}
+
+impl<'a> RedirectAttempt<'a> {
+    /// The `Url` that the redirect is to.
+    pub fn url(&self) -> &Url {
+        self.next
+    }
+
+    /// The `Url`s of the requests so far, in order, the last being the one
+    /// that was redirected.
+    pub fn previous(&self) -> &[Url] {
+        self.previous
+    }
+
+    /// The headers of the redirect (3xx) response that this attempt is
+    /// for, such as its `Location`.
+    ///
+    /// These are what the server sent, not the headers of the request that
+    /// would follow the redirect.
+    pub fn headers(&self) -> &Headers {
+        self.headers
+    }
+}
+
 impl Default for RedirectPolicy {
     fn default() -> RedirectPolicy {
         RedirectPolicy::limited(10)
@@ -87,22 +190,51 @@ impl Default for RedirectPolicy {
 
 enum Policy {
     Custom(Box<Fn(&Url, &[Url]) -> ::Result<bool> + Send + Sync + 'static>),
+    Attempt(Box<Fn(&RedirectAttempt) -> ::Result<bool> + Send + Sync + 'static>),
     Limit(usize),
+    SafeMethods(usize),
     None,
 }
 
 impl fmt::Debug for Policy {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Policy::Custom(..) => f.pad("Custom"),
+            Policy::Custom(..) |
+            Policy::Attempt(..) => f.pad("Custom"),
             Policy::Limit(max) => f.debug_tuple("Limit").field(&max).finish(),
+            Policy::SafeMethods(max) => f.debug_tuple("SafeMethods").field(&max).finish(),
             Policy::None => f.pad("None"),
         }
     }
 }
 
-pub fn check_redirect(policy: &RedirectPolicy, next: &Url, previous: &[Url]) -> ::Result<bool> {
-    policy.redirect(next, previous)
+/// Checks whether to follow a redirect to `next`, with the `method` the
+/// next request would be sent with, and the `headers` of the redirect
+/// response.
+pub fn check_redirect(policy: &RedirectPolicy, method: &Method, next: &Url, previous: &[Url],
+                      headers: &Headers) -> ::Result<bool> {
+    policy.redirect(method, next, previous, headers)
+}
+
+/// A hook to adjust the headers of the next request in a redirect chain.
+pub type RedirectMutator = Box<Fn(&mut Headers, &Url) + Send + Sync + 'static>;
+
+/// Removes credentials from the headers when redirecting to a different host.
+///
+/// The origin is compared on host and port, so a redirect to the same host,
+/// but on another port, will also have these headers removed.
+pub fn remove_sensitive_headers(headers: &mut Headers, next: &Url, previous: &[Url]) {
+    if let Some(previous) = previous.last() {
+        let cross_host = next.host_str() != previous.host_str() ||
+                         next.port_or_known_default() != previous.port_or_known_default();
+        if cross_host {
+            headers.remove_raw("Authorization");
+            headers.remove_raw("Cookie");
+            headers.remove_raw("Cookie2");
+            headers.remove_raw("Proxy-Authorization");
+            headers.remove_raw("WWW-Authenticate");
+        }
+    }
 }
 
 /*
@@ -132,15 +264,15 @@ fn test_redirect_policy_limit() {
         .collect::<Vec<_>>();
 
 
-    match policy.redirect(&next, &previous) {
+    match policy.redirect(&Method::Get, &next, &previous, &Headers::new()) {
         Ok(true) => {},
         other => panic!("expected Ok(true), got: {:?}", other)
     }
 
     previous.push(Url::parse("http://a.b.d/e/33").unwrap());
 
-    match policy.redirect(&next, &previous) {
-        Err(::Error::TooManyRedirects) => {},
+    match policy.redirect(&Method::Get, &next, &previous, &Headers::new()) {
+        Err(::Error::TooManyRedirects(..)) => {},
         other => panic!("expected TooManyRedirects, got: {:?}", other)
     }
 }
@@ -156,8 +288,87 @@ fn test_redirect_policy_custom() {
     });
 
     let next = Url::parse("http://bar/baz").unwrap();
-    assert_eq!(policy.redirect(&next, &[]).unwrap(), true);
+    assert_eq!(policy.redirect(&Method::Get, &next, &[], &Headers::new()).unwrap(), true);
 
     let next = Url::parse("http://foo/baz").unwrap();
-    assert_eq!(policy.redirect(&next, &[]).unwrap(), false);
+    assert_eq!(policy.redirect(&Method::Get, &next, &[], &Headers::new()).unwrap(), false);
+}
+
+#[test]
+fn test_redirect_policy_custom_attempt() {
+    let policy = RedirectPolicy::custom_attempt(|attempt| {
+        let reason = attempt.headers().get_raw("X-Redirect-Reason");
+        if reason.map_or(false, |values| values.iter().any(|v| v == b"maintenance")) {
+            Ok(false)
+        } else {
+            Ok(attempt.previous().len() < 2)
+        }
+    });
+    let next = Url::parse("http://x.y/z").unwrap();
+    let previous = vec![Url::parse("http://a.b/c").unwrap()];
+
+    let mut headers = Headers::new();
+    headers.set_raw("Location", vec![b"http://x.y/z".to_vec()]);
+    assert_eq!(policy.redirect(&Method::Get, &next, &previous, &headers).unwrap(), true);
+    headers.set_raw("X-Redirect-Reason", vec![b"moved".to_vec()]);
+    assert_eq!(policy.redirect(&Method::Get, &next, &previous, &headers).unwrap(), true);
+    headers.set_raw("X-Redirect-Reason", vec![b"maintenance".to_vec()]);
+    assert_eq!(policy.redirect(&Method::Get, &next, &previous, &headers).unwrap(), false);
+
+    // the built-in policies don't look at the headers
+    let limited = RedirectPolicy::limited(2);
+    assert_eq!(limited.redirect(&Method::Get, &next, &previous, &headers).unwrap(), true);
+    let none = RedirectPolicy::none();
+    assert_eq!(none.redirect(&Method::Get, &next, &previous, &Headers::new()).unwrap(), false);
+}
+
+#[test]
+fn test_redirect_policy_safe_methods_only() {
+    let policy = RedirectPolicy::safe_methods_only(2);
+    let next = Url::parse("http://x.y/z").unwrap();
+
+    for method in &[Method::Get, Method::Head, Method::Options, Method::Trace] {
+        assert_eq!(policy.redirect(method, &next, &[], &Headers::new()).unwrap(), true);
+    }
+    for method in &[Method::Post, Method::Put, Method::Patch, Method::Delete] {
+        assert_eq!(policy.redirect(method, &next, &[], &Headers::new()).unwrap(), false);
+    }
+
+    let previous = vec![
+        Url::parse("http://a.b/c/0").unwrap(),
+        Url::parse("http://a.b/c/1").unwrap(),
+    ];
+    match policy.redirect(&Method::Get, &next, &previous, &Headers::new()) {
+        Err(::Error::TooManyRedirects(..)) => {},
+        other => panic!("expected TooManyRedirects, got: {:?}", other)
+    }
+    match policy.redirect(&Method::Get, &previous[0], &previous[..1], &Headers::new()) {
+        Err(::Error::RedirectLoop(ref urls)) => assert_eq!(urls, &[previous[0].clone(),
+                                                                    previous[0].clone()]),
+        other => panic!("expected RedirectLoop, got: {:?}", other)
+    }
+}
+
+#[test]
+fn test_remove_sensitive_headers() {
+    use hyper::header::{Accept, Authorization, Cookie};
+
+    let mut headers = Headers::new();
+    headers.set(Accept::star());
+    headers.set(Authorization("let me in".to_owned()));
+    headers.set(Cookie(vec!["foo=bar".to_owned()]));
+
+    let next = Url::parse("http://initial-domain.com/path").unwrap();
+    let mut prev = vec![Url::parse("http://initial-domain.com/new_path").unwrap()];
+    let mut filtered_headers = headers.clone();
+
+    remove_sensitive_headers(&mut headers, &next, &prev);
+    assert_eq!(headers, filtered_headers);
+
+    prev.push(Url::parse("http://new-domain.com/path").unwrap());
+    filtered_headers.remove::<Authorization<String>>();
+    filtered_headers.remove::<Cookie>();
+
+    remove_sensitive_headers(&mut headers, &next, &prev);
+    assert_eq!(headers, filtered_headers);
 }
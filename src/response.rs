@@ -1,96 +1,1348 @@
 use std::fmt;
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
-use hyper::header::{Headers, ContentEncoding, ContentLength, Encoding, TransferEncoding};
+use hyper::header::{Headers, ContentEncoding, ContentLength, ContentType, Encoding,
+    TransferEncoding};
+use hyper::method::Method;
+use hyper::mime::Attr;
 use hyper::status::StatusCode;
 use hyper::version::HttpVersion;
 use hyper::Url;
-use libflate::gzip;
+use libflate::{deflate, gzip, zlib};
 use serde::Deserialize;
 use serde_json;
 
+use ::alt_svc::{self, AltService};
+use ::challenge::{self, Challenge};
+use ::connect::{Addrs, Framing, Upload};
+use ::deprecation::{self, DeprecationInfo};
+use ::error::{DecompressReason, TimeoutKind};
+use ::prefer::{self, Preference};
+
 
 /// A Response to a submitted `Request`.
 pub struct Response {
     inner: Decoder,
+    request_body_incomplete: bool,
+    connection: Connection,
+    idempotency_key: Option<String>,
+    base_url: Option<Url>,
+    ambiguous_redirects: Vec<Vec<String>>,
+    read_timeout: Option<Duration>,
+    // how much of the body was read, for a timeout error
+    position: u64,
+    release: Arc<Release>,
+    tee: Option<Tee>,
+}
+
+/// What is known about the connection a `Response` came from.
+#[derive(Clone, Debug)]
+pub struct Connection {
+    /// Whether the connection was from the pool.
+    pub reused: bool,
+    pub addrs: Addrs,
+    pub framing: Framing,
+    /// Aborted to keep the connection from being used again.
+    pub upload: Upload,
 }
 
-pub fn new(res: ::hyper::client::Response, gzip: bool) -> Response {
+/// Makes the `Response` of `res`, decoding the `content_codings` it may
+/// have. With `sniff_content`, a `deflate` content coding is decoded
+/// whether or not it is wrapped in zlib, whatever `deflate` says.
+pub fn new(res: ::hyper::client::Response, content_codings: &[Encoding], sniff_content: bool,
+           transfer_codings: bool, deflate: DeflateOptions, request_body_incomplete: bool,
+           connection: Connection, release: Arc<Release>) -> Response {
     Response {
-        inner: Decoder::from_hyper_response(res, gzip)
+        inner: Decoder::from_hyper_response(res, content_codings, sniff_content, transfer_codings,
+                                            deflate),
+        request_body_incomplete: request_body_incomplete,
+        connection: connection,
+        idempotency_key: None,
+        base_url: None,
+        ambiguous_redirects: Vec::new(),
+        read_timeout: None,
+        position: 0,
+        release: release,
+        tee: None,
+    }
+}
+
+pub fn set_idempotency_key(res: &mut Response, key: Option<String>) {
+    res.idempotency_key = key;
+}
+
+pub fn set_base_url(res: &mut Response, base_url: Option<Url>) {
+    res.base_url = base_url;
+}
+
+pub fn set_ambiguous_redirects(res: &mut Response, redirects: Vec<Vec<String>>) {
+    res.ambiguous_redirects = redirects;
+}
+
+pub fn set_read_timeout(res: &mut Response, timeout: Option<Duration>) {
+    res.read_timeout = timeout;
+}
+
+/// Keeps the head of `res`, dropping it to release its connection.
+pub fn discard(res: Response) -> ResponseHead {
+    ResponseHead {
+        url: res.url().clone(),
+        status: *res.status(),
+        headers: res.headers().clone(),
+        version: *res.version(),
+    }
+}
+
+/// A `Response` with its body read into memory, to make identical ones from.
+pub struct Shared {
+    head: Head,
+    body: Arc<Vec<u8>>,
+    request_body_incomplete: bool,
+    connection: Connection,
+    idempotency_key: Option<String>,
+    base_url: Option<Url>,
+    ambiguous_redirects: Vec<Vec<String>>,
+    release: Arc<Release>,
+}
+
+impl Shared {
+    /// Makes a `Response` that reads the shared body from the start.
+    ///
+    /// It counts as a request in flight, like the one it was made from.
+    pub fn response(&self) -> Response {
+        Response {
+            inner: Decoder::Buffered {
+                body: self.body.clone(),
+                pos: 0,
+                rest: None,
+                head: self.head.clone(),
+            },
+            request_body_incomplete: self.request_body_incomplete,
+            connection: self.connection.clone(),
+            idempotency_key: self.idempotency_key.clone(),
+            base_url: self.base_url.clone(),
+            ambiguous_redirects: self.ambiguous_redirects.clone(),
+            // the body is already in memory
+            read_timeout: None,
+            position: 0,
+            release: self.release.clone(),
+            tee: None,
+        }
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+/// Reads the body of `res` into memory, to share it, if it's at most
+/// `limit` bytes.
+///
+/// Otherwise, or if reading fails, `res` reads what was already read
+/// before the rest of the body, or the error.
+pub fn share(res: &mut Response, limit: usize) -> Option<Shared> {
+    // a tee gets the body when it is read again
+    let tee = res.tee.take();
+    let mut body = Vec::new();
+    let read = (&mut *res).take(limit as u64 + 1).read_to_end(&mut body);
+    res.tee = tee;
+    let head = Head {
+        headers: res.headers().clone(),
+        url: res.url().clone(),
+        version: *res.version(),
+        status: *res.status(),
+    };
+    let placeholder = Decoder::Errored {
+        err: None,
+        head: head.clone(),
+    };
+    let rest = mem::replace(&mut res.inner, placeholder);
+    let rest = match read {
+        Ok(_) if body.len() <= limit => None,
+        Ok(_) => Some(Box::new(rest)),
+        Err(e) => Some(Box::new(Decoder::Errored {
+            err: Some(e),
+            head: head.clone(),
+        })),
+    };
+    let shared = rest.is_none();
+    let body = Arc::new(body);
+    res.position = 0;
+    res.inner = Decoder::Buffered {
+        body: body.clone(),
+        pos: 0,
+        rest: rest,
+        head: head.clone(),
+    };
+    if !shared {
+        return None;
+    }
+    Some(Shared {
+        head: head,
+        body: body,
+        request_body_incomplete: res.request_body_incomplete,
+        connection: res.connection.clone(),
+        idempotency_key: res.idempotency_key.clone(),
+        base_url: res.base_url.clone(),
+        ambiguous_redirects: res.ambiguous_redirects.clone(),
+        release: res.release.clone(),
+    })
+}
+
+/// How the connection of a `Response` dropped with an unread body is released.
+///
+/// A body of at most `drain_limit` bytes is read and discarded, so that the
+/// connection can go back to the pool. Otherwise, the connection is closed.
+#[derive(Debug)]
+pub struct Release {
+    drain_limit: AtomicUsize,
+    drained: AtomicUsize,
+    closed: AtomicUsize,
+    // requests sent, whose `Response` wasn't dropped yet
+    in_flight: AtomicUsize,
+}
+
+impl Release {
+    pub fn new() -> Release {
+        Release {
+            drain_limit: AtomicUsize::new(DEFAULT_DRAIN_LIMIT),
+            drained: AtomicUsize::new(0),
+            closed: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn set_drain_limit(&self, limit: usize) {
+        self.drain_limit.store(limit, Ordering::Relaxed);
+    }
+
+    pub fn drained(&self) -> usize {
+        self.drained.load(Ordering::Relaxed)
+    }
+
+    pub fn closed(&self) -> usize {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Counts a request as in flight, until `end_request` is called, or its
+    /// `Response` is dropped.
+    pub fn begin_request(&self) {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn end_request(&self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub const DEFAULT_DRAIN_LIMIT: usize = 32 * 1024;
+
+impl fmt::Debug for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("url", self.url())
+            .field("status", self.status())
+            .field("headers", &DebugHeaders(self.headers()))
+            .field("version", self.version())
+            .finish()
+    }
+}
+
+impl Response {
+    /// Get the final `Url` of this response.
+    #[inline]
+    pub fn url(&self) -> &Url {
+        match self.inner {
+            Decoder::PlainText(ref hyper_response) => &hyper_response.url,
+            Decoder::Gzip{ ref head, .. } |
+            Decoder::Deflate { ref head, .. } |
+            Decoder::Buffered { ref head, .. } |
+            Decoder::Errored { ref head, .. } => &head.url,
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli { ref head, .. } => &head.url,
+        }
+    }
+
+    /// Get the `StatusCode`.
+    #[inline]
+    pub fn status(&self) -> &StatusCode {
+        match self.inner {
+            Decoder::PlainText(ref hyper_response) => &hyper_response.status,
+            Decoder::Gzip{ ref head, .. } |
+            Decoder::Deflate { ref head, .. } |
+            Decoder::Buffered { ref head, .. } |
+            Decoder::Errored { ref head, .. } => &head.status,
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli { ref head, .. } => &head.status,
+        }
+    }
+
+    /// Get the status as a number, such as `404`.
+    #[inline]
+    pub fn status_u16(&self) -> u16 {
+        self.status().to_u16()
+    }
+
+    /// Whether the status is a success (2xx).
+    ///
+    /// ```no_run
+    /// let res = reqwest::get("http://httpbin.org/status/204").unwrap();
+    /// assert!(res.is_success());
+    /// ```
+    #[inline]
+    pub fn is_success(&self) -> bool {
+        self.status().is_success()
+    }
+
+    /// Whether the status is a redirect (3xx).
+    ///
+    /// Redirects the `RedirectPolicy` didn't follow are returned as is.
+    ///
+    /// ```no_run
+    /// let res = reqwest::get("http://httpbin.org/status/304").unwrap();
+    /// assert!(res.is_redirect());
+    /// ```
+    #[inline]
+    pub fn is_redirect(&self) -> bool {
+        self.status().is_redirection()
+    }
+
+    /// Whether the status is a client error (4xx).
+    ///
+    /// `error_for_status` returns an error for these.
+    ///
+    /// ```no_run
+    /// let res = reqwest::get("http://httpbin.org/status/404").unwrap();
+    /// assert!(res.is_client_error());
+    /// ```
+    #[inline]
+    pub fn is_client_error(&self) -> bool {
+        self.status().is_client_error()
+    }
+
+    /// Whether the status is a server error (5xx).
+    ///
+    /// `error_for_status` returns an error for these.
+    ///
+    /// ```no_run
+    /// let res = reqwest::get("http://httpbin.org/status/503").unwrap();
+    /// assert!(res.is_server_error());
+    /// ```
+    #[inline]
+    pub fn is_server_error(&self) -> bool {
+        self.status().is_server_error()
+    }
+
+    /// Get the `Headers`.
+    #[inline]
+    pub fn headers(&self) -> &Headers {
+        match self.inner {
+            Decoder::PlainText(ref hyper_response) => &hyper_response.headers,
+            Decoder::Gzip{ ref head, .. } |
+            Decoder::Deflate { ref head, .. } |
+            Decoder::Buffered { ref head, .. } |
+            Decoder::Errored { ref head, .. } => &head.headers,
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli { ref head, .. } => &head.headers,
+        }
+    }
+
+    /// Get the first value of the header `name`, exactly as it was
+    /// received.
+    ///
+    /// This works for any value, even one that isn't ASCII, which the
+    /// typed headers of `headers` can't parse. The name is matched ignoring
+    /// case, and the value has no surrounding whitespace.
+    pub fn header_bytes(&self, name: &str) -> Option<&[u8]> {
+        header_all(self.headers(), name).into_iter().next()
+    }
+
+    /// Get each value of the header `name`, one per line it was received
+    /// on, exactly as it was received.
+    pub fn header_all(&self, name: &str) -> Vec<&[u8]> {
+        header_all(self.headers(), name)
+    }
+
+    /// Get the first value of the header `name` if it is valid UTF-8.
+    ///
+    /// Use `header_bytes` to tell a value that isn't apart from a missing
+    /// header, or `header_lossy` to decode it anyway.
+    pub fn header_str(&self, name: &str) -> Option<&str> {
+        self.header_bytes(name).and_then(|value| ::std::str::from_utf8(value).ok())
+    }
+
+    /// Get the first value of the header `name`, decoded as ISO-8859-1
+    /// (latin-1), as HTTP historically defined header values.
+    ///
+    /// Every byte is decoded to the character of the same number, so this
+    /// never fails, and the bytes can be recovered from the result. A value
+    /// in UTF-8 comes out garbled, so use `header_str` for one that is
+    /// expected to be.
+    ///
+    /// ```no_run
+    /// # fn run() -> Result<(), Box<std::error::Error>> {
+    /// let res = try!(reqwest::get("http://example.com/download"));
+    /// if let Some(disposition) = res.header_lossy("Content-Disposition") {
+    ///     println!("{}", disposition);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn header_lossy(&self, name: &str) -> Option<String> {
+        self.header_bytes(name).map(decode_latin1)
+    }
+
+    /// Get the `HttpVersion`.
+    #[inline]
+    pub fn version(&self) -> &HttpVersion {
+        match self.inner {
+            Decoder::PlainText(ref hyper_response) => &hyper_response.version,
+            Decoder::Gzip{ ref head, .. } |
+            Decoder::Deflate { ref head, .. } |
+            Decoder::Buffered { ref head, .. } |
+            Decoder::Errored { ref head, .. } => &head.version,
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli { ref head, .. } => &head.version,
+        }
+    }
+
+    /// Whether the server responded before the request body was completely sent.
+    ///
+    /// When a server decides early to reject a request, such as with a
+    /// `413 Payload Too Large`, the rest of the body is not sent, and this
+    /// response is returned instead.
+    #[inline]
+    pub fn request_body_incomplete(&self) -> bool {
+        self.request_body_incomplete
+    }
+
+    /// Whether the request was sent on a connection from the pool.
+    ///
+    /// This is `false` when a new connection had to be made for the
+    /// request. Connections made by `Client::warm_up` count as reused.
+    #[inline]
+    pub fn connection_reused(&self) -> bool {
+        self.connection.reused
+    }
+
+    /// How many bytes the server sent after the end of the body that its
+    /// `Content-Length` declared, if it sent more.
+    ///
+    /// The body is never read past its `Content-Length`, so the excess
+    /// isn't taken as the start of the response to the next request on the
+    /// connection. It is discarded instead, and the connection is closed
+    /// rather than reused. This is only known once the end of the body was
+    /// received, and only counts what the server sent by then. See
+    /// `PoolStats::overran_content_length`.
+    pub fn framing_anomaly(&self) -> Option<u64> {
+        self.connection.framing.excess()
+    }
+
+    /// Get the address of the server the response came from.
+    #[inline]
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.connection.addrs.remote
+    }
+
+    /// Get all addresses the host resolved to, when its connection was made.
+    ///
+    /// The `remote_addr` is one of them. A reused connection has the
+    /// addresses from when it was first connected, which may differ from
+    /// what the host resolves to now.
+    ///
+    /// This is `None` when the host wasn't resolved by the client: for a
+    /// request through a proxy, which resolves it instead, or one sent with
+    /// `Client::request_over`.
+    ///
+    /// ```no_run
+    /// let res = reqwest::get("https://www.rust-lang.org").unwrap();
+    /// println!("{} resolved to {:?}, used {}",
+    ///          res.url().host_str().unwrap(),
+    ///          res.resolved_addrs(),
+    ///          res.remote_addr());
+    /// ```
+    #[inline]
+    pub fn resolved_addrs(&self) -> Option<&[SocketAddr]> {
+        self.connection.addrs.resolved.as_ref().map(|addrs| &addrs[..])
+    }
+
+    /// Whether the `resolved_addrs` are those the host is pinned to with
+    /// `Client::resolve` or `Client::resolve_to_addrs`, rather than what
+    /// the resolver returned.
+    #[inline]
+    pub fn resolved_addrs_pinned(&self) -> bool {
+        self.connection.addrs.pinned
+    }
+
+    /// Get the statistics of the connection the request was sent on, to
+    /// keep after the response is gone.
+    ///
+    /// ```no_run
+    /// let res = reqwest::get("https://www.rust-lang.org").unwrap();
+    /// let stats = res.stats();
+    /// drop(res);
+    /// println!("talked to {}, of {:?}", stats.remote_addr(), stats.resolved_addrs());
+    /// ```
+    pub fn stats(&self) -> RequestStats {
+        RequestStats {
+            connection_reused: self.connection.reused,
+            remote_addr: self.connection.addrs.remote,
+            resolved_addrs: self.connection.addrs.resolved.clone(),
+            resolved_addrs_pinned: self.connection.addrs.pinned,
+        }
+    }
+
+    /// Get the `Idempotency-Key` that was sent with the request.
+    ///
+    /// This is the key generated by `Client::idempotency_keys`, or the one
+    /// set on the request.
+    #[inline]
+    pub fn idempotency_key(&self) -> Option<&str> {
+        self.idempotency_key.as_ref().map(|key| &key[..])
+    }
+
+    /// Get the base `Url` of the server the request was sent to.
+    ///
+    /// This is `None`, unless the request was to a primary server set with
+    /// `Client::fallback_base_urls`. It is then the primary `Url`, or the
+    /// fallback that was used instead.
+    #[inline]
+    pub fn base_url(&self) -> Option<&Url> {
+        self.base_url.as_ref()
+    }
+
+    /// Get the `Location` values of each redirect that was followed even
+    /// though it had more than one, with `Client::lenient_redirects`.
+    ///
+    /// The first value of each was followed.
+    #[inline]
+    pub fn ambiguous_redirects(&self) -> &[Vec<String>] {
+        &self.ambiguous_redirects
+    }
+
+    /// Whether the status is `206 Partial Content`, the response to a
+    /// request with a `Range` that the server honored.
+    #[inline]
+    pub fn is_partial(&self) -> bool {
+        *self.status() == StatusCode::PartialContent
+    }
+
+    /// Get the byte range from the `Content-Range` header.
+    ///
+    /// Returns `None` if there is no such header, or if it isn't a valid
+    /// byte range, such as one that ends before it starts.
+    ///
+    /// ```no_run
+    /// let client = reqwest::Client::new().unwrap();
+    /// let res = client.get("http://example.com/large.iso")
+    ///     .range(1024, None)
+    ///     .send()
+    ///     .unwrap();
+    /// match res.content_range() {
+    ///     Some(range) if res.is_partial() && range.start() == Some(1024) => {
+    ///         println!("resuming, {:?} bytes in total", range.total());
+    ///     },
+    ///     _ => println!("range not honored, downloading from the start"),
+    /// }
+    /// ```
+    pub fn content_range(&self) -> Option<ContentRange> {
+        self.headers().get_raw("Content-Range")
+            .and_then(|values| if values.len() == 1 { values.first() } else { None })
+            .and_then(|value| ::std::str::from_utf8(value).ok())
+            .and_then(parse_content_range)
+    }
+
+    /// Get the alternative services advertised in the `Alt-Svc` header.
+    ///
+    /// These are other hosts or ports, possibly with other protocols, that
+    /// the same origin can be reached at. The list is empty without the
+    /// header, and for `Alt-Svc: clear`, which withdraws the earlier ones.
+    /// Alternatives that can't be parsed are left out.
+    ///
+    /// To have the client connect to them, see
+    /// `ClientBuilder::honor_alt_svc`.
+    ///
+    /// ```no_run
+    /// let res = reqwest::get("https://www.rust-lang.org").unwrap();
+    /// for alt in res.alt_svc() {
+    ///     println!("{} at {:?}:{}", alt.protocol(), alt.host(), alt.port());
+    /// }
+    /// ```
+    pub fn alt_svc(&self) -> Vec<AltService> {
+        self.headers().get_raw("Alt-Svc")
+            .and_then(alt_svc::parse)
+            .unwrap_or_else(Vec::new)
+    }
+
+    /// Get the authentication challenges of the `WWW-Authenticate` headers.
+    ///
+    /// A `401 Unauthorized` response has at least one, each with the
+    /// scheme that can be authenticated with, and its parameters, such as
+    /// the `realm`. The list is empty without the header. Parts of it that
+    /// can't be parsed are left out.
+    ///
+    /// ```no_run
+    /// let res = reqwest::get("https://example.com/private").unwrap();
+    /// for challenge in res.auth_challenges() {
+    ///     println!("{} for {:?}", challenge.scheme(), challenge.param("realm"));
+    /// }
+    /// ```
+    pub fn auth_challenges(&self) -> Vec<Challenge> {
+        self.headers().get_raw("WWW-Authenticate")
+            .map(challenge::parse)
+            .unwrap_or_else(Vec::new)
+    }
+
+    /// Get what the response says about its endpoint being deprecated, from
+    /// its `Deprecation` and `Sunset` headers, and the `Link`s with a
+    /// `rel="deprecation"`.
+    ///
+    /// `None` unless the `Deprecation` header says it is deprecated, or
+    /// there is a `Sunset`. The forms that the drafts of `Deprecation` had
+    /// are all accepted: `true`, `?1`, a date like `@1688169599`, or an
+    /// HTTP-date.
+    pub fn deprecation(&self) -> Option<DeprecationInfo> {
+        deprecation::parse(self.url(), self.headers())
+    }
+
+    /// Get the preferences that the server applied, from the
+    /// `Preference-Applied` headers.
+    ///
+    /// These are the ones of `RequestBuilder::prefer` that the server
+    /// followed. The list is empty without the header.
+    pub fn preferences_applied(&self) -> Vec<Preference> {
+        self.headers().get_raw("Preference-Applied")
+            .map(prefer::parse)
+            .unwrap_or_else(Vec::new)
+    }
+
+    /// Returns true if the server accepted to process the request after
+    /// responding, as asked for with `Preference::RespondAsync`.
+    ///
+    /// That is a `202 Accepted` response that applied `respond-async`. The
+    /// result is then usually polled at the URL of its `Location` header.
+    pub fn is_async_accepted(&self) -> bool {
+        *self.status() == StatusCode::Accepted &&
+            self.preferences_applied().contains(&Preference::RespondAsync)
+    }
+
+    /// Get the methods listed in the `Allow` header.
+    ///
+    /// Methods are parsed leniently: whitespace and empty entries are
+    /// ignored, duplicates are removed, and unknown methods are returned
+    /// as `Method::Extension`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use reqwest::Method;
+    /// let client = reqwest::Client::new().unwrap();
+    /// let res = client.options("https://example.com/resource").send().unwrap();
+    /// if res.allowed_methods().contains(&Method::Put) {
+    ///     println!("resource can be replaced");
+    /// }
+    /// ```
+    pub fn allowed_methods(&self) -> Vec<Method> {
+        parse_methods(self.headers().get_raw("Allow"))
+    }
+
+    /// Get the CORS information of this response.
+    ///
+    /// Returns `None` if the response doesn't have an
+    /// `Access-Control-Allow-Origin` header.
+    pub fn cors(&self) -> Option<CorsInfo> {
+        let headers = self.headers();
+        let origin = match parse_list(headers.get_raw("Access-Control-Allow-Origin")).into_iter().next() {
+            Some(origin) => origin,
+            None => return None,
+        };
+        let max_age = parse_list(headers.get_raw("Access-Control-Max-Age"))
+            .into_iter()
+            .next()
+            .and_then(|secs| secs.parse().ok())
+            .map(Duration::from_secs);
+        let allow_headers = parse_list(headers.get_raw("Access-Control-Allow-Headers"))
+            .into_iter()
+            .map(|name| name.to_ascii_lowercase())
+            .fold(Vec::new(), dedup);
+
+        Some(CorsInfo {
+            allow_origin: origin,
+            allow_methods: parse_methods(headers.get_raw("Access-Control-Allow-Methods")),
+            allow_headers: allow_headers,
+            max_age: max_age,
+        })
+    }
+
+    /// Turn a response with a client or server error status into an `Error`.
+    ///
+    /// The `Error::Status` has the status and the `Url` of the response.
+    /// Responses with any other status are returned as they are.
+    ///
+    /// ```no_run
+    /// let res = reqwest::get("http://httpbin.org/status/404")
+    ///     .and_then(|res| res.error_for_status());
+    /// match res {
+    ///     Ok(_res) => (),
+    ///     Err(reqwest::Error::Status(status, url)) => {
+    ///         println!("{} returned {}", url, status);
+    ///     },
+    ///     Err(err) => println!("request failed: {}", err),
+    /// }
+    /// ```
+    pub fn error_for_status(self) -> ::Result<Response> {
+        match self.status_error() {
+            Some(err) => Err(err),
+            None => Ok(self),
+        }
+    }
+
+    /// Check the status of a response like `error_for_status`, but keep the
+    /// response.
+    ///
+    /// This is useful when the body of an error response has more details.
+    ///
+    /// ```no_run
+    /// # extern crate reqwest;
+    /// # #[macro_use] extern crate serde_derive;
+    /// #[derive(Deserialize)]
+    /// struct ApiError {
+    ///     message: String,
+    /// }
+    ///
+    /// # fn run() -> reqwest::Result<()> {
+    /// let mut res = try!(reqwest::get("https://api.example.com/items/1"));
+    /// if let Err(err) = res.error_for_status_ref() {
+    ///     let detail: ApiError = try!(res.json());
+    ///     println!("{}: {}", err, detail.message);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// # fn main() {}
+    /// ```
+    pub fn error_for_status_ref(&self) -> ::Result<&Response> {
+        match self.status_error() {
+            Some(err) => Err(err),
+            None => Ok(self),
+        }
+    }
+
+    fn status_error(&self) -> Option<::Error> {
+        if self.is_client_error() || self.is_server_error() {
+            Some(::Error::Status(self.status().clone(), self.url().clone()))
+        } else {
+            None
+        }
+    }
+
+    /// Try and deserialize the response body as JSON.
+    #[inline]
+    pub fn json<T: Deserialize>(&mut self) -> ::Result<T> {
+        if self.tee.is_some() {
+            // read first, so that a failing sink isn't taken for bad JSON
+            let mut body = Vec::new();
+            try!(self.read_to_end(&mut body));
+            return serde_json::from_slice(&body).map_err(::Error::from);
+        }
+        serde_json::from_reader(self).map_err(::Error::from)
+    }
+
+    /// Copy the body into `sink` as it is read.
+    ///
+    /// The returned response reads as this one would, with `read`, `json`,
+    /// `text` or any other way, and each byte it returns is first written to
+    /// `sink`, so the sink gets the whole body once it has been read, as
+    /// decoded, such as for keeping the exact response that was parsed. The
+    /// sink is flushed when the end of the body is read, but not if the
+    /// response is dropped before that.
+    ///
+    /// # Errors
+    ///
+    /// If writing to or flushing `sink` fails, reading fails with an
+    /// `io::Error` of the same kind, which converts to `Error::TeeSink`, as
+    /// `json` and `text` return. The body can't be read any further.
+    ///
+    /// ```no_run
+    /// # use std::collections::HashMap;
+    /// # use std::fs::File;
+    /// # fn run() -> Result<(), Box<std::error::Error>> {
+    /// let audit = try!(File::create("response.json"));
+    /// let mut res = try!(reqwest::get("http://httpbin.org/ip")).tee(audit);
+    /// let ip: HashMap<String, String> = try!(res.json());
+    /// # drop(ip);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn tee<W: Write + Send + 'static>(mut self, sink: W) -> Response {
+        self.tee = Some(Tee {
+            sink: Box::new(sink),
+            failed: false,
+            flushed: false,
+        });
+        self
+    }
+
+    /// Read the response body as text.
+    ///
+    /// A body with an `ISO-8859-1` charset in its `Content-Type` is decoded
+    /// as such. Any other body is decoded as UTF-8, with each invalid
+    /// sequence replaced by `U+FFFD REPLACEMENT CHARACTER`, even if its
+    /// charset is one that isn't supported. Use `text_strict` to fail
+    /// instead.
+    pub fn text(&mut self) -> ::Result<String> {
+        let mut body = Vec::new();
+        try!(self.read_to_end(&mut body));
+        decode_text(body, self.charset().as_ref().map(|c| &**c), false)
+    }
+
+    /// Read the response body as text, failing if it isn't valid in its
+    /// charset.
+    ///
+    /// The charset is from the `Content-Type`, and is UTF-8 if there is
+    /// none. `UTF-8`, `US-ASCII` and `ISO-8859-1` are supported, and any
+    /// other fails with `Error::UnsupportedCharset`. A body that isn't
+    /// valid fails with `Error::InvalidText`, with the offset of the first
+    /// invalid byte.
+    pub fn text_strict(&mut self) -> ::Result<String> {
+        let mut body = Vec::new();
+        try!(self.read_to_end(&mut body));
+        decode_text(body, self.charset().as_ref().map(|c| &**c), true)
+    }
+
+    /// Copy the body into a series of writers, each getting at most
+    /// `chunk_size` bytes, such as files of a capped size.
+    ///
+    /// `open` is called with the index of each chunk, starting at 0, once
+    /// there is data for it, so an empty body opens none. Each writer is
+    /// flushed and dropped before the next one is opened, and the body is
+    /// never buffered beyond a small read buffer. The bytes are those that
+    /// `read` returns, so a compressed body is split after decoding.
+    ///
+    /// # Errors
+    ///
+    /// If reading the body, or opening, writing or flushing a chunk fails,
+    /// the error is `Error::RotatedChunk`, with the index of the chunk and
+    /// how many bytes it had been given. A `chunk_size` of 0 fails with
+    /// `Error::ZeroChunkSize`, before any of the body is read.
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use std::io::Write;
+    ///
+    /// let mut res = reqwest::get("http://example.com/export.log").unwrap();
+    /// let summary = res.copy_to_rotating(64 * 1024 * 1024, |index| {
+    ///     let file = try!(File::create(format!("export.{}.log", index)));
+    ///     Ok(Box::new(file) as Box<Write>)
+    /// }).unwrap();
+    /// println!("{} bytes in {} files", summary.total_bytes(), summary.chunks());
+    /// ```
+    pub fn copy_to_rotating<F>(&mut self, chunk_size: u64, mut open: F)
+        -> ::Result<RotationSummary>
+    where F: FnMut(usize) -> io::Result<Box<Write>> {
+        if chunk_size == 0 {
+            return Err(::Error::ZeroChunkSize);
+        }
+        let failed = |index, written, err: io::Error| {
+            ::Error::RotatedChunk(index, written, Box::new(::Error::from(err)))
+        };
+
+        let mut buf = [0; 8192];
+        let mut summary = RotationSummary {
+            chunks: 0,
+            total_bytes: 0,
+        };
+        // the chunk being written, with how many bytes it has
+        let mut current: Option<(Box<Write>, u64)> = None;
+        loop {
+            let index = summary.chunks.saturating_sub(1);
+            let written = current.as_ref().map_or(0, |&(_, written)| written);
+            let n = match self.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(failed(index, written, err)),
+            };
+            let mut data = &buf[..n];
+            while !data.is_empty() {
+                if current.as_ref().map_or(true, |&(_, written)| written == chunk_size) {
+                    if let Some((mut full, written)) = current.take() {
+                        try!(full.flush().map_err(|e| failed(summary.chunks - 1, written, e)));
+                    }
+                    let index = summary.chunks;
+                    let writer = try!(open(index).map_err(|e| failed(index, 0, e)));
+                    current = Some((writer, 0));
+                    summary.chunks += 1;
+                }
+                let index = summary.chunks - 1;
+                let &mut (ref mut writer, ref mut written) = current.as_mut().unwrap();
+                let room = chunk_size - *written;
+                let len = ::std::cmp::min(room, data.len() as u64) as usize;
+                // written a bit at a time, so a failure says exactly how much got in
+                let n = match writer.write(&data[..len]) {
+                    Ok(0) => {
+                        let e = io::Error::new(io::ErrorKind::WriteZero,
+                                               "chunk accepted no more data");
+                        return Err(failed(index, *written, e));
+                    },
+                    Ok(n) => n,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(failed(index, *written, e)),
+                };
+                *written += n as u64;
+                summary.total_bytes += n as u64;
+                data = &data[n..];
+            }
+        }
+        if let Some((mut last, written)) = current {
+            try!(last.flush().map_err(|e| failed(summary.chunks - 1, written, e)));
+        }
+        Ok(summary)
+    }
+
+    /// Read and discard the rest of the body, so that the connection goes
+    /// back to the pool right away.
+    ///
+    /// Unlike dropping the response, this reads a body of any length, even
+    /// one larger than `ClientBuilder::drain_on_drop` allows, and a compressed body
+    /// is decoded to its end, then read to the end of the message. What was
+    /// already read, such as the start of the body, is fine.
+    ///
+    /// Returns the trailers of a chunked body, as with `trailers`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if reading the body fails, such as with a timeout, or if it
+    /// can't be decoded. The connection is then closed.
+    ///
+    /// ```no_run
+    /// # fn run() -> Result<(), Box<std::error::Error>> {
+    /// let client = try!(reqwest::Client::new());
+    /// let res = try!(client.get("http://httpbin.org/bytes/65536").send());
+    /// if res.status().is_success() {
+    ///     if let Some(trailers) = try!(res.drain()) {
+    ///         println!("trailers: {:?}", trailers);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn drain(mut self) -> ::Result<Option<Headers>> {
+        try!(io::copy(&mut self, &mut io::sink()));
+        // a decoder stops at the end of its stream, which may be before
+        // the end of the message, such as the last chunk
+        if let Some(mut raw) = self.take_decoder().into_raw() {
+            try!(io::copy(&mut raw, &mut io::sink()));
+        }
+        Ok(self.trailers())
+    }
+
+    /// Get the trailers of a chunked body, the header fields sent after it,
+    /// once the body was read to its end.
+    ///
+    /// This is `None` until then, and for a body that isn't chunked, and
+    /// empty for a chunked body without any. A response from
+    /// `Client::request_over` has none.
+    pub fn trailers(&self) -> Option<Headers> {
+        self.connection.framing.trailers()
+    }
+
+    /// Close the connection of the response, instead of letting it go back
+    /// to the pool.
+    ///
+    /// The rest of the body isn't read, and the next request to the same
+    /// host makes a new connection, even if the whole body was already
+    /// read. This is for a response that suggests something went wrong with
+    /// the connection or the server behind it.
+    ///
+    /// A stream given to `Client::request_over` isn't closed, since it
+    /// isn't the client's.
+    pub fn close(mut self) {
+        self.connection.upload.abort(None);
+        // dropped before anything of the body could be drained
+        drop(self.take_decoder());
+    }
+
+    /// Takes the decoder out of the response, leaving one that fails to
+    /// read.
+    fn take_decoder(&mut self) -> Decoder {
+        let placeholder = Decoder::Errored {
+            err: None,
+            head: Head {
+                headers: self.headers().clone(),
+                url: self.url().clone(),
+                version: *self.version(),
+                status: *self.status(),
+            },
+        };
+        mem::replace(&mut self.inner, placeholder)
+    }
+
+    fn charset(&self) -> Option<String> {
+        self.headers().get::<ContentType>()
+            .and_then(|content_type| content_type.get_param(Attr::Charset))
+            .map(|charset| charset.to_string())
+    }
+}
+
+/// Decodes `body` as text in `charset`, or UTF-8 if there is none.
+fn decode_text(body: Vec<u8>, charset: Option<&str>, strict: bool) -> ::Result<String> {
+    let charset = charset.unwrap_or("utf-8");
+    let is = |names: &[&str]| names.iter().any(|name| name.eq_ignore_ascii_case(charset));
+    if is(&["iso-8859-1", "latin1"]) {
+        return Ok(body.iter().map(|&b| b as char).collect());
+    }
+    if !strict {
+        return Ok(match String::from_utf8(body) {
+            Ok(text) => text,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        });
+    }
+    if is(&["us-ascii", "ascii"]) {
+        if let Some(offset) = body.iter().position(|&b| b >= 0x80) {
+            return Err(::Error::InvalidText(charset.to_owned(), offset));
+        }
+    } else if !is(&["utf-8", "utf8"]) {
+        return Err(::Error::UnsupportedCharset(charset.to_owned()));
+    }
+    String::from_utf8(body).map_err(|e| {
+        ::Error::InvalidText(charset.to_owned(), e.utf8_error().valid_up_to())
+    })
+}
+
+/// The head of a response whose body was discarded, from
+/// `RequestBuilder::send_and_discard`.
+#[derive(Clone)]
+pub struct ResponseHead {
+    url: Url,
+    status: StatusCode,
+    headers: Headers,
+    version: HttpVersion,
+}
+
+impl ResponseHead {
+    /// Get the final `Url` of this response.
+    #[inline]
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Get the `StatusCode`.
+    #[inline]
+    pub fn status(&self) -> &StatusCode {
+        &self.status
+    }
+
+    /// Get the status as a number, such as `404`.
+    #[inline]
+    pub fn status_u16(&self) -> u16 {
+        self.status.to_u16()
+    }
+
+    /// Whether the status is a success (2xx).
+    #[inline]
+    pub fn is_success(&self) -> bool {
+        self.status.is_success()
+    }
+
+    /// Whether the status is a redirect (3xx).
+    #[inline]
+    pub fn is_redirect(&self) -> bool {
+        self.status.is_redirection()
+    }
+
+    /// Whether the status is a client error (4xx).
+    #[inline]
+    pub fn is_client_error(&self) -> bool {
+        self.status.is_client_error()
+    }
+
+    /// Whether the status is a server error (5xx).
+    #[inline]
+    pub fn is_server_error(&self) -> bool {
+        self.status.is_server_error()
+    }
+
+    /// Get the `Headers`.
+    ///
+    /// A `Content-Encoding` is left as the server sent it, since the body
+    /// wasn't decoded.
+    #[inline]
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Get the `HttpVersion`.
+    #[inline]
+    pub fn version(&self) -> &HttpVersion {
+        &self.version
+    }
+}
+
+impl fmt::Debug for ResponseHead {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ResponseHead")
+            .field("url", &self.url)
+            .field("status", &self.status)
+            .field("headers", &DebugHeaders(&self.headers))
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+/// The CORS headers of a `Response`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CorsInfo {
+    allow_origin: String,
+    allow_methods: Vec<Method>,
+    allow_headers: Vec<String>,
+    max_age: Option<Duration>,
+}
+
+impl CorsInfo {
+    /// The allowed origin, which may be `*`.
+    #[inline]
+    pub fn allow_origin(&self) -> &str {
+        &self.allow_origin
+    }
+
+    /// The methods from `Access-Control-Allow-Methods`.
+    #[inline]
+    pub fn allow_methods(&self) -> &[Method] {
+        &self.allow_methods
+    }
+
+    /// The lowercased header names from `Access-Control-Allow-Headers`.
+    #[inline]
+    pub fn allow_headers(&self) -> &[String] {
+        &self.allow_headers
+    }
+
+    /// How long the preflight results may be cached, from `Access-Control-Max-Age`.
+    #[inline]
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+}
+
+/// Statistics on the connection a request was sent on.
+///
+/// See `Response::stats`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestStats {
+    connection_reused: bool,
+    remote_addr: SocketAddr,
+    resolved_addrs: Option<Vec<SocketAddr>>,
+    resolved_addrs_pinned: bool,
+}
+
+impl RequestStats {
+    /// Whether the request was sent on a connection from the pool.
+    ///
+    /// See `Response::connection_reused`.
+    #[inline]
+    pub fn connection_reused(&self) -> bool {
+        self.connection_reused
+    }
+
+    /// The address of the server the response came from.
+    ///
+    /// See `Response::remote_addr`.
+    #[inline]
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+
+    /// All addresses the host resolved to, when the connection was made.
+    ///
+    /// See `Response::resolved_addrs`.
+    #[inline]
+    pub fn resolved_addrs(&self) -> Option<&[SocketAddr]> {
+        self.resolved_addrs.as_ref().map(|addrs| &addrs[..])
+    }
+
+    /// Whether the `resolved_addrs` are those the host is pinned to.
+    ///
+    /// See `Response::resolved_addrs_pinned`.
+    #[inline]
+    pub fn resolved_addrs_pinned(&self) -> bool {
+        self.resolved_addrs_pinned
     }
 }
 
-impl fmt::Debug for Response {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.inner {
-            Decoder::PlainText(ref hyper_response) => {
-                f.debug_struct("Response")
-                    .field("url", &hyper_response.url)
-                    .field("status", &hyper_response.status)
-                    .field("headers", &hyper_response.headers)
-                    .field("version", &hyper_response.version)
-                    .finish()
-            },
-            Decoder::Gzip{ ref head, .. } |
-            Decoder::Errored { ref head, .. } => {
-                f.debug_struct("Response")
-                    .field("url", &head.url)
-                    .field("status", &head.status)
-                    .field("headers", &head.headers)
-                    .field("version", &head.version)
-                    .finish()
-            }
-        }
-    }
+/// What `Response::copy_to_rotating` wrote.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RotationSummary {
+    chunks: usize,
+    total_bytes: u64,
 }
 
-impl Response {
-    /// Get the final `Url` of this response.
+impl RotationSummary {
+    /// How many chunks were opened. Each one but the last is full.
     #[inline]
-    pub fn url(&self) -> &Url {
-        match self.inner {
-            Decoder::PlainText(ref hyper_response) => &hyper_response.url,
-            Decoder::Gzip{ ref head, .. } |
-            Decoder::Errored { ref head, .. } => &head.url,
-        }
+    pub fn chunks(&self) -> usize {
+        self.chunks
     }
 
-    /// Get the `StatusCode`.
+    /// How many bytes were written, over all of the chunks.
     #[inline]
-    pub fn status(&self) -> &StatusCode {
-        match self.inner {
-            Decoder::PlainText(ref hyper_response) => &hyper_response.status,
-            Decoder::Gzip{ ref head, .. } |
-            Decoder::Errored { ref head, .. } => &head.status,
-        }
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
     }
+}
 
-    /// Get the `Headers`.
+/// The byte range of a partial response, from its `Content-Range` header.
+///
+/// See `Response::content_range`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContentRange {
+    range: Option<(u64, u64)>,
+    total: Option<u64>,
+}
+
+impl ContentRange {
+    /// The offset of the first byte in the body.
+    ///
+    /// This is `None` for an unsatisfied range, `bytes */1234`, which comes
+    /// with a `416 Range Not Satisfiable`.
     #[inline]
-    pub fn headers(&self) -> &Headers {
-        match self.inner {
-            Decoder::PlainText(ref hyper_response) => &hyper_response.headers,
-            Decoder::Gzip{ ref head, .. } |
-            Decoder::Errored { ref head, .. } => &head.headers,
-        }
+    pub fn start(&self) -> Option<u64> {
+        self.range.map(|(start, _)| start)
     }
 
-    /// Get the `HttpVersion`.
+    /// The offset of the last byte in the body, which is inclusive.
     #[inline]
-    pub fn version(&self) -> &HttpVersion {
-        match self.inner {
-            Decoder::PlainText(ref hyper_response) => &hyper_response.version,
-            Decoder::Gzip{ ref head, .. } |
-            Decoder::Errored { ref head, .. } => &head.version,
-        }
+    pub fn end(&self) -> Option<u64> {
+        self.range.map(|(_, end)| end)
     }
 
-    /// Try and deserialize the response body as JSON.
+    /// The length of the whole representation, if the server knows it.
     #[inline]
-    pub fn json<T: Deserialize>(&mut self) -> ::Result<T> {
-        serde_json::from_reader(self).map_err(::Error::from)
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+}
+
+/// Parses a `Content-Range` of bytes, as in RFC 7233, section 4.2.
+fn parse_content_range(value: &str) -> Option<ContentRange> {
+    let value = value.trim();
+    if !value.starts_with("bytes ") {
+        return None;
+    }
+    let mut parts = value["bytes ".len()..].splitn(2, '/');
+    let (range, total) = match (parts.next(), parts.next()) {
+        (Some(range), Some(total)) => (range, total),
+        _ => return None,
+    };
+    let total = match total {
+        "*" => None,
+        total => match parse_digits(total) {
+            Some(total) => Some(total),
+            None => return None,
+        },
+    };
+    let range = match range {
+        // the length has to be known for an unsatisfied range
+        "*" if total.is_some() => None,
+        "*" => return None,
+        range => {
+            let mut bounds = range.splitn(2, '-');
+            let (start, end) = match (bounds.next().and_then(parse_digits),
+                                      bounds.next().and_then(parse_digits)) {
+                (Some(start), Some(end)) => (start, end),
+                _ => return None,
+            };
+            if end < start || total.map_or(false, |total| end >= total) {
+                return None;
+            }
+            Some((start, end))
+        },
+    };
+    Some(ContentRange {
+        range: range,
+        total: total,
+    })
+}
+
+fn parse_digits(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+fn header_all<'a>(headers: &'a Headers, name: &str) -> Vec<&'a [u8]> {
+    headers.get_raw(name).map_or(Vec::new(), |values| {
+        values.iter().map(|value| &value[..]).collect()
+    })
+}
+
+/// Decodes each byte of `value` as the character of the same number.
+fn decode_latin1(value: &[u8]) -> String {
+    value.iter().map(|&b| b as char).collect()
+}
+
+/// Decodes a header value as UTF-8, or as ISO-8859-1 if it isn't valid
+/// UTF-8, so that any bytes make some text.
+pub fn decode_value(value: &[u8]) -> String {
+    match ::std::str::from_utf8(value) {
+        Ok(value) => value.to_owned(),
+        Err(_) => decode_latin1(value),
+    }
+}
+
+/// Gets the values of the header `name`, joined with commas, as text.
+///
+/// Unlike `HeaderView::value_string`, this doesn't panic on a value that
+/// isn't UTF-8.
+pub fn value_string(headers: &Headers, name: &str) -> String {
+    header_all(headers, name).into_iter().map(decode_value).collect::<Vec<_>>().join(", ")
+}
+
+/// Formats headers whose values may not be UTF-8, which `Headers` fails to.
+struct DebugHeaders<'a>(&'a Headers);
+
+impl<'a> fmt::Debug for DebugHeaders<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.iter().map(|header| {
+                (header.name(), value_string(self.0, header.name()))
+            }))
+            .finish()
+    }
+}
+
+/// Splits all lines of a comma separated header into trimmed, non-empty values.
+fn parse_list(raw: Option<&[Vec<u8>]>) -> Vec<String> {
+    raw.unwrap_or(&[])
+        .iter()
+        .flat_map(|line| String::from_utf8_lossy(line).split(',')
+            .map(|s| s.trim().to_owned())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>())
+        .collect()
+}
+
+fn parse_methods(raw: Option<&[Vec<u8>]>) -> Vec<Method> {
+    parse_list(raw)
+        .into_iter()
+        .filter_map(|method| method.to_ascii_uppercase().parse().ok())
+        .fold(Vec::new(), dedup)
+}
+
+fn dedup<T: PartialEq>(mut list: Vec<T>, item: T) -> Vec<T> {
+    if !list.contains(&item) {
+        list.push(item);
     }
+    list
 }
 
 enum Decoder {
@@ -101,6 +1353,26 @@ enum Decoder {
         decoder: gzip::Decoder<Peeked>,
         head: Head,
     },
+    /// A `Deflate` decoder will uncompress a `deflate` content or transfer
+    /// coding.
+    Deflate {
+        decoder: Inflater,
+        head: Head,
+    },
+    /// A `Brotli` decoder will uncompress a `br` content coding.
+    #[cfg(feature = "brotli")]
+    Brotli {
+        decoder: ::brotli_decompressor::Decompressor<Peeked>,
+        head: Head,
+    },
+    /// A `Buffered` body was read into memory, maybe followed by the `rest`
+    /// of it that wasn't.
+    Buffered {
+        body: Arc<Vec<u8>>,
+        pos: usize,
+        rest: Option<Box<Decoder>>,
+        head: Head,
+    },
     /// An error occured reading the Gzip header, so return that error
     /// when the user tries to read on the `Response`.
     Errored {
@@ -110,89 +1382,298 @@ enum Decoder {
 }
 
 impl Decoder {
+    /// Gets the undecoded body, which is at the end of what the decoder
+    /// read, or `None` if it wasn't from a connection or failed.
+    fn into_raw(self) -> Option<::hyper::client::Response> {
+        match self {
+            Decoder::PlainText(res) => Some(res),
+            Decoder::Gzip { decoder, .. } => Some(decoder.into_inner().inner),
+            Decoder::Deflate { decoder: Inflater::Zlib(decoder), .. } => {
+                Some(decoder.into_inner().inner)
+            },
+            Decoder::Deflate { decoder: Inflater::Raw(decoder), .. } => {
+                Some(decoder.into_inner().inner)
+            },
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli { decoder, .. } => Some(decoder.into_inner().inner),
+            Decoder::Buffered { rest, .. } => rest.and_then(|rest| rest.into_raw()),
+            Decoder::Errored { .. } => None,
+        }
+    }
+
     /// Constructs a Decoder from a hyper request.
     ///
     /// A decoder is just a wrapper around the hyper request that knows
     /// how to decode the content body of the request.
     ///
-    /// Uses the correct variant by inspecting the Content-Encoding header.
-    fn from_hyper_response(mut res: ::hyper::client::Response, check_gzip: bool) -> Self {
-        if !check_gzip {
-            return Decoder::PlainText(res);
-        }
-        let content_encoding_gzip: bool;
-        let mut is_gzip = {
-            content_encoding_gzip = res.headers.get::<ContentEncoding>().map_or(false, |encs|{
-                encs.contains(&Encoding::Gzip)
-            });
-            content_encoding_gzip || res.headers.get::<TransferEncoding>().map_or(false, |encs|{
-                encs.contains(&Encoding::Gzip)
-            })
+    /// Uses the correct variant by inspecting the Content-Encoding header,
+    /// for the `content_codings` to decode, and the Transfer-Encoding header
+    /// when `transfer_codings` is enabled.
+    fn from_hyper_response(mut res: ::hyper::client::Response, content_codings: &[Encoding],
+                           sniff_content: bool, transfer_codings: bool, deflate: DeflateOptions)
+                           -> Self {
+        // hyper already took care of a final `chunked`
+        let codings = match res.headers.get::<TransferEncoding>() {
+            Some(&TransferEncoding(ref encs)) => {
+                encs.iter().filter(|enc| **enc != Encoding::Chunked).cloned().collect::<Vec<_>>()
+            },
+            None => Vec::new(),
         };
-        if is_gzip {
+        if !codings.is_empty() {
+            return Decoder::from_transfer_codings(res, codings, transfer_codings, deflate);
+        }
+
+        let mut coding = res.headers.get::<ContentEncoding>().and_then(|encs| {
+            content_codings.iter().find(|enc| encs.contains(enc)).cloned()
+        });
+        if coding.is_some() {
             if let Some(content_length) = res.headers.get::<ContentLength>() {
                 if content_length.0 == 0 {
-                    warn!("GZipped response with content-length of 0");
-                    is_gzip = false;
+                    warn!("encoded response with content-length of 0");
+                    coding = None;
                 }
             }
         }
-        if content_encoding_gzip {
-            res.headers.remove::<ContentEncoding>();
-            res.headers.remove::<ContentLength>();
+        match coding {
+            Some(coding) => {
+                res.headers.remove::<ContentEncoding>();
+                res.headers.remove::<ContentLength>();
+                match coding {
+                    Encoding::Gzip => new_gzip(res),
+                    #[cfg(feature = "brotli")]
+                    Encoding::EncodingExt(ref ext) if ext == "br" => new_brotli(res),
+                    _ if sniff_content => new_deflate(res, DeflateOptions {
+                        sniff: true,
+                        ..deflate
+                    }),
+                    _ => new_deflate(res, deflate),
+                }
+            },
+            None => Decoder::PlainText(res),
+        }
+    }
+
+    /// Decodes a `gzip` or `deflate` transfer coding, so that the body
+    /// reads as if it was only chunked.
+    fn from_transfer_codings(mut res: ::hyper::client::Response, codings: Vec<Encoding>,
+                             enabled: bool, deflate: DeflateOptions) -> Self {
+        let supported = enabled && codings.len() == 1 &&
+            (codings[0] == Encoding::Gzip || codings[0] == Encoding::Deflate);
+        if !supported {
+            let names = codings.iter().map(|enc| enc.to_string()).collect::<Vec<_>>();
+            let msg = if enabled {
+                format!("unsupported transfer coding: {}", names.join(", "))
+            } else {
+                format!("transfer coding {} is only decoded with \
+                         Client::accept_transfer_encodings", names.join(", "))
+            };
+            return Decoder::Errored {
+                err: Some(io::Error::new(io::ErrorKind::InvalidData, msg)),
+                head: Head::from(&res),
+            };
         }
-        if is_gzip {
-            new_gzip(res)
+
+        let chunked = res.headers.get::<TransferEncoding>()
+            .map_or(false, |encs| encs.last() == Some(&Encoding::Chunked));
+        if chunked {
+            res.headers.set(TransferEncoding(vec![Encoding::Chunked]));
         } else {
-            Decoder::PlainText(res)
+            res.headers.remove::<TransferEncoding>();
+        }
+        res.headers.remove::<ContentLength>();
+        match codings[0] {
+            Encoding::Gzip => new_gzip(res),
+            _ => new_deflate(res, deflate),
+        }
+    }
+}
+
+fn new_gzip(res: ::hyper::client::Response) -> Decoder {
+    let (reader, head) = match peek(res) {
+        Ok(peeked) => peeked,
+        Err(decoder) => return decoder,
+    };
+    match gzip::Decoder::new(reader) {
+        Ok(gzip) => Decoder::Gzip {
+            decoder: gzip,
+            head: head,
+        },
+        Err(e) => Decoder::Errored {
+            err: Some(decompress_error(e)),
+            head: head,
+        }
+    }
+}
+
+fn new_deflate(res: ::hyper::client::Response, options: DeflateOptions) -> Decoder {
+    let (mut reader, head) = match peek(res) {
+        Ok(peeked) => peeked,
+        Err(decoder) => return decoder,
+    };
+    let raw = if options.sniff {
+        match reader.peek_more() {
+            Ok(()) => !is_zlib_header(&reader.peeked),
+            Err(e) => {
+                return Decoder::Errored {
+                    err: Some(e),
+                    head: head,
+                };
+            },
+        }
+    } else {
+        options.raw
+    };
+    if raw {
+        return Decoder::Deflate {
+            decoder: Inflater::Raw(deflate::Decoder::new(reader)),
+            head: head,
+        };
+    }
+    let zlib = match zlib::Decoder::new(reader) {
+        Ok(zlib) => zlib,
+        Err(e) => {
+            return Decoder::Errored {
+                err: Some(decompress_error(e)),
+                head: head,
+            };
+        },
+    };
+    let window = zlib.header().window_size().to_u16() as u32;
+    if window > 1 << options.window_bits {
+        let msg = format!("zlib window of {} bytes is larger than the {} bytes allowed",
+                          window, 1u32 << options.window_bits);
+        return Decoder::Errored {
+            err: Some(decompress_error(io::Error::new(io::ErrorKind::InvalidData, msg))),
+            head: head,
+        };
+    }
+    Decoder::Deflate {
+        decoder: Inflater::Zlib(zlib),
+        head: head,
+    }
+}
+
+#[cfg(feature = "brotli")]
+fn new_brotli(res: ::hyper::client::Response) -> Decoder {
+    let (reader, head) = match peek(res) {
+        Ok(peeked) => peeked,
+        Err(decoder) => return decoder,
+    };
+    Decoder::Brotli {
+        decoder: ::brotli_decompressor::Decompressor::new(reader, BROTLI_BUFFER_SIZE),
+        head: head,
+    }
+}
+
+#[cfg(feature = "brotli")]
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// The content codings that are decoded when `Client::gzip` is on, and
+/// that requests ask for with `Accept-Encoding`, in order.
+///
+/// `br` is only one of them with the `brotli` feature.
+pub fn auto_codings() -> Vec<Encoding> {
+    let mut codings = vec![Encoding::Gzip, Encoding::Deflate];
+    if cfg!(feature = "brotli") {
+        codings.push(brotli());
+    }
+    codings
+}
+
+/// The `br` content coding, which hyper has no variant for.
+pub fn brotli() -> Encoding {
+    Encoding::EncodingExt("br".to_owned())
+}
+
+/// Whether `start` is a zlib header, with the `deflate` method, a valid
+/// window size, and a check that is a multiple of 31.
+fn is_zlib_header(start: &[u8]) -> bool {
+    if start.len() < 2 {
+        return false;
+    }
+    let (cmf, flg) = (start[0], start[1]);
+    cmf & 0x0f == 8 && cmf >> 4 <= 7 && ((cmf as u16) << 8 | flg as u16) % 31 == 0
+}
+
+/// How `deflate` content and transfer codings are decoded.
+///
+/// See `ClientBuilder::deflate_options`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeflateOptions {
+    /// Decode raw deflate streams (RFC 1951), instead of streams wrapped in
+    /// zlib (RFC 1950), which is what `deflate` is meant to be.
+    ///
+    /// Ignored with `sniff`.
+    pub raw: bool,
+    /// The base-2 logarithm of the largest window a zlib stream may use,
+    /// from 8 to 15.
+    ///
+    /// A zlib stream whose header asks for a larger window fails to decode.
+    /// A raw stream has no header to check, and is decoded with a window of
+    /// up to 32KB, which also fits any smaller window it was made with.
+    pub window_bits: u8,
+    /// Tell zlib and raw streams apart by their first two bytes, instead of
+    /// going by `raw`.
+    ///
+    /// A raw stream only rarely starts like a zlib header, but when it does,
+    /// it is decoded as zlib, and fails.
+    pub sniff: bool,
+}
+
+impl Default for DeflateOptions {
+    fn default() -> DeflateOptions {
+        DeflateOptions {
+            raw: false,
+            window_bits: 15,
+            sniff: false,
+        }
+    }
+}
+
+/// The decoder of a `deflate` body, with or without the zlib wrapper.
+enum Inflater {
+    Zlib(zlib::Decoder<Peeked>),
+    Raw(deflate::Decoder<Peeked>),
+}
+
+impl Read for Inflater {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Inflater::Zlib(ref mut decoder) => decoder.read(buf),
+            Inflater::Raw(ref mut decoder) => decoder.read(buf),
         }
     }
 }
 
-fn new_gzip(mut res: ::hyper::client::Response) -> Decoder {
+/// Reads the first byte of the body, before a decoder is started.
+///
+/// An empty body, or an error, is returned as the `Decoder` to use instead.
+fn peek(mut res: ::hyper::client::Response) -> Result<(Peeked, Head), Decoder> {
     // libflate does a read_exact([0; 2]), so its impossible to tell
     // if the stream was empty, or truly had an UnexpectedEof.
     // Therefore, we need to peek a byte to make check for EOF first.
     let mut peek = [0];
     match res.read(&mut peek) {
-        Ok(0) => return Decoder::PlainText(res),
+        Ok(0) => return Err(Decoder::PlainText(res)),
         Ok(n) => {
             debug_assert_eq!(n, 1);
         },
-        Err(e) => return Decoder::Errored {
+        Err(e) => return Err(Decoder::Errored {
             err: Some(e),
-            head: Head {
-                headers: res.headers.clone(),
-                status: res.status,
-                url: res.url.clone(),
-                version: res.version,
-            }
-        },
+            head: Head::from(&res),
+        }),
     }
 
-    let head = Head {
-        headers: res.headers.clone(),
-        status: res.status,
-        url: res.url.clone(),
-        version: res.version,
-    };
-
+    let head = Head::from(&res);
     let reader = Peeked {
-        peeked: Some(peek[0]),
+        peeked: vec![peek[0]],
         inner: res,
+        eof: false,
     };
-    match gzip::Decoder::new(reader) {
-        Ok(gzip) => Decoder::Gzip {
-            decoder: gzip,
-            head: head,
-        },
-        Err(e) => Decoder::Errored {
-            err: Some(e),
-            head: head,
-        }
-    }
+    Ok((reader, head))
 }
 
+#[derive(Clone)]
 struct Head {
     headers: ::hyper::header::Headers,
     url: ::hyper::Url,
@@ -200,9 +1681,44 @@ struct Head {
     status: ::hyper::status::StatusCode,
 }
 
+impl<'a> From<&'a ::hyper::client::Response> for Head {
+    fn from(res: &'a ::hyper::client::Response) -> Head {
+        Head {
+            headers: res.headers.clone(),
+            status: res.status,
+            url: res.url.clone(),
+            version: res.version,
+        }
+    }
+}
+
 struct Peeked {
-    peeked: Option<u8>,
+    // read ahead, not yet given to the decoder
+    peeked: Vec<u8>,
     inner: ::hyper::client::Response,
+    // the body ended, which the brotli decoder doesn't tell from bad data
+    eof: bool,
+}
+
+impl Peeked {
+    /// Reads one more byte ahead, unless the body already ended.
+    fn peek_more(&mut self) -> io::Result<()> {
+        let mut more = [0];
+        loop {
+            match self.inner.read(&mut more) {
+                Ok(0) => {
+                    self.eof = true;
+                    return Ok(());
+                },
+                Ok(_) => {
+                    self.peeked.push(more[0]);
+                    return Ok(());
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
 }
 
 impl Read for Peeked {
@@ -211,13 +1727,84 @@ impl Read for Peeked {
         if buf.is_empty() {
             return Ok(0);
         }
-        if let Some(byte) = self.peeked.take() {
-            buf[0] = byte;
-            Ok(1)
+        if !self.peeked.is_empty() {
+            let n = ::std::cmp::min(buf.len(), self.peeked.len());
+            buf[..n].copy_from_slice(&self.peeked[..n]);
+            self.peeked.drain(..n);
+            Ok(n)
         } else {
-            self.inner.read(buf)
+            match self.inner.read(buf) {
+                Ok(0) => {
+                    self.eof = true;
+                    Ok(0)
+                },
+                Ok(n) => Ok(n),
+                Err(e) => Err(io::Error::new(e.kind(), BodyError(e))),
+            }
+        }
+    }
+}
+
+/// An error reading the body under a decoder, such as a timeout, which is
+/// passed on as it is by `decompress_error`.
+#[derive(Debug)]
+struct BodyError(io::Error);
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl ::std::error::Error for BodyError {
+    fn description(&self) -> &str {
+        self.0.description()
+    }
+}
+
+/// Makes an error of a decoder into an `Error::Decompress`, with its
+/// reason, unless it is from reading the body itself, other than the body
+/// ending early.
+fn decompress_error(e: io::Error) -> io::Error {
+    if e.get_ref().map_or(false, |inner| inner.is::<BodyError>()) {
+        let e = match e.into_inner().map(|inner| inner.downcast::<BodyError>()) {
+            Some(Ok(inner)) => inner.0,
+            _ => unreachable!(),
+        };
+        // the body ending before its `Content-Length` cuts the compressed
+        // stream short too
+        if e.kind() != io::ErrorKind::UnexpectedEof && e.to_string() != "early eof" {
+            return e;
         }
+        let reason = DecompressReason::UnexpectedEof;
+        return io::Error::new(io::ErrorKind::UnexpectedEof, ::Error::Decompress(reason, e));
+    }
+    // libflate has no error types, only its messages tell a bad checksum
+    // apart from other invalid data
+    let reason = match e.kind() {
+        io::ErrorKind::UnexpectedEof => DecompressReason::UnexpectedEof,
+        _ => {
+            let msg = e.to_string();
+            if msg.contains("CRC32") || msg.contains("Adler32") {
+                DecompressReason::CrcMismatch
+            } else {
+                DecompressReason::InvalidData
+            }
+        },
+    };
+    io::Error::new(e.kind(), ::Error::Decompress(reason, e))
+}
+
+/// Like `decompress_error`, for the brotli decoder, which fails with
+/// `InvalidData` for a stream that the body ended in the middle of too.
+#[cfg(feature = "brotli")]
+fn brotli_error(e: io::Error, eof: bool) -> io::Error {
+    if eof && e.kind() == io::ErrorKind::InvalidData {
+        let reason = DecompressReason::UnexpectedEof;
+        let e = io::Error::new(io::ErrorKind::UnexpectedEof, e);
+        return io::Error::new(e.kind(), ::Error::Decompress(reason, e));
     }
+    decompress_error(e)
 }
 
 impl Read for Decoder {
@@ -227,7 +1814,30 @@ impl Read for Decoder {
                 hyper_response.read(buf)
             },
             Decoder::Gzip{ref mut decoder, ..} => {
-                decoder.read(buf)
+                decoder.read(buf).map_err(decompress_error)
+            },
+            Decoder::Deflate { ref mut decoder, .. } => {
+                decoder.read(buf).map_err(decompress_error)
+            },
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli { ref mut decoder, .. } => {
+                match decoder.read(buf) {
+                    Ok(n) => Ok(n),
+                    Err(e) => Err(brotli_error(e, decoder.get_ref().eof)),
+                }
+            },
+            Decoder::Buffered { ref body, ref mut pos, ref mut rest, .. } => {
+                if *pos < body.len() {
+                    let n = ::std::cmp::min(buf.len(), body.len() - *pos);
+                    buf[..n].copy_from_slice(&body[*pos..*pos + n]);
+                    *pos += n;
+                    Ok(n)
+                } else {
+                    match *rest {
+                        Some(ref mut rest) => rest.read(buf),
+                        None => Ok(0),
+                    }
+                }
             },
             Decoder::Errored { ref mut err, .. } => {
                 Err(err.take().unwrap_or_else(previously_errored))
@@ -241,11 +1851,220 @@ fn previously_errored() -> io::Error {
     io::Error::new(io::ErrorKind::Other, "permanently errored")
 }
 
+impl Drop for Response {
+    fn drop(&mut self) {
+        self.release.end_request();
+        let res = match self.inner {
+            Decoder::PlainText(ref mut res) => res,
+            // decoded bodies are never drained, hyper closes the connection
+            _ => return,
+        };
+        if !res.get_ref().has_body() {
+            return;
+        }
+
+        let limit = self.release.drain_limit.load(Ordering::Relaxed);
+        let drained = match res.headers.get::<ContentLength>() {
+            Some(&ContentLength(len)) if len <= limit as u64 => drain(res, limit),
+            _ => false,
+        };
+        if drained && !res.get_ref().has_body() {
+            trace!("drained unread response body");
+            self.release.drained.fetch_add(1, Ordering::Relaxed);
+        } else {
+            trace!("closing connection of unread response body");
+            self.release.closed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Reads and discards at most `limit` bytes, returning whether the end was reached.
+fn drain(res: &mut ::hyper::client::Response, limit: usize) -> bool {
+    let mut buf = [0; 4096];
+    let mut total = 0;
+    loop {
+        match res.read(&mut buf) {
+            Ok(0) => return true,
+            Ok(n) => {
+                total += n;
+                if total > limit {
+                    return false;
+                }
+            },
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Where `Response::tee` copies a body to.
+struct Tee {
+    sink: Box<Write + Send>,
+    failed: bool,
+    flushed: bool,
+}
+
+impl Tee {
+    /// Copies what was read into the sink, with an empty `read` for the end
+    /// of the body.
+    fn copy(&mut self, read: &[u8]) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::new(io::ErrorKind::Other,
+                                      ::Error::TeeSink(previously_errored())));
+        }
+        let copied = if !read.is_empty() {
+            self.sink.write_all(read)
+        } else if !self.flushed {
+            self.flushed = true;
+            self.sink.flush()
+        } else {
+            Ok(())
+        };
+        copied.map_err(|e| {
+            self.failed = true;
+            io::Error::new(e.kind(), ::Error::TeeSink(e))
+        })
+    }
+}
+
 /// Read the body of the Response.
 impl Read for Response {
     #[inline]
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner.read(buf)
+        match self.inner.read(buf) {
+            Ok(n) => {
+                if let Some(ref mut tee) = self.tee {
+                    if n > 0 || !buf.is_empty() {
+                        try!(tee.copy(&buf[..n]));
+                    }
+                }
+                self.position += n as u64;
+                Ok(n)
+            },
+            Err(e) => {
+                match self.read_timeout {
+                    Some(timeout) if ::error::is_io_timeout(&e) => {
+                        let during = format!("while reading the response body at byte {}",
+                                             self.position);
+                        Err(io::Error::new(e.kind(),
+                                           ::Error::Timeout(TimeoutKind::Read, timeout, during)))
+                    },
+                    _ => Err(e),
+                }
+            },
+        }
+    }
+}
+
+
+#[test]
+fn test_decode_text() {
+    let decode = |body: &[u8], charset, strict| decode_text(body.to_vec(), charset, strict);
+
+    assert_eq!(decode(b"caf\xc3\xa9", None, true).unwrap(), "caf\u{e9}");
+    assert_eq!(decode(b"caf\xe9", Some("ISO-8859-1"), true).unwrap(), "caf\u{e9}");
+    assert_eq!(decode(b"plain", Some("US-ASCII"), true).unwrap(), "plain");
+
+    assert_eq!(decode(b"ab\xffcd", Some("utf-8"), false).unwrap(), "ab\u{fffd}cd");
+    match decode(b"ab\xffcd", Some("utf-8"), true) {
+        Err(::Error::InvalidText(ref charset, 2)) if charset == "utf-8" => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match decode(b"caf\xc3\xa9", Some("us-ascii"), true) {
+        Err(::Error::InvalidText(ref charset, 3)) if charset == "us-ascii" => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    // a truncated sequence at the end
+    match decode(b"caf\xc3", None, true) {
+        Err(::Error::InvalidText(_, 3)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_value_string() {
+    let mut headers = Headers::new();
+    headers.set_raw("X-Utf8", vec![b"caf\xc3\xa9".to_vec()]);
+    headers.set_raw("X-Latin1", vec![b"caf\xe9".to_vec(), b"plain".to_vec()]);
+    assert_eq!(value_string(&headers, "x-utf8"), "caf\u{e9}");
+    assert_eq!(value_string(&headers, "X-Latin1"), "caf\u{e9}, plain");
+    assert_eq!(value_string(&headers, "X-Missing"), "");
+
+    let debug = format!("{:?}", DebugHeaders(&headers));
+    assert!(debug.contains("\"X-Latin1\": \"caf\u{e9}, plain\""), "{}", debug);
+}
+
+#[test]
+fn test_parse_methods() {
+    let raw = vec![
+        b"GET, post ,HEAD,,".to_vec(),
+        b" Get, PROPFIND, OPTIONS".to_vec(),
+    ];
+    assert_eq!(parse_methods(Some(&raw)), vec![
+        Method::Get,
+        Method::Post,
+        Method::Head,
+        Method::Extension("PROPFIND".to_owned()),
+        Method::Options,
+    ]);
+
+    assert_eq!(parse_methods(Some(&[b"  ".to_vec()])), vec![]);
+    assert_eq!(parse_methods(None), vec![]);
+}
+
+#[test]
+fn test_parse_list() {
+    let raw = vec![
+        b"Content-Type,x-requested-with".to_vec(),
+        b"  content-type , Authorization".to_vec(),
+    ];
+    assert_eq!(parse_list(Some(&raw)), vec![
+        "Content-Type",
+        "x-requested-with",
+        "content-type",
+        "Authorization",
+    ]);
+}
+
+#[test]
+fn test_parse_content_range() {
+    let parsed = [
+        // the examples of RFC 7233
+        ("bytes 42-1233/1234", Some((42, 1233)), Some(1234)),
+        ("bytes 42-1233/*", Some((42, 1233)), None),
+        ("bytes */1234", None, Some(1234)),
+        ("bytes 0-499/1234", Some((0, 499)), Some(1234)),
+        ("bytes 500-999/1234", Some((500, 999)), Some(1234)),
+        ("bytes 734-1233/1234", Some((734, 1233)), Some(1234)),
+        ("bytes 0-0/1", Some((0, 0)), Some(1)),
+    ];
+    for &(value, range, total) in &parsed {
+        assert_eq!(parse_content_range(value), Some(ContentRange {
+            range: range,
+            total: total,
+        }), "{:?}", value);
+    }
+
+    let malformed = [
+        "", "bytes", "bytes 0-499", "bytes */*", "bytes 500-499/1234", "bytes 0-1234/1234",
+        "bytes -5/10", "bytes 5-/10", "bytes +1-2/10", "bytes 1-2/-10", "items 0-1/2",
+        "bytes 0-1/2/3", "bytes 0x1-2/3",
+    ];
+    for value in &malformed {
+        assert_eq!(parse_content_range(value), None, "{:?}", value);
     }
 }
 
+#[test]
+fn test_is_zlib_header() {
+    // the default header, and the ones for the smallest and the fastest
+    assert!(is_zlib_header(b"\x78\x9c"));
+    assert!(is_zlib_header(b"\x08\x1d"));
+    assert!(is_zlib_header(b"\x78\x01\x00"));
+
+    // a raw stream, a bad check, a 64KB window, and too short
+    assert!(!is_zlib_header(b"\x2b\x4a"));
+    assert!(!is_zlib_header(b"\x78\x9d"));
+    assert!(!is_zlib_header(b"\x88\x98"));
+    assert!(!is_zlib_header(b"\x78"));
+}
@@ -0,0 +1,224 @@
+//! The `rustls` TLS backend, with the `rustls-tls` feature.
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::net::{NetworkStream, SslClient};
+use rustls::{self, ClientConfig, ClientSession, RootCertStore, Session, TLSError};
+use webpki_roots;
+
+use ::connect::{RUSTLS_BACKEND, TlsOptions};
+
+/// Makes the `rustls` connections of a `Client`.
+#[derive(Clone)]
+pub struct RustlsClient {
+    config: Arc<ClientConfig>,
+}
+
+impl RustlsClient {
+    /// Sets up a client with `options`, trusting the roots of the Mozilla
+    /// CA program, unless `no_built_in_roots` is set, besides any of the
+    /// options.
+    ///
+    /// rustls only verifies servers by their name, so
+    /// `accept_invalid_hostnames` isn't supported, and neither are PKCS #12
+    /// identities, which rustls can't read.
+    pub fn new(options: &TlsOptions) -> ::Result<RustlsClient> {
+        let init = |msg: String| ::Error::TlsInit(RUSTLS_BACKEND, msg.into());
+        let mut config = ClientConfig::new();
+        if !options.no_built_in_roots {
+            config.root_store.add_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        }
+        for cert in &options.root_certificates {
+            let cert = rustls::Certificate(cert.to_der().to_vec());
+            try!(config.root_store.add(&cert).map_err(|e| {
+                init(format!("adding a root certificate failed: {:?}", e))
+            }));
+        }
+        if options.identity.is_some() {
+            let msg = "PKCS #12 identities aren't supported with rustls";
+            return Err(::Error::InvalidIdentity(msg.into()));
+        }
+        if options.accept_invalid_certs {
+            config.dangerous().set_certificate_verifier(Arc::new(NoVerifier));
+        } else if options.accept_invalid_hostnames {
+            return Err(init("accepting invalid hostnames isn't supported with rustls".to_owned()));
+        }
+        Ok(RustlsClient {
+            config: Arc::new(config),
+        })
+    }
+}
+
+impl fmt::Debug for RustlsClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RustlsClient").finish()
+    }
+}
+
+impl<T: NetworkStream + Send + Clone> SslClient<T> for RustlsClient {
+    type Stream = TlsStream<T>;
+
+    fn wrap_client(&self, stream: T, host: &str) -> ::hyper::Result<TlsStream<T>> {
+        let mut tls = Tls {
+            session: ClientSession::new(&self.config, host),
+            stream: stream,
+        };
+        match tls.handshake() {
+            Ok(()) => Ok(TlsStream {
+                inner: Arc::new(Mutex::new(tls)),
+            }),
+            Err(e) => Err(::hyper::Error::Ssl(Box::new(e))),
+        }
+    }
+}
+
+/// Accepts any certificate, for `accept_invalid_certs`.
+struct NoVerifier;
+
+impl rustls::ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(&self, _roots: &RootCertStore, _presented: &[rustls::Certificate],
+                          _dns_name: &str) -> Result<(), TLSError> {
+        Ok(())
+    }
+}
+
+/// A TLS connection over `stream`.
+struct Tls<T> {
+    session: ClientSession,
+    stream: T,
+}
+
+impl<T: Read + Write> Tls<T> {
+    /// Reads and writes until the handshake is done.
+    fn handshake(&mut self) -> io::Result<()> {
+        while self.session.is_handshaking() {
+            try!(self.write_tls());
+            if self.session.is_handshaking() && self.session.wants_read() &&
+                try!(self.read_tls()) {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                          "connection closed during the TLS handshake"));
+            }
+        }
+        self.write_tls()
+    }
+
+    fn write_tls(&mut self) -> io::Result<()> {
+        while self.session.wants_write() {
+            try!(self.session.write_tls(&mut self.stream));
+        }
+        Ok(())
+    }
+
+    /// Reads more of the connection, returning whether it ended.
+    fn read_tls(&mut self) -> io::Result<bool> {
+        if try!(self.session.read_tls(&mut self.stream)) == 0 {
+            return Ok(true);
+        }
+        try!(self.session.process_new_packets().map_err(tls_error));
+        Ok(false)
+    }
+}
+
+impl<T: Read + Write> Read for Tls<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = try!(self.session.read(buf));
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            try!(self.write_tls());
+            if try!(self.read_tls()) {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+impl<T: Read + Write> Write for Tls<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = try!(self.session.write(buf));
+        try!(self.write_tls());
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        try!(self.session.flush());
+        try!(self.write_tls());
+        self.stream.flush()
+    }
+}
+
+fn tls_error(e: TLSError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, RustlsError(e))
+}
+
+/// A `TLSError`, which doesn't implement `Error`.
+#[derive(Debug)]
+struct RustlsError(TLSError);
+
+impl fmt::Display for RustlsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl StdError for RustlsError {
+    fn description(&self) -> &str {
+        "TLS error"
+    }
+}
+
+/// A `rustls` stream, that can be cloned like hyper needs.
+pub struct TlsStream<T> {
+    inner: Arc<Mutex<Tls<T>>>,
+}
+
+impl<T> Clone for TlsStream<T> {
+    fn clone(&self) -> TlsStream<T> {
+        TlsStream {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Read + Write> Read for TlsStream<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.lock().unwrap().read(buf)
+    }
+}
+
+impl<T: Read + Write> Write for TlsStream<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.lock().unwrap().flush()
+    }
+}
+
+impl<T: NetworkStream + Send + Clone> NetworkStream for TlsStream<T> {
+    fn peer_addr(&mut self) -> io::Result<SocketAddr> {
+        self.inner.lock().unwrap().stream.peer_addr()
+    }
+
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.inner.lock().unwrap().stream.set_read_timeout(dur)
+    }
+
+    fn set_write_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.inner.lock().unwrap().stream.set_write_timeout(dur)
+    }
+
+    fn close(&mut self, how: Shutdown) -> io::Result<()> {
+        let mut tls = self.inner.lock().unwrap();
+        tls.session.send_close_notify();
+        let _ = tls.write_tls();
+        tls.stream.close(how)
+    }
+}
@@ -0,0 +1,396 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::time::Duration;
+
+/// Options set on each socket the `Connector` makes, before it connects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SocketOptions {
+    /// The `SO_MARK` (fwmark) of the socket, only on Linux.
+    pub mark: Option<u32>,
+    /// The DSCP of the packets, in the upper 6 bits of the traffic class.
+    pub dscp: Option<u8>,
+    /// The address to bind the socket to, with any port, only on Unix.
+    pub local_address: Option<IpAddr>,
+    /// TCP keepalive, only on Unix: `Some(Some(idle))` turns it on, probing
+    /// after the connection was idle that long, and `Some(None)` turns it
+    /// off. `None` leaves it as the system has it.
+    pub keepalive: Option<Option<Duration>>,
+    /// Whether `TCP_NODELAY` is set, turning off Nagle's algorithm. It is
+    /// set once connected.
+    pub nodelay: bool,
+}
+
+impl Default for SocketOptions {
+    fn default() -> SocketOptions {
+        SocketOptions {
+            mark: None,
+            dscp: None,
+            local_address: None,
+            keepalive: None,
+            nodelay: true,
+        }
+    }
+}
+
+/// The most a DSCP can be, since it only has 6 bits.
+pub const MAX_DSCP: u8 = 63;
+
+/// Checks that the platform can set the `SO_MARK` of a socket.
+pub fn check_mark() -> io::Result<()> {
+    if cfg!(target_os = "linux") {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::Other, "SO_MARK is only supported on Linux"))
+    }
+}
+
+/// Checks that `dscp` is valid, and that the platform can set it.
+pub fn check_dscp(dscp: u8) -> io::Result<()> {
+    if !cfg!(unix) {
+        return Err(io::Error::new(io::ErrorKind::Other,
+                                  "setting the DSCP is only supported on Unix"));
+    }
+    if dscp > MAX_DSCP {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("DSCP {} is more than {}", dscp, MAX_DSCP)));
+    }
+    Ok(())
+}
+
+/// Checks that the platform can bind a socket before connecting it.
+pub fn check_local_address() -> io::Result<()> {
+    if cfg!(unix) {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::Other,
+                           "binding to a local address is only supported on Unix"))
+    }
+}
+
+/// Checks that the platform can set the TCP keepalive of a socket.
+pub fn check_keepalive() -> io::Result<()> {
+    if cfg!(unix) {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::Other, "TCP keepalive is only supported on Unix"))
+    }
+}
+
+/// Whether `addr` can be connected to from `local`, which it can't if only
+/// one of them is IPv6.
+pub fn same_family(local: &IpAddr, addr: &SocketAddr) -> bool {
+    local.is_ipv6() == addr.is_ipv6()
+}
+
+/// A failure to set a socket option while connecting.
+///
+/// This is the inner error of the `io::Error` that connecting fails with,
+/// and becomes `Error::SocketOption`.
+#[derive(Debug)]
+pub struct OptionFailed {
+    pub option: &'static str,
+    pub cause: io::Error,
+}
+
+impl fmt::Display for OptionFailed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "setting {} failed: {}", self.option, self.cause)
+    }
+}
+
+impl StdError for OptionFailed {
+    fn description(&self) -> &str {
+        "setting a socket option failed"
+    }
+
+    fn cause(&self) -> Option<&StdError> {
+        Some(&self.cause)
+    }
+}
+
+fn option_failed(option: &'static str, cause: io::Error) -> io::Error {
+    io::Error::new(cause.kind(), OptionFailed {
+        option: option,
+        cause: cause,
+    })
+}
+
+/// Connects to `addr`, with the socket options set before connecting, and
+/// giving up after `timeout`.
+pub fn connect(addr: &SocketAddr, options: &SocketOptions, timeout: Option<Duration>)
+               -> io::Result<TcpStream> {
+    // with nothing to set before connecting
+    let plain = SocketOptions {
+        nodelay: options.nodelay,
+        ..SocketOptions::default()
+    };
+    let stream = if *options == plain {
+        try!(match timeout {
+            Some(timeout) => TcpStream::connect_timeout(addr, timeout),
+            None => TcpStream::connect(addr),
+        })
+    } else {
+        try!(imp::connect(addr, options, timeout))
+    };
+    try!(stream.set_nodelay(options.nodelay).map_err(|e| option_failed("TCP_NODELAY", e)));
+    Ok(stream)
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::io;
+    use std::mem;
+    use std::net::{SocketAddr, TcpStream};
+    use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+    use std::time::{Duration, Instant};
+
+    use libc;
+
+    use super::{SocketOptions, option_failed};
+
+    pub fn connect(addr: &SocketAddr, options: &SocketOptions, timeout: Option<Duration>)
+                   -> io::Result<TcpStream> {
+        let family = match *addr {
+            SocketAddr::V4(..) => libc::AF_INET,
+            SocketAddr::V6(..) => libc::AF_INET6,
+        };
+        let fd = unsafe { libc::socket(family, libc::SOCK_STREAM, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // owned by the stream from here on, so it is closed on any error
+        let stream = unsafe { TcpStream::from_raw_fd(fd) };
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        if let Some(mark) = options.mark {
+            try!(set_mark(fd, mark).map_err(|e| option_failed("SO_MARK", e)));
+        }
+        if let Some(dscp) = options.dscp {
+            // the lower 2 bits of the traffic class are for ECN
+            let tos = (dscp as libc::c_int) << 2;
+            let set = match *addr {
+                SocketAddr::V4(..) => {
+                    setsockopt(fd, libc::IPPROTO_IP, libc::IP_TOS, tos)
+                        .map_err(|e| option_failed("IP_TOS", e))
+                },
+                SocketAddr::V6(..) => {
+                    setsockopt(fd, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos)
+                        .map_err(|e| option_failed("IPV6_TCLASS", e))
+                },
+            };
+            try!(set);
+        }
+        if let Some(keepalive) = options.keepalive {
+            try!(set_keepalive(fd, keepalive));
+        }
+
+        if let Some(local) = options.local_address {
+            let local = SocketAddr::new(local, 0);
+            if unsafe { with_sockaddr(&local, |sa, len| libc::bind(fd, sa, len)) } < 0 {
+                return Err(option_failed("local address", io::Error::last_os_error()));
+            }
+        }
+
+        // with a timeout, the connect is waited for with `poll`
+        if timeout.is_some() {
+            try!(stream.set_nonblocking(true));
+        }
+        let ret = unsafe { with_sockaddr(addr, |sa, len| libc::connect(fd, sa, len)) };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            match timeout {
+                Some(timeout) if err.raw_os_error() == Some(libc::EINPROGRESS) => {
+                    try!(wait_writable(fd, timeout));
+                    if let Some(err) = try!(stream.take_error()) {
+                        return Err(err);
+                    }
+                },
+                _ => return Err(err),
+            }
+        }
+        if timeout.is_some() {
+            try!(stream.set_nonblocking(false));
+        }
+        debug_assert_eq!(stream.as_raw_fd(), fd);
+        Ok(stream)
+    }
+
+    /// Calls `f` with `addr` as a `sockaddr`, and its length.
+    unsafe fn with_sockaddr<F>(addr: &SocketAddr, f: F) -> libc::c_int
+    where F: FnOnce(*const libc::sockaddr, libc::socklen_t) -> libc::c_int {
+        match *addr {
+            SocketAddr::V4(ref addr) => {
+                let mut sin: libc::sockaddr_in = mem::zeroed();
+                sin.sin_family = libc::AF_INET as libc::sa_family_t;
+                sin.sin_port = addr.port().to_be();
+                sin.sin_addr = libc::in_addr {
+                    s_addr: u32::from(*addr.ip()).to_be(),
+                };
+                f(&sin as *const _ as *const libc::sockaddr,
+                  mem::size_of::<libc::sockaddr_in>() as libc::socklen_t)
+            },
+            SocketAddr::V6(ref addr) => {
+                let mut sin6: libc::sockaddr_in6 = mem::zeroed();
+                sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sin6.sin6_port = addr.port().to_be();
+                sin6.sin6_flowinfo = addr.flowinfo();
+                sin6.sin6_addr.s6_addr = addr.ip().octets();
+                sin6.sin6_scope_id = addr.scope_id();
+                f(&sin6 as *const _ as *const libc::sockaddr,
+                  mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t)
+            },
+        }
+    }
+
+    /// Waits until `fd` is writable, which is when a non-blocking connect
+    /// has finished, whether it succeeded or not.
+    fn wait_writable(fd: RawFd, timeout: Duration) -> io::Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "connect timed out"));
+            }
+            let left = deadline - now;
+            // rounded up, so it doesn't spin for the last fraction of a millisecond
+            let millis = left.as_secs() * 1000 + (left.subsec_nanos() as u64 + 999_999) / 1_000_000;
+            let millis = if millis > libc::c_int::max_value() as u64 {
+                libc::c_int::max_value()
+            } else {
+                millis as libc::c_int
+            };
+            let mut pollfd = libc::pollfd {
+                fd: fd,
+                events: libc::POLLOUT,
+                revents: 0,
+            };
+            match unsafe { libc::poll(&mut pollfd, 1, millis) } {
+                n if n > 0 => return Ok(()),
+                0 => (),
+                _ => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                },
+            }
+        }
+    }
+
+    /// The option for how long a connection is idle before keepalive
+    /// probes are sent, and its name.
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    const KEEPALIVE_IDLE: (libc::c_int, &'static str) = (libc::TCP_KEEPALIVE, "TCP_KEEPALIVE");
+    #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+    const KEEPALIVE_IDLE: (libc::c_int, &'static str) = (libc::TCP_KEEPIDLE, "TCP_KEEPIDLE");
+
+    fn set_keepalive(fd: RawFd, keepalive: Option<Duration>) -> io::Result<()> {
+        let on = keepalive.is_some() as libc::c_int;
+        try!(setsockopt(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, on)
+            .map_err(|e| option_failed("SO_KEEPALIVE", e)));
+        if let Some(idle) = keepalive {
+            // in whole seconds, rounded up, and at least one
+            let secs = idle.as_secs() + if idle.subsec_nanos() > 0 { 1 } else { 0 };
+            let secs = ::std::cmp::min(::std::cmp::max(secs, 1), libc::c_int::max_value() as u64);
+            let (option, name) = KEEPALIVE_IDLE;
+            try!(setsockopt(fd, libc::IPPROTO_TCP, option, secs as libc::c_int)
+                .map_err(|e| option_failed(name, e)));
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_mark(fd: RawFd, mark: u32) -> io::Result<()> {
+        setsockopt(fd, libc::SOL_SOCKET, libc::SO_MARK, mark as libc::c_int)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_mark(_fd: RawFd, _mark: u32) -> io::Result<()> {
+        // `Client::socket_mark` refuses to set one
+        Err(io::Error::new(io::ErrorKind::Other, "SO_MARK is only supported on Linux"))
+    }
+
+    fn setsockopt(fd: RawFd, level: libc::c_int, name: libc::c_int, value: libc::c_int)
+                  -> io::Result<()> {
+        let ret = unsafe {
+            libc::setsockopt(fd, level, name, &value as *const _ as *const libc::c_void,
+                             mem::size_of::<libc::c_int>() as libc::socklen_t)
+        };
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use std::io;
+    use std::net::{SocketAddr, TcpStream};
+    use std::time::Duration;
+
+    use super::SocketOptions;
+
+    pub fn connect(addr: &SocketAddr, _options: &SocketOptions, timeout: Option<Duration>)
+                   -> io::Result<TcpStream> {
+        // the `Client` setters refuse options that can't be set here
+        match timeout {
+            Some(timeout) => TcpStream::connect_timeout(addr, timeout),
+            None => TcpStream::connect(addr),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_keepalive() {
+    use std::mem;
+    use std::net::TcpListener;
+    use std::os::unix::io::AsRawFd;
+
+    use libc;
+
+    fn get(stream: &TcpStream, level: libc::c_int, name: libc::c_int) -> libc::c_int {
+        let mut value: libc::c_int = 0;
+        let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(stream.as_raw_fd(), level, name,
+                             &mut value as *mut _ as *mut libc::c_void, &mut len)
+        };
+        assert_eq!(ret, 0);
+        value
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut options = SocketOptions::default();
+
+    options.keepalive = Some(Some(Duration::from_millis(29_500)));
+    let stream = connect(&addr, &options, None).unwrap();
+    assert_eq!(get(&stream, libc::SOL_SOCKET, libc::SO_KEEPALIVE), 1);
+    assert_eq!(get(&stream, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE), 30);
+
+    options.keepalive = Some(None);
+    let stream = connect(&addr, &options, Some(Duration::from_secs(1))).unwrap();
+    assert_eq!(get(&stream, libc::SOL_SOCKET, libc::SO_KEEPALIVE), 0);
+}
+
+#[test]
+fn test_nodelay() {
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut options = SocketOptions::default();
+    let stream = connect(&addr, &options, None).unwrap();
+    assert!(stream.nodelay().unwrap());
+
+    options.nodelay = false;
+    let stream = connect(&addr, &options, Some(Duration::from_secs(1))).unwrap();
+    assert!(!stream.nodelay().unwrap());
+}
@@ -0,0 +1,47 @@
+//! Helpers for the tests of code that uses reqwest, with the `test-util`
+//! feature.
+
+use std::sync::atomic::Ordering;
+
+use ::connect::NETWORK_GUARDS;
+
+/// Denies connecting anywhere, from every `Client` in the process, while
+/// the returned guard is alive.
+///
+/// This is a tripwire for tests that should never reach the network, such
+/// as when a server wasn't mocked. Each new connection then fails with
+/// `Error::NetworkDenied`, before anything is resolved or connected to,
+/// including loopback addresses. Requests sent with `Client::request_over`
+/// don't connect, so they still work, such as over an in-memory stream
+/// that stands in for a server. Connections that were already open, such
+/// as idle ones in a pool, can still be used too.
+///
+/// Guards can be nested, and connecting is allowed again once all of them
+/// are dropped. Since they are process-wide, a test that uses one should
+/// not run alongside tests that connect.
+///
+/// ```
+/// let _guard = reqwest::test::deny_network();
+/// let err = reqwest::get("http://example.com/").unwrap_err();
+/// assert_eq!(err.to_string(),
+///            "network access denied by test guard: tried example.com:80");
+/// ```
+pub fn deny_network() -> NetworkGuard {
+    NETWORK_GUARDS.fetch_add(1, Ordering::SeqCst);
+    NetworkGuard {
+        _priv: (),
+    }
+}
+
+/// Denies connecting while alive, from `deny_network`.
+#[derive(Debug)]
+#[must_use = "connecting is allowed again as soon as the guard is dropped"]
+pub struct NetworkGuard {
+    _priv: (),
+}
+
+impl Drop for NetworkGuard {
+    fn drop(&mut self) {
+        NETWORK_GUARDS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
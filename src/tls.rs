@@ -0,0 +1,290 @@
+use std::fmt;
+
+/// A certificate to trust as a root, in addition to the system's.
+///
+/// See `ClientBuilder::add_root_certificate`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Certificate {
+    der: Vec<u8>,
+}
+
+impl Certificate {
+    /// Parses a DER-encoded X.509 certificate.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::InvalidCertificate` if `der` isn't a certificate.
+    ///
+    /// ```
+    /// # fn run() -> Result<(), Box<std::error::Error>> {
+    /// # let der = include_bytes!("../tests/tls/ca.der");
+    /// let cert = try!(reqwest::Certificate::from_der(der));
+    /// # drop(cert);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn from_der(der: &[u8]) -> ::Result<Certificate> {
+        try!(check_der(der));
+        try!(check_backend(der));
+        Ok(Certificate {
+            der: der.to_vec(),
+        })
+    }
+
+    /// Parses a PEM-encoded X.509 certificate, a `CERTIFICATE` block such
+    /// as:
+    ///
+    /// ```text
+    /// -----BEGIN CERTIFICATE-----
+    /// MIIDJTCCAg2gAwIBAgIU...
+    /// -----END CERTIFICATE-----
+    /// ```
+    ///
+    /// Text around the block, and other blocks, such as a private key, are
+    /// ignored.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::InvalidCertificate` if `pem` doesn't have exactly
+    /// one certificate, or it isn't valid. Use `from_pem_bundle` for a file
+    /// with several.
+    pub fn from_pem(pem: &[u8]) -> ::Result<Certificate> {
+        let mut certs = try!(Certificate::from_pem_bundle(pem));
+        if certs.len() > 1 {
+            return Err(invalid(format!("expected one certificate in PEM, found {}, \
+                                        use from_pem_bundle for several", certs.len())));
+        }
+        Ok(certs.remove(0))
+    }
+
+    /// Parses each PEM-encoded X.509 certificate of a bundle, such as a
+    /// file of CA certificates, in order.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::InvalidCertificate` if `pem` has no certificates,
+    /// or any of them isn't valid.
+    ///
+    /// ```
+    /// # fn run() -> Result<(), Box<std::error::Error>> {
+    /// # let pem = include_bytes!("../tests/tls/bundle.pem");
+    /// let mut builder = reqwest::Client::builder();
+    /// for cert in try!(reqwest::Certificate::from_pem_bundle(pem)) {
+    ///     builder = builder.add_root_certificate(cert);
+    /// }
+    /// let client = try!(builder.build());
+    /// # drop(client);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn from_pem_bundle(pem: &[u8]) -> ::Result<Vec<Certificate>> {
+        let text = try!(::std::str::from_utf8(pem).map_err(|_| invalid("PEM is not ASCII")));
+        let mut certs = Vec::new();
+        let mut block: Option<String> = None;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if block.is_none() {
+                if line == PEM_BEGIN {
+                    block = Some(String::new());
+                }
+                continue;
+            }
+            if line == PEM_END {
+                let encoded = block.take().unwrap();
+                let der = try!(decode_base64(&encoded).ok_or_else(|| {
+                    invalid(format!("invalid base64 in the PEM block ending on line {}", i + 1))
+                }));
+                certs.push(try!(Certificate::from_der(&der)));
+            } else if line.starts_with("-----") {
+                return Err(invalid(format!("unexpected {:?} on line {} of PEM", line, i + 1)));
+            } else if let Some(ref mut encoded) = block {
+                encoded.push_str(line);
+            }
+        }
+        if block.is_some() {
+            return Err(invalid("unterminated certificate in PEM"));
+        }
+        if certs.is_empty() {
+            return Err(invalid("no certificate in PEM"));
+        }
+        Ok(certs)
+    }
+
+    /// The DER encoding of the certificate.
+    pub fn to_der(&self) -> &[u8] {
+        &self.der
+    }
+}
+
+impl fmt::Debug for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Certificate")
+            .field("der_len", &self.der.len())
+            .finish()
+    }
+}
+
+/// A certificate with its private key, to present to servers that ask the
+/// client to authenticate, for mutual TLS.
+///
+/// See `ClientBuilder::identity`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Identity {
+    der: Vec<u8>,
+    password: String,
+}
+
+impl Identity {
+    /// Uses a DER-encoded PKCS #12 archive, such as a `.p12` or `.pfx`
+    /// file, with the certificate and its private key, and the password to
+    /// decrypt it.
+    ///
+    /// The archive is parsed when the `Client` is built, which fails with
+    /// `Error::InvalidIdentity` if it is malformed, or the password is
+    /// wrong.
+    ///
+    /// ```
+    /// # fn run() -> Result<(), Box<std::error::Error>> {
+    /// # let der = include_bytes!("../tests/tls/client.p12");
+    /// let identity = reqwest::Identity::from_pkcs12_der(der, "reqwest");
+    /// let client = try!(reqwest::Client::builder()
+    ///     .identity(identity)
+    ///     .build());
+    /// # drop(client);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn from_pkcs12_der(der: &[u8], password: &str) -> Identity {
+        Identity {
+            der: der.to_vec(),
+            password: password.to_owned(),
+        }
+    }
+}
+
+/// Parses the archive of `identity` with the TLS backend.
+#[cfg(feature = "default-tls")]
+pub fn to_pkcs12(identity: &Identity) -> ::Result<::native_tls::Pkcs12> {
+    ::native_tls::Pkcs12::from_der(&identity.der, &identity.password)
+        .map_err(|e| ::Error::InvalidIdentity(Box::new(e)))
+}
+
+// the password stays out of logs
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Identity")
+            .field("der_len", &self.der.len())
+            .finish()
+    }
+}
+
+const PEM_BEGIN: &'static str = "-----BEGIN CERTIFICATE-----";
+const PEM_END: &'static str = "-----END CERTIFICATE-----";
+
+fn invalid<E: Into<Box<::std::error::Error + Send + Sync>>>(e: E) -> ::Error {
+    ::Error::InvalidCertificate(e.into())
+}
+
+/// Checks that `der` is a single DER `SEQUENCE`, as a certificate is, so
+/// that garbage fails the same way with any TLS backend, or none.
+fn check_der(der: &[u8]) -> ::Result<()> {
+    if der.len() < 2 || der[0] != 0x30 {
+        return Err(invalid("DER is not a certificate"));
+    }
+    let (len, header) = if der[1] < 0x80 {
+        (der[1] as usize, 2)
+    } else {
+        let n = (der[1] & 0x7f) as usize;
+        if n == 0 || n > 4 || der.len() < 2 + n {
+            return Err(invalid("DER has an invalid length"));
+        }
+        let len = der[2..2 + n].iter().fold(0, |len, &b| len << 8 | b as usize);
+        (len, 2 + n)
+    };
+    if header + len != der.len() {
+        return Err(invalid(format!("DER is {} bytes, but its certificate is {}",
+                                   der.len(), header + len)));
+    }
+    Ok(())
+}
+
+/// Checks that the TLS backend can parse `der`, so that it fails now,
+/// rather than when the `Client` is built.
+#[cfg(feature = "default-tls")]
+fn check_backend(der: &[u8]) -> ::Result<()> {
+    ::native_tls::Certificate::from_der(der).map(|_| ()).map_err(invalid)
+}
+
+#[cfg(not(feature = "default-tls"))]
+fn check_backend(_der: &[u8]) -> ::Result<()> {
+    Ok(())
+}
+
+/// Decodes standard, padded base64, as in PEM.
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let bytes = encoded.as_bytes();
+    if bytes.len() % 4 != 0 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(bytes.len() / 4 * 3);
+    for (i, chunk) in bytes.chunks(4).enumerate() {
+        let last = i + 1 == bytes.len() / 4;
+        let pad = chunk.iter().rev().take_while(|&&b| b == b'=').count();
+        if pad > 2 || (pad > 0 && !last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for &b in &chunk[..4 - pad] {
+            n = n << 6 | match sextet(b) {
+                Some(sextet) => sextet as u32,
+                None => return None,
+            };
+        }
+        n <<= 6 * pad as u32;
+        let decoded = [(n >> 16) as u8, (n >> 8) as u8, n as u8];
+        out.extend_from_slice(&decoded[..3 - pad]);
+    }
+    Some(out)
+}
+
+fn sextet(b: u8) -> Option<u8> {
+    match b {
+        b'A'...b'Z' => Some(b - b'A'),
+        b'a'...b'z' => Some(b - b'a' + 26),
+        b'0'...b'9' => Some(b - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64("").unwrap(), b"");
+        assert_eq!(decode_base64("Zg==").unwrap(), b"f");
+        assert_eq!(decode_base64("Zm8=").unwrap(), b"fo");
+        assert_eq!(decode_base64("Zm9v").unwrap(), b"foo");
+        assert_eq!(decode_base64("Zm9vYmFy").unwrap(), b"foobar");
+        assert!(decode_base64("Zm9").is_none());
+        assert!(decode_base64("Zg==Zm9v").is_none());
+        assert!(decode_base64("Z===").is_none());
+        assert!(decode_base64("Zm9*").is_none());
+    }
+
+    #[test]
+    fn test_check_der() {
+        assert!(check_der(b"\x30\x03\x02\x01\x01").is_ok());
+        assert!(check_der(b"\x30\x81\x03\x02\x01\x01").is_ok());
+        assert!(check_der(b"").is_err());
+        assert!(check_der(b"\x04\x03\x02\x01\x01").is_err());
+        assert!(check_der(b"\x30\x03\x02\x01").is_err());
+        assert!(check_der(b"\x30\x03\x02\x01\x01\x00").is_err());
+    }
+}
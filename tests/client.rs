@@ -1,5 +1,7 @@
 extern crate reqwest;
 extern crate libflate;
+extern crate log;
+extern crate serde_json;
 
 #[macro_use] mod server;
 
@@ -14,7 +16,7 @@ fn test_get() {
             Host: $HOST\r\n\
             User-Agent: $USERAGENT\r\n\
             Accept: */*\r\n\
-            Accept-Encoding: gzip\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
             \r\n\
             ",
         response: b"\
@@ -33,12 +35,99 @@ fn test_get() {
     assert_eq!(res.version(), &reqwest::HttpVersion::Http11);
     assert_eq!(res.headers().get(), Some(&reqwest::header::Server("test".to_string())));
     assert_eq!(res.headers().get(), Some(&reqwest::header::ContentLength(0)));
+    assert!(!res.connection_reused());
 
     let mut buf = [0; 1024];
     let n = res.read(&mut buf).unwrap();
     assert_eq!(n, 0)
 }
 
+#[test]
+fn test_non_ascii_header_values() {
+    let server = server! {
+        request: b"\
+            GET /non-ascii HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Disposition: attachment; filename=\"caf\xc3\xa9.txt\"\r\n\
+            X-Message: caf\xe9\r\n\
+            X-Message: cr\xe8me\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    // formats the response headers, which must not fail on these
+    let mut client = reqwest::Client::new().unwrap();
+    client.capture_diagnostics(1024);
+    let res = client.get(&format!("http://{}/non-ascii", server.addr())).send().unwrap();
+
+    let disposition = res.header_bytes("content-disposition").unwrap();
+    assert_eq!(disposition, &b"attachment; filename=\"caf\xc3\xa9.txt\""[..]);
+    assert_eq!(res.header_str("Content-Disposition"),
+               Some("attachment; filename=\"caf\u{e9}.txt\""));
+    assert_eq!(res.header_lossy("Content-Disposition").unwrap(),
+               "attachment; filename=\"caf\u{c3}\u{a9}.txt\"");
+
+    assert_eq!(res.header_bytes("X-Message"), Some(&b"caf\xe9"[..]));
+    assert_eq!(res.header_all("X-Message"), vec![&b"caf\xe9"[..], &b"cr\xe8me"[..]]);
+    assert_eq!(res.header_str("X-Message"), None);
+    assert_eq!(res.header_lossy("X-Message").unwrap(), "caf\u{e9}");
+
+    assert_eq!(res.header_bytes("X-Missing"), None);
+    assert!(res.header_all("X-Missing").is_empty());
+    assert_eq!(res.header_lossy("X-Missing"), None);
+
+    let debug = format!("{:?}", res);
+    assert!(debug.contains("caf\u{e9}"), "{}", debug);
+}
+
+#[test]
+fn test_redirect_to_non_ascii_location() {
+    let server = server! {
+        request: b"\
+            GET /old HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Location: /caf\xe9\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /caf%C3%A9 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Referer: http://$HOST/old\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    // latin-1, which is decoded as such, rather than replaced
+    let res = reqwest::get(&format!("http://{}/old", server.addr())).unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert_eq!(res.url().path(), "/caf%C3%A9");
+}
+
 #[test]
 fn test_redirect_301_and_302_and_303_changes_post_to_get() {
     let client = reqwest::Client::new().unwrap();
@@ -51,7 +140,7 @@ fn test_redirect_301_and_302_and_303_changes_post_to_get() {
                 Host: $HOST\r\n\
                 User-Agent: $USERAGENT\r\n\
                 Accept: */*\r\n\
-                Accept-Encoding: gzip\r\n\
+                Accept-Encoding: $ACCEPT_ENCODING\r\n\
                 Content-Length: 0\r\n\
                 \r\n\
                 ", code),
@@ -69,7 +158,7 @@ fn test_redirect_301_and_302_and_303_changes_post_to_get() {
                 Host: $HOST\r\n\
                 User-Agent: $USERAGENT\r\n\
                 Accept: */*\r\n\
-                Accept-Encoding: gzip\r\n\
+                Accept-Encoding: $ACCEPT_ENCODING\r\n\
                 Referer: http://$HOST/{}\r\n\
                 \r\n\
                 ", code),
@@ -103,7 +192,7 @@ fn test_redirect_307_and_308_tries_to_post_again() {
                 Host: $HOST\r\n\
                 User-Agent: $USERAGENT\r\n\
                 Accept: */*\r\n\
-                Accept-Encoding: gzip\r\n\
+                Accept-Encoding: $ACCEPT_ENCODING\r\n\
                 Content-Length: 5\r\n\
                 \r\n\
                 Hello\
@@ -122,7 +211,7 @@ fn test_redirect_307_and_308_tries_to_post_again() {
                 Host: $HOST\r\n\
                 User-Agent: $USERAGENT\r\n\
                 Accept: */*\r\n\
-                Accept-Encoding: gzip\r\n\
+                Accept-Encoding: $ACCEPT_ENCODING\r\n\
                 Referer: http://$HOST/{}\r\n\
                 Content-Length: 5\r\n\
                 \r\n\
@@ -159,7 +248,7 @@ fn test_redirect_307_does_not_try_if_reader_cannot_reset() {
                 Host: $HOST\r\n\
                 User-Agent: $USERAGENT\r\n\
                 Accept: */*\r\n\
-                Accept-Encoding: gzip\r\n\
+                Accept-Encoding: $ACCEPT_ENCODING\r\n\
                 Transfer-Encoding: chunked\r\n\
                 \r\n\
                 5\r\n\
@@ -186,6 +275,176 @@ fn test_redirect_307_does_not_try_if_reader_cannot_reset() {
     }
 }
 
+/// A body that counts how often it was reset, and can be made to fail it.
+struct Rewinding {
+    data: std::io::Cursor<Vec<u8>>,
+    resets: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    fail: bool,
+}
+
+impl Rewinding {
+    fn new(data: &[u8], fail: bool)
+           -> (Rewinding, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+        let resets = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let body = Rewinding {
+            data: std::io::Cursor::new(data.to_vec()),
+            resets: resets.clone(),
+            fail: fail,
+        };
+        (body, resets)
+    }
+}
+
+impl Read for Rewinding {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.data.read(buf)
+    }
+}
+
+impl reqwest::ResetableReader for Rewinding {
+    fn reset(&mut self) -> std::io::Result<()> {
+        self.resets.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        if self.fail {
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, "object is gone"));
+        }
+        self.data.set_position(0);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_redirect_307_resets_resettable_body() {
+    let redirect = server! {
+        request: b"\
+            POST /upload HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            5\r\n\
+            Hello\r\n\
+            0\r\n\r\n\
+            ",
+        response: b"\
+            HTTP/1.1 307 Temporary Redirect\r\n\
+            Server: test-redirect\r\n\
+            Content-Length: 0\r\n\
+            Location: /dst\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            POST /dst HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Referer: http://$HOST/upload\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            5\r\n\
+            Hello\r\n\
+            0\r\n\r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-dst\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let (body, resets) = Rewinding::new(b"Hello", false);
+    let res = client.post(&format!("http://{}/upload", redirect.addr()))
+        .body(reqwest::Body::resettable(body))
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert_eq!(res.url().as_str(), format!("http://{}/dst", redirect.addr()));
+    // only once, before it was sent the second time
+    assert_eq!(resets.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_redirect_307_reset_failure() {
+    let redirect = server! {
+        request: b"\
+            POST /upload HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            5\r\n\
+            Hello\r\n\
+            0\r\n\r\n\
+            ",
+        response: b"\
+            HTTP/1.1 307 Temporary Redirect\r\n\
+            Server: test-redirect\r\n\
+            Content-Length: 0\r\n\
+            Location: /dst\r\n\
+            Connection: close\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let (body, resets) = Rewinding::new(b"Hello", true);
+    let err = client.post(&format!("http://{}/upload", redirect.addr()))
+        .body(reqwest::Body::resettable(body))
+        .send()
+        .unwrap_err();
+    match err {
+        reqwest::Error::BodyReset(ref e) => assert_eq!(e.to_string(), "object is gone"),
+        ref other => panic!("unexpected error: {:?}", other),
+    }
+    assert_eq!(resets.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_fallback_resets_resettable_body() {
+    let server = server! {
+        request: b"\
+            PUT /blob HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            4\r\n\
+            blob\r\n\
+            0\r\n\r\n\
+            ",
+        response: b"\
+            HTTP/1.1 204 No Content\r\n\
+            Server: test-fallback\r\n\
+            \r\n\
+            "
+    };
+
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let primary = reqwest::Url::parse(&format!("http://{}", closed)).unwrap();
+    let secondary = reqwest::Url::parse(&format!("http://{}", server.addr())).unwrap();
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.fallback_base_urls(vec![primary, secondary]);
+    let (body, resets) = Rewinding::new(b"blob", false);
+    let res = client.put(&format!("http://{}/blob", closed))
+        .body(reqwest::Body::resettable(body))
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::NoContent);
+    // the request is tried again on the next server
+    assert_eq!(resets.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
 #[test]
 fn test_redirect_policy_can_return_errors() {
     let server = server! {
@@ -194,7 +453,7 @@ fn test_redirect_policy_can_return_errors() {
             Host: $HOST\r\n\
             User-Agent: $USERAGENT\r\n\
             Accept: */*\r\n\
-            Accept-Encoding: gzip\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
             \r\n\
             ",
         response: b"\
@@ -208,9 +467,84 @@ fn test_redirect_policy_can_return_errors() {
 
     let err = reqwest::get(&format!("http://{}/loop", server.addr())).unwrap_err();
     match err {
-        reqwest::Error::RedirectLoop => (),
+        reqwest::Error::RedirectLoop(..) => (),
+        e => panic!("wrong error received: {:?}", e),
+    }
+}
+
+/// Serves each of `hops` in turn, a path that is redirected to the next.
+fn redirect_hops_server(hops: &[(&str, &str)]) -> server::Server {
+    let mut txns = Vec::new();
+    let mut referer = None;
+    for &(path, location) in hops {
+        let mut request = format!("\
+            GET {} HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n", path);
+        if let Some(referer) = referer {
+            request.push_str(&format!("Referer: http://$HOST{}\r\n", referer));
+        }
+        request.push_str("\r\n");
+        let response = format!("\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-hops\r\n\
+            Location: {}\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n", location);
+        txns.push((request.into_bytes(), response.into_bytes()));
+        referer = Some(path);
+    }
+    server::spawn(txns)
+}
+
+#[test]
+fn test_redirect_loop_chain() {
+    let server = redirect_hops_server(&[
+        ("/hop-a", "/hop-b"),
+        ("/hop-b", "/hop-c"),
+        ("/hop-c", "/hop-a"),
+    ]);
+
+    let err = reqwest::get(&format!("http://{}/hop-a", server.addr())).unwrap_err();
+    let url = |path: &str| format!("http://{}{}", server.addr(), path);
+    let chain = err.redirect_chain().unwrap().iter().map(|url| url.to_string()).collect::<Vec<_>>();
+    assert_eq!(chain, vec![url("/hop-a"), url("/hop-b"), url("/hop-c"), url("/hop-a")]);
+    match err {
+        reqwest::Error::RedirectLoop(..) => (),
+        e => panic!("wrong error received: {:?}", e),
+    }
+    assert_eq!(err.to_string(), format!("Infinite redirect loop: {} -> {} -> {} -> {}",
+                                        url("/hop-a"), url("/hop-b"), url("/hop-c"),
+                                        url("/hop-a")));
+}
+
+#[test]
+fn test_too_many_redirects_chain() {
+    let hops = (0..6)
+        .map(|i| (format!("/hop-{}", i), format!("/hop-{}", i + 1)))
+        .collect::<Vec<_>>();
+    let server = redirect_hops_server(&hops.iter()
+        .map(|&(ref path, ref location)| (&path[..], &location[..]))
+        .collect::<Vec<_>>());
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.redirect(reqwest::RedirectPolicy::limited(6));
+    let err = client.get(&format!("http://{}/hop-0", server.addr())).send().unwrap_err();
+    let url = |i: usize| format!("http://{}/hop-{}", server.addr(), i);
+    let chain = err.redirect_chain().unwrap().iter().map(|url| url.to_string()).collect::<Vec<_>>();
+    assert_eq!(chain, (0..7).map(&url).collect::<Vec<_>>());
+    match err {
+        reqwest::Error::TooManyRedirects(..) => (),
         e => panic!("wrong error received: {:?}", e),
     }
+    // only the first and last two are shown
+    assert_eq!(err.to_string(), format!("Too many redirects: {} -> {} -> (3 more) -> {} -> {}",
+                                        url(0), url(1), url(5), url(6)));
+    assert!(reqwest::Error::TooManyRedirects(Vec::new()).redirect_chain().unwrap().is_empty());
+    assert_eq!(reqwest::Error::TooManyRedirects(Vec::new()).to_string(), "Too many redirects");
 }
 
 #[test]
@@ -221,7 +555,7 @@ fn test_redirect_policy_can_stop_redirects_without_an_error() {
             Host: $HOST\r\n\
             User-Agent: $USERAGENT\r\n\
             Accept: */*\r\n\
-            Accept-Encoding: gzip\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
             \r\n\
             ",
         response: b"\
@@ -246,160 +580,7613 @@ fn test_redirect_policy_can_stop_redirects_without_an_error() {
 }
 
 #[test]
-fn test_accept_header_is_not_changed_if_set() {
+fn test_redirect_policy_sees_redirect_headers() {
     let server = server! {
         request: b"\
-            GET /accept HTTP/1.1\r\n\
+            GET /moved HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Location: /maintenance\r\n\
+            X-Redirect-Reason: moved\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ",
+        request: b"\
+            GET /maintenance HTTP/1.1\r\n\
             Host: $HOST\r\n\
-            Accept: application/json\r\n\
             User-Agent: $USERAGENT\r\n\
-            Accept-Encoding: gzip\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Referer: http://$HOST/moved\r\n\
             \r\n\
             ",
         response: b"\
-            HTTP/1.1 200 OK\r\n\
-            Server: test-accept\r\n\
+            HTTP/1.1 302 Found\r\n\
+            Location: /down\r\n\
+            X-Redirect-Reason: maintenance\r\n\
             Content-Length: 0\r\n\
             \r\n\
             "
     };
-    let client = reqwest::Client::new().unwrap();
-
-    let res = client.get(&format!("http://{}/accept", server.addr()))
-        .header(reqwest::header::Accept::json())
-        .send()
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::RedirectPolicy::custom_attempt(|attempt| {
+            let reason = attempt.headers().get_raw("X-Redirect-Reason");
+            Ok(reason.map_or(true, |values| values.iter().all(|v| v != b"maintenance")))
+        }))
+        .build()
         .unwrap();
 
-    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    let res = client.get(&format!("http://{}/moved", server.addr())).send().unwrap();
+    assert_eq!(res.url().as_str(), format!("http://{}/maintenance", server.addr()));
+    assert_eq!(res.status(), &reqwest::StatusCode::Found);
 }
 
 #[test]
-fn test_accept_encoding_header_is_not_changed_if_set() {
+fn test_referer_disabled() {
     let server = server! {
         request: b"\
-            GET /accept-encoding HTTP/1.1\r\n\
+            GET /moved HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Location: /landing\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ",
+        request: b"\
+            GET /landing HTTP/1.1\r\n\
             Host: $HOST\r\n\
-            Accept-Encoding: identity\r\n\
             User-Agent: $USERAGENT\r\n\
             Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
             \r\n\
             ",
         response: b"\
             HTTP/1.1 200 OK\r\n\
-            Server: test-accept-encoding\r\n\
             Content-Length: 0\r\n\
             \r\n\
             "
     };
-    let client = reqwest::Client::new().unwrap();
-
-    let res = client.get(&format!("http://{}/accept-encoding", server.addr()))
-        .header(reqwest::header::AcceptEncoding(
-            vec![reqwest::header::qitem(reqwest::header::Encoding::Identity)]
-        ))
-        .send()
+    let client = reqwest::Client::builder()
+        .referer(false)
+        .build()
         .unwrap();
 
+    let res = client.get(&format!("http://{}/moved", server.addr())).send().unwrap();
+    assert_eq!(res.url().as_str(), format!("http://{}/landing", server.addr()));
     assert_eq!(res.status(), &reqwest::StatusCode::Ok);
 }
 
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
 #[test]
-fn test_gzip_response() {
-    let mut encoder = ::libflate::gzip::Encoder::new(Vec::new()).unwrap();
-    match encoder.write(b"test request") {
-        Ok(n) => assert!(n > 0, "Failed to write to encoder."),
-        _ => panic!("Failed to gzip encode string.")
-    };
-
-    let gzipped_content = encoder.finish().into_result().unwrap();
-
-    let mut response = format!("\
-            HTTP/1.1 200 OK\r\n\
-            Server: test-accept\r\n\
-            Content-Encoding: gzip\r\n\
-            Content-Length: {}\r\n\
-            \r\n", &gzipped_content.len())
-        .into_bytes();
-    response.extend(&gzipped_content);
-
+fn test_referer_not_sent_from_https_to_http() {
     let server = server! {
         request: b"\
-            GET /gzip HTTP/1.1\r\n\
+            GET /insecure HTTP/1.1\r\n\
             Host: $HOST\r\n\
             User-Agent: $USERAGENT\r\n\
             Accept: */*\r\n\
-            Accept-Encoding: gzip\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
             \r\n\
             ",
-        response: response
-    };
-    let mut res = reqwest::get(&format!("http://{}/gzip", server.addr()))
-        .unwrap();
-
-    let mut body = ::std::string::String::new();
-    match res.read_to_string(&mut body) {
-        Ok(n) => assert!(n > 0, "Failed to write to buffer."),
-        _ => panic!("Failed to write to buffer.")
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
     };
+    let secure = server::spawn_tls_reply(format!("\
+        HTTP/1.1 302 Found\r\n\
+        Location: http://{}/insecure\r\n\
+        Content-Length: 0\r\n\
+        Connection: close\r\n\
+        \r\n", server.addr()).into_bytes());
 
-    assert_eq!(body, "test request");
+    let ca = reqwest::Certificate::from_der(include_bytes!("tls/ca.der")).unwrap();
+    let client = reqwest::Client::builder()
+        .add_root_certificate(ca)
+        .resolve("localhost", secure.addr())
+        .build()
+        .unwrap();
+    let url = format!("https://localhost:{}/secure?token=abc", secure.addr().port());
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.url().as_str(), format!("http://{}/insecure", server.addr()));
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
 }
 
 #[test]
-fn test_gzip_empty_body() {
+fn test_cookie_store_login() {
     let server = server! {
         request: b"\
-            HEAD /gzip HTTP/1.1\r\n\
+            POST /login HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Content-Type: application/x-www-form-urlencoded\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 10\r\n\
+            \r\n\
+            user=alice\
+            ",
+        response: b"\
+            HTTP/1.1 204 No Content\r\n\
+            Set-Cookie: session=abc123; Path=/; HttpOnly\r\n\
+            \r\n\
+            ",
+        request: b"\
+            GET /account/settings HTTP/1.1\r\n\
             Host: $HOST\r\n\
             User-Agent: $USERAGENT\r\n\
             Accept: */*\r\n\
-            Accept-Encoding: gzip\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Cookie: session=abc123\r\n\
             \r\n\
             ",
         response: b"\
             HTTP/1.1 200 OK\r\n\
-            Server: test-accept\r\n\
-            Content-Encoding: gzip\r\n\
-            Content-Length: 100\r\n\
-            \r\n"
-    };
-
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+    let client = reqwest::Client::builder()
+        .cookie_store(true)
+        .build()
+        .unwrap();
+
+    let res = client.post(&format!("http://{}/login", server.addr()))
+        .form(&[("user", "alice")])
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::NoContent);
+    let res = client.get(&format!("http://{}/account/settings", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_cookie_store_redirect() {
+    let server = server! {
+        request: b"\
+            GET /start HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        // a secure cookie isn't stored from http, and one with a path is
+        // only sent to that path
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Location: /landing\r\n\
+            Set-Cookie: hop=1\r\n\
+            Set-Cookie: secure=2; Secure\r\n\
+            Set-Cookie: admin=3; Path=/admin\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ",
+        request: b"\
+            GET /landing HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Referer: http://$HOST/start\r\n\
+            Cookie: hop=1\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Set-Cookie: hop=4\r\n\
+            Set-Cookie: done=5\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ",
+        request: b"\
+            GET /admin/users HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Cookie: theme=dark; admin=3; hop=4; done=5\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+    let client = reqwest::Client::builder()
+        .cookie_store(true)
+        .build()
+        .unwrap();
+
+    let res = client.get(&format!("http://{}/start", server.addr())).send().unwrap();
+    assert_eq!(res.url().as_str(), format!("http://{}/landing", server.addr()));
+    // the stored cookies come after those of the request
+    let res = client.get(&format!("http://{}/admin/users", server.addr()))
+        .header(reqwest::header::Cookie(vec!["theme=dark".to_owned()]))
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_cookie_store_redirect_to_other_host() {
+    use std::net;
+    use std::sync::mpsc;
+    use std::thread;
+
+    // the other host, which isn't sent the cookie of the first one
+    let other = server! {
+        request: b"\
+            GET /landing HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Set-Cookie: other=2\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    // the first host is `localhost`, so that it isn't the same host as the
+    // other one, at 127.0.0.1
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let location = format!("http://{}/landing", other.addr());
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+        for socket in listener.incoming().take(2) {
+            let mut socket = socket.unwrap();
+            let mut req = Vec::new();
+            while !req.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).unwrap();
+                req.extend_from_slice(&buf[..n]);
+            }
+            let req = String::from_utf8(req).unwrap();
+            let res = if req.starts_with("GET /login ") {
+                format!("\
+                    HTTP/1.1 302 Found\r\n\
+                    Location: {}\r\n\
+                    Set-Cookie: session=1\r\n\
+                    Connection: close\r\n\
+                    Content-Length: 0\r\n\
+                    \r\n", location)
+            } else {
+                "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n".to_owned()
+            };
+            tx.send(req).unwrap();
+            socket.write_all(res.as_bytes()).unwrap();
+        }
+    });
+
+    // the `Referer` would have the port of the first host
+    let client = reqwest::Client::builder()
+        .cookie_store(true)
+        .referer(false)
+        .build()
+        .unwrap();
+    let res = client.get(&format!("http://localhost:{}/login", port)).send().unwrap();
+    assert_eq!(res.url().as_str(), format!("http://{}/landing", other.addr()));
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert!(!rx.recv().unwrap().contains("Cookie:"));
+
+    // the first host still gets its own cookie, and not the other's
+    let res = client.get(&format!("http://localhost:{}/dashboard", port)).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::NoContent);
+    let req = rx.recv().unwrap();
+    assert!(req.contains("\r\nCookie: session=1\r\n"), "{}", req);
+}
+
+#[test]
+fn test_redirect_request_mutator_applies_to_each_hop() {
+    let server = server! {
+        request: b"\
+            GET /sign-1 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-sign\r\n\
+            Location: /sign-2\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /sign-2 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Referer: http://$HOST/sign-1\r\n\
+            X-Signature: signed:/sign-2\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-sign\r\n\
+            Location: /sign-3\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /sign-3 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Referer: http://$HOST/sign-2\r\n\
+            X-Signature: signed:/sign-3\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-signed\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let mut client = reqwest::Client::new().unwrap();
+    // appending means a signature leaking into the next hop would be sent twice
+    client.redirect_request_mutator(|headers, next| {
+        let signature = format!("signed:{}", next.path());
+        headers.append_raw("X-Signature", signature.into_bytes());
+    });
+
+    let url = format!("http://{}/sign-1", server.addr());
+    let res = client.get(&url)
+        .send()
+        .unwrap();
+
+    assert_eq!(res.url().as_str(), format!("http://{}/sign-3", server.addr()));
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert_eq!(res.headers().get(), Some(&reqwest::header::Server("test-signed".to_string())));
+}
+
+#[cfg(feature = "default-tls")]
+#[test]
+fn test_user_agent_with_details() {
+    let server = server! {
+        request: b"\
+            GET /ua HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT (native-tls)\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-ua\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+    let mut client = reqwest::Client::new().unwrap();
+    client.user_agent_with_details(true);
+
+    let res = client.get(&format!("http://{}/ua", server.addr()))
+        .send()
+        .unwrap();
+
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_user_agent() {
+    let server = server! {
+        request: b"\
+            GET /default HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+        request: b"\
+            GET /custom HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: my-app/1.0\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+        request: b"\
+            GET /none HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            "
+    };
+
+    let mut client = reqwest::Client::new().unwrap();
+    let res = client.get(&format!("http://{}/default", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+
+    let custom = reqwest::Client::builder()
+        .user_agent("my-app/1.0")
+        .user_agent_with_details(true)
+        .build()
+        .unwrap();
+    let res = custom.get(&format!("http://{}/custom", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+
+    client.user_agent("");
+    let res = client.get(&format!("http://{}/none", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[cfg(feature = "rustls-tls")]
+#[test]
+fn test_user_agent_with_details_rustls() {
+    let server = server! {
+        request: b"\
+            GET /ua HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT (rustls)\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-ua\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+    let client = reqwest::Client::builder()
+        .use_rustls_tls()
+        .user_agent_with_details(true)
+        .build()
+        .unwrap();
+
+    let res = client.get(&format!("http://{}/ua", server.addr()))
+        .send()
+        .unwrap();
+
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[cfg(all(feature = "default-tls", not(feature = "rustls-tls"), not(feature = "brotli")))]
+#[test]
+fn test_features() {
+    assert_eq!(reqwest::version(), env!("CARGO_PKG_VERSION"));
+    assert_eq!(reqwest::features(), &["native-tls", "gzip", "deflate"]);
+}
+
+#[cfg(all(feature = "default-tls", feature = "rustls-tls", not(feature = "brotli")))]
+#[test]
+fn test_features_with_both_tls_backends() {
+    assert_eq!(reqwest::features(), &["native-tls", "rustls", "gzip", "deflate"]);
+}
+
+#[cfg(all(not(feature = "default-tls"), feature = "rustls-tls", not(feature = "brotli")))]
+#[test]
+fn test_features_with_rustls() {
+    assert_eq!(reqwest::features(), &["rustls", "gzip", "deflate"]);
+}
+
+#[cfg(feature = "brotli")]
+#[test]
+fn test_features_with_brotli() {
+    assert!(reqwest::features().ends_with(&["gzip", "deflate", "br"]));
+}
+
+#[test]
+fn test_http_only() {
+    let server = server! {
+        request: b"\
+            GET /plain HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-http-only\r\n\
+            Location: https://secure.example.com/plain\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::builder()
+        .http_only()
+        .build()
+        .unwrap();
+    // the redirect is refused, before connecting to anything
+    let url = format!("http://{}/plain", server.addr());
+    match client.get(&url).send() {
+        Err(reqwest::Error::UnsupportedScheme(url)) => {
+            assert_eq!(url.as_str(), "https://secure.example.com/plain");
+        },
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let err = client.get("https://secure.example.com/").send().unwrap_err();
+    assert_eq!(err.to_string(),
+               "Unsupported scheme, no TLS to send to https://secure.example.com/");
+}
+
+#[cfg(not(any(feature = "default-tls", feature = "rustls-tls")))]
+#[test]
+fn test_features_without_tls() {
+    let decodings: &[&str] = if cfg!(feature = "brotli") {
+        &["gzip", "deflate", "br"]
+    } else {
+        &["gzip", "deflate"]
+    };
+    assert_eq!(reqwest::features(), decodings);
+
+    let client = reqwest::Client::new().unwrap();
+    match client.get("https://example.com/").send() {
+        Err(reqwest::Error::UnsupportedScheme(url)) => assert_eq!(url.scheme(), "https"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_headers_merge() {
+    let server = server! {
+        request: b"\
+            GET /merge HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Accept: text/html\r\n\
+            Cookie: a=1; b=2\r\n\
+            Via: 1.1 proxy-a, 1.1 proxy-b\r\n\
+            X-Forwarded-For: 10.0.0.1, 10.0.0.2\r\n\
+            X-Request-Id: two\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-headers\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let mut first = reqwest::header::Headers::new();
+    first.set(reqwest::header::Accept::json());
+    first.set(reqwest::header::Cookie(vec!["a=1".to_owned()]));
+    first.set_raw("Via", vec![b"1.1 proxy-a".to_vec()]);
+    first.set_raw("X-Forwarded-For", vec![b"10.0.0.1".to_vec()]);
+    first.set_raw("X-Request-Id", vec![b"one".to_vec()]);
+
+    let mut second = reqwest::header::Headers::new();
+    second.set_raw("Accept", vec![b"text/html".to_vec()]);
+    second.set(reqwest::header::Cookie(vec!["b=2".to_owned()]));
+    second.set_raw("Via", vec![b"1.1 proxy-b".to_vec()]);
+    second.set_raw("X-Forwarded-For", vec![b"10.0.0.2".to_vec()]);
+    second.set_raw("X-Request-Id", vec![b"two".to_vec()]);
+
+    let client = reqwest::Client::builder()
+        .append_headers(&["x-forwarded-for"])
+        .build()
+        .unwrap();
+    let res = client.get(&format!("http://{}/merge", server.addr()))
+        .headers(first)
+        .headers(second)
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_headers_replace() {
+    let server = server! {
+        request: b"\
+            GET /replace HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            X-Second: 2\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-headers\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let mut first = reqwest::header::Headers::new();
+    first.set(reqwest::header::Accept::json());
+    first.set_raw("X-First", vec![b"1".to_vec()]);
+    let mut second = reqwest::header::Headers::new();
+    second.set_raw("X-Second", vec![b"2".to_vec()]);
+
+    let client = reqwest::Client::new().unwrap();
+    let res = client.get(&format!("http://{}/replace", server.addr()))
+        .headers(first)
+        .headers_replace(second)
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_accept_header_is_not_changed_if_set() {
+    let server = server! {
+        request: b"\
+            GET /accept HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Accept: application/json\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-accept\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+    let client = reqwest::Client::new().unwrap();
+
+    let res = client.get(&format!("http://{}/accept", server.addr()))
+        .header(reqwest::header::Accept::json())
+        .send()
+        .unwrap();
+
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_accept_encoding_header_is_not_changed_if_set() {
+    let server = server! {
+        request: b"\
+            GET /accept-encoding HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Accept-Encoding: identity\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-accept-encoding\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+    let client = reqwest::Client::new().unwrap();
+
+    let res = client.get(&format!("http://{}/accept-encoding", server.addr()))
+        .header(reqwest::header::AcceptEncoding(
+            vec![reqwest::header::qitem(reqwest::header::Encoding::Identity)]
+        ))
+        .send()
+        .unwrap();
+
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_gzip_response() {
+    let mut encoder = ::libflate::gzip::Encoder::new(Vec::new()).unwrap();
+    match encoder.write(b"test request") {
+        Ok(n) => assert!(n > 0, "Failed to write to encoder."),
+        _ => panic!("Failed to gzip encode string.")
+    };
+
+    let gzipped_content = encoder.finish().into_result().unwrap();
+
+    let mut response = format!("\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-accept\r\n\
+            Content-Encoding: gzip\r\n\
+            Content-Length: {}\r\n\
+            \r\n", &gzipped_content.len())
+        .into_bytes();
+    response.extend(&gzipped_content);
+
+    let server = server! {
+        request: b"\
+            GET /gzip HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: response
+    };
+    let mut res = reqwest::get(&format!("http://{}/gzip", server.addr()))
+        .unwrap();
+
+    let mut body = ::std::string::String::new();
+    match res.read_to_string(&mut body) {
+        Ok(n) => assert!(n > 0, "Failed to write to buffer."),
+        _ => panic!("Failed to write to buffer.")
+    };
+
+    assert_eq!(body, "test request");
+}
+
+/// Serves `body` with `Content-Encoding: deflate`.
+fn deflate_server(body: &[u8]) -> server::Server {
+    let mut response = format!("\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-deflate\r\n\
+            Content-Encoding: deflate\r\n\
+            Content-Length: {}\r\n\
+            \r\n", body.len())
+        .into_bytes();
+    response.extend(body);
+
+    server! {
+        request: b"\
+            GET /deflate HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: response
+    }
+}
+
+fn assert_deflated(server: server::Server, expected: &str) {
+    let mut res = reqwest::get(&format!("http://{}/deflate", server.addr())).unwrap();
+    assert_eq!(res.headers().get::<reqwest::header::ContentEncoding>(), None);
+    assert_eq!(res.headers().get::<reqwest::header::ContentLength>(), None);
+
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, expected);
+}
+
+#[test]
+fn test_deflate_response_zlib() {
+    let mut encoder = ::libflate::zlib::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(b"zlib wrapped deflate").unwrap();
+    let server = deflate_server(&encoder.finish().into_result().unwrap());
+    assert_deflated(server, "zlib wrapped deflate");
+}
+
+#[test]
+fn test_deflate_response_raw() {
+    let mut encoder = ::libflate::deflate::Encoder::new(Vec::new());
+    encoder.write_all(b"raw deflate").unwrap();
+    let server = deflate_server(&encoder.finish().into_result().unwrap());
+    assert_deflated(server, "raw deflate");
+
+    // a smaller window than zlib's default still decodes
+    let server = deflate_server(RAW_DEFLATE_9);
+    assert_deflated(server, &"raw deflate with a 512 byte window, ".repeat(32));
+}
+
+#[test]
+fn test_deflate_response_with_deflate_options() {
+    let mut encoder = ::libflate::zlib::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(b"zlib wrapped deflate").unwrap();
+    let server = deflate_server(&encoder.finish().into_result().unwrap());
+
+    // the options that were set aren't overridden by sniffing
+    let client = reqwest::Client::builder()
+        .deflate_options(reqwest::DeflateOptions {
+            raw: true,
+            window_bits: 15,
+            sniff: false,
+        })
+        .build()
+        .unwrap();
+    let mut res = client.get(&format!("http://{}/deflate", server.addr())).send().unwrap();
+    assert!(res.read_to_string(&mut String::new()).is_err());
+}
+
+// "brotli encoded body", as one uncompressed meta-block, and an empty last
+// one, which any brotli decoder must read
+#[cfg(feature = "brotli")]
+static BROTLI: &'static [u8] = b"\
+    \x20\x01\x10\x62\x72\x6f\x74\x6c\x69\x20\x65\x6e\x63\x6f\x64\x65\
+    \x64\x20\x62\x6f\x64\x79\x03";
+
+#[cfg(feature = "brotli")]
+#[test]
+fn test_brotli_response() {
+    let mut response = format!("\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-brotli\r\n\
+            Content-Encoding: br\r\n\
+            Content-Length: {}\r\n\
+            \r\n", BROTLI.len())
+        .into_bytes();
+    response.extend(BROTLI);
+
+    let server = server! {
+        request: b"\
+            GET /brotli HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: gzip, deflate, br\r\n\
+            \r\n\
+            ",
+        response: response
+    };
+    let mut res = reqwest::get(&format!("http://{}/brotli", server.addr())).unwrap();
+    assert_eq!(res.headers().get::<reqwest::header::ContentEncoding>(), None);
+    assert_eq!(res.headers().get::<reqwest::header::ContentLength>(), None);
+
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "brotli encoded body");
+}
+
+#[cfg(feature = "brotli")]
+#[test]
+fn test_brotli_truncated() {
+    let truncated = &BROTLI[..BROTLI.len() / 2];
+    let mut response = format!("\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-brotli\r\n\
+            Content-Encoding: br\r\n\
+            Content-Length: {}\r\n\
+            \r\n", truncated.len())
+        .into_bytes();
+    response.extend(truncated);
+
+    let server = server! {
+        request: b"\
+            GET /brotli HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: gzip, deflate, br\r\n\
+            \r\n\
+            ",
+        response: response
+    };
+    let mut res = reqwest::get(&format!("http://{}/brotli", server.addr())).unwrap();
+    let err = res.text().unwrap_err();
+    assert!(err.is_decompress(), "{:?}", err);
+    assert_eq!(err.decompress_reason(), Some(reqwest::DecompressReason::UnexpectedEof));
+}
+
+/// Serves `body` with `Content-Encoding: gzip`, and a `Content-Length` of
+/// what is sent.
+fn gzip_server(body: &[u8]) -> server::Server {
+    let mut response = format!("\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-gzip\r\n\
+            Content-Encoding: gzip\r\n\
+            Content-Length: {}\r\n\
+            \r\n", body.len())
+        .into_bytes();
+    response.extend(body);
+
+    server! {
+        request: b"\
+            GET /gzip HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: response
+    }
+}
+
+fn gzipped(body: &[u8]) -> Vec<u8> {
+    let mut encoder = ::libflate::gzip::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(body).unwrap();
+    encoder.finish().into_result().unwrap()
+}
+
+fn gzip_error(body: &[u8]) -> reqwest::Error {
+    let server = gzip_server(body);
+    let mut res = reqwest::get(&format!("http://{}/gzip", server.addr())).unwrap();
+    res.text().unwrap_err()
+}
+
+#[test]
+fn test_gzip_truncated() {
+    let gzipped = gzipped(&b"cut short ".repeat(100));
+    let err = gzip_error(&gzipped[..gzipped.len() / 2]);
+    assert!(err.is_decompress(), "{:?}", err);
+    assert_eq!(err.decompress_reason(), Some(reqwest::DecompressReason::UnexpectedEof));
+}
+
+#[test]
+fn test_gzip_short_body() {
+    // the connection is closed before the whole `Content-Length` is sent
+    let gzipped = gzipped(&b"cut short ".repeat(100));
+    let mut response = format!("\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-gzip\r\n\
+            Content-Encoding: gzip\r\n\
+            Content-Length: {}\r\n\
+            \r\n", gzipped.len())
+        .into_bytes();
+    response.extend(&gzipped[..gzipped.len() / 2]);
+
+    let server = server! {
+        request: b"\
+            GET /gzip HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: response
+    };
+    let mut res = reqwest::get(&format!("http://{}/gzip", server.addr())).unwrap();
+    let err = res.text().unwrap_err();
+    assert!(err.is_decompress(), "{:?}", err);
+    assert_eq!(err.decompress_reason(), Some(reqwest::DecompressReason::UnexpectedEof));
+}
+
+#[test]
+fn test_gzip_corrupt() {
+    // flips a bit of the CRC32 in the trailer
+    let mut gzipped = gzipped(b"corrupted");
+    let crc = gzipped.len() - 8;
+    gzipped[crc] ^= 1;
+    let err = gzip_error(&gzipped);
+    assert_eq!(err.decompress_reason(), Some(reqwest::DecompressReason::CrcMismatch));
+    assert!(err.to_string().contains("checksum mismatch"), "{}", err);
+
+    let err = gzip_error(b"not gzip at all");
+    assert_eq!(err.decompress_reason(), Some(reqwest::DecompressReason::InvalidData));
+}
+
+#[test]
+fn test_gzip_empty_body() {
+    let server = server! {
+        request: b"\
+            HEAD /gzip HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-accept\r\n\
+            Content-Encoding: gzip\r\n\
+            Content-Length: 100\r\n\
+            \r\n"
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let mut res = client.head(&format!("http://{}/gzip", server.addr()))
+        .send()
+        .unwrap();
+
+    let mut body = ::std::string::String::new();
+    res.read_to_string(&mut body).unwrap();
+
+    assert_eq!(body, "");
+}
+
+#[test]
+fn test_gzip_invalid_body() {
+    let server = server! {
+        request: b"\
+            GET /gzip HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-accept\r\n\
+            Content-Encoding: gzip\r\n\
+            Content-Length: 100\r\n\
+            \r\n\
+            0"
+    };
+
+    let mut res = reqwest::get(&format!("http://{}/gzip", server.addr()))
+        .unwrap();
+    // this tests that the request.send() didn't error, but that the error
+    // is in reading the body
+
+    let mut body = ::std::string::String::new();
+    res.read_to_string(&mut body).unwrap_err();
+}
+
+#[test]
+fn test_response_before_request_body_is_sent() {
+    use std::net;
+    use std::thread;
+
+    const LEN: u64 = 64 * 1024 * 1024;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut socket, _addr) = listener.accept().unwrap();
+        let mut buf = [0; 1024];
+        socket.read_exact(&mut buf).unwrap();
+        socket.write_all(b"\
+            HTTP/1.1 413 Payload Too Large\r\n\
+            Server: test-early\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ").unwrap();
+
+        // keep reading what was already sent, until the client gives up
+        let mut received = buf.len() as u64;
+        let mut buf = [0; 8192];
+        loop {
+            match socket.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => received += n as u64,
+            }
+        }
+        received
+    });
+
+    let client = reqwest::Client::new().unwrap();
+    let body = reqwest::Body::sized(::std::io::repeat(b'a').take(LEN), LEN);
+    let res = client.post(&format!("http://{}/upload", addr))
+        .body(body)
+        .send()
+        .unwrap();
+
+    assert_eq!(res.status(), &reqwest::StatusCode::PayloadTooLarge);
+    assert_eq!(res.headers().get(), Some(&reqwest::header::Server("test-early".to_string())));
+    assert!(res.request_body_incomplete());
+    drop(res);
+
+    let received = server.join().unwrap();
+    assert!(received < LEN, "the whole body was sent");
+}
+
+#[test]
+fn test_options_cors() {
+    let server = server! {
+        request: b"\
+            OPTIONS /cors HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 204 No Content\r\n\
+            Server: test-cors\r\n\
+            Allow: GET, HEAD,options\r\n\
+            Access-Control-Allow-Origin: https://example.com\r\n\
+            Access-Control-Allow-Methods: GET,PUT\r\n\
+            Access-Control-Allow-Methods: put, DELETE\r\n\
+            Access-Control-Allow-Headers: Content-Type, X-Custom, content-type\r\n\
+            Access-Control-Max-Age: 600\r\n\
+            \r\n\
+            "
+    };
+    let client = reqwest::Client::new().unwrap();
+    let res = client.options(&format!("http://{}/cors", server.addr()))
+        .send()
+        .unwrap();
+
+    assert_eq!(res.allowed_methods(), vec![
+        reqwest::Method::Get,
+        reqwest::Method::Head,
+        reqwest::Method::Options,
+    ]);
+
+    let cors = res.cors().unwrap();
+    assert_eq!(cors.allow_origin(), "https://example.com");
+    assert_eq!(cors.allow_methods(), &[
+        reqwest::Method::Get,
+        reqwest::Method::Put,
+        reqwest::Method::Delete,
+    ]);
+    assert_eq!(cors.allow_headers(), &["content-type".to_owned(), "x-custom".to_owned()]);
+    assert_eq!(cors.max_age(), Some(::std::time::Duration::from_secs(600)));
+}
+
+#[test]
+fn test_body_channel() {
+    let server = server! {
+        request: b"\
+            POST /channel HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Transfer-Encoding: chunked\r\n\
+            \r\n\
+            5\r\n\
+            Hello\r\n\
+            1\r\n\
+            \x20\r\n\
+            5\r\n\
+            World\r\n\
+            0\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-channel\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let (mut tx, body) = reqwest::Body::channel(1);
+    let producer = ::std::thread::spawn(move || {
+        for chunk in &["Hello", " ", "World"] {
+            tx.send(chunk.as_bytes().to_vec()).unwrap();
+        }
+        tx.finish().unwrap();
+    });
+
+    let client = reqwest::Client::new().unwrap();
+    let res = client.post(&format!("http://{}/channel", server.addr()))
+        .body(body)
+        .send()
+        .unwrap();
+
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    producer.join().unwrap();
+}
+
+#[test]
+fn test_body_channel_sender_dropped() {
+    use std::net;
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut socket, _addr) = listener.accept().unwrap();
+        let mut buf = Vec::new();
+        let _ = socket.read_to_end(&mut buf);
+    });
+
+    let (mut tx, body) = reqwest::Body::channel(1);
+    let producer = thread::spawn(move || {
+        tx.send(b"partial".to_vec()).unwrap();
+        // dropped without finish()
+    });
+
+    let client = reqwest::Client::new().unwrap();
+    let err = client.post(&format!("http://{}/channel", addr))
+        .body(body)
+        .send()
+        .unwrap_err();
+
+    match err {
+        reqwest::Error::BodyAborted => (),
+        e => panic!("wrong error received: {:?}", e),
+    }
+    producer.join().unwrap();
+}
+
+#[test]
+fn test_dropped_responses_are_drained() {
+    use std::net;
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        // only a single connection is accepted, so every request must reuse it
+        let (mut socket, _addr) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        for _ in 0..11 {
+            let mut req = Vec::new();
+            while !req.ends_with(b"\r\n\r\n") {
+                let n = socket.read(&mut buf).unwrap();
+                assert!(n > 0, "client closed the connection");
+                req.extend_from_slice(&buf[..n]);
+            }
+            socket.write_all(b"\
+                HTTP/1.1 200 OK\r\n\
+                Server: test-drain\r\n\
+                Content-Length: 5\r\n\
+                \r\n\
+                hello\
+                ").unwrap();
+        }
+    });
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.timeout(::std::time::Duration::from_secs(5)).unwrap();
+    let url = format!("http://{}/drain", addr);
+
+    for _ in 0..10 {
+        let res = client.get(&url).send().unwrap();
+        assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    }
+
+    let mut res = client.get(&url).send().unwrap();
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "hello");
+    drop(res);
+
+    let stats = client.pool_stats();
+    assert_eq!(stats.drained_on_drop(), 10);
+    assert_eq!(stats.closed_on_drop(), 0);
+}
+
+#[test]
+fn test_send_and_discard() {
+    use std::net;
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        // only a single connection is accepted, so the second request must reuse it
+        let (mut socket, _addr) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        for response in &[&b"\
+            HTTP/1.1 202 Accepted\r\n\
+            Content-Encoding: gzip\r\n\
+            Content-Length: 15\r\n\
+            \r\n\
+            not gzip at all"[..], &b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 5\r\n\
+            \r\n\
+            hello"[..]] {
+            let mut req = Vec::new();
+            while !req.ends_with(b"\r\n\r\n") {
+                let n = socket.read(&mut buf).unwrap();
+                assert!(n > 0, "client closed the connection");
+                req.extend_from_slice(&buf[..n]);
+            }
+            socket.write_all(response).unwrap();
+        }
+    });
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.timeout(::std::time::Duration::from_secs(5)).unwrap();
+    let url = format!("http://{}/hook", addr);
+
+    // the body would fail to decode
+    let head = client.get(&url).send_and_discard().unwrap();
+    assert_eq!(head.status(), &reqwest::StatusCode::Accepted);
+    assert!(head.is_success());
+    assert_eq!(head.url().as_str(), url);
+    assert_eq!(head.headers().get(), Some(&reqwest::header::ContentEncoding(vec![
+        reqwest::header::Encoding::Gzip,
+    ])));
+
+    let mut res = client.get(&url).send().unwrap();
+    assert!(res.connection_reused());
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "hello");
+    drop(res);
+    assert_eq!(client.pool_stats().drained_on_drop(), 1);
+}
+
+#[test]
+fn test_response_drain_and_close() {
+    use std::net;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    let mut encoder = ::libflate::gzip::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(&vec![b'a'; 256 * 1024]).unwrap();
+    let gzipped = encoder.finish().into_result().unwrap();
+    let mut large = b"\
+        HTTP/1.1 200 OK\r\n\
+        Content-Encoding: gzip\r\n\
+        Transfer-Encoding: chunked\r\n\
+        \r\n\
+        ".to_vec();
+    for chunk in gzipped.chunks(1000) {
+        large.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+        large.extend_from_slice(chunk);
+        large.extend_from_slice(b"\r\n");
+    }
+    large.extend_from_slice(b"0\r\n\r\n");
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let accepted = connections.clone();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            accepted.fetch_add(1, Ordering::SeqCst);
+            let large = large.clone();
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                loop {
+                    let mut req = Vec::new();
+                    while !req.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf) {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => req.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let res = if req.starts_with(b"GET /large ") {
+                        &large[..]
+                    } else {
+                        &b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"[..]
+                    };
+                    if socket.write_all(res).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.timeout(::std::time::Duration::from_secs(5)).unwrap();
+
+    // too large to drain on drop, and partially read
+    let mut res = client.get(&format!("http://{}/large", addr)).send().unwrap();
+    let mut start = [0; 100];
+    res.read_exact(&mut start).unwrap();
+    res.drain().unwrap();
+    let mut res = client.get(&format!("http://{}/small", addr)).send().unwrap();
+    assert!(res.connection_reused());
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    // the whole body was read, and the connection would be reused
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "hello");
+    res.close();
+    let res = client.get(&format!("http://{}/small", addr)).send().unwrap();
+    assert!(!res.connection_reused());
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+    res.drain().unwrap();
+
+    let stats = client.pool_stats();
+    assert_eq!(stats.drained_on_drop(), 0);
+    assert_eq!(stats.closed_on_drop(), 0);
+}
+
+#[test]
+fn test_response_drain_captures_trailers() {
+    use std::net;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let accepted = connections.clone();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            accepted.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                loop {
+                    let mut req = Vec::new();
+                    while !req.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf) {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => req.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let res = if req.starts_with(b"GET /trailers ") {
+                        &b"\
+                            HTTP/1.1 200 OK\r\n\
+                            Transfer-Encoding: chunked\r\n\
+                            Trailer: Checksum, Server-Timing\r\n\
+                            \r\n\
+                            5;ext=1\r\n\
+                            hello\r\n\
+                            0\r\n\
+                            Checksum: abc\r\n\
+                            Server-Timing: db;dur=53\r\n\
+                            Server-Timing: app;dur=47\r\n\
+                            \r\n\
+                            "[..]
+                    } else if req.starts_with(b"GET /plain ") {
+                        &b"\
+                            HTTP/1.1 200 OK\r\n\
+                            Transfer-Encoding: chunked\r\n\
+                            \r\n\
+                            5\r\n\
+                            world\r\n\
+                            0\r\n\
+                            \r\n\
+                            "[..]
+                    } else {
+                        &b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nagain"[..]
+                    };
+                    if socket.write_all(res).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+
+    let client = reqwest::Client::new().unwrap();
+    let res = client.get(&format!("http://{}/trailers", addr)).send().unwrap();
+    let trailers = res.drain().unwrap().unwrap();
+    assert_eq!(trailers.get_raw("Checksum").unwrap(), &[b"abc".to_vec()][..]);
+    assert_eq!(trailers.get_raw("Server-Timing").unwrap(),
+               &[b"db;dur=53".to_vec(), b"app;dur=47".to_vec()][..]);
+    assert_eq!(trailers.len(), 2);
+
+    // the trailers weren't taken as the next response
+    let mut res = client.get(&format!("http://{}/plain", addr)).send().unwrap();
+    assert!(res.connection_reused());
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "world");
+    assert_eq!(res.trailers().map(|trailers| trailers.len()), Some(0));
+    assert_eq!(res.framing_anomaly(), None);
+    drop(res);
+
+    let mut res = client.get(&format!("http://{}/again", addr)).send().unwrap();
+    assert!(res.connection_reused());
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "again");
+    assert!(res.trailers().is_none());
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_dropped_responses_with_large_body_are_closed() {
+    let server = server! {
+        request: b"\
+            GET /large HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-large\r\n\
+            Content-Length: 2048\r\n\
+            \r\n\
+            only the start of the body\
+            ",
+
+        request: b"\
+            GET /large HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-large\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.drain_on_drop(1024);
+    let url = format!("http://{}/large", server.addr());
+
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    drop(res);
+
+    // the server only answers a second request on a new connection
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+
+    let stats = client.pool_stats();
+    assert_eq!(stats.drained_on_drop(), 0);
+    assert_eq!(stats.closed_on_drop(), 1);
+}
+
+#[test]
+fn test_get_reuses_default_client() {
+    use std::net;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let connections = Arc::new(AtomicUsize::new(0));
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            let id = connections.fetch_add(1, Ordering::SeqCst) + 1;
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                loop {
+                    let mut req = Vec::new();
+                    while !req.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf) {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => req.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let res = format!("\
+                        HTTP/1.1 200 OK\r\n\
+                        X-Connection: {}\r\n\
+                        Content-Length: 0\r\n\
+                        \r\n\
+                        ", id);
+                    socket.write_all(res.as_bytes()).unwrap();
+                }
+            });
+        }
+    });
+
+    let url = format!("http://{}/shared", addr);
+    for _ in 0..2 {
+        let res = reqwest::get(&url).unwrap();
+        assert_eq!(res.headers().get_raw("X-Connection"), Some(&[b"1".to_vec()][..]));
+    }
+}
+
+#[test]
+fn test_unsolicited_interim_responses_are_skipped() {
+    use std::net;
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut socket, _addr) = listener.accept().unwrap();
+        let responses: [&[u8]; 2] = [
+            b"\
+            HTTP/1.1 100 Continue\r\n\
+            \r\n\
+            HTTP/1.1 204 No Content\r\n\
+            Server: test-interim\r\n\
+            \r\n\
+            ",
+            b"\
+            HTTP/1.1 102 Processing\r\n\
+            \r\n\
+            HTTP/1.1 103 Early Hints\r\n\
+            Link: </style.css>; rel=preload\r\n\
+            \r\n\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 5\r\n\
+            \r\n\
+            Hello\
+            ",
+        ];
+        let mut buf = [0; 4096];
+        for res in &responses {
+            let mut req = Vec::new();
+            while !req.ends_with(b"\r\n\r\n") {
+                match socket.read(&mut buf) {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => req.extend_from_slice(&buf[..n]),
+                }
+            }
+            socket.write_all(res).unwrap();
+        }
+    });
+
+    let client = reqwest::Client::new().unwrap();
+    let res = client.get(&format!("http://{}/interim", addr)).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::NoContent);
+    assert_eq!(res.headers().get(), Some(&reqwest::header::Server("test-interim".to_string())));
+    drop(res);
+
+    // the same connection is used again
+    let mut res = client.get(&format!("http://{}/interim", addr)).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "Hello");
+}
+
+#[test]
+fn test_negative_cache_fails_fast_until_ttl_expires() {
+    use std::net;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // find a port that refuses connections
+    let addr = net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let url = format!("http://{}/down", addr);
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.negative_cache_ttl(Duration::from_millis(500));
+
+    let err = client.get(&url).send().unwrap_err();
+    assert!(!err.from_negative_cache());
+
+    // the server comes up, but the failure is still remembered
+    let listener = net::TcpListener::bind(addr).unwrap();
+    let started = Instant::now();
+    let err = client.get(&url).send().unwrap_err();
+    assert!(err.from_negative_cache(), "error was not cached: {}", err);
+
+    thread::spawn(move || {
+        let (mut socket, _addr) = listener.accept().unwrap();
+        let mut req = Vec::new();
+        let mut buf = [0; 4096];
+        while !req.ends_with(b"\r\n\r\n") {
+            match socket.read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => req.extend_from_slice(&buf[..n]),
+            }
+        }
+        socket.write_all(b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ").unwrap();
+    });
+
+    let elapsed = started.elapsed();
+    if elapsed < Duration::from_millis(600) {
+        thread::sleep(Duration::from_millis(600) - elapsed);
+    }
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_target_forms() {
+    let client = reqwest::Client::new().unwrap();
+    let forms = [
+        (reqwest::TargetForm::Origin, "/path?q=1"),
+        (reqwest::TargetForm::Absolute, "http://$HOST/path?q=1"),
+        (reqwest::TargetForm::Asterisk, "*"),
+    ];
+
+    for &(form, target) in forms.iter() {
+        let server = server! {
+            request: format!("\
+                OPTIONS {} HTTP/1.1\r\n\
+                Host: $HOST\r\n\
+                User-Agent: $USERAGENT\r\n\
+                Accept: */*\r\n\
+                Accept-Encoding: $ACCEPT_ENCODING\r\n\
+                Content-Length: 0\r\n\
+                \r\n\
+                ", target),
+            response: b"\
+                HTTP/1.1 204 No Content\r\n\
+                Server: test-target\r\n\
+                \r\n\
+                "
+        };
+
+        let url = format!("http://{}/path?q=1", server.addr());
+        let res = client.options(&url)
+            .target_form(form)
+            .send()
+            .unwrap();
+        assert_eq!(res.status(), &reqwest::StatusCode::NoContent);
+        assert_eq!(res.headers().get(), Some(&reqwest::header::Server("test-target".to_string())));
+    }
+}
+
+#[test]
+fn test_asterisk_target_form_requires_options() {
+    let client = reqwest::Client::new().unwrap();
+    let err = client.get("http://127.0.0.1:1/")
+        .target_form(reqwest::TargetForm::Asterisk)
+        .send()
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Only OPTIONS may have the request target *, not GET");
+    match err {
+        reqwest::Error::AsteriskTarget(reqwest::Method::Get) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[test]
+fn test_max_request_line_length() {
+    let query = "x".repeat(100);
+    let client = reqwest::Client::builder()
+        .max_request_line_length(64)
+        .build()
+        .unwrap();
+    // nothing is listening, so it fails before connecting
+    let err = client.get(&format!("http://127.0.0.1:1/search?q={}", query)).send().unwrap_err();
+    match err {
+        reqwest::Error::UriTooLong(110, 64) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+    assert_eq!(err.to_string(), "Request target too long: 110 bytes, the limit is 64");
+}
+
+#[test]
+fn test_query_via_body() {
+    let query = format!("q={}&lang=en", "x".repeat(100));
+    let server = server! {
+        request: format!("\
+            POST /search HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            X-HTTP-Method-Override: GET\r\n\
+            Content-Type: application/x-www-form-urlencoded\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: {}\r\n\
+            \r\n\
+            {}", query.len(), query),
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-query-via-body\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::builder()
+        .max_request_line_length(64)
+        .build()
+        .unwrap();
+    let res = client.get(&format!("http://{}/search?{}", server.addr(), query))
+        .query_via_body()
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_http10_request() {
+    use std::net;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let received = requests.clone();
+    thread::spawn(move || {
+        // the first response doesn't keep the connection alive, the second does
+        let responses = [
+            &b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\none"[..],
+            &b"HTTP/1.0 200 OK\r\nConnection: keep-alive\r\nContent-Length: 3\r\n\r\ntwo"[..],
+            &b"HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nthree"[..],
+        ];
+        let mut responses = responses.iter();
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            let mut buf = [0; 4096];
+            loop {
+                let mut req = Vec::new();
+                while !req.ends_with(b"\r\n\r\n") {
+                    match socket.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => req.extend_from_slice(&buf[..n]),
+                    }
+                }
+                if req.is_empty() {
+                    break;
+                }
+                received.lock().unwrap().push(req);
+                match responses.next() {
+                    Some(response) => socket.write_all(response).unwrap(),
+                    None => return,
+                }
+            }
+        }
+    });
+
+    let client = reqwest::Client::new().unwrap();
+    let url = format!("http://{}/status", addr);
+    let mut bodies = Vec::new();
+    let mut reused = Vec::new();
+    for _ in 0..3 {
+        let mut res = client.post(&url)
+            .version(reqwest::HttpVersion::Http10)
+            .body("ping")
+            .send()
+            .unwrap();
+        assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+        reused.push(res.connection_reused());
+        let mut body = String::new();
+        res.read_to_string(&mut body).unwrap();
+        bodies.push(body);
+    }
+    assert_eq!(bodies, ["one", "two", "three"]);
+    assert_eq!(reused, [false, false, true]);
+
+    let requests = requests.lock().unwrap();
+    for req in requests.iter() {
+        let req = String::from_utf8_lossy(req);
+        assert!(req.starts_with("POST /status HTTP/1.0\r\n"), "{}", req);
+        assert!(req.contains("Content-Length: 4\r\n"), "{}", req);
+        assert!(!req.contains("Transfer-Encoding"), "{}", req);
+    }
+}
+
+#[test]
+fn test_http10_request_with_unsized_body() {
+    let client = reqwest::Client::new().unwrap();
+    let err = client.post("http://127.0.0.1:1/")
+        .version(reqwest::HttpVersion::Http10)
+        .body(reqwest::Body::new(std::io::Cursor::new(b"streamed".to_vec())))
+        .send()
+        .unwrap_err();
+    match err {
+        reqwest::Error::UnsizedBody => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+
+    let err = client.get("http://127.0.0.1:1/")
+        .version(reqwest::HttpVersion::Http20)
+        .send()
+        .unwrap_err();
+    match err {
+        reqwest::Error::UnsupportedVersion(reqwest::HttpVersion::Http20) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[test]
+fn test_body_after_204_is_not_read_as_next_response() {
+    use std::net;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            let first = counter.fetch_add(1, Ordering::SeqCst) == 0;
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                loop {
+                    let mut req = Vec::new();
+                    while !req.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf) {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => req.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    if req.starts_with(b"GET /empty ") {
+                        socket.write_all(b"\
+                            HTTP/1.1 204 No Content\r\n\
+                            \r\n\
+                            ").unwrap();
+                        if first {
+                            thread::sleep(Duration::from_millis(50));
+                            socket.write_all(b"\
+                                junk\r\n\
+                                HTTP/1.1 200 OK\r\n\
+                                Content-Length: 5\r\n\
+                                \r\n\
+                                wrong\
+                                ").unwrap();
+                        }
+                    } else {
+                        socket.write_all(b"\
+                            HTTP/1.1 200 OK\r\n\
+                            Content-Length: 5\r\n\
+                            \r\n\
+                            right\
+                            ").unwrap();
+                    }
+                }
+            });
+        }
+    });
+
+    let client = reqwest::Client::new().unwrap();
+    let res = client.get(&format!("http://{}/empty", addr)).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::NoContent);
+    drop(res);
+
+    // let the stray bytes arrive on the idle connection
+    thread::sleep(Duration::from_millis(200));
+
+    let mut res = client.get(&format!("http://{}/next", addr)).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "right");
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+#[test]
+fn test_body_after_204_over_tls_is_not_read_as_next_response() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    for (backend, use_tls) in tls_backends() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        let server = server::spawn_tls_with(move |i, socket| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            let mut buf = [0; 4096];
+            loop {
+                let mut req = Vec::new();
+                while !req.ends_with(b"\r\n\r\n") {
+                    match socket.read(&mut buf) {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => req.extend_from_slice(&buf[..n]),
+                    }
+                }
+                if req.starts_with(b"GET /empty ") {
+                    socket.write_all(b"\
+                        HTTP/1.1 204 No Content\r\n\
+                        \r\n\
+                        ").unwrap();
+                    socket.flush().unwrap();
+                    if i == 0 {
+                        thread::sleep(Duration::from_millis(50));
+                        socket.write_all(b"\
+                            junk\r\n\
+                            HTTP/1.1 200 OK\r\n\
+                            Content-Length: 5\r\n\
+                            \r\n\
+                            wrong\
+                            ").unwrap();
+                        socket.flush().unwrap();
+                    }
+                } else {
+                    socket.write_all(b"\
+                        HTTP/1.1 200 OK\r\n\
+                        Content-Length: 5\r\n\
+                        \r\n\
+                        right\
+                        ").unwrap();
+                    socket.flush().unwrap();
+                }
+            }
+        });
+
+        let ca = reqwest::Certificate::from_der(include_bytes!("tls/ca.der")).unwrap();
+        let client = use_tls(reqwest::Client::builder())
+            .add_root_certificate(ca)
+            .build()
+            .unwrap();
+        let url = format!("https://localhost:{}", server.addr().port());
+        let res = client.get(&format!("{}/empty", url)).send().unwrap();
+        assert_eq!(res.status(), &reqwest::StatusCode::NoContent, "{}", backend);
+        drop(res);
+
+        // let the stray bytes arrive on the idle connection
+        thread::sleep(Duration::from_millis(200));
+
+        let mut res = client.get(&format!("{}/next", url)).send().unwrap();
+        assert_eq!(res.status(), &reqwest::StatusCode::Ok, "{}", backend);
+        let mut body = String::new();
+        res.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "right", "{}", backend);
+        assert_eq!(connections.load(Ordering::SeqCst), 2, "{}", backend);
+    }
+}
+
+/// A server that closes every connection after one response, without
+/// saying so, and keeps the requests it was sent.
+fn closing_server() -> (std::net::SocketAddr, std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>,
+                        std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+    use std::net;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let connections = Arc::new(AtomicUsize::new(0));
+    let (received, counter) = (requests.clone(), connections.clone());
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+            let mut buf = [0; 4096];
+            let mut req = Vec::new();
+            loop {
+                match socket.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => req.extend_from_slice(&buf[..n]),
+                }
+                let chunked = String::from_utf8_lossy(&req).contains("Transfer-Encoding: chunked");
+                if req.ends_with(if chunked { b"0\r\n\r\n" } else { b"\r\n\r\n" }) {
+                    break;
+                }
+            }
+            received.lock().unwrap().push(req);
+            let _ = socket.write_all(b"\
+                HTTP/1.1 200 OK\r\n\
+                Content-Length: 2\r\n\
+                \r\n\
+                ok\
+                ");
+        }
+    });
+    (addr, requests, connections)
+}
+
+#[test]
+fn test_stale_connection_is_retried() {
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+
+    let (addr, requests, connections) = closing_server();
+    let client = reqwest::Client::new().unwrap();
+    let mut res = client.get(&format!("http://{}/first", addr)).send().unwrap();
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    drop(res);
+
+    // let the connection be closed while it is idle in the pool
+    thread::sleep(Duration::from_millis(100));
+
+    let mut res = client.get(&format!("http://{}/second", addr)).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert!(!res.connection_reused());
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "ok");
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+    let requests = requests.lock().unwrap();
+    assert!(requests[1].starts_with(b"GET /second "), "{:?}", requests);
+}
+
+#[test]
+fn test_stale_connection_retry_resends_body() {
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+
+    let (addr, requests, connections) = closing_server();
+    let client = reqwest::Client::new().unwrap();
+    let url = format!("http://{}/upload", addr);
+    let (body, _) = Rewinding::new(b"first", false);
+    let mut res = client.post(&url).body(reqwest::Body::resettable(body)).send().unwrap();
+    let mut text = String::new();
+    res.read_to_string(&mut text).unwrap();
+    drop(res);
+    thread::sleep(Duration::from_millis(100));
+
+    let (body, resets) = Rewinding::new(b"Hello", false);
+    let mut res = client.post(&url).body(reqwest::Body::resettable(body)).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    // once, before it was sent again on a new connection
+    assert_eq!(resets.load(Ordering::SeqCst), 1);
+    assert!(requests.lock().unwrap()[1].ends_with(b"5\r\nHello\r\n0\r\n\r\n"));
+    let mut text = String::new();
+    res.read_to_string(&mut text).unwrap();
+    drop(res);
+    thread::sleep(Duration::from_millis(100));
+
+    // a body that can't be sent again isn't retried
+    let body = reqwest::Body::new(std::io::Cursor::new(b"once".to_vec()));
+    let err = client.post(&url).body(body).send().unwrap_err();
+    match err.io_kind() {
+        Some(std::io::ErrorKind::ConnectionAborted) |
+        Some(std::io::ErrorKind::ConnectionReset) |
+        Some(std::io::ErrorKind::BrokenPipe) => (),
+        other => panic!("unexpected error: {:?} ({:?})", err, other),
+    }
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_body_past_content_length_is_discarded() {
+    use std::net;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    const EXCESS: &'static [u8] = b"\
+        HTTP/1.1 200 OK\r\n\
+        Content-Length: 5\r\n\
+        \r\n\
+        wrong\
+        ";
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                loop {
+                    let mut req = Vec::new();
+                    while !req.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf) {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => req.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    let mut response = b"\
+                        HTTP/1.1 200 OK\r\n\
+                        Content-Length: 5\r\n\
+                        \r\n\
+                        ".to_vec();
+                    if req.starts_with(b"GET /overrun ") {
+                        // with what looks like the next response right after
+                        response.extend_from_slice(b"hello");
+                        response.extend_from_slice(EXCESS);
+                    } else {
+                        response.extend_from_slice(b"right");
+                    }
+                    socket.write_all(&response).unwrap();
+                }
+            });
+        }
+    });
+
+    let client = reqwest::Client::new().unwrap();
+    let mut res = client.get(&format!("http://{}/overrun", addr)).send().unwrap();
+    assert_eq!(res.text().unwrap(), "hello");
+    assert_eq!(res.framing_anomaly(), Some(EXCESS.len() as u64));
+    drop(res);
+
+    let mut res = client.get(&format!("http://{}/next", addr)).send().unwrap();
+    assert_eq!(res.text().unwrap(), "right");
+    assert_eq!(res.framing_anomaly(), None);
+    assert!(!res.connection_reused());
+    drop(res);
+
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+    assert_eq!(client.pool_stats().overran_content_length(), 1);
+
+    // and a connection without any excess is still reused
+    let mut res = client.get(&format!("http://{}/again", addr)).send().unwrap();
+    assert_eq!(res.text().unwrap(), "right");
+    assert!(res.connection_reused());
+}
+
+#[cfg(feature = "bridge")]
+#[test]
+fn test_send_in_background_with_more_requests_than_threads() {
+    use std::net;
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                loop {
+                    let mut req = Vec::new();
+                    while !req.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf) {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => req.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    thread::sleep(Duration::from_millis(20));
+                    socket.write_all(b"\
+                        HTTP/1.1 200 OK\r\n\
+                        Content-Length: 2\r\n\
+                        \r\n\
+                        ok\
+                        ").unwrap();
+                }
+            });
+        }
+    });
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.background_threads(2);
+
+    let receivers = (0..10).map(|i| {
+        client.send_in_background(client.get(&format!("http://{}/{}", addr, i)))
+    }).collect::<Vec<_>>();
+    drop(client);
+
+    for rx in receivers {
+        let mut res = rx.recv_timeout(Duration::from_secs(10)).unwrap().unwrap();
+        let mut body = String::new();
+        res.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "ok");
+    }
+}
+
+#[test]
+fn test_error_for_status() {
+    for &(code, reason) in [(400, "Bad Request"), (500, "Internal Server Error")].iter() {
+        let server = server! {
+            request: format!("\
+                GET /err HTTP/1.1\r\n\
+                Host: $HOST\r\n\
+                User-Agent: $USERAGENT\r\n\
+                Accept: */*\r\n\
+                Accept-Encoding: $ACCEPT_ENCODING\r\n\
+                \r\n\
+                "),
+            response: format!("\
+                HTTP/1.1 {} {}\r\n\
+                Server: test-status\r\n\
+                Content-Length: 16\r\n\
+                \r\n\
+                {{\"error\":\"nope\"}}\
+                ", code, reason)
+        };
+
+        let url = format!("http://{}/err", server.addr());
+        let res = reqwest::get(&url).unwrap();
+        match res.error_for_status() {
+            Err(reqwest::Error::Status(status, err_url)) => {
+                assert_eq!(status, reqwest::StatusCode::from_u16(code));
+                assert_eq!(err_url.as_str(), url);
+            },
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_error_for_status_ref_keeps_body() {
+    let server = server! {
+        request: b"\
+            GET /err HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 404 Not Found\r\n\
+            Server: test-status\r\n\
+            Content-Length: 16\r\n\
+            \r\n\
+            {\"error\":\"nope\"}\
+            "
+    };
+
+    let url = format!("http://{}/err", server.addr());
+    let mut res = reqwest::get(&url).unwrap();
+    match res.error_for_status_ref() {
+        Err(reqwest::Error::Status(status, err_url)) => {
+            assert_eq!(status, reqwest::StatusCode::NotFound);
+            assert_eq!(err_url.as_str(), url);
+        },
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "{\"error\":\"nope\"}");
+}
+
+#[test]
+fn test_error_for_status_ok() {
+    let server = server! {
+        request: b"\
+            GET /ok HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-status\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let res = reqwest::get(&format!("http://{}/ok", server.addr())).unwrap();
+    assert!(res.error_for_status_ref().is_ok());
+    let res = res.error_for_status().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_log_requests() {
+    use std::sync::{Arc, Mutex};
+
+    struct Capture(Arc<Mutex<Vec<(log::LogLevel, String)>>>);
+
+    impl log::Log for Capture {
+        fn enabled(&self, _: &log::LogMetadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::LogRecord) {
+            if record.target() == "reqwest::request_log" {
+                self.0.lock().unwrap().push((record.level(), record.args().to_string()));
+            }
+        }
+    }
+
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let capture = Capture(lines.clone());
+    log::set_logger(|max| {
+        max.set(log::LogLevelFilter::Info);
+        Box::new(capture)
+    }).unwrap();
+
+    let server = server! {
+        request: b"\
+            GET /logged HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Authorization: Bearer secret\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Set-Cookie: session=secret\r\n\
+            Content-Length: 1229\r\n\
+            Connection: close\r\n\
+            \r\n\
+            "
+    };
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.log_requests(reqwest::LogOptions {
+        include_headers: true,
+        ..Default::default()
+    });
+    let url = format!("http://{}/logged", server.addr());
+    client.get(&url)
+        .header(reqwest::header::Authorization("Bearer secret".to_owned()))
+        .send()
+        .unwrap();
+
+    {
+        let lines = lines.lock().unwrap();
+        assert_eq!(lines.len(), 1);
+        let (level, ref line) = lines[0];
+        assert_eq!(level, log::LogLevel::Info);
+        assert!(line.starts_with(&format!("GET {} -> 200 OK in ", url)), "{}", line);
+        assert!(line.contains("ms, 1.2 KB; request headers: {"), "{}", line);
+        assert!(line.contains("Authorization: [redacted]"), "{}", line);
+        assert!(line.contains("Set-Cookie: [redacted]"), "{}", line);
+        assert!(!line.contains("secret"), "{}", line);
+    }
+
+    // a quarter of the responses, and every error, without the credentials
+    // of the URL
+    lines.lock().unwrap().clear();
+    client.log_requests(reqwest::LogOptions {
+        level: log::LogLevel::Info,
+        sample_rate: 0.25,
+        include_headers: false,
+    });
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    for _ in 0..8 {
+        let err = client.get(&format!("http://sean:secret@{}/down?token=secret", addr)).send();
+        assert!(err.is_err());
+    }
+    let lines = lines.lock().unwrap();
+    assert_eq!(lines.len(), 8);
+    for &(level, ref line) in lines.iter() {
+        assert_eq!(level, log::LogLevel::Warn);
+        assert!(line.starts_with(&format!("GET http://{}/down?[redacted] -> ", addr)), "{}", line);
+        assert!(line.contains("-> error during connect after "), "{}", line);
+        assert!(!line.contains("secret"), "{}", line);
+    }
+}
+
+#[test]
+fn test_warm_up() {
+    use std::net;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+    // both requests are answered only once both were received
+    let both = Arc::new(Barrier::new(2));
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+            let both = both.clone();
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                loop {
+                    let mut req = Vec::new();
+                    while !req.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf) {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => req.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    both.wait();
+                    socket.write_all(b"\
+                        HTTP/1.1 200 OK\r\n\
+                        Content-Length: 0\r\n\
+                        \r\n\
+                        ").unwrap();
+                }
+            });
+        }
+    });
+
+    let client = reqwest::Client::new().unwrap();
+    let url = format!("http://{}/warm", addr);
+    client.warm_up(&url, 2).unwrap();
+
+    let requests = (0..2).map(|_| {
+        let client = client.clone();
+        let url = url.clone();
+        thread::spawn(move || client.get(&url).send().unwrap().connection_reused())
+    }).collect::<Vec<_>>();
+    for req in requests {
+        assert!(req.join().unwrap());
+    }
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_warm_up_reports_failed_attempt() {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let client = reqwest::Client::new().unwrap();
+    match client.warm_up(&format!("http://{}/", addr), 2) {
+        Err(reqwest::Error::WarmUp(1, _)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+fn slow_server(delay: ::std::time::Duration) -> ::std::net::SocketAddr {
+    use std::net;
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            thread::spawn(move || {
+                let mut req = Vec::new();
+                let mut buf = [0; 4096];
+                while !req.ends_with(b"\r\n\r\n") {
+                    match socket.read(&mut buf) {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => req.extend_from_slice(&buf[..n]),
+                    }
+                }
+                thread::sleep(delay);
+                let _ = socket.write_all(b"\
+                    HTTP/1.1 200 OK\r\n\
+                    Content-Length: 0\r\n\
+                    \r\n\
+                    ");
+            });
+        }
+    });
+    addr
+}
+
+#[test]
+fn test_timeout() {
+    use std::time::Duration;
+
+    let addr = slow_server(Duration::from_millis(500));
+    let mut client = reqwest::Client::new().unwrap();
+    client.timeout(Duration::from_millis(100)).unwrap();
+    let err = client.get(&format!("http://{}/slow", addr)).send().unwrap_err();
+    assert!(err.is_timeout(), "not a timeout: {:?}", err);
+}
+
+#[test]
+fn test_timeout_kind_waiting_for_response() {
+    use std::time::Duration;
+
+    let addr = slow_server(Duration::from_millis(500));
+    let mut client = reqwest::Client::new().unwrap();
+    client.timeout(Duration::from_millis(100)).unwrap();
+    let err = client.get(&format!("http://{}/slow", addr)).send().unwrap_err();
+    assert_eq!(err.timeout_kind(), Some(reqwest::TimeoutKind::Read));
+    assert_eq!(err.to_string(), "read timeout of 100ms while waiting for the response");
+}
+
+#[test]
+fn test_response_header_timeout() {
+    use std::net;
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        socket.read(&mut buf).unwrap();
+        // each line comes well within the read timeout, but the head doesn't
+        for line in &["HTTP/1.1 200 OK\r\n", "Server: dribble\r\n", "Content-Length: 0\r\n"] {
+            if socket.write_all(line.as_bytes()).is_err() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(150));
+        }
+        let _ = socket.write_all(b"\r\n");
+    });
+
+    let client = reqwest::Client::builder()
+        .read_timeout(Duration::from_secs(5))
+        .response_header_timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let err = client.get(&format!("http://{}/dribble", addr)).send().unwrap_err();
+    assert!(err.is_timeout());
+    assert_eq!(err.timeout_kind(), Some(reqwest::TimeoutKind::ResponseHeader), "{:?}", err);
+    assert_eq!(err.to_string(),
+               "response header timeout of 200ms while waiting for the response head");
+}
+
+#[test]
+fn test_response_header_timeout_leaves_body_to_read_timeout() {
+    use std::net;
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        socket.read(&mut buf).unwrap();
+        socket.write_all(b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 4\r\n\
+            \r\n\
+            ").unwrap();
+        // the body takes longer than the response header timeout
+        for chunk in &[b"a", b"b", b"c", b"d"] {
+            thread::sleep(Duration::from_millis(100));
+            socket.write_all(&chunk[..]).unwrap();
+        }
+    });
+
+    let client = reqwest::Client::builder()
+        .read_timeout(Duration::from_secs(5))
+        .response_header_timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let mut res = client.get(&format!("http://{}/slow-body", addr)).send().unwrap();
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "abcd");
+}
+
+#[test]
+fn test_response_header_timeout_huge() {
+    use std::time::Duration;
+
+    let server = server! {
+        request: b"\
+            GET /huge HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-timeout\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    // too long to be a deadline, so the read timeout is all there is
+    let client = reqwest::Client::builder()
+        .response_header_timeout(Duration::from_secs(::std::u64::MAX))
+        .build()
+        .unwrap();
+    let res = client.get(&format!("http://{}/huge", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_total_timeout() {
+    use std::net;
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        socket.read(&mut buf).unwrap();
+        // each line comes well within the read timeout, but the head doesn't
+        for line in &["HTTP/1.1 200 OK\r\n", "Server: dribble\r\n", "Content-Length: 0\r\n"] {
+            if socket.write_all(line.as_bytes()).is_err() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(150));
+        }
+        let _ = socket.write_all(b"\r\n");
+    });
+
+    let client = reqwest::Client::builder()
+        .read_timeout(Duration::from_secs(5))
+        .total_timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let err = client.get(&format!("http://{}/dribble", addr)).send().unwrap_err();
+    assert!(err.is_timeout());
+    assert_eq!(err.timeout_kind(), Some(reqwest::TimeoutKind::Total), "{:?}", err);
+    assert_eq!(err.to_string(),
+               "total timeout of 200ms while sending the request and waiting for the response");
+}
+
+#[test]
+fn test_total_timeout_huge() {
+    use std::time::Duration;
+
+    let server = server! {
+        request: b"\
+            GET /huge HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-timeout\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    // too long to be a deadline, so there is none
+    let client = reqwest::Client::builder()
+        .total_timeout(Duration::from_secs(::std::u64::MAX))
+        .build()
+        .unwrap();
+    let res = client.get(&format!("http://{}/huge", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_timeout_kind_reading_body() {
+    use std::net;
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        socket.read(&mut buf).unwrap();
+        socket.write_all(b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 10\r\n\
+            \r\n\
+            0123\
+            ").unwrap();
+        thread::sleep(Duration::from_millis(500));
+    });
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.timeout(Duration::from_millis(100)).unwrap();
+    let mut res = client.get(&format!("http://{}/stalls", addr)).send().unwrap();
+    let mut body = Vec::new();
+    let err = reqwest::Error::from(res.read_to_end(&mut body).unwrap_err());
+    assert_eq!(body, b"0123");
+    assert!(err.is_timeout());
+    assert_eq!(err.timeout_kind(), Some(reqwest::TimeoutKind::Read));
+    assert_eq!(err.to_string(), "read timeout of 100ms while reading the response body at byte 4");
+}
+
+#[test]
+fn test_timeout_kind_sending_body() {
+    use std::net;
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        // never reads, so the body can't be sent once the buffers are full
+        let (_socket, _) = listener.accept().unwrap();
+        thread::sleep(Duration::from_secs(2));
+    });
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.timeout(Duration::from_millis(100)).unwrap();
+    let err = client.post(&format!("http://{}/upload", addr))
+        .body(vec![0; 64 * 1024 * 1024])
+        .send()
+        .unwrap_err();
+    assert_eq!(err.timeout_kind(), Some(reqwest::TimeoutKind::Write));
+    assert_eq!(err.to_string(), "write timeout of 100ms while sending the request");
+}
+
+#[test]
+fn test_no_timeout() {
+    use std::time::Duration;
+
+    let addr = slow_server(Duration::from_millis(300));
+    let mut client = reqwest::Client::new().unwrap();
+    client.timeout(Duration::from_millis(100)).unwrap();
+    client.no_timeout();
+    let res = client.get(&format!("http://{}/slow", addr)).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_idempotency_key_is_kept_for_307() {
+    use std::net;
+    use std::sync::mpsc;
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+        for (i, socket) in listener.incoming().take(2).enumerate() {
+            let mut socket = socket.unwrap();
+            let mut req = Vec::new();
+            while !req.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = socket.read(&mut buf).unwrap();
+                req.extend_from_slice(&buf[..n]);
+            }
+            let req = String::from_utf8(req).unwrap();
+            let key = req.lines()
+                .find(|line| line.starts_with("Idempotency-Key: "))
+                .map(|line| line["Idempotency-Key: ".len()..].to_owned());
+            tx.send(key).unwrap();
+            let res: &[u8] = if i == 0 {
+                b"\
+                HTTP/1.1 307 Temporary Redirect\r\n\
+                Location: /dst\r\n\
+                Content-Length: 0\r\n\
+                Connection: close\r\n\
+                \r\n\
+                "
+            } else {
+                b"\
+                HTTP/1.1 201 Created\r\n\
+                Content-Length: 0\r\n\
+                Connection: close\r\n\
+                \r\n\
+                "
+            };
+            socket.write_all(res).unwrap();
+        }
+    });
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.idempotency_keys(true);
+    let res = client.post(&format!("http://{}/pay", addr))
+        .body("amount=1")
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Created);
+
+    let first = rx.recv().unwrap().expect("no key on the first request");
+    let second = rx.recv().unwrap().expect("no key on the redirect");
+    assert_eq!(first, second);
+    assert_eq!(first.len(), 36);
+    assert_eq!(res.idempotency_key(), Some(&first[..]));
+}
+
+#[test]
+fn test_idempotency_key_set_explicitly() {
+    let server = server! {
+        request: b"\
+            POST /pay HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Idempotency-Key: mine\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let mut headers = reqwest::header::Headers::new();
+    headers.set_raw("Idempotency-Key", vec![b"mine".to_vec()]);
+    let mut client = reqwest::Client::new().unwrap();
+    client.idempotency_keys(true);
+    let res = client.post(&format!("http://{}/pay", server.addr()))
+        .headers(headers)
+        .send()
+        .unwrap();
+    assert_eq!(res.idempotency_key(), Some("mine"));
+}
+
+#[test]
+fn test_idempotency_key_of_error() {
+    // nothing listens on it
+    let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let url = format!("http://{}/pay", addr);
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.idempotency_keys(true);
+    let err = client.post(&url).send().unwrap_err();
+    assert_eq!(err.idempotency_key().map(|key| key.len()), Some(36), "{:?}", err);
+    assert!(err.is_connect(), "{:?}", err);
+    // only `POST` and `PATCH` get one
+    let err = client.get(&url).send().unwrap_err();
+    assert_eq!(err.idempotency_key(), None);
+
+    // under the diagnostics too
+    let client = reqwest::Client::builder()
+        .capture_diagnostics(1024)
+        .build()
+        .unwrap();
+    let mut headers = reqwest::header::Headers::new();
+    headers.set_raw("Idempotency-Key", vec![b"mine".to_vec()]);
+    let err = client.put(&url).headers(headers).send().unwrap_err();
+    assert!(err.diagnostics().is_some(), "{:?}", err);
+    assert_eq!(err.idempotency_key(), Some("mine"));
+}
+
+#[test]
+fn test_resolved_addrs() {
+    use std::net::{self, ToSocketAddrs};
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut socket, _addr) = listener.accept().unwrap();
+        let mut req = Vec::new();
+        let mut buf = [0; 4096];
+        while !req.ends_with(b"\r\n\r\n") {
+            let n = socket.read(&mut buf).unwrap();
+            req.extend_from_slice(&buf[..n]);
+        }
+        socket.write_all(b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ").unwrap();
+    });
+
+    // localhost may also resolve to an IPv6 address, which refuses
+    let url = format!("http://localhost:{}/addrs", addr.port());
+    let res = reqwest::get(&url).unwrap();
+    assert_eq!(res.remote_addr(), addr);
+    let expected = ("localhost", addr.port()).to_socket_addrs().unwrap().collect::<Vec<_>>();
+    assert_eq!(res.resolved_addrs(), Some(&expected[..]));
+    assert!(res.resolved_addrs().unwrap().contains(&addr));
+    assert!(!res.resolved_addrs_pinned());
+}
+
+#[test]
+fn test_fallback_base_urls() {
+    let server = server! {
+        request: b"\
+            GET /users?page=2 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-fallback\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let primary = reqwest::Url::parse(&format!("http://{}", closed)).unwrap();
+    let secondary = reqwest::Url::parse(&format!("http://{}", server.addr())).unwrap();
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.fallback_base_urls(vec![primary.clone(), secondary.clone()]);
+    let res = client.get(&format!("http://{}/users?page=2", closed)).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert_eq!(res.url().as_str(), format!("http://{}/users?page=2", server.addr()));
+    assert_eq!(res.base_url(), Some(&secondary));
+}
+
+#[test]
+fn test_fallback_base_urls_all_fail() {
+    let closed1 = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let closed2 = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.fallback_base_urls(vec![
+        reqwest::Url::parse(&format!("http://{}", closed1)).unwrap(),
+        reqwest::Url::parse(&format!("http://{}", closed2)).unwrap(),
+    ]);
+    match client.get(&format!("http://{}/users", closed1)).send() {
+        Err(reqwest::Error::Fallback(attempts)) => {
+            let urls = attempts.iter().map(|&(ref url, _)| url.to_string()).collect::<Vec<_>>();
+            assert_eq!(urls, vec![
+                format!("http://{}/users", closed1),
+                format!("http://{}/users", closed2),
+            ]);
+        },
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_metrics_sink() {
+    use std::sync::Arc;
+
+    let server = server! {
+        request: b"\
+            POST /metrics HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 5\r\n\
+            \r\n\
+            Hello\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-metrics\r\n\
+            Content-Length: 7\r\n\
+            \r\n\
+            Welcome\
+            "
+    };
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    let metrics = Arc::new(reqwest::InMemoryMetrics::new());
+    let mut client = reqwest::Client::new().unwrap();
+    client.metrics_sink(metrics.clone());
+
+    let mut res = client.post(&format!("http://{}/metrics", server.addr()))
+        .body("Hello")
+        .send()
+        .unwrap();
+    let snapshot = metrics.snapshot();
+    assert_eq!((snapshot.idle, snapshot.in_flight), (0, 1));
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    drop(res);
+
+    client.get(&format!("http://{}/metrics", closed)).send().unwrap_err();
+    client.get(&format!("http://{}/metrics", closed)).send().unwrap_err();
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.requests.len(), 2);
+    let ok = reqwest::Labels::new(reqwest::Method::Post, "127.0.0.1".to_owned(),
+                                  Some(reqwest::StatusClass::Success));
+    let ok = &snapshot.requests[&ok];
+    assert_eq!((ok.count, ok.errors), (1, 0));
+    assert_eq!((ok.request_bytes, ok.response_bytes), (5, 7));
+    let failed = reqwest::Labels::new(reqwest::Method::Get, "127.0.0.1".to_owned(), None);
+    let failed = &snapshot.requests[&failed];
+    assert_eq!((failed.count, failed.errors), (2, 2));
+    assert_eq!(failed.duration_buckets.iter().sum::<u64>(), 2);
+    // the connection of the first request went back to the pool
+    assert_eq!((snapshot.idle, snapshot.in_flight), (1, 0));
+}
+
+#[test]
+fn test_health_check_statuses() {
+    use std::time::Duration;
+
+    let client = reqwest::Client::new().unwrap();
+    let cases = [
+        ("200 OK", "healthy"),
+        ("204 No Content", "healthy"),
+        ("301 Moved Permanently", "degraded"),
+        ("429 Too Many Requests", "degraded"),
+        ("404 Not Found", "unhealthy"),
+        ("503 Service Unavailable", "unhealthy"),
+    ];
+
+    for &(status, expected) in cases.iter() {
+        let server = server! {
+            request: b"\
+                HEAD /healthz HTTP/1.1\r\n\
+                Host: $HOST\r\n\
+                User-Agent: $USERAGENT\r\n\
+                \r\n\
+                ",
+            response: format!("\
+                HTTP/1.1 {}\r\n\
+                Server: test-health\r\n\
+                Content-Length: 0\r\n\
+                \r\n\
+                ", status)
+        };
+
+        let url = format!("http://{}/healthz", server.addr());
+        let health = client.health_check(&url, Duration::from_secs(5));
+        let actual = if health.is_healthy() {
+            "healthy"
+        } else if health.is_degraded() {
+            "degraded"
+        } else {
+            "unhealthy"
+        };
+        assert_eq!(actual, expected, "{}: {:?}", status, health);
+        assert!(health.latency() < Duration::from_secs(5));
+        if let reqwest::HealthStatus::Unhealthy { error: reqwest::Error::Status(code, _), .. } = health {
+            assert_eq!(code.to_string(), status);
+        }
+    }
+}
+
+#[test]
+fn test_health_check_connect_failure() {
+    use std::time::Duration;
+
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let client = reqwest::Client::new().unwrap();
+    match client.health_check(&format!("http://{}/healthz", closed), Duration::from_secs(1)) {
+        reqwest::HealthStatus::Unhealthy { error: reqwest::Error::Http(_), .. } => (),
+        other => panic!("unexpected health: {:?}", other),
+    }
+}
+
+#[test]
+fn test_health_check_timeout() {
+    use std::time::Duration;
+
+    let addr = slow_server(Duration::from_millis(500));
+    let client = reqwest::Client::new().unwrap();
+    match client.health_check(&format!("http://{}/healthz", addr), Duration::from_millis(100)) {
+        reqwest::HealthStatus::Unhealthy { error, latency } => {
+            assert!(error.is_timeout(), "not a timeout: {:?}", error);
+            assert!(latency < Duration::from_millis(500));
+        },
+        other => panic!("unexpected health: {:?}", other),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_health_check_budget_bounds_connecting() {
+    use std::time::Duration;
+
+    let server = server::spawn_backlogged();
+    // nothing limits connecting but the budget
+    let client = reqwest::Client::builder().no_timeout().build().unwrap();
+    let budget = Duration::from_millis(200);
+    match client.health_check(&format!("http://{}/healthz", server.addr()), budget) {
+        reqwest::HealthStatus::Unhealthy { error, latency } => {
+            assert_eq!(error.timeout_kind(), Some(reqwest::TimeoutKind::Connect), "{:?}", error);
+            assert!(latency < budget + Duration::from_millis(300), "{:?}", latency);
+        },
+        other => panic!("unexpected health: {:?}", other),
+    }
+}
+
+#[test]
+fn test_health_check_budget_bounds_a_dribbling_server() {
+    use std::net;
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        socket.read(&mut buf).unwrap();
+        // each line comes well within the budget, but the head doesn't
+        for line in &["HTTP/1.1 200 OK\r\n", "Server: dribble\r\n", "Content-Length: 0\r\n"] {
+            if socket.write_all(line.as_bytes()).is_err() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(150));
+        }
+        let _ = socket.write_all(b"\r\n");
+    });
+
+    let client = reqwest::Client::builder().no_timeout().build().unwrap();
+    let budget = Duration::from_millis(200);
+    match client.health_check(&format!("http://{}/healthz", addr), budget) {
+        reqwest::HealthStatus::Unhealthy { error, latency } => {
+            assert!(error.is_timeout(), "not a timeout: {:?}", error);
+            assert!(latency < budget + Duration::from_millis(100), "{:?}", latency);
+        },
+        other => panic!("unexpected health: {:?}", other),
+    }
+}
+
+fn transfer_coded_server(coding: &str, body: &[u8]) -> server::Server {
+    let mut response = format!("\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-transfer-coding\r\n\
+            Transfer-Encoding: {}, chunked\r\n\
+            \r\n\
+            {:x}\r\n", coding, body.len())
+        .into_bytes();
+    response.extend(body);
+    response.extend(b"\r\n0\r\n\r\n");
+
+    server! {
+        request: b"\
+            GET /coded HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            TE: gzip, deflate\r\n\
+            Connection: TE\r\n\
+            \r\n\
+            ",
+        response: response
+    }
+}
+
+#[test]
+fn test_gzip_transfer_coding() {
+    let mut encoder = ::libflate::gzip::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(b"gzip transfer coding").unwrap();
+    let server = transfer_coded_server("gzip", &encoder.finish().into_result().unwrap());
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.accept_transfer_encodings(true);
+    let mut res = client.get(&format!("http://{}/coded", server.addr())).send().unwrap();
+    assert_eq!(res.headers().get(), Some(&reqwest::header::TransferEncoding(vec![
+        reqwest::header::Encoding::Chunked,
+    ])));
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "gzip transfer coding");
+}
+
+#[test]
+fn test_deflate_transfer_coding() {
+    let mut encoder = ::libflate::zlib::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(b"deflate transfer coding").unwrap();
+    let server = transfer_coded_server("deflate", &encoder.finish().into_result().unwrap());
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.accept_transfer_encodings(true);
+    let mut res = client.get(&format!("http://{}/coded", server.addr())).send().unwrap();
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "deflate transfer coding");
+}
+
+#[test]
+fn test_unsupported_transfer_coding() {
+    let server = transfer_coded_server("compress", b"not really compressed");
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.accept_transfer_encodings(true);
+    let mut res = client.get(&format!("http://{}/coded", server.addr())).send().unwrap();
+    let err = res.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert_eq!(err.to_string(), "unsupported transfer coding: compress");
+}
+
+#[test]
+fn test_transfer_coding_without_opt_in() {
+    let mut encoder = ::libflate::gzip::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(b"gzip transfer coding").unwrap();
+    let gzipped = encoder.finish().into_result().unwrap();
+    let mut response = format!("\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-transfer-coding\r\n\
+            Transfer-Encoding: gzip, chunked\r\n\
+            \r\n\
+            {:x}\r\n", gzipped.len())
+        .into_bytes();
+    response.extend(&gzipped);
+    response.extend(b"\r\n0\r\n\r\n");
+
+    let server = server! {
+        request: b"\
+            GET /coded HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: response
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let mut res = client.get(&format!("http://{}/coded", server.addr())).send().unwrap();
+    let err = res.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("accept_transfer_encodings"), "{}", err);
+}
+
+/// Accepts a single request, and sends back what it read, head and body.
+fn capturing_server() -> (std::net::SocketAddr, std::sync::mpsc::Receiver<Vec<u8>>) {
+    use std::net;
+    use std::sync::mpsc;
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut req = Vec::new();
+        let mut buf = [0; 4096];
+        let head_end = loop {
+            if let Some(pos) = req.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+            let n = socket.read(&mut buf).unwrap();
+            req.extend_from_slice(&buf[..n]);
+        };
+        let head = String::from_utf8_lossy(&req[..head_end]).to_lowercase();
+        let len = head.lines()
+            .filter_map(|line| line.trim().splitn(2, "content-length: ").nth(1))
+            .next()
+            .map_or(0, |len| len.parse::<usize>().unwrap());
+        while req.len() < head_end + len {
+            let n = socket.read(&mut buf).unwrap();
+            req.extend_from_slice(&buf[..n]);
+        }
+        socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+        tx.send(req).unwrap();
+    });
+    (addr, rx)
+}
+
+#[test]
+fn test_to_wire_bytes_matches_sent_request() {
+    let (addr, rx) = capturing_server();
+    let url = format!("http://{}/wire?x=1", addr);
+    let mut client = reqwest::Client::new().unwrap();
+    client.idempotency_keys(true);
+    let build = || {
+        // a generated key would differ between the two requests
+        let mut headers = reqwest::header::Headers::new();
+        headers.set_raw("Idempotency-Key", vec![b"fixed-key".to_vec()]);
+        client.post(&url)
+            .header(reqwest::header::Authorization("token".to_owned()))
+            .headers(headers)
+            .json(&vec!["a", "b"])
+    };
+
+    let expected = build().to_wire_bytes(true).unwrap();
+    let res = build().send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    let sent = rx.recv().unwrap();
+    assert_eq!(String::from_utf8_lossy(&sent), String::from_utf8_lossy(&expected));
+    assert!(expected.ends_with(b"\r\n\r\n[\"a\",\"b\"]"));
+
+    let head = client.post(&url).json(&vec!["a"]).to_wire_bytes(false).unwrap();
+    assert!(head.ends_with(b"Content-Length: 5\r\n\r\n"), "{}", String::from_utf8_lossy(&head));
+}
+
+#[test]
+fn test_to_wire_bytes_streamed_body() {
+    let client = reqwest::Client::new().unwrap();
+    let req = client.post("http://example.com/upload")
+        .body(reqwest::Body::new(std::io::Cursor::new(b"streamed".to_vec())));
+    let head = req.to_wire_bytes(false).unwrap();
+    let head = String::from_utf8(head).unwrap();
+    assert!(head.starts_with("POST /upload HTTP/1.1\r\nHost: example.com\r\n"), "{}", head);
+    assert!(head.contains("Transfer-Encoding: chunked\r\n"), "{}", head);
+    assert!(req.to_wire_bytes(true).is_err());
+}
+
+/// The request line that a client with `policy` sends for `path`.
+fn sent_request_line(policy: reqwest::PathPolicy, path: &str) -> String {
+    let (addr, rx) = capturing_server();
+    let client = reqwest::Client::builder()
+        .path_normalization(policy)
+        .build()
+        .unwrap();
+    let res = client.get(&format!("http://{}{}", addr, path)).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    let sent = String::from_utf8(rx.recv().unwrap()).unwrap();
+    sent.lines().next().unwrap().to_owned()
+}
+
+#[test]
+fn test_path_preserved_by_default() {
+    use reqwest::PathPolicy::Preserve;
+
+    // the url parser resolves the `.`, but nothing else changes
+    assert_eq!(sent_request_line(Preserve, "/a//b/./c%2F.."), "GET /a//b/c%2F.. HTTP/1.1");
+    assert_eq!(sent_request_line(Preserve, "/a//b/?q=%2F"), "GET /a//b/?q=%2F HTTP/1.1");
+    assert_eq!(sent_request_line(Preserve, "//%7Euser/"), "GET //%7Euser/ HTTP/1.1");
+
+    let client = reqwest::Client::new().unwrap();
+    let head = client.get("http://example.com/a//b/./c%2F..").to_wire_bytes(false).unwrap();
+    assert!(head.starts_with(b"GET /a//b/c%2F.. HTTP/1.1\r\n"),
+            "{}", String::from_utf8_lossy(&head));
+}
+
+#[test]
+fn test_path_normalized() {
+    use reqwest::PathPolicy::Normalize;
+
+    assert_eq!(sent_request_line(Normalize, "/a//b/./c%2F.."), "GET /a/b/c%2F.. HTTP/1.1");
+    assert_eq!(sent_request_line(Normalize, "/a//b/?q=%2F"), "GET /a/b/?q=%2F HTTP/1.1");
+    assert_eq!(sent_request_line(Normalize, "//%7Euser/"), "GET /%7Euser/ HTTP/1.1");
+    assert_eq!(sent_request_line(Normalize, "//"), "GET / HTTP/1.1");
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.path_normalization(Normalize);
+    let head = client.get("http://example.com/a//b/./c%2F..").to_wire_bytes(false).unwrap();
+    assert!(head.starts_with(b"GET /a/b/c%2F.. HTTP/1.1\r\n"),
+            "{}", String::from_utf8_lossy(&head));
+}
+
+/// The request line that a client with `base` sends for `url`.
+fn joined_request_line(base: &str, url: &str) -> String {
+    let client = reqwest::Client::builder()
+        .base_url(reqwest::Url::parse(base).unwrap())
+        .build()
+        .unwrap();
+    let head = client.get(url).to_wire_bytes(false).unwrap();
+    String::from_utf8(head).unwrap().lines().next().unwrap().to_owned()
+}
+
+#[test]
+fn test_base_url_join() {
+    // with a trailing slash, the relative path is added to the base path
+    assert_eq!(joined_request_line("http://example.com/v1/", "users"),
+               "GET /v1/users HTTP/1.1");
+    assert_eq!(joined_request_line("http://example.com/v1/", "users/7?full=1"),
+               "GET /v1/users/7?full=1 HTTP/1.1");
+    // without one, it replaces the last segment
+    assert_eq!(joined_request_line("http://example.com/v1", "users"), "GET /users HTTP/1.1");
+    assert_eq!(joined_request_line("http://example.com/v1/admin", "users"),
+               "GET /v1/users HTTP/1.1");
+    // and a path starting with a slash replaces the whole path
+    assert_eq!(joined_request_line("http://example.com/v1/", "/users"), "GET /users HTTP/1.1");
+    assert_eq!(joined_request_line("http://example.com", "v1/users"), "GET /v1/users HTTP/1.1");
+    assert_eq!(joined_request_line("http://example.com/v1/", "../v2/users"),
+               "GET /v2/users HTTP/1.1");
+
+    // an absolute URL doesn't use the base
+    let client = reqwest::Client::builder()
+        .base_url(reqwest::Url::parse("http://example.com/v1/").unwrap())
+        .build()
+        .unwrap();
+    let head = client.get("http://other.example.com/users").to_wire_bytes(false).unwrap();
+    let head = String::from_utf8(head).unwrap();
+    assert!(head.starts_with("GET /users HTTP/1.1\r\nHost: other.example.com\r\n"), "{}", head);
+}
+
+#[test]
+fn test_base_url_send() {
+    let server = server! {
+        request: b"\
+            GET /v1/users HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+    let mut client = reqwest::Client::new().unwrap();
+    let base = reqwest::Url::parse(&format!("http://{}/v1/", server.addr())).unwrap();
+    client.base_url(Some(base));
+
+    let res = client.get("users").send().unwrap();
+    assert_eq!(res.url().as_str(), format!("http://{}/v1/users", server.addr()));
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+
+    client.base_url(None);
+    match client.get("users").send() {
+        Err(reqwest::Error::RelativeUrlWithoutBase(ref url)) if url == "users" => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_relative_url_without_base() {
+    let client = reqwest::Client::new().unwrap();
+    match client.get("/v1/users").send() {
+        Err(reqwest::Error::RelativeUrlWithoutBase(ref url)) if url == "/v1/users" => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let err = client.post(&"v1/users".to_owned()).to_wire_bytes(false).unwrap_err();
+    assert_eq!(err.to_string(), "Relative URL \"v1/users\" without a base URL set on the client");
+
+    // other invalid URLs fail as before
+    match client.get("http://[::1").send() {
+        Err(reqwest::Error::Http(reqwest::HyperError::Uri(_))) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_timeout_rejects_zero() {
+    use std::time::Duration;
+
+    let mut client = reqwest::Client::new().unwrap();
+    let err = client.timeout(Duration::from_secs(0)).unwrap_err();
+    assert_eq!(err.to_string(), "Invalid timeout for Client::timeout: 0ns, must be at least 1ms");
+
+    // a very long timeout is fine
+    client.timeout(Duration::from_secs(100 * 365 * 24 * 60 * 60)).unwrap();
+    let addr = slow_server(Duration::from_millis(10));
+    let res = client.get(&format!("http://{}/slow", addr)).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_health_check_rejects_zero_budget() {
+    use std::time::Duration;
+
+    let client = reqwest::Client::new().unwrap();
+    match client.health_check("http://127.0.0.1:1/healthz", Duration::from_secs(0)) {
+        reqwest::HealthStatus::Unhealthy {
+            error: reqwest::Error::InvalidTimeout("Client::health_check budget", _), ..
+        } => (),
+        other => panic!("unexpected health: {:?}", other),
+    }
+}
+
+#[test]
+fn test_status_helpers() {
+    // interim (1xx) responses are skipped, so a `Response` never has one
+    let cases = [
+        (200, "success"),
+        (299, "success"),
+        (300, "redirect"),
+        (399, "redirect"),
+        (400, "client error"),
+        (499, "client error"),
+        (500, "server error"),
+        (599, "server error"),
+    ];
+
+    let client = reqwest::Client::new().unwrap();
+    for &(code, class) in cases.iter() {
+        let server = server! {
+            request: b"\
+                GET /status HTTP/1.1\r\n\
+                Host: $HOST\r\n\
+                User-Agent: $USERAGENT\r\n\
+                Accept: */*\r\n\
+                Accept-Encoding: $ACCEPT_ENCODING\r\n\
+                \r\n\
+                ",
+            response: format!("\
+                HTTP/1.1 {} Status\r\n\
+                Server: test-status\r\n\
+                Content-Length: 0\r\n\
+                \r\n\
+                ", code)
+        };
+
+        let res = client.get(&format!("http://{}/status", server.addr())).send().unwrap();
+        assert_eq!(res.status_u16(), code);
+        let classes = [
+            (res.is_success(), "success"),
+            (res.is_redirect(), "redirect"),
+            (res.is_client_error(), "client error"),
+            (res.is_server_error(), "server error"),
+        ];
+        let matched = classes.iter().filter(|c| c.0).map(|c| c.1).collect::<Vec<_>>();
+        assert_eq!(matched, vec![class], "status {}", code);
+        let is_error = res.is_client_error() || res.is_server_error();
+        assert_eq!(res.error_for_status_ref().is_err(), is_error, "status {}", code);
+    }
+}
+
+#[test]
+fn test_dscp_rejects_more_than_6_bits() {
+    let mut client = reqwest::Client::new().unwrap();
+    match client.dscp(64) {
+        Err(reqwest::Error::SocketOption("DSCP", _)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_dscp() {
+    let server = server! {
+        request: b"\
+            GET /dscp HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-dscp\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let mut client = reqwest::Client::new().unwrap();
+    // expedited forwarding
+    client.dscp(46).unwrap();
+    let res = client.get(&format!("http://{}/dscp", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_socket_mark() {
+    let server = server! {
+        request: b"\
+            GET /mark HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-mark\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.socket_mark(0x2a).unwrap();
+    // setting a mark needs CAP_NET_ADMIN, which only some runs have
+    match client.get(&format!("http://{}/mark", server.addr())).send() {
+        Ok(res) => assert_eq!(res.status(), &reqwest::StatusCode::Ok),
+        Err(reqwest::Error::SocketOption("SO_MARK", err)) => {
+            assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+        },
+        Err(err) => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+#[test]
+fn test_socket_mark_unsupported() {
+    let mut client = reqwest::Client::new().unwrap();
+    match client.socket_mark(0x2a) {
+        Err(reqwest::Error::SocketOption("SO_MARK", _)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_local_address() {
+    use std::net::{self, IpAddr, SocketAddr};
+    use std::thread;
+
+    // all of 127.0.0.0/8 is the loopback interface on Linux
+    let local: IpAddr = "127.0.0.2".parse().unwrap();
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let peer = thread::spawn(move || {
+        let (mut socket, peer) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        socket.read(&mut buf).unwrap();
+        socket.write_all(b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-local\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ").unwrap();
+        peer
+    });
+
+    // the IPv6 address can't be connected to from an IPv4 one, so it's skipped
+    let v6 = SocketAddr::new("::1".parse().unwrap(), addr.port());
+    let client = reqwest::Client::builder()
+        .local_address(local)
+        .resolve_to_addrs("multi.example.com", &[v6, addr])
+        .build()
+        .unwrap();
+    let res = client.get("http://multi.example.com/").send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert_eq!(res.remote_addr(), addr);
+    assert_eq!(peer.join().unwrap().ip(), local);
+    let stats = client.pool_stats();
+    assert_eq!(stats.partitions()[0].local_address(), Some(local));
+
+    // and without an address of the same family, it fails
+    let mut client = reqwest::Client::new().unwrap();
+    client.local_address(local).unwrap();
+    client.resolve("v6.example.com", v6);
+    let err = client.get("http://v6.example.com/").send().unwrap_err();
+    assert!(err.to_string().contains("family"), "{}", err);
+}
+
+fn accept_encoding_server(coding: &str, body: &[u8]) -> server::Server {
+    let mut response = format!("\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-accept-encoding\r\n\
+            Content-Encoding: {}\r\n\
+            Content-Length: {}\r\n\
+            \r\n", coding, body.len())
+        .into_bytes();
+    response.extend(body);
+
+    server! {
+        request: b"\
+            GET /probe HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Accept-Encoding: gzip, deflate; q=0.8, br; q=0.5, identity; q=0.25, *; q=0\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            \r\n\
+            ",
+        response: response
+    }
+}
+
+fn get_with_accept_encoding(server: &server::Server) -> reqwest::Response {
+    use reqwest::header::Encoding;
+
+    let mut client = reqwest::Client::new().unwrap();
+    // the per-request encodings are decoded even without `gzip`
+    client.gzip(false);
+    client.get(&format!("http://{}/probe", server.addr()))
+        .accept_encoding(&[
+            (Encoding::Gzip, 1.0),
+            (Encoding::Deflate, 0.8),
+            (Encoding::EncodingExt("br".to_owned()), 0.5),
+            (Encoding::Identity, 0.25),
+            (Encoding::EncodingExt("*".to_owned()), 0.0),
+        ])
+        .send()
+        .unwrap()
+}
+
+#[test]
+fn test_accept_encoding_gzip_chosen() {
+    let mut encoder = ::libflate::gzip::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(b"gzip chosen").unwrap();
+    let server = accept_encoding_server("gzip", &encoder.finish().into_result().unwrap());
+
+    let mut res = get_with_accept_encoding(&server);
+    assert_eq!(res.headers().get::<reqwest::header::ContentEncoding>(), None);
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "gzip chosen");
+}
+
+#[test]
+fn test_accept_encoding_deflate_chosen() {
+    let mut encoder = ::libflate::zlib::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(b"deflate chosen").unwrap();
+    let server = accept_encoding_server("deflate", &encoder.finish().into_result().unwrap());
+
+    let mut res = get_with_accept_encoding(&server);
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "deflate chosen");
+}
+
+#[test]
+fn test_accept_encoding_undecodable_chosen() {
+    // `compress` is never decoded, with or without the `brotli` feature
+    let server = accept_encoding_server("compress", b"raw compressed bytes");
+
+    let mut res = get_with_accept_encoding(&server);
+    assert_eq!(res.headers().get(), Some(&reqwest::header::ContentEncoding(vec![
+        reqwest::header::Encoding::Compress,
+    ])));
+    assert_eq!(res.headers().get(), Some(&reqwest::header::ContentLength(20)));
+    let mut body = Vec::new();
+    res.read_to_end(&mut body).unwrap();
+    assert_eq!(body, b"raw compressed bytes");
+}
+
+#[test]
+fn test_accept_encoding_invalid_quality() {
+    use reqwest::header::Encoding;
+
+    let client = reqwest::Client::new().unwrap();
+    let req = client.get("http://example.com/probe")
+        .accept_encoding(&[(Encoding::Gzip, 1.0), (Encoding::Deflate, 1.5)]);
+    match req.to_wire_bytes(false) {
+        Err(reqwest::Error::InvalidQuality(Encoding::Deflate, q)) => assert_eq!(q, 1.5),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match req.send() {
+        Err(reqwest::Error::InvalidQuality(Encoding::Deflate, q)) => assert_eq!(q, 1.5),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_redirect_safe_methods_only() {
+    use reqwest::Method;
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.redirect(reqwest::RedirectPolicy::safe_methods_only(10));
+
+    let methods = [Method::Get, Method::Head, Method::Post, Method::Put, Method::Patch,
+                   Method::Delete];
+    for method in methods.iter() {
+        for code in [301, 303, 307].iter() {
+            // the policy sees the method after 301 and 303 change it to GET
+            let next = match (method, *code) {
+                (&Method::Get, _) | (&Method::Head, _) => method.clone(),
+                (_, 307) => method.clone(),
+                _ => Method::Get,
+            };
+            let followed = next == Method::Get || next == Method::Head;
+            let content_length = match *method {
+                Method::Get | Method::Head => "",
+                _ => "Content-Length: 0\r\n",
+            };
+            let server = server! {
+                request: format!("\
+                    {} /{} HTTP/1.1\r\n\
+                    Host: $HOST\r\n\
+                    User-Agent: $USERAGENT\r\n\
+                    Accept: */*\r\n\
+                    Accept-Encoding: $ACCEPT_ENCODING\r\n\
+                    {}\
+                    \r\n\
+                    ", method, code, content_length),
+                response: format!("\
+                    HTTP/1.1 {} reason\r\n\
+                    Server: test-redirect\r\n\
+                    Content-Length: 0\r\n\
+                    Location: /dst\r\n\
+                    Connection: close\r\n\
+                    \r\n\
+                    ", code),
+
+                request: format!("\
+                    {} /dst HTTP/1.1\r\n\
+                    Host: $HOST\r\n\
+                    User-Agent: $USERAGENT\r\n\
+                    Accept: */*\r\n\
+                    Accept-Encoding: $ACCEPT_ENCODING\r\n\
+                    Referer: http://$HOST/{}\r\n\
+                    \r\n\
+                    ", next, code),
+                response: b"\
+                    HTTP/1.1 200 OK\r\n\
+                    Server: test-dst\r\n\
+                    Content-Length: 0\r\n\
+                    \r\n\
+                    "
+            };
+
+            let url = format!("http://{}/{}", server.addr(), code);
+            let res = client.request(method.clone(), &url).send().unwrap();
+            if followed {
+                assert_eq!(res.status(), &reqwest::StatusCode::Ok, "{} {}", method, code);
+                assert_eq!(res.url().as_str(), format!("http://{}/dst", server.addr()));
+            } else {
+                assert_eq!(res.status().to_u16(), *code, "{} {}", method, code);
+                assert_eq!(res.url().as_str(), url);
+                assert_eq!(res.headers().get(), Some(&reqwest::header::Location("/dst".to_owned())));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_request_over_stream() {
+    let mut encoder = ::libflate::gzip::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(b"over the pipe").unwrap();
+    let gzipped = encoder.finish().into_result().unwrap();
+    let mut response = format!("\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-over\r\n\
+            Content-Encoding: gzip\r\n\
+            Content-Length: {}\r\n\
+            \r\n", gzipped.len())
+        .into_bytes();
+    response.extend(&gzipped);
+
+    let (client_end, server_end) = server::pipe();
+    server! {
+        over server_end, host: "tunnel.local",
+        request: b"\
+            GET /over HTTP/1.1\r\n\
+            Host: tunnel.local\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: response
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let mut res = client.request_over(client_end, client.get("http://tunnel.local/over"))
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert!(!res.connection_reused());
+    assert_eq!(res.resolved_addrs(), None);
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "over the pipe");
+}
+
+#[test]
+fn test_request_over_stream_follows_same_origin_redirect() {
+    let (client_end, server_end) = server::pipe();
+    server! {
+        over server_end, host: "tunnel.local",
+        request: b"\
+            POST /login HTTP/1.1\r\n\
+            Host: tunnel.local\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 4\r\n\
+            \r\n\
+            user\
+            ",
+        response: b"\
+            HTTP/1.1 303 See Other\r\n\
+            Server: test-over\r\n\
+            Location: /home\r\n\
+            Content-Length: 5\r\n\
+            \r\n\
+            moved\
+            ",
+
+        request: b"\
+            GET /home HTTP/1.1\r\n\
+            Host: tunnel.local\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Referer: http://tunnel.local/login\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-over\r\n\
+            Content-Length: 4\r\n\
+            \r\n\
+            home\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let mut res = client.request_over(client_end,
+                                      client.post("http://tunnel.local/login").body("user"))
+        .unwrap();
+    assert_eq!(res.url().as_str(), "http://tunnel.local/home");
+    assert!(res.connection_reused());
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "home");
+}
+
+#[test]
+fn test_request_over_stream_cross_origin_redirect() {
+    let (client_end, server_end) = server::pipe();
+    server! {
+        over server_end, host: "tunnel.local",
+        request: b"\
+            GET /elsewhere HTTP/1.1\r\n\
+            Host: tunnel.local\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-over\r\n\
+            Location: http://other.local/\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    match client.request_over(client_end, client.get("http://tunnel.local/elsewhere")) {
+        Err(reqwest::Error::RedirectOverStream(url)) => {
+            assert_eq!(url.as_str(), "http://other.local/");
+        },
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_request_over_stream_redirect_after_close() {
+    let (client_end, server_end) = server::pipe();
+    server! {
+        over server_end, host: "tunnel.local",
+        request: b"\
+            GET /closing HTTP/1.1\r\n\
+            Host: tunnel.local\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-over\r\n\
+            Location: /next\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    match client.request_over(client_end, client.get("http://tunnel.local/closing")) {
+        Err(reqwest::Error::RedirectOverStream(url)) => {
+            assert_eq!(url.as_str(), "http://tunnel.local/next");
+        },
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+/// A server that counts the requests it gets, and responds to each after
+/// `delay`, or closes the connection when there is no `response`.
+fn counting_server(response: Option<&'static [u8]>, delay: std::time::Duration)
+                   -> (std::net::SocketAddr, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let count = Arc::new(AtomicUsize::new(0));
+    let counted = count.clone();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            let counted = counted.clone();
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                let _ = socket.read(&mut buf).unwrap();
+                counted.fetch_add(1, Ordering::SeqCst);
+                thread::sleep(delay);
+                if let Some(response) = response {
+                    socket.write_all(response).unwrap();
+                }
+            });
+        }
+    });
+    (addr, count)
+}
+
+fn get_in_parallel(client: &reqwest::Client, url: &str, n: usize)
+                   -> Vec<reqwest::Result<reqwest::Response>> {
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    let barrier = Arc::new(Barrier::new(n));
+    let threads = (0..n).map(|_| {
+        let client = client.clone();
+        let url = url.to_owned();
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            barrier.wait();
+            client.get(&url).send()
+        })
+    }).collect::<Vec<_>>();
+    threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+}
+
+#[test]
+fn test_coalesce_identical_gets() {
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    let (addr, count) = counting_server(Some(b"\
+        HTTP/1.1 200 OK\r\n\
+        Server: test-coalesce\r\n\
+        Content-Length: 6\r\n\
+        Connection: close\r\n\
+        \r\n\
+        shared\
+        "), Duration::from_millis(500));
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.coalesce_identical_gets(true);
+    let results = get_in_parallel(&client, &format!("http://{}/popular", addr), 50);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    for result in results {
+        let mut res = result.unwrap();
+        assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+        assert_eq!(res.headers().get(), Some(&reqwest::header::ContentLength(6)));
+        let mut body = String::new();
+        res.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "shared");
+    }
+}
+
+#[test]
+fn test_coalesce_identical_gets_shares_error() {
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    let (addr, count) = counting_server(None, Duration::from_millis(500));
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.coalesce_identical_gets(true);
+    let results = get_in_parallel(&client, &format!("http://{}/failing", addr), 50);
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+    for result in results {
+        match result {
+            Err(reqwest::Error::Coalesced(..)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_coalesced_timeout() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    let timeout = reqwest::Error::Timeout(reqwest::TimeoutKind::Read, Duration::from_secs(1),
+                                          "while reading the response head".to_owned());
+    let err = reqwest::Error::Coalesced(Arc::new(timeout));
+    assert!(err.is_timeout());
+    assert_eq!(err.timeout_kind(), Some(reqwest::TimeoutKind::Read));
+    assert!(!err.from_negative_cache());
+}
+
+#[test]
+fn test_coalesce_only_identical_headers() {
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::Duration;
+
+    let (addr, count) = counting_server(Some(b"\
+        HTTP/1.1 200 OK\r\n\
+        Server: test-coalesce\r\n\
+        Content-Length: 0\r\n\
+        Connection: close\r\n\
+        \r\n\
+        "), Duration::from_millis(300));
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.coalesce_identical_gets(true);
+    let url = format!("http://{}/languages", addr);
+    let threads = ["en", "de"].iter().map(|lang| {
+        let req = client.get(&url)
+            .header(reqwest::header::AcceptLanguage(vec![
+                reqwest::header::qitem(lang.parse().unwrap()),
+            ]));
+        thread::spawn(move || req.send().unwrap())
+    }).collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_json_patch() {
+    use reqwest::PatchOp;
+
+    let body = concat!(r#"[{"op":"replace","path":"/title","value":"Hello"},"#,
+                       r#"{"op":"move","from":"/a~1b","path":"/c"}]"#);
+    let server = server! {
+        request: format!("\
+            PATCH /posts/1 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Content-Type: application/json-patch+json\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: {}\r\n\
+            \r\n\
+            {}", body.len(), body),
+        response: b"\
+            HTTP/1.1 204 No Content\r\n\
+            Server: test-patch\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let res = client.patch(&format!("http://{}/posts/1", server.addr()))
+        .json_patch(&[
+            PatchOp::Replace {
+                path: "/title".to_owned(),
+                value: serde_json::to_value("Hello").unwrap(),
+            },
+            PatchOp::Move { from: "/a~1b".to_owned(), path: "/c".to_owned() },
+        ])
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::NoContent);
+}
+
+#[test]
+fn test_json_patch_invalid_path() {
+    use reqwest::PatchOp;
+
+    let client = reqwest::Client::new().unwrap();
+    let req = client.patch("http://example.com/posts/1")
+        .json_patch(&[
+            PatchOp::Remove { path: "/title".to_owned() },
+            PatchOp::Copy { from: "title".to_owned(), path: "/heading".to_owned() },
+        ]);
+    match req.to_wire_bytes(false) {
+        Err(reqwest::Error::InvalidPatchPath(path)) => assert_eq!(path, "title"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match req.send() {
+        Err(reqwest::Error::InvalidPatchPath(path)) => assert_eq!(path, "title"),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_json_merge_patch() {
+    use std::collections::BTreeMap;
+
+    let body = r#"{"author":null,"title":"Hello!"}"#;
+    let server = server! {
+        request: format!("\
+            PATCH /posts/1 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Content-Type: application/merge-patch+json\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: {}\r\n\
+            \r\n\
+            {}", body.len(), body),
+        response: b"\
+            HTTP/1.1 204 No Content\r\n\
+            Server: test-patch\r\n\
+            \r\n\
+            "
+    };
+
+    let mut changes = BTreeMap::new();
+    changes.insert("title", Some("Hello!"));
+    changes.insert("author", None);
+    let client = reqwest::Client::new().unwrap();
+    let res = client.patch(&format!("http://{}/posts/1", server.addr()))
+        .json_merge_patch(&changes)
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::NoContent);
+}
+
+fn ambiguous_redirect_server() -> server::Server {
+    server! {
+        request: b"\
+            GET /ambiguous HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-ambiguous\r\n\
+            Location: /first\r\n\
+            Location: /second\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /first HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Referer: http://$HOST/ambiguous\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-first\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    }
+}
+
+#[test]
+fn test_ambiguous_redirect() {
+    let server = ambiguous_redirect_server();
+
+    let client = reqwest::Client::new().unwrap();
+    match client.get(&format!("http://{}/ambiguous", server.addr())).send() {
+        Err(reqwest::Error::AmbiguousRedirect(locations)) => {
+            assert_eq!(locations, vec!["/first".to_owned(), "/second".to_owned()]);
+        },
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_lenient_redirects() {
+    let server = ambiguous_redirect_server();
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.lenient_redirects(true);
+    let res = client.get(&format!("http://{}/ambiguous", server.addr())).send().unwrap();
+    assert_eq!(res.url().as_str(), format!("http://{}/first", server.addr()));
+    assert_eq!(res.ambiguous_redirects(), &[vec!["/first".to_owned(), "/second".to_owned()]]);
+}
+
+#[test]
+fn test_ambiguous_redirect_not_followed() {
+    let server = ambiguous_redirect_server();
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.redirect(reqwest::RedirectPolicy::none());
+    let res = client.get(&format!("http://{}/ambiguous", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Found);
+    assert!(res.ambiguous_redirects().is_empty());
+}
+
+#[test]
+fn test_range_partial_response() {
+    let server = server! {
+        request: b"\
+            GET /large HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Range: bytes=4-\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 206 Partial Content\r\n\
+            Server: test-range\r\n\
+            Content-Range: bytes 4-9/10\r\n\
+            Content-Length: 6\r\n\
+            \r\n\
+            456789\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let mut res = client.get(&format!("http://{}/large", server.addr()))
+        .range(4, None)
+        .send()
+        .unwrap();
+    assert!(res.is_partial());
+    let range = res.content_range().unwrap();
+    assert_eq!((range.start(), range.end(), range.total()), (Some(4), Some(9), Some(10)));
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "456789");
+}
+
+#[test]
+fn test_range_not_honored() {
+    let server = server! {
+        request: b"\
+            GET /large HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Range: bytes=0-3\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-range\r\n\
+            Content-Length: 10\r\n\
+            \r\n\
+            0123456789\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let res = client.get(&format!("http://{}/large", server.addr()))
+        .range(0, Some(3))
+        .send()
+        .unwrap();
+    assert!(!res.is_partial());
+    assert_eq!(res.content_range(), None);
+}
+
+#[test]
+fn test_prefer_respond_async() {
+    use reqwest::Preference;
+
+    let server = server! {
+        request: b"\
+            POST /reports HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Prefer: respond-async, wait=5, odata.maxpagesize=50\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 2\r\n\
+            \r\n\
+            {}\
+            ",
+        response: b"\
+            HTTP/1.1 202 Accepted\r\n\
+            Server: test-prefer\r\n\
+            Location: /reports/1\r\n\
+            Preference-Applied: respond-async\r\n\
+            Preference-Applied: odata.maxpagesize=50\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let res = client.post(&format!("http://{}/reports", server.addr()))
+        .prefer(&[
+            Preference::RespondAsync,
+            Preference::Wait(5),
+            Preference::Raw("odata.maxpagesize=50".to_owned()),
+        ])
+        .body("{}")
+        .send()
+        .unwrap();
+    assert!(res.is_async_accepted());
+    assert_eq!(res.preferences_applied(), vec![
+        Preference::RespondAsync,
+        Preference::Raw("odata.maxpagesize=50".to_owned()),
+    ]);
+}
+
+#[test]
+fn test_prefer_not_applied() {
+    use reqwest::Preference;
+
+    let server = server! {
+        request: b"\
+            PATCH /Patients(1) HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 2\r\n\
+            \r\n\
+            {}\
+            ",
+        response: b"\
+            HTTP/1.1 202 Accepted\r\n\
+            Server: test-prefer\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    // an empty list takes the header out again
+    let client = reqwest::Client::new().unwrap();
+    let res = client.patch(&format!("http://{}/Patients(1)", server.addr()))
+        .prefer(&[Preference::ReturnMinimal])
+        .prefer(&[])
+        .body("{}")
+        .send()
+        .unwrap();
+    // accepted, but without having applied `respond-async`
+    assert!(!res.is_async_accepted());
+    assert_eq!(res.preferences_applied(), vec![]);
+}
+
+#[test]
+fn test_connection_refused() {
+    use std::io::ErrorKind;
+
+    let addr = {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap()
+    };
+    let client = reqwest::Client::new().unwrap();
+    let err = client.get(&format!("http://{}/refused", addr)).send().unwrap_err();
+    assert_eq!(err.io_kind(), Some(ErrorKind::ConnectionRefused));
+    assert!(err.is_connect());
+    assert!(!err.is_connection_reset());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_connection_reset() {
+    let server = server::spawn_reset();
+    let client = reqwest::Client::new().unwrap();
+    let err = client.get(&format!("http://{}/reset", server.addr())).send().unwrap_err();
+    assert!(err.is_connection_reset(), "{:?}", err);
+    assert!(!err.is_connect());
+}
+
+#[cfg(unix)]
+#[cfg(feature = "default-tls")]
+#[test]
+fn test_connection_reset_during_tls_handshake() {
+    let server = server::spawn_reset();
+    let client = reqwest::Client::new().unwrap();
+    let err = client.get(&format!("https://{}/reset", server.addr())).send().unwrap_err();
+    assert!(err.is_connection_reset(), "{:?}", err);
+    assert!(!err.is_connect());
+}
+
+#[test]
+fn test_io_kind_without_io_error() {
+    let server = server! {
+        request: b"\
+            GET /missing HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 404 Not Found\r\n\
+            Server: test-io-kind\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let res = client.get(&format!("http://{}/missing", server.addr())).send().unwrap();
+    let err = res.error_for_status().unwrap_err();
+    assert_eq!(err.io_kind(), None);
+    assert!(!err.is_connect());
+    assert!(!err.is_connection_reset());
+}
+
+#[test]
+fn test_default_basic_auth() {
+    let server = server! {
+        request: b"\
+            GET /auth HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Authorization: Basic dXNlcjpzZWNyZXQ=\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-auth\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.default_basic_auth("user", Some("secret"));
+    let debug = format!("{:?}", client);
+    assert!(!debug.contains("dXNlcjpzZWNyZXQ="), "{}", debug);
+    assert!(debug.contains("[redacted]"), "{}", debug);
+
+    let res = client.get(&format!("http://{}/auth", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_default_auth_overridden() {
+    let server = server! {
+        request: b"\
+            GET /auth HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Authorization: Bearer per-request\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-auth\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.default_bearer_auth("default");
+    let res = client.get(&format!("http://{}/auth", server.addr()))
+        .bearer_auth("per-request")
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_default_auth_same_origin_redirect() {
+    let server = server! {
+        request: b"\
+            GET /auth-1 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Authorization: Bearer token\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-auth\r\n\
+            Location: /auth-2\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /auth-2 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Authorization: Bearer token\r\n\
+            Referer: http://$HOST/auth-1\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-auth\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.default_bearer_auth("token");
+    let res = client.get(&format!("http://{}/auth-1", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert_eq!(res.url().path(), "/auth-2");
+}
+
+#[test]
+fn test_default_auth_cross_origin_redirect() {
+    use std::net::TcpListener;
+    use std::thread;
+
+    // the `Referer` has the port of the other server, so the request is
+    // only checked for the credentials
+    let other = TcpListener::bind("127.0.0.1:0").unwrap();
+    let other_addr = other.local_addr().unwrap();
+    let received = thread::spawn(move || {
+        let (mut socket, _) = other.accept().unwrap();
+        let mut buf = [0; 4096];
+        let n = socket.read(&mut buf).unwrap();
+        socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    });
+    let redirect = format!("\
+        HTTP/1.1 302 Found\r\n\
+        Server: test-auth\r\n\
+        Location: http://{}/elsewhere\r\n\
+        Content-Length: 0\r\n\
+        Connection: close\r\n\
+        \r\n\
+        ", other_addr);
+    let server = server::spawn(vec![(b"\
+        GET /auth HTTP/1.1\r\n\
+        Host: $HOST\r\n\
+        User-Agent: $USERAGENT\r\n\
+        Accept: */*\r\n\
+        Accept-Encoding: $ACCEPT_ENCODING\r\n\
+        Authorization: Bearer token\r\n\
+        \r\n\
+        ".to_vec(), redirect.into_bytes())]);
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.default_bearer_auth("token");
+    let res = client.get(&format!("http://{}/auth", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert_eq!(res.url().path(), "/elsewhere");
+    let received = received.join().unwrap();
+    assert!(received.starts_with("GET /elsewhere HTTP/1.1\r\n"), "{}", received);
+    assert!(!received.contains("Authorization"), "{}", received);
+}
+
+fn text_server(content_type: &str, body: &[u8], requests: usize) -> server::Server {
+    let mut response = format!("\
+        HTTP/1.1 200 OK\r\n\
+        Server: test-text\r\n\
+        Content-Type: {}\r\n\
+        Content-Length: {}\r\n\
+        Connection: close\r\n\
+        \r\n\
+        ", content_type, body.len()).into_bytes();
+    response.extend_from_slice(body);
+    let request = b"\
+        GET /text HTTP/1.1\r\n\
+        Host: $HOST\r\n\
+        User-Agent: $USERAGENT\r\n\
+        Accept: */*\r\n\
+        Accept-Encoding: $ACCEPT_ENCODING\r\n\
+        \r\n\
+        ".to_vec();
+    server::spawn(vec![(request, response); requests])
+}
+
+#[test]
+fn test_text_invalid_utf8() {
+    let server = text_server("text/plain; charset=utf-8", b"abc\xffdef", 2);
+    let client = reqwest::Client::new().unwrap();
+    let url = format!("http://{}/text", server.addr());
+
+    let text = client.get(&url).send().unwrap().text().unwrap();
+    assert_eq!(text, "abc\u{fffd}def");
+    match client.get(&url).send().unwrap().text_strict() {
+        Err(reqwest::Error::InvalidText(ref charset, 3)) if charset == "utf-8" => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_text_unsupported_charset() {
+    // "日本" in Shift_JIS
+    let server = text_server("text/plain; charset=Shift_JIS", b"\x93\xfa\x96\x7b", 2);
+    let client = reqwest::Client::new().unwrap();
+    let url = format!("http://{}/text", server.addr());
+
+    let text = client.get(&url).send().unwrap().text().unwrap();
+    assert_eq!(text, "\u{fffd}\u{fffd}\u{fffd}{");
+    match client.get(&url).send().unwrap().text_strict() {
+        Err(reqwest::Error::UnsupportedCharset(ref charset)) if charset == "shift_jis" => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_text_latin1() {
+    let server = text_server("text/plain; charset=ISO-8859-1", b"caf\xe9", 2);
+    let client = reqwest::Client::new().unwrap();
+    let url = format!("http://{}/text", server.addr());
+
+    assert_eq!(client.get(&url).send().unwrap().text().unwrap(), "caf\u{e9}");
+    assert_eq!(client.get(&url).send().unwrap().text_strict().unwrap(), "caf\u{e9}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pool_partitioned_by_dscp() {
+    use std::net;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    // keeps each connection alive, counting them
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counted = connections.clone();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            counted.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                loop {
+                    match socket.read(&mut buf) {
+                        Ok(0) | Err(_) => return,
+                        Ok(_) => {
+                            socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                                .unwrap();
+                        },
+                    }
+                }
+            });
+        }
+    });
+
+    let mut client = reqwest::Client::new().unwrap();
+    let url = format!("http://{}/", addr);
+    let get = |client: &reqwest::Client| {
+        let res = client.get(&url).send().unwrap();
+        assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    };
+    get(&client);
+    get(&client);
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    client.dscp(10).unwrap();
+    get(&client);
+    client.dscp(20).unwrap();
+    get(&client);
+    client.dscp(10).unwrap();
+    get(&client);
+    client.dscp(20).unwrap();
+    get(&client);
+    assert_eq!(connections.load(Ordering::SeqCst), 3);
+
+    let stats = client.pool_stats();
+    let partitions = stats.partitions().iter()
+        .map(|p| (p.scheme(), p.dscp(), p.open()))
+        .collect::<Vec<_>>();
+    assert_eq!(partitions, vec![("http", None, 1), ("http", Some(10), 1), ("http", Some(20), 1)]);
+}
+
+#[test]
+fn test_client_builder() {
+    use std::time::Duration;
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::RedirectPolicy::limited(3))
+        .gzip(false)
+        .timeout(Duration::from_secs(5))
+        .default_bearer_auth("secret")
+        .build()
+        .unwrap();
+    let debug = format!("{:?}", client);
+    assert!(debug.contains("Limit(3)"), "{}", debug);
+    assert!(debug.contains("auto_ungzip: false"), "{}", debug);
+    assert!(debug.contains("5s"), "{}", debug);
+    assert!(!debug.contains("secret"), "{}", debug);
+
+    let default = format!("{:?}", reqwest::Client::new().unwrap());
+    assert!(default.contains("Limit(10)"), "{}", default);
+}
+
+#[test]
+fn test_client_builder_invalid_setting() {
+    use std::time::Duration;
+
+    let built = reqwest::Client::builder()
+        .timeout(Duration::from_secs(0))
+        .dscp(64)
+        .build();
+    match built {
+        Err(reqwest::Error::InvalidTimeout("ClientBuilder::timeout", _)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_client_builder_applies_settings() {
+    let server = server! {
+        request: b"\
+            GET /built HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-builder\r\n\
+            Location: /elsewhere\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::builder()
+        .gzip(false)
+        .redirect(reqwest::RedirectPolicy::none())
+        .build()
+        .unwrap();
+    let res = client.get(&format!("http://{}/built", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Found);
+}
+
+#[test]
+fn test_update_with_retry_after_conflict() {
+    let server = server! {
+        request: b"\
+            GET /counter HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-update\r\n\
+            ETag: \"v1\"\r\n\
+            Content-Length: 1\r\n\
+            Connection: close\r\n\
+            \r\n\
+            1\
+            ",
+
+        // someone else updated it to 5 in the meantime
+        request: b"\
+            PUT /counter HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            If-Match: \"v1\"\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 1\r\n\
+            \r\n\
+            2\
+            ",
+        response: b"\
+            HTTP/1.1 412 Precondition Failed\r\n\
+            Server: test-update\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /counter HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-update\r\n\
+            ETag: \"v2\"\r\n\
+            Content-Length: 1\r\n\
+            Connection: close\r\n\
+            \r\n\
+            5\
+            ",
+
+        request: b"\
+            PUT /counter HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            If-Match: \"v2\"\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 1\r\n\
+            \r\n\
+            6\
+            ",
+        response: b"\
+            HTTP/1.1 204 No Content\r\n\
+            Server: test-update\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let mut seen = Vec::new();
+    let res = client.update_with_retry(&format!("http://{}/counter", server.addr()), 3,
+                                       |body, etag| {
+        seen.push(etag.tag().to_owned());
+        let count: u64 = ::std::str::from_utf8(body).unwrap().parse().unwrap();
+        Ok((count + 1).to_string())
+    }).unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::NoContent);
+    assert_eq!(seen, vec!["v1", "v2"]);
+}
+
+#[test]
+fn test_update_with_retry_exhausted() {
+    let server = server! {
+        request: b"\
+            GET /doc HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-update\r\n\
+            ETag: W/\"old\"\r\n\
+            Content-Length: 3\r\n\
+            Connection: close\r\n\
+            \r\n\
+            old\
+            ",
+
+        request: b"\
+            PUT /doc HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            If-Match: W/\"old\"\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 3\r\n\
+            \r\n\
+            new\
+            ",
+        response: b"\
+            HTTP/1.1 412 Precondition Failed\r\n\
+            Server: test-update\r\n\
+            Content-Length: 8\r\n\
+            \r\n\
+            conflict\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let url = format!("http://{}/doc", server.addr());
+    let err = client.update_with_retry(&url, 1, |_, _| Ok("new")).unwrap_err();
+    match err {
+        reqwest::Error::PreconditionFailed(attempts, ref failed, ref body) => {
+            assert_eq!(attempts, 1);
+            assert_eq!(failed.as_str(), url);
+            assert_eq!(body, b"conflict");
+        },
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn test_update_with_retry_missing_etag() {
+    let server = server! {
+        request: b"\
+            GET /doc HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-update\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let url = format!("http://{}/doc", server.addr());
+    match client.update_with_retry(&url, 3, |_, _| Ok("new")) {
+        Err(reqwest::Error::MissingETag(..)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_download_if_changed_not_modified() {
+    let server = server! {
+        request: b"\
+            GET /artifact HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Accept-Encoding: identity\r\n\
+            If-None-Match: \"v1\"\r\n\
+            If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 304 Not Modified\r\n\
+            Server: test-download\r\n\
+            ETag: \"v1\"\r\n\
+            \r\n\
+            "
+    };
+
+    let local = reqwest::LocalMeta {
+        etag: Some(reqwest::header::EntityTag::strong("v1".to_owned())),
+        last_modified: Some("Sun, 06 Nov 1994 08:49:37 GMT".parse().unwrap()),
+        size: Some(7),
+    };
+    let client = reqwest::Client::new().unwrap();
+    let mut dest = Vec::new();
+    let outcome = client.download_if_changed(&format!("http://{}/artifact", server.addr()),
+                                             &local, &mut dest).unwrap();
+    assert_eq!(outcome, reqwest::DownloadOutcome::NotModified);
+    assert!(dest.is_empty());
+}
+
+#[test]
+fn test_download_if_changed_downloads() {
+    let server = server! {
+        request: b"\
+            GET /artifact HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Accept-Encoding: identity\r\n\
+            If-None-Match: \"v1\"\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-download\r\n\
+            ETag: \"v2\"\r\n\
+            Last-Modified: Mon, 07 Nov 1994 08:49:37 GMT\r\n\
+            Content-Length: 7\r\n\
+            \r\n\
+            new one\
+            "
+    };
+
+    let local = reqwest::LocalMeta {
+        etag: Some(reqwest::header::EntityTag::strong("v1".to_owned())),
+        ..reqwest::LocalMeta::default()
+    };
+    let client = reqwest::Client::new().unwrap();
+    let mut dest = Vec::new();
+    let outcome = client.download_if_changed(&format!("http://{}/artifact", server.addr()),
+                                             &local, &mut dest).unwrap();
+    assert_eq!(outcome, reqwest::DownloadOutcome::Downloaded {
+        bytes: 7,
+        new_meta: reqwest::LocalMeta {
+            etag: Some(reqwest::header::EntityTag::strong("v2".to_owned())),
+            last_modified: Some("Mon, 07 Nov 1994 08:49:37 GMT".parse().unwrap()),
+            size: Some(7),
+        },
+    });
+    assert_eq!(dest, b"new one");
+}
+
+#[test]
+fn test_download_if_changed_compares_size() {
+    let server = server! {
+        request: b"\
+            HEAD /artifact HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Accept-Encoding: identity\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-download\r\n\
+            Content-Length: 7\r\n\
+            \r\n\
+            "
+    };
+
+    let local = reqwest::LocalMeta {
+        size: Some(7),
+        ..reqwest::LocalMeta::default()
+    };
+    let client = reqwest::Client::new().unwrap();
+    let mut dest = Vec::new();
+    let outcome = client.download_if_changed(&format!("http://{}/artifact", server.addr()),
+                                             &local, &mut dest).unwrap();
+    assert_eq!(outcome, reqwest::DownloadOutcome::NotModified);
+    assert!(dest.is_empty());
+}
+
+#[test]
+fn test_download_if_changed_not_compressed() {
+    use std::net;
+    use std::thread;
+
+    // gzips the body unless only `identity` is accepted, so sizes would
+    // otherwise be of the compressed copy
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            let mut buf = [0; 4096];
+            let n = socket.read(&mut buf).unwrap();
+            let head = String::from_utf8_lossy(&buf[..n]).into_owned();
+            let (coding, body) = if head.contains("Accept-Encoding: identity\r\n") {
+                ("identity", b"new one".to_vec())
+            } else {
+                ("gzip", gzipped(b"new one"))
+            };
+            let _ = write!(socket, "HTTP/1.1 200 OK\r\n\
+                                    Content-Encoding: {}\r\n\
+                                    Content-Length: {}\r\n\
+                                    Connection: close\r\n\
+                                    \r\n", coding, body.len());
+            if !head.starts_with("HEAD ") {
+                let _ = socket.write_all(&body);
+            }
+        }
+    });
+
+    let url = format!("http://{}/artifact", addr);
+    let client = reqwest::Client::new().unwrap();
+    let local = reqwest::LocalMeta {
+        size: Some(7),
+        ..reqwest::LocalMeta::default()
+    };
+    let mut dest = Vec::new();
+    let outcome = client.download_if_changed(&url, &local, &mut dest).unwrap();
+    assert_eq!(outcome, reqwest::DownloadOutcome::NotModified);
+
+    let local = reqwest::LocalMeta {
+        size: Some(3),
+        ..reqwest::LocalMeta::default()
+    };
+    let outcome = client.download_if_changed(&url, &local, &mut dest).unwrap();
+    assert_eq!(outcome, reqwest::DownloadOutcome::Downloaded {
+        bytes: 7,
+        new_meta: reqwest::LocalMeta {
+            size: Some(7),
+            ..reqwest::LocalMeta::default()
+        },
+    });
+    assert_eq!(dest, b"new one");
+}
+
+#[test]
+fn test_download_if_changed_head_rejected() {
+    let server = server! {
+        request: b"\
+            HEAD /artifact HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Accept-Encoding: identity\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 405 Method Not Allowed\r\n\
+            Server: test-download\r\n\
+            Allow: GET\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let local = reqwest::LocalMeta {
+        size: Some(7),
+        ..reqwest::LocalMeta::default()
+    };
+    let client = reqwest::Client::new().unwrap();
+    let mut dest = Vec::new();
+    let outcome = client.download_if_changed(&format!("http://{}/artifact", server.addr()),
+                                             &local, &mut dest).unwrap();
+    assert_eq!(outcome, reqwest::DownloadOutcome::ValidatorsUnavailable);
+    assert!(dest.is_empty());
+}
+
+#[test]
+fn test_poll_until_complete() {
+    use std::time::Duration;
+    use reqwest::header::{Authorization, Bearer, Headers};
+
+    let server = server! {
+        request: b"\
+            POST /jobs HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 2\r\n\
+            \r\n\
+            {}\
+            ",
+        response: b"\
+            HTTP/1.1 202 Accepted\r\n\
+            Server: test-poll\r\n\
+            Location: /jobs/1\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /jobs/1 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Authorization: Bearer secret\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 202 Accepted\r\n\
+            Server: test-poll\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /jobs/1 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Authorization: Bearer secret\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-poll\r\n\
+            Content-Length: 20\r\n\
+            Connection: close\r\n\
+            \r\n\
+            {\"status\":\"running\"}\
+            ",
+
+        request: b"\
+            GET /jobs/1 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Authorization: Bearer secret\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-poll\r\n\
+            Content-Length: 22\r\n\
+            \r\n\
+            {\"status\":\"succeeded\"}\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let started = client.post(&format!("http://{}/jobs", server.addr()))
+        .body("{}")
+        .send()
+        .unwrap();
+    let mut headers = Headers::new();
+    headers.set(Authorization(Bearer { token: "secret".to_owned() }));
+    let mut res = client.poll_until_complete(started, reqwest::PollOptions {
+        interval: Duration::from_millis(10),
+        headers: headers,
+        ..reqwest::PollOptions::default()
+    }).unwrap();
+    assert_eq!(*res.status(), reqwest::StatusCode::Ok);
+    assert_eq!(res.url().path(), "/jobs/1");
+    // the body of the last poll is still there to read
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "{\"status\":\"succeeded\"}");
+}
+
+#[test]
+fn test_poll_until_complete_retry_after() {
+    use std::time::{Duration, Instant};
+
+    let server = server! {
+        request: b"\
+            POST /jobs HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 202 Accepted\r\n\
+            Server: test-poll\r\n\
+            Operation-Location: /operations/2\r\n\
+            Location: /jobs/2\r\n\
+            Retry-After: 1\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /operations/2 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 201 Created\r\n\
+            Server: test-poll\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::new().unwrap();
+    let started = client.post(&format!("http://{}/jobs", server.addr())).send().unwrap();
+    let start = Instant::now();
+    let res = client.poll_until_complete(started, reqwest::PollOptions {
+        interval: Duration::from_millis(10),
+        ..reqwest::PollOptions::default()
+    }).unwrap();
+    assert_eq!(*res.status(), reqwest::StatusCode::Created);
+    assert!(start.elapsed() >= Duration::from_secs(1), "{:?}", start.elapsed());
+}
+
+#[test]
+fn test_poll_until_complete_deadline() {
+    use std::time::Duration;
+
+    let server = server! {
+        request: b"\
+            POST /jobs HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 202 Accepted\r\n\
+            Server: test-poll\r\n\
+            Location: /jobs/3\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /jobs/3 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-poll\r\n\
+            Content-Length: 20\r\n\
+            \r\n\
+            {\"status\":\"running\"}\
+            "
+    };
+
+    // the second wait would end after the deadline, so it isn't waited for
+    let client = reqwest::Client::new().unwrap();
+    let started = client.post(&format!("http://{}/jobs", server.addr())).send().unwrap();
+    let err = client.poll_until_complete(started, reqwest::PollOptions {
+        interval: Duration::from_millis(100),
+        max_duration: Duration::from_millis(150),
+        ..reqwest::PollOptions::default()
+    }).unwrap_err();
+    assert!(err.is_timeout());
+    match err {
+        reqwest::Error::PollTimeout(max_duration, status, url) => {
+            assert_eq!(max_duration, Duration::from_millis(150));
+            assert_eq!(status, reqwest::StatusCode::Ok);
+            assert_eq!(url.path(), "/jobs/3");
+        },
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn test_poll_until_complete_huge_retry_after() {
+    let server = server! {
+        request: b"\
+            POST /jobs HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 202 Accepted\r\n\
+            Location: /jobs/4\r\n\
+            Retry-After: 18446744073709551615\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    // a wait too long to add up is past the deadline, rather than a panic
+    let client = reqwest::Client::new().unwrap();
+    let started = client.post(&format!("http://{}/jobs", server.addr())).send().unwrap();
+    match client.poll_until_complete(started, reqwest::PollOptions::default()) {
+        Err(reqwest::Error::PollTimeout(_, reqwest::StatusCode::Accepted, _)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_default_headers() {
+    use reqwest::header::{Authorization, Headers};
+
+    let server = server! {
+        request: b"\
+            GET /default HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Authorization: Token default\r\n\
+            X-Api-Key: key\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-default-headers\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /override HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Authorization: Token mine\r\n\
+            X-Api-Key: key\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-default-headers\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let mut headers = Headers::new();
+    headers.set(Authorization("Token default".to_owned()));
+    headers.set_raw("X-Api-Key", vec![b"key".to_vec()]);
+    let client = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .unwrap();
+    let debug = format!("{:?}", client);
+    assert!(!debug.contains("Token default"), "{}", debug);
+
+    let res = client.get(&format!("http://{}/default", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    let res = client.get(&format!("http://{}/override", server.addr()))
+        .header(Authorization("Token mine".to_owned()))
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_default_headers_replace_user_agent() {
+    use reqwest::header::{Accept, Headers, UserAgent};
+
+    let server = server! {
+        request: b"\
+            GET /ua HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: my-app/1.0\r\n\
+            Accept: application/json\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-default-headers\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let mut headers = Headers::new();
+    headers.set(UserAgent("my-app/1.0".to_owned()));
+    headers.set(Accept::json());
+    let mut client = reqwest::Client::new().unwrap();
+    client.default_headers(headers);
+    let res = client.get(&format!("http://{}/ua", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_trace_context_provider() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let server = server! {
+        request: b"\
+            GET /traced-1 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            traceparent: span-1\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-trace\r\n\
+            Location: /traced-2\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /traced-2 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            traceparent: span-1\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Referer: http://$HOST/traced-1\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-trace\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /explicit HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            traceparent: mine\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-trace\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let client = reqwest::Client::builder()
+        .trace_context_provider(Arc::new(move || {
+            let span = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            vec![("traceparent".to_owned(), format!("span-{}", span))]
+        }))
+        .build()
+        .unwrap();
+
+    // the redirect keeps the value of the first hop
+    let res = client.get(&format!("http://{}/traced-1", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    let res = client.get(&format!("http://{}/explicit", server.addr()))
+        .headers({
+            let mut headers = reqwest::header::Headers::new();
+            headers.set_raw("traceparent", vec![b"mine".to_vec()]);
+            headers
+        })
+        .send()
+        .unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_alt_svc_routes_to_alternative() {
+    use std::net;
+    use std::thread;
+
+    let alternative = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let alt_addr = alternative.local_addr().unwrap();
+    let origin = server! {
+        request: b"\
+            GET /first HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: format!("\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-origin\r\n\
+            Alt-Svc: h2=\":443\", http/1.1=\":{}\"; ma=60\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ", alt_addr.port())
+    };
+    let origin_addr = origin.addr();
+    let served = thread::spawn(move || {
+        let (mut socket, _addr) = alternative.accept().unwrap();
+        let mut buf = [0; 4096];
+        let n = socket.read(&mut buf).unwrap();
+        socket.write_all(b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-alternative\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ").unwrap();
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    });
+
+    let client = reqwest::Client::builder()
+        .honor_alt_svc(true)
+        .build()
+        .unwrap();
+    let res = client.get(&format!("http://{}/first", origin_addr)).send().unwrap();
+    assert_eq!(res.alt_svc().len(), 2);
+    assert_eq!(res.alt_svc()[1].protocol(), "http/1.1");
+    assert_eq!(res.alt_svc()[1].port(), alt_addr.port());
+
+    let res = client.get(&format!("http://{}/second", origin_addr)).send().unwrap();
+    assert_eq!(res.headers().get(), Some(&reqwest::header::Server("test-alternative".to_string())));
+    assert_eq!(res.remote_addr(), alt_addr);
+    assert_eq!(res.url().as_str(), format!("http://{}/second", origin_addr));
+    // the request is still for the origin
+    let request = served.join().unwrap();
+    assert!(request.starts_with("GET /second HTTP/1.1\r\n"), "{}", request);
+    assert!(request.contains(&format!("Host: {}\r\n", origin_addr)), "{}", request);
+
+    let alternatives = client.pool_stats().partitions().iter()
+        .filter(|partition| partition.alternative().is_some())
+        .count();
+    assert_eq!(alternatives, 1);
+}
+
+#[test]
+fn test_alt_svc_falls_back_to_origin() {
+    use std::net;
+
+    // nothing listens on the alternative
+    let alt_port = net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let origin = server! {
+        request: b"\
+            GET /first HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: format!("\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-origin\r\n\
+            Alt-Svc: http/1.1=\":{}\"\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ", alt_port),
+
+        request: b"\
+            GET /second HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-origin\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::builder()
+        .honor_alt_svc(true)
+        .build()
+        .unwrap();
+    client.get(&format!("http://{}/first", origin.addr())).send().unwrap();
+    let res = client.get(&format!("http://{}/second", origin.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert_eq!(res.remote_addr(), origin.addr());
+}
+
+#[cfg(feature = "bridge")]
+#[test]
+fn test_client_builder_zero_background_threads() {
+    match reqwest::Client::builder().background_threads(0).build() {
+        Err(reqwest::Error::InvalidThreadCount("ClientBuilder::background_threads", 0)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+/// A `ClientBuilder` set up for one of the TLS backends.
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+type UseTls = fn(reqwest::ClientBuilder) -> reqwest::ClientBuilder;
+
+/// The TLS backends reqwest was built with, to run the same tests against
+/// the TLS servers with each. The servers use native-tls when it is built,
+/// whichever backends the client has, and rustls otherwise.
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+fn tls_backends() -> Vec<(&'static str, UseTls)> {
+    #[cfg(feature = "default-tls")]
+    fn native_tls(builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        builder
+    }
+    #[cfg(feature = "rustls-tls")]
+    fn rustls(builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        builder.use_rustls_tls()
+    }
+
+    let mut backends = Vec::new();
+    #[cfg(feature = "default-tls")]
+    backends.push(("native-tls", native_tls as UseTls));
+    #[cfg(feature = "rustls-tls")]
+    backends.push(("rustls", rustls as UseTls));
+    backends
+}
+
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+#[test]
+fn test_danger_accept_invalid_certs() {
+    let server = server::spawn_tls();
+    let url = format!("https://{}/self-signed", server.addr());
+
+    for (backend, use_tls) in tls_backends() {
+        let client = use_tls(reqwest::Client::builder()).build().unwrap();
+        let err = client.get(&url).send().unwrap_err();
+        match err {
+            reqwest::Error::Http(reqwest::HyperError::Ssl(_)) => (),
+            err => panic!("unexpected error with {}: {:?}", backend, err),
+        }
+
+        let client = use_tls(reqwest::Client::builder())
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+        let res = client.get(&url).send().unwrap();
+        assert_eq!(res.status(), &reqwest::StatusCode::Ok, "with {}", backend);
+    }
+}
+
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+#[test]
+fn test_add_root_certificate() {
+    let server = server::spawn_tls();
+    let url = format!("https://localhost:{}/private-ca", server.addr().port());
+
+    for (backend, use_tls) in tls_backends() {
+        let client = |certs: Vec<reqwest::Certificate>| {
+            let mut builder = use_tls(reqwest::Client::builder())
+                .resolve("localhost", server.addr());
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+            builder.build().unwrap()
+        };
+
+        match client(vec![]).get(&url).send() {
+            Err(reqwest::Error::Http(reqwest::HyperError::Ssl(_))) => (),
+            other => panic!("unexpected result with {}: {:?}", backend, other),
+        }
+
+        let pem = reqwest::Certificate::from_pem(include_bytes!("tls/ca.pem")).unwrap();
+        let der = reqwest::Certificate::from_der(include_bytes!("tls/ca.der")).unwrap();
+        assert_eq!(pem, der);
+        let res = client(vec![pem]).get(&url).send().unwrap();
+        assert_eq!(res.status(), &reqwest::StatusCode::Ok, "with {}", backend);
+        let res = client(vec![der]).get(&url).send().unwrap();
+        assert_eq!(res.status(), &reqwest::StatusCode::Ok, "with {}", backend);
+
+        // the test CA is the second of the bundle
+        let bundle = reqwest::Certificate::from_pem_bundle(include_bytes!("tls/bundle.pem"))
+            .unwrap();
+        assert_eq!(bundle.len(), 2);
+        let res = client(bundle).get(&url).send().unwrap();
+        assert_eq!(res.status(), &reqwest::StatusCode::Ok, "with {}", backend);
+    }
+}
+
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+#[test]
+fn test_tls_built_in_root_certs() {
+    let server = server::spawn_tls();
+    let url = format!("https://localhost:{}/private", server.addr().port());
+    // the first certificate of the bundle isn't the test CA
+    let bundle = reqwest::Certificate::from_pem_bundle(include_bytes!("tls/bundle.pem")).unwrap();
+    let ca = reqwest::Certificate::from_der(include_bytes!("tls/ca.der")).unwrap();
+    for (backend, use_tls) in tls_backends() {
+        // an empty trust store
+        match use_tls(reqwest::Client::builder()).tls_built_in_root_certs(false).build() {
+            Err(reqwest::Error::TlsInit(name, ref cause)) => {
+                assert_eq!(name, backend);
+                assert!(cause.to_string().starts_with("no root certificates"), "{}", cause);
+            },
+            other => panic!("unexpected result with {}: {:?}", backend, other),
+        }
+
+        let client = |cert: &reqwest::Certificate| {
+            use_tls(reqwest::Client::builder())
+                .resolve("localhost", server.addr())
+                .tls_built_in_root_certs(false)
+                .add_root_certificate(cert.clone())
+                .build()
+                .unwrap()
+        };
+        match client(&bundle[0]).get(&url).send() {
+            Err(reqwest::Error::Http(reqwest::HyperError::Ssl(_))) => (),
+            other => panic!("unexpected result with {}: {:?}", backend, other),
+        }
+        let res = client(&ca).get(&url).send().unwrap();
+        assert_eq!(res.status(), &reqwest::StatusCode::Ok, "with {}", backend);
+    }
+}
+
+#[cfg(all(feature = "default-tls", feature = "rustls-tls"))]
+#[test]
+fn test_rustls_unsupported_options() {
+    let client = reqwest::Client::builder()
+        .use_rustls_tls()
+        .danger_accept_invalid_hostnames(true)
+        .build();
+    match client {
+        Err(reqwest::Error::TlsInit("rustls", _)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let identity = reqwest::Identity::from_pkcs12_der(include_bytes!("tls/client.p12"), "reqwest");
+    match reqwest::Client::builder().use_rustls_tls().identity(identity).build() {
+        Err(reqwest::Error::InvalidIdentity(_)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // both are still supported by native-tls, which stays the default
+    let identity = reqwest::Identity::from_pkcs12_der(include_bytes!("tls/client.p12"), "reqwest");
+    assert!(reqwest::Client::builder()
+        .danger_accept_invalid_hostnames(true)
+        .identity(identity)
+        .build()
+        .is_ok());
+}
+
+#[cfg(feature = "default-tls")]
+#[test]
+fn test_danger_accept_invalid_hostnames() {
+    let server = server::spawn_tls();
+    // the certificate is only for localhost
+    let url = format!("https://{}/by-ip", server.addr());
+    let client = |accept, trust_ca| {
+        let mut builder = reqwest::Client::builder().danger_accept_invalid_hostnames(accept);
+        if trust_ca {
+            let cert = reqwest::Certificate::from_pem(include_bytes!("tls/ca.pem")).unwrap();
+            builder = builder.add_root_certificate(cert);
+        }
+        builder.build().unwrap()
+    };
+
+    // the chain is still verified, and the test CA isn't trusted
+    for &accept in &[false, true] {
+        match client(accept, false).get(&url).send() {
+            Err(reqwest::Error::Http(reqwest::HyperError::Ssl(_))) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    match client(false, true).get(&url).send() {
+        Err(reqwest::Error::Http(reqwest::HyperError::Ssl(_))) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    let res = client(true, true).get(&url).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_invalid_certificate() {
+    let pem = include_bytes!("tls/ca.pem");
+    let bundle = include_bytes!("tls/bundle.pem");
+    let der = include_bytes!("tls/ca.der");
+    let invalid = vec![
+        reqwest::Certificate::from_pem(b"not a certificate").unwrap_err(),
+        reqwest::Certificate::from_pem(&pem[..pem.len() / 2]).unwrap_err(),
+        reqwest::Certificate::from_pem(&bundle[..]).unwrap_err(),
+        reqwest::Certificate::from_pem_bundle(b"").unwrap_err(),
+        reqwest::Certificate::from_der(&pem[..]).unwrap_err(),
+        reqwest::Certificate::from_der(&der[..der.len() - 1]).unwrap_err(),
+    ];
+    for err in invalid {
+        match err {
+            reqwest::Error::InvalidCertificate(_) => (),
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+}
+
+#[cfg(feature = "default-tls")]
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+#[test]
+fn test_identity() {
+    let server = server::spawn_tls_client_auth();
+    let url = format!("https://localhost:{}/mtls", server.addr().port());
+    let builder = || {
+        let cert = reqwest::Certificate::from_pem(include_bytes!("tls/ca.pem")).unwrap();
+        reqwest::Client::builder()
+            .resolve("localhost", server.addr())
+            .add_root_certificate(cert)
+    };
+
+    // the server may only reject the handshake once the client sent a request
+    assert!(builder().build().unwrap().get(&url).send().is_err());
+
+    let identity = reqwest::Identity::from_pkcs12_der(include_bytes!("tls/client.p12"), "reqwest");
+    let client = builder().identity(identity).build().unwrap();
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[cfg(feature = "default-tls")]
+#[test]
+fn test_invalid_identity() {
+    let p12 = include_bytes!("tls/client.p12");
+    let identities = vec![
+        reqwest::Identity::from_pkcs12_der(p12, "wrong password"),
+        reqwest::Identity::from_pkcs12_der(&p12[..p12.len() / 2], "reqwest"),
+        reqwest::Identity::from_pkcs12_der(b"not an archive", "reqwest"),
+    ];
+    for identity in identities {
+        match reqwest::Client::builder().identity(identity).build() {
+            Err(reqwest::Error::InvalidIdentity(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
+
+#[cfg(feature = "default-tls")]
+#[test]
+fn test_tls_init_error() {
+    use std::error::Error;
+    use std::io;
+
+    let cause = io::Error::new(io::ErrorKind::NotFound, "no CA bundle");
+    let err = reqwest::Error::TlsInit(reqwest::features()[0], Box::new(cause));
+    assert_eq!(err.to_string(), "Initializing TLS with native-tls failed: no CA bundle");
+    assert_eq!(err.cause().map(|cause| cause.to_string()), Some("no CA bundle".to_owned()));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_connect_timeout() {
+    use std::time::{Duration, Instant};
+
+    let server = server::spawn_backlogged();
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let start = Instant::now();
+    let err = client.get(&format!("http://{}/hang", server.addr())).send().unwrap_err();
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+    assert!(err.is_timeout(), "{:?}", err);
+    assert_eq!(err.timeout_kind(), Some(reqwest::TimeoutKind::Connect));
+    assert_eq!(err.to_string(),
+               format!("connect timeout of 200ms while connecting to {}", server.addr()));
+}
+
+#[test]
+fn test_connect_timeout_huge() {
+    use std::time::Duration;
+
+    let server = server! {
+        request: b"\
+            GET /huge HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-timeout\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    // too long to be a deadline, so there is none
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(::std::u64::MAX))
+        .build()
+        .unwrap();
+    let res = client.get(&format!("http://{}/huge", server.addr())).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[cfg(unix)]
+#[cfg(feature = "default-tls")]
+#[test]
+fn test_connect_timeout_with_socket_options() {
+    use std::time::{Duration, Instant};
+
+    let server = server::spawn_backlogged();
+    let client = reqwest::Client::builder()
+        .dscp(10)
+        .connect_timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let start = Instant::now();
+    let err = client.get(&format!("https://{}/hang", server.addr())).send().unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(2), "{:?}", start.elapsed());
+    assert_eq!(err.timeout_kind(), Some(reqwest::TimeoutKind::Connect), "{:?}", err);
+}
+
+#[test]
+fn test_connect_timeout_invalid() {
+    use std::time::Duration;
+
+    let mut client = reqwest::Client::new().unwrap();
+    match client.connect_timeout(Duration::from_secs(0)) {
+        Err(reqwest::Error::InvalidTimeout("Client::connect_timeout", _)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[cfg(feature = "default-tls")]
+#[test]
+fn test_connect_timeout_during_tls_handshake() {
+    use std::net;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        // accepts, but never answers the client hello
+        let (socket, _addr) = listener.accept().unwrap();
+        thread::sleep(Duration::from_secs(5));
+        drop(socket);
+    });
+
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let start = Instant::now();
+    let err = client.get(&format!("https://{}/", addr)).send().unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(2), "{:?}", start.elapsed());
+    assert_eq!(err.timeout_kind(), Some(reqwest::TimeoutKind::Connect), "{:?}", err);
+    assert_eq!(err.to_string(),
+               "connect timeout of 200ms during the TLS handshake with 127.0.0.1");
+}
+
+#[test]
+fn test_read_timeout_only() {
+    use std::net;
+    use std::thread;
+    use std::time::Duration;
+
+    let addr = slow_server(Duration::from_millis(500));
+    let client = reqwest::Client::builder()
+        .read_timeout(Duration::from_millis(100))
+        .write_timeout(None)
+        .build()
+        .unwrap();
+    let err = client.get(&format!("http://{}/slow", addr)).send().unwrap_err();
+    assert_eq!(err.timeout_kind(), Some(reqwest::TimeoutKind::Read));
+
+    // without a write timeout, a stalled upload waits until the server is gone
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (socket, _) = listener.accept().unwrap();
+        thread::sleep(Duration::from_millis(500));
+        drop(socket);
+    });
+    let err = client.post(&format!("http://{}/upload", addr))
+        .body(vec![0; 64 * 1024 * 1024])
+        .send()
+        .unwrap_err();
+    assert!(!err.is_timeout(), "{:?}", err);
+}
+
+#[test]
+fn test_write_timeout_only() {
+    use std::net;
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (_socket, _) = listener.accept().unwrap();
+        thread::sleep(Duration::from_secs(2));
+    });
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.write_timeout(Duration::from_millis(100)).unwrap();
+    client.read_timeout(None).unwrap();
+    let err = client.post(&format!("http://{}/upload", addr))
+        .body(vec![0; 64 * 1024 * 1024])
+        .send()
+        .unwrap_err();
+    assert_eq!(err.timeout_kind(), Some(reqwest::TimeoutKind::Write));
+    assert_eq!(err.to_string(), "write timeout of 100ms while sending the request");
+
+    // a response slower than the write timeout is still waited for
+    let addr = slow_server(Duration::from_millis(300));
+    let res = client.get(&format!("http://{}/slow", addr)).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_read_write_timeout_invalid() {
+    use std::time::Duration;
+
+    let mut client = reqwest::Client::new().unwrap();
+    match client.read_timeout(Duration::from_secs(0)) {
+        Err(reqwest::Error::InvalidTimeout("Client::read_timeout", _)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match reqwest::Client::builder().write_timeout(Duration::from_secs(0)).build() {
+        Err(reqwest::Error::InvalidTimeout("ClientBuilder::write_timeout", _)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+/// A `Write` that appends to a buffer shared with the test, failing once it
+/// has `fail_after` bytes, if set.
+struct SharedChunk {
+    buf: ::std::sync::Arc<::std::sync::Mutex<Vec<u8>>>,
+    fail_after: Option<usize>,
+}
+
+impl Write for SharedChunk {
+    fn write(&mut self, data: &[u8]) -> ::std::io::Result<usize> {
+        let mut buf = self.buf.lock().unwrap();
+        let room = self.fail_after.map_or(data.len(), |max| max - buf.len());
+        if room == 0 {
+            return Err(::std::io::Error::new(::std::io::ErrorKind::Other, "disk full"));
+        }
+        let n = ::std::cmp::min(room, data.len());
+        buf.extend_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> ::std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn large_body_server(len: usize) -> (server::Server, Vec<u8>) {
+    let body = (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let mut response = format!("\
+        HTTP/1.1 200 OK\r\n\
+        Server: test-export\r\n\
+        Content-Length: {}\r\n\
+        \r\n\
+        ", len).into_bytes();
+    response.extend_from_slice(&body);
+    let server = server! {
+        request: b"\
+            GET /export HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: response
+    };
+    (server, body)
+}
+
+#[test]
+fn test_copy_to_rotating() {
+    use std::sync::{Arc, Mutex};
+
+    const MB: usize = 1024 * 1024;
+    let (server, body) = large_body_server(10 * MB + 123);
+
+    let chunks = Arc::new(Mutex::new(Vec::new()));
+    let opened = chunks.clone();
+    let mut res = reqwest::get(&format!("http://{}/export", server.addr())).unwrap();
+    let summary = res.copy_to_rotating(MB as u64, move |index| {
+        let mut opened = opened.lock().unwrap();
+        assert_eq!(index, opened.len());
+        let buf = Arc::new(Mutex::new(Vec::new()));
+        opened.push(buf.clone());
+        Ok(Box::new(SharedChunk {
+            buf: buf,
+            fail_after: None,
+        }) as Box<Write>)
+    }).unwrap();
+
+    assert_eq!(summary.chunks(), 11);
+    assert_eq!(summary.total_bytes(), body.len() as u64);
+    let chunks = chunks.lock().unwrap();
+    let mut reassembled = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let chunk = chunk.lock().unwrap();
+        let expected = if i == 10 { 123 } else { MB };
+        assert_eq!(chunk.len(), expected, "chunk {}", i);
+        reassembled.extend_from_slice(&chunk);
+    }
+    assert!(reassembled == body);
+}
+
+#[test]
+fn test_copy_to_rotating_reports_failed_chunk() {
+    use std::sync::{Arc, Mutex};
+
+    let (server, _) = large_body_server(10_000);
+    let mut res = reqwest::get(&format!("http://{}/export", server.addr())).unwrap();
+    let err = res.copy_to_rotating(4000, |index| {
+        Ok(Box::new(SharedChunk {
+            buf: Arc::new(Mutex::new(Vec::new())),
+            // the third chunk runs out of space
+            fail_after: if index == 2 { Some(1500) } else { None },
+        }) as Box<Write>)
+    }).unwrap_err();
+    match err {
+        reqwest::Error::RotatedChunk(2, 1500, ref e) => {
+            assert_eq!(e.io_kind(), Some(::std::io::ErrorKind::Other));
+        },
+        ref other => panic!("unexpected error: {:?}", other),
+    }
+    assert_eq!(err.to_string(), "Copying chunk 2 failed after 1500 bytes: disk full");
+
+    match res.copy_to_rotating(0, |_| panic!("opened a chunk of size 0")) {
+        Err(reqwest::Error::ZeroChunkSize) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_max_idle_per_host_zero() {
+    // each transaction is on a connection of its own
+    let server = server! {
+        request: b"\
+            GET /first HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test\r\n\
+            Content-Length: 5\r\n\
+            \r\n\
+            first\
+            ",
+
+        request: b"\
+            GET /second HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test\r\n\
+            Content-Length: 6\r\n\
+            \r\n\
+            second\
+            "
+    };
+
+    let client = reqwest::Client::builder()
+        .max_idle_per_host(0)
+        .build()
+        .unwrap();
+    let mut res = client.get(&format!("http://{}/first", server.addr())).send().unwrap();
+    assert_eq!(res.text().unwrap(), "first");
+    assert!(!res.connection_reused());
+    drop(res);
+
+    let mut res = client.get(&format!("http://{}/second", server.addr())).send().unwrap();
+    assert_eq!(res.text().unwrap(), "second");
+    assert!(!res.connection_reused());
+    drop(res);
+    // and the connection was closed, not kept
+    let stats = client.pool_stats();
+    assert!(stats.partitions().iter().all(|partition| partition.open() == 0), "{:?}", stats);
+}
+
+#[test]
+fn test_pool_idle_timeout() {
+    use std::net;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            counter.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                loop {
+                    let mut req = Vec::new();
+                    while !req.ends_with(b"\r\n\r\n") {
+                        match socket.read(&mut buf) {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => req.extend_from_slice(&buf[..n]),
+                        }
+                    }
+                    socket.write_all(b"\
+                        HTTP/1.1 200 OK\r\n\
+                        Content-Length: 2\r\n\
+                        \r\n\
+                        ok\
+                        ").unwrap();
+                }
+            });
+        }
+    });
+
+    let client = reqwest::Client::builder()
+        .pool_idle_timeout(Duration::from_millis(200))
+        .build()
+        .unwrap();
+    let url = format!("http://{}/", addr);
+    let get = || {
+        let mut res = client.get(&url).send().unwrap();
+        assert_eq!(res.text().unwrap(), "ok");
+        res.connection_reused()
+    };
+
+    assert!(!get());
+    // within the idle window, the connection is reused
+    assert!(get());
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    thread::sleep(Duration::from_millis(400));
+    assert!(!get());
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+    let err = reqwest::Client::builder()
+        .pool_idle_timeout(Duration::from_secs(0))
+        .build()
+        .unwrap_err();
+    match err {
+        reqwest::Error::InvalidTimeout("ClientBuilder::pool_idle_timeout", _) => (),
+        err => panic!("unexpected error: {:?}", err),
+    }
+}
+
+#[test]
+fn test_deprecation() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let server = server! {
+        request: b"\
+            GET /v1/users HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-deprecated\r\n\
+            Deprecation: @1688169599\r\n\
+            Sunset: Sat, 01 Jun 2030 00:00:00 GMT\r\n\
+            Link: </v2/users>; rel=\"successor-version\", </docs/migrate>; rel=\"deprecation\"\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET /v1/groups HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-deprecated\r\n\
+            Deprecation: true\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let url = format!("http://{}/v1/users", server.addr());
+    let res = reqwest::get(&url).unwrap();
+    let info = res.deprecation().unwrap();
+    assert!(info.is_deprecated());
+    assert_eq!(info.deprecated_at(), Some(UNIX_EPOCH + Duration::from_secs(1688169599)));
+    assert_eq!(info.sunset(), Some(UNIX_EPOCH + Duration::from_secs(1906502400)));
+    assert_eq!(info.links().len(), 1);
+    assert_eq!(info.links()[0].as_str(), format!("http://{}/docs/migrate", server.addr()));
+
+    let res = reqwest::get(&format!("http://{}/v1/groups", server.addr())).unwrap();
+    let info = res.deprecation().unwrap();
+    assert!(info.is_deprecated());
+    assert_eq!(info.deprecated_at(), None);
+    assert_eq!(info.sunset(), None);
+    assert!(info.links().is_empty());
+}
+
+#[test]
+fn test_on_deprecated_endpoint_once_per_path() {
+    use std::sync::{Arc, Mutex};
+
+    let deprecated = b"\
+        HTTP/1.1 200 OK\r\n\
+        Server: test-deprecated\r\n\
+        Deprecation: ?1\r\n\
+        Content-Length: 0\r\n\
+        Connection: close\r\n\
+        \r\n\
+        ";
+    let request = |path: &str| format!("\
+        GET {} HTTP/1.1\r\n\
+        Host: $HOST\r\n\
+        User-Agent: $USERAGENT\r\n\
+        Accept: */*\r\n\
+        Accept-Encoding: $ACCEPT_ENCODING\r\n\
+        \r\n\
+        ", path).into_bytes();
+    let server = server! {
+        request: request("/old?page=1"),
+        response: deprecated,
+
+        request: request("/old?page=2"),
+        response: deprecated,
+
+        request: request("/older"),
+        response: deprecated,
+
+        request: request("/current"),
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-deprecated\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let hook = reported.clone();
+    let client = reqwest::Client::builder()
+        .on_deprecated_endpoint(move |url, info| {
+            assert!(info.is_deprecated());
+            hook.lock().unwrap().push(url.path().to_owned());
+        })
+        .build()
+        .unwrap();
+    for path in &["/old?page=1", "/old?page=2", "/older", "/current"] {
+        let url = format!("http://{}{}", server.addr(), path);
+        client.get(&url).send().unwrap();
+    }
+
+    assert_eq!(*reported.lock().unwrap(), vec!["/old", "/older"]);
+}
+
+#[test]
+fn test_tee_json() {
+    use std::sync::{Arc, Mutex};
+
+    let server = server! {
+        request: b"\
+            GET /audit HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-audit\r\n\
+            Content-Type: application/json\r\n\
+            Content-Length: 26\r\n\
+            \r\n\
+            {\"id\":7,\"tags\":[\"a\",\"b\"]}\n\
+            "
+    };
+
+    let copy = Arc::new(Mutex::new(Vec::new()));
+    let res = reqwest::get(&format!("http://{}/audit", server.addr())).unwrap();
+    let mut res = res.tee(SharedChunk {
+        buf: copy.clone(),
+        fail_after: None,
+    });
+    let value: serde_json::Value = res.json().unwrap();
+
+    assert_eq!(value["id"], 7);
+    let copy = copy.lock().unwrap();
+    assert_eq!(&copy[..], &b"{\"id\":7,\"tags\":[\"a\",\"b\"]}\n"[..]);
+    assert_eq!(String::from_utf8_lossy(&copy).trim(), serde_json::to_string(&value).unwrap());
+}
+
+#[test]
+fn test_tee_sink_failure() {
+    use std::sync::{Arc, Mutex};
+
+    let (server, _) = large_body_server(20_000);
+    let copy = Arc::new(Mutex::new(Vec::new()));
+    let res = reqwest::get(&format!("http://{}/export", server.addr())).unwrap();
+    let mut res = res.tee(SharedChunk {
+        buf: copy.clone(),
+        fail_after: Some(5000),
+    });
+
+    let err = res.text().unwrap_err();
+    match err {
+        reqwest::Error::TeeSink(ref e) => assert_eq!(e.kind(), ::std::io::ErrorKind::Other),
+        ref other => panic!("unexpected error: {:?}", other),
+    }
+    assert_eq!(err.to_string(), "Copying the response body failed: disk full");
+    assert_eq!(copy.lock().unwrap().len(), 5000);
+
+    // and the rest of the body isn't read without it
+    let mut buf = [0; 100];
+    let err = res.read(&mut buf).unwrap_err();
+    assert!(reqwest::Error::from(err).to_string().starts_with("Copying the response body failed"));
+}
+
+#[test]
+fn test_proxy_http_absolute_form() {
+    // the proxy is sent the whole URL, and the redirect goes through it too
+    let proxy = server! {
+        request: b"\
+            GET http://origin.test/old HTTP/1.1\r\n\
+            Host: origin.test\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-proxy\r\n\
+            Location: /new\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET http://origin.test/new HTTP/1.1\r\n\
+            Host: origin.test\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Referer: http://origin.test/old\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-proxy\r\n\
+            Content-Length: 6\r\n\
+            \r\n\
+            proxed\
+            "
+    };
+
+    let client = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::https("http://127.0.0.1:1").unwrap())
+        .proxy(reqwest::Proxy::http(&format!("http://{}", proxy.addr())).unwrap())
+        .build()
+        .unwrap();
+    let mut res = client.get("http://origin.test/old").send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+    assert_eq!(res.url().as_str(), "http://origin.test/new");
+    assert_eq!(res.remote_addr(), proxy.addr());
+    // the proxy resolves the host
+    assert_eq!(res.resolved_addrs(), None);
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "proxed");
+
+    let proxied = client.pool_stats().partitions().iter()
+        .filter(|partition| partition.proxy() == Some(("127.0.0.1", proxy.addr().port())))
+        .count();
+    assert_eq!(proxied, 1);
+}
+
+#[cfg(feature = "default-tls")]
+#[test]
+fn test_proxy_https_connect() {
+    use std::net;
+    use std::thread;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_url = format!("http://{}/", listener.local_addr().unwrap());
+    let recorded = thread::spawn(move || {
+        let (mut socket, _addr) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        let n = socket.read(&mut buf).unwrap();
+        socket.write_all(b"\
+            HTTP/1.1 407 Proxy Authentication Required\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ").unwrap();
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    });
+
+    let client = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all(&*proxy_url).unwrap())
+        .build()
+        .unwrap();
+    let err = client.get("https://origin.test:8443/secret").send().unwrap_err();
+    match err {
+        reqwest::Error::Proxy(ref url, ref e) => {
+            assert_eq!(url.as_str(), proxy_url);
+            assert!(e.to_string().contains("407 Proxy Authentication Required"), "{}", e);
+        },
+        ref other => panic!("unexpected error: {:?}", other),
+    }
+    // only the tunnel is asked for, the request itself would go through it
+    assert_eq!(recorded.join().unwrap(), "\
+        CONNECT origin.test:8443 HTTP/1.1\r\n\
+        Host: origin.test:8443\r\n\
+        \r\n\
+        ");
+}
+
+#[test]
+fn test_proxy_basic_auth_http() {
+    // "user:pass", from the URL of the proxy
+    let proxy = server! {
+        request: b"\
+            GET http://origin.test/old HTTP/1.1\r\n\
+            Host: origin.test\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-proxy\r\n\
+            Location: /new\r\n\
+            Content-Length: 0\r\n\
+            Connection: close\r\n\
+            \r\n\
+            ",
+
+        request: b"\
+            GET http://origin.test/new HTTP/1.1\r\n\
+            Host: origin.test\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Referer: http://origin.test/old\r\n\
+            Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 204 No Content\r\n\
+            Server: test-proxy\r\n\
+            \r\n\
+            "
+    };
+
+    let proxy_url = format!("http://user:pass@{}", proxy.addr());
+    let mut client = reqwest::Client::new().unwrap();
+    client.proxy(reqwest::Proxy::http(&*proxy_url).unwrap());
+    let res = client.get("http://origin.test/old").send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::NoContent);
+    assert!(!format!("{:?}", client).contains("dXNlcjpwYXNz"));
+}
+
+#[cfg(feature = "default-tls")]
+#[test]
+fn test_proxy_basic_auth_connect() {
+    use std::net;
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy_url = format!("http://{}/", listener.local_addr().unwrap());
+    let recorded = thread::spawn(move || {
+        let (mut socket, _addr) = listener.accept().unwrap();
+        let mut head = Vec::new();
+        let mut byte = [0];
+        while !head.ends_with(b"\r\n\r\n") {
+            socket.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        socket.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n").unwrap();
+        // whatever the client sends through the tunnel, until it gives up
+        let mut tunneled = Vec::new();
+        socket.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+        let _ = socket.read_to_end(&mut tunneled);
+        (String::from_utf8_lossy(&head).into_owned(), tunneled)
+    });
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.proxy(reqwest::Proxy::https(&*proxy_url).unwrap().basic_auth("user", "pass"));
+    client.timeout(Duration::from_secs(1)).unwrap();
+    let _ = client.get("https://origin.test:8443/secret").send();
+    let (connect, tunneled) = recorded.join().unwrap();
+    assert_eq!(connect, "\
+        CONNECT origin.test:8443 HTTP/1.1\r\n\
+        Host: origin.test:8443\r\n\
+        Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\
+        \r\n\
+        ");
+    let tunneled = String::from_utf8_lossy(&tunneled);
+    assert!(!tunneled.contains("Proxy-Authorization"), "{}", tunneled);
+    assert!(!tunneled.contains("dXNlcjpwYXNz"), "{}", tunneled);
+}
+
+#[test]
+fn test_proxy_connect_failure() {
+    use std::net;
+
+    // nothing listens on the proxy
+    let port = net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let mut client = reqwest::Client::new().unwrap();
+    client.proxy(reqwest::Proxy::all(&format!("http://127.0.0.1:{}", port)).unwrap());
+    let err = client.get("http://origin.test/").send().unwrap_err();
+    assert!(err.is_connect(), "{:?}", err);
+    match err {
+        reqwest::Error::Proxy(ref url, _) => assert_eq!(url.port(), Some(port)),
+        ref other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(err.to_string().starts_with(&format!("Proxy http://127.0.0.1:{}/ failed: ", port)),
+            "{}", err);
+}
+
+#[test]
+fn test_https_only_rejects_http() {
+    let client = reqwest::Client::builder()
+        .https_only(true)
+        .allow_http_host(".lab.example.com")
+        .build()
+        .unwrap();
+    // nothing is connected to, so the hosts don't have to exist
+    for url in &["http://example.com/", "http://lab.example.com/", "http://lab.example.com.evil/"] {
+        match client.get(*url).send() {
+            Err(reqwest::Error::HttpsRequired(ref rejected)) => assert_eq!(rejected.as_str(), *url),
+            other => panic!("unexpected result for {}: {:?}", url, other),
+        }
+    }
+    match client.warm_up("http://example.com/", 1) {
+        Err(reqwest::Error::HttpsRequired(..)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_https_only_allowed_host() {
+    let server = server! {
+        request: b"\
+            GET /status HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let mut client = reqwest::Client::builder()
+        .https_only(true)
+        .build()
+        .unwrap();
+    let url = format!("http://{}/status", server.addr());
+    match client.get(&url).send() {
+        Err(reqwest::Error::HttpsRequired(..)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    client.allow_http_host("127.0.0.1");
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
+
+#[test]
+fn test_https_only_blocks_redirect_to_http() {
+    // the stream stands in for a TLS connection to the server
+    let (client_end, server_end) = server::pipe();
+    server! {
+        over server_end, host: "tunnel.local",
+        request: b"\
+            GET /secure HTTP/1.1\r\n\
+            Host: tunnel.local\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-over\r\n\
+            Location: http://tunnel.local/insecure\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::builder()
+        .https_only(true)
+        .build()
+        .unwrap();
+    match client.request_over(client_end, client.get("https://tunnel.local/secure")) {
+        Err(reqwest::Error::HttpsRequired(ref url)) => {
+            assert_eq!(url.as_str(), "http://tunnel.local/insecure");
+        },
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_allowed_methods_for_host() {
+    use reqwest::Method;
+
+    let client = reqwest::Client::builder()
+        .allowed_methods_for_host(".prod.example.com", &[Method::Get, Method::Head])
+        .allowed_methods_for_host("admin.prod.example.com", &[Method::Get, Method::Delete])
+        .allowed_methods_for_host("Readonly.Example.com", &[Method::Get])
+        .build()
+        .unwrap();
+    // nothing is connected to, so the hosts don't have to exist
+    let denied = [
+        (Method::Delete, "http://db.prod.example.com/users/1", "db.prod.example.com"),
+        (Method::Post, "http://a.b.prod.example.com/", "a.b.prod.example.com"),
+        (Method::Head, "http://admin.prod.example.com/", "admin.prod.example.com"),
+        (Method::Put, "http://READONLY.example.com/", "readonly.example.com"),
+        // a fully qualified name, with a trailing dot, is the same host
+        (Method::Delete, "http://db.prod.example.com./users/1", "db.prod.example.com"),
+        (Method::Put, "http://readonly.example.com./", "readonly.example.com"),
+    ];
+    for &(ref method, url, host) in &denied {
+        match client.request(method.clone(), url).send() {
+            Err(reqwest::Error::MethodNotAllowed(ref m, ref h, _)) => {
+                assert_eq!((m, &**h), (method, host));
+            },
+            other => panic!("unexpected result for {} {}: {:?}", method, url, other),
+        }
+    }
+
+    let err = client.delete("http://db.prod.example.com/").send().unwrap_err();
+    assert_eq!(err.to_string(),
+               "Method DELETE is not allowed for db.prod.example.com, only: GET, HEAD");
+}
+
+#[test]
+fn test_allowed_methods_for_host_allows() {
+    let server = server! {
+        request: b"\
+            DELETE /users/1 HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 204 No Content\r\n\
+            \r\n\
+            "
+    };
+
+    // only other hosts are restricted
+    let mut client = reqwest::Client::builder()
+        .allowed_methods_for_host(".example.com", &[reqwest::Method::Get])
+        .build()
+        .unwrap();
+    let url = format!("http://{}/users/1", server.addr());
+    client.allowed_methods_for_host("127.0.0.1", &[reqwest::Method::Get]);
+    match client.delete(&url).send() {
+        Err(reqwest::Error::MethodNotAllowed(..)) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    // replaces the methods
+    client.allowed_methods_for_host("127.0.0.1", &[reqwest::Method::Get, reqwest::Method::Delete]);
+    let res = client.delete(&url).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::NoContent);
+}
+
+#[test]
+fn test_allowed_methods_for_host_checks_redirects() {
+    let server = server! {
+        request: b"\
+            POST /submit HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            Content-Length: 5\r\n\
+            \r\n\
+            hello\
+            ",
+        response: b"\
+            HTTP/1.1 307 Temporary Redirect\r\n\
+            Location: http://readonly.example.com/submit\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    };
+
+    let client = reqwest::Client::builder()
+        .allowed_methods_for_host("readonly.example.com", &[reqwest::Method::Get])
+        .build()
+        .unwrap();
+    match client.post(&format!("http://{}/submit", server.addr())).body("hello").send() {
+        Err(reqwest::Error::MethodNotAllowed(reqwest::Method::Post, ref host, ref allowed)) => {
+            assert_eq!(host, "readonly.example.com");
+            assert_eq!(allowed, &[reqwest::Method::Get]);
+        },
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+fn diagnosed_redirect_server() -> server::Server {
+    server! {
+        request: b"\
+            GET /ambiguous HTTP/1.1\r\n\
+            Host: $HOST\r\n\
+            Authorization: Bearer secret\r\n\
+            User-Agent: $USERAGENT\r\n\
+            Accept: */*\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
+            \r\n\
+            ",
+        response: b"\
+            HTTP/1.1 302 Found\r\n\
+            Server: test-diagnostics\r\n\
+            Location: /first\r\n\
+            Location: /second\r\n\
+            Set-Cookie: session=hunter2\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            "
+    }
+}
+
+#[test]
+fn test_capture_diagnostics() {
+    use reqwest::header::Authorization;
+
+    let server = diagnosed_redirect_server();
+    let client = reqwest::Client::builder()
+        .capture_diagnostics(4096)
+        .build()
+        .unwrap();
+    let url = format!("http://{}/ambiguous", server.addr());
+    let err = client.get(&url)
+        .header(Authorization("Bearer secret".to_owned()))
+        .send()
+        .unwrap_err();
+    match err {
+        reqwest::Error::Diagnosed(ref e, _) => match **e {
+            reqwest::Error::AmbiguousRedirect(..) => (),
+            ref other => panic!("unexpected error: {:?}", other),
+        },
+        ref other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(err.to_string().starts_with("Ambiguous redirect"), "{}", err);
+
+    let diagnostics = err.diagnostics().unwrap();
+    assert_eq!(diagnostics.method(), &reqwest::Method::Get);
+    assert_eq!(diagnostics.url().as_str(), url);
+    assert_eq!(diagnostics.status(), Some(reqwest::StatusCode::Found));
+    assert!(!diagnostics.truncated());
+    let header = |headers: &[(String, String)], name: &str| {
+        headers.iter().find(|&&(ref n, _)| n == name).map(|&(_, ref v)| v.clone())
+    };
+    let request_headers = diagnostics.request_headers();
+    assert_eq!(header(request_headers, "Host"), Some(server.addr().to_string()));
+    assert_eq!(header(request_headers, "Authorization"), Some("[redacted]".to_owned()));
+    let response_headers = diagnostics.response_headers();
+    assert_eq!(header(response_headers, "Server"), Some("test-diagnostics".to_owned()));
+    assert_eq!(header(response_headers, "Set-Cookie"), Some("[redacted]".to_owned()));
+
+    let json = serde_json::to_string(diagnostics).unwrap();
+    assert!(json.contains("\"status\":302"), "{}", json);
+    assert!(!json.contains("secret") && !json.contains("hunter2"), "{}", json);
+}
+
+#[test]
+fn test_capture_diagnostics_off() {
+    let server = diagnosed_redirect_server();
     let client = reqwest::Client::new().unwrap();
-    let mut res = client.head(&format!("http://{}/gzip", server.addr()))
+    let err = client.get(&format!("http://{}/ambiguous", server.addr()))
+        .header(reqwest::header::Authorization("Bearer secret".to_owned()))
         .send()
+        .unwrap_err();
+    match err {
+        reqwest::Error::AmbiguousRedirect(..) => (),
+        ref other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(err.diagnostics().is_none());
+}
+
+#[test]
+fn test_capture_diagnostics_size_cap() {
+    use std::net;
+
+    // nothing listens, so there is no response
+    let port = net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let mut client = reqwest::Client::new().unwrap();
+    client.capture_diagnostics(128);
+    let err = client.post(&format!("http://127.0.0.1:{}/upload", port))
+        .body(vec![b'x'; 1000])
+        .send()
+        .unwrap_err();
+    assert!(err.is_connect(), "{:?}", err);
+
+    let diagnostics = err.diagnostics().unwrap();
+    assert_eq!(diagnostics.status(), None);
+    assert!(diagnostics.response_headers().is_empty());
+    assert!(diagnostics.truncated());
+    let headers = diagnostics.request_headers().iter()
+        .map(|&(ref name, ref value)| name.len() + value.len())
+        .sum::<usize>();
+    let body = diagnostics.request_body();
+    assert!(!body.is_empty() && body.iter().all(|&b| b == b'x'));
+    assert!(headers + body.len() <= 128);
+    assert!(diagnostics.request_headers().iter().any(|&(ref name, ref value)| {
+        name == "Content-Length" && value == "1000"
+    }));
+
+    let json: serde_json::Value = serde_json::to_value(diagnostics).unwrap();
+    assert_eq!(json["method"], serde_json::Value::String("POST".to_owned()));
+    assert_eq!(json["status"], serde_json::Value::Null);
+    assert_eq!(json["truncated"], serde_json::Value::Bool(true));
+}
+
+// "raw deflate with a 512 byte window, " 32 times, made with zlib's
+// `deflateInit2` and a `windowBits` of -9
+static RAW_DEFLATE_9: &'static [u8] = b"\
+    \x2b\x4a\x2c\x57\x48\x49\x4d\xcb\x49\x2c\x49\x55\x28\xcf\x2c\xc9\
+    \x50\x48\x54\x30\x35\x34\x52\x48\xaa\x04\xf3\xf3\x52\xf2\xcb\x75\
+    \x14\x8a\x46\xd5\x8c\xaa\x19\x55\x33\xaa\x86\x06\x6a\x00";
+
+#[test]
+fn test_deflate_options_raw() {
+    let expected = "raw deflate with a 512 byte window, ".repeat(32);
+
+    let server = transfer_coded_server("deflate", RAW_DEFLATE_9);
+    let mut client = reqwest::Client::new().unwrap();
+    client.accept_transfer_encodings(true);
+    let mut res = client.get(&format!("http://{}/coded", server.addr())).send().unwrap();
+    assert!(res.read_to_string(&mut String::new()).is_err());
+
+    let options = reqwest::DeflateOptions {
+        raw: true,
+        window_bits: 9,
+        sniff: false,
+    };
+    for &(raw, sniff) in &[(true, false), (false, true)] {
+        let server = transfer_coded_server("deflate", RAW_DEFLATE_9);
+        let client = reqwest::Client::builder()
+            .accept_transfer_encodings(true)
+            .deflate_options(reqwest::DeflateOptions { raw: raw, sniff: sniff, ..options })
+            .build()
+            .unwrap();
+        let mut res = client.get(&format!("http://{}/coded", server.addr())).send().unwrap();
+        let mut body = String::new();
+        res.read_to_string(&mut body).unwrap();
+        assert_eq!(body, expected);
+    }
+}
+
+#[test]
+fn test_deflate_options_sniff_zlib() {
+    let mut encoder = ::libflate::zlib::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(b"still zlib").unwrap();
+    let server = transfer_coded_server("deflate", &encoder.finish().into_result().unwrap());
+
+    let client = reqwest::Client::builder()
+        .accept_transfer_encodings(true)
+        .deflate_options(reqwest::DeflateOptions {
+            raw: true,
+            window_bits: 15,
+            sniff: true,
+        })
+        .build()
         .unwrap();
+    let mut res = client.get(&format!("http://{}/coded", server.addr())).send().unwrap();
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "still zlib");
+}
 
-    let mut body = ::std::string::String::new();
+#[test]
+fn test_deflate_options_zlib_window_too_large() {
+    // the header of libflate asks for a 32KB window
+    let mut encoder = ::libflate::zlib::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(b"a large window").unwrap();
+    let server = transfer_coded_server("deflate", &encoder.finish().into_result().unwrap());
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.accept_transfer_encodings(true);
+    client.deflate_options(reqwest::DeflateOptions {
+        raw: false,
+        window_bits: 12,
+        sniff: false,
+    }).unwrap();
+    let mut res = client.get(&format!("http://{}/coded", server.addr())).send().unwrap();
+    let err = res.read_to_string(&mut String::new()).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("larger than the 4096 bytes allowed"), "{}", err);
+}
+
+#[test]
+fn test_deflate_options_invalid_window_bits() {
+    for &bits in &[0, 7, 16] {
+        let options = reqwest::DeflateOptions {
+            raw: true,
+            window_bits: bits,
+            sniff: false,
+        };
+        match reqwest::Client::builder().deflate_options(options).build() {
+            Err(reqwest::Error::InvalidWindowBits("ClientBuilder::deflate_options", b))
+                if b == bits => (),
+            other => panic!("unexpected result for {}: {:?}", bits, other),
+        }
+        let mut client = reqwest::Client::new().unwrap();
+        match client.deflate_options(options) {
+            Err(reqwest::Error::InvalidWindowBits("Client::deflate_options", _)) => (),
+            other => panic!("unexpected result for {}: {:?}", bits, other),
+        }
+    }
+}
+
+#[test]
+fn test_dns_resolver() {
+    use std::io;
+    use std::net::{self, SocketAddr};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    struct Blue {
+        addr: SocketAddr,
+        asked: Mutex<Vec<String>>,
+    }
+
+    impl reqwest::Resolve for Blue {
+        fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+            self.asked.lock().unwrap().push(format!("{}:{}", host, port));
+            if host == "example.test" {
+                Ok(vec![self.addr])
+            } else {
+                reqwest::SystemResolver.resolve(host, port)
+            }
+        }
+    }
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let resolver = Arc::new(Blue {
+        addr: listener.local_addr().unwrap(),
+        asked: Mutex::new(Vec::new()),
+    });
+    let received = thread::spawn(move || {
+        let (mut socket, _addr) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        let n = socket.read(&mut buf).unwrap();
+        socket.write_all(b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-dns\r\n\
+            Content-Length: 4\r\n\
+            \r\n\
+            blue\
+            ").unwrap();
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    });
+
+    let client = reqwest::Client::builder()
+        .dns_resolver(resolver.clone())
+        .build()
+        .unwrap();
+    let mut res = client.get("http://example.test:8080/deploy").send().unwrap();
+    let mut body = String::new();
     res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "blue");
+    assert_eq!(res.remote_addr(), resolver.addr);
+    assert_eq!(res.resolved_addrs(), Some(&[resolver.addr][..]));
+    assert!(!res.resolved_addrs_pinned());
+    // the server still sees the host from the URL
+    assert!(received.join().unwrap().contains("\r\nHost: example.test:8080\r\n"));
+    assert_eq!(*resolver.asked.lock().unwrap(), vec!["example.test:8080".to_owned()]);
 
-    assert_eq!(body, "");
+    // a resolver error is the error of the request
+    struct Broken;
+    impl reqwest::Resolve for Broken {
+        fn resolve(&self, _host: &str, _port: u16) -> io::Result<Vec<SocketAddr>> {
+            Err(io::Error::new(io::ErrorKind::Other, "no DNS today"))
+        }
+    }
+    let mut client = reqwest::Client::new().unwrap();
+    client.dns_resolver(Arc::new(Broken));
+    let err = client.get("http://example.test/").send().unwrap_err();
+    assert!(err.to_string().contains("no DNS today"), "{}", err);
 }
 
 #[test]
-fn test_gzip_invalid_body() {
+fn test_request_stats_resolved_addrs() {
+    use std::io;
+    use std::net::{self, SocketAddr};
+    use std::sync::Arc;
+    use std::thread;
+
+    struct Stub(Vec<SocketAddr>);
+
+    impl reqwest::Resolve for Stub {
+        fn resolve(&self, _host: &str, _port: u16) -> io::Result<Vec<SocketAddr>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    // nothing listens at the first address, so the second one is used
+    let closed = net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut socket, _addr) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        socket.read(&mut buf).unwrap();
+        socket.write_all(b"\
+            HTTP/1.1 200 OK\r\n\
+            Server: test-stats\r\n\
+            Content-Length: 0\r\n\
+            \r\n\
+            ").unwrap();
+    });
+
+    let client = reqwest::Client::builder()
+        .dns_resolver(Arc::new(Stub(vec![closed, addr])))
+        .build()
+        .unwrap();
+    let res = client.get("http://stub.test/stats").send().unwrap();
+    let stats = res.stats();
+    drop(res);
+    assert!(!stats.connection_reused());
+    assert_eq!(stats.remote_addr(), addr);
+    assert_eq!(stats.resolved_addrs(), Some(&[closed, addr][..]));
+    assert!(!stats.resolved_addrs_pinned());
+}
+
+#[test]
+fn test_resolve_overrides() {
+    use std::io;
+    use std::net::{self, SocketAddr};
+    use std::sync::Arc;
+    use std::thread;
+
+    fn serve(listener: net::TcpListener) -> thread::JoinHandle<String> {
+        thread::spawn(move || {
+            let (mut socket, _addr) = listener.accept().unwrap();
+            let mut buf = [0; 4096];
+            let n = socket.read(&mut buf).unwrap();
+            socket.write_all(b"\
+                HTTP/1.1 200 OK\r\n\
+                Server: test-resolve\r\n\
+                Content-Length: 6\r\n\
+                \r\n\
+                pinned\
+                ").unwrap();
+            String::from_utf8_lossy(&buf[..n]).into_owned()
+        })
+    }
+
+    // nothing listens here anymore
+    let closed = net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let received = serve(listener);
+
+    // the last address for a host wins
+    let client = reqwest::Client::builder()
+        .resolve("api.example.com", closed)
+        .resolve("API.example.com", addr)
+        .build()
+        .unwrap();
+    let mut res = client.get("http://api.example.com/v1/status").send().unwrap();
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "pinned");
+    assert_eq!(res.remote_addr(), addr);
+    assert_eq!(res.resolved_addrs(), Some(&[addr][..]));
+    assert!(res.resolved_addrs_pinned());
+    assert!(received.join().unwrap().contains("\r\nHost: api.example.com\r\n"));
+
+    // the addresses are tried in order, and the resolver isn't asked
+    struct Broken;
+    impl reqwest::Resolve for Broken {
+        fn resolve(&self, _host: &str, _port: u16) -> io::Result<Vec<SocketAddr>> {
+            Err(io::Error::new(io::ErrorKind::Other, "no DNS today"))
+        }
+    }
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut any_port = listener.local_addr().unwrap();
+    let port = any_port.port();
+    any_port.set_port(0);
+    let received = serve(listener);
+
+    let mut client = reqwest::Client::new().unwrap();
+    client.dns_resolver(Arc::new(Broken));
+    client.resolve_to_addrs("staging.example.com", &[closed, any_port]);
+    let url = format!("http://staging.example.com:{}/", port);
+    let res = client.get(&*url).send().unwrap();
+    assert_eq!(*res.status(), reqwest::StatusCode::Ok);
+    let expected_host = format!("\r\nHost: staging.example.com:{}\r\n", port);
+    assert!(received.join().unwrap().contains(&expected_host));
+
+    // and without any, the host is resolved again
+    client.resolve_to_addrs("staging.example.com", &[]);
+    let err = client.get(&*url).send().unwrap_err();
+    assert!(err.to_string().contains("no DNS today"), "{}", err);
+}
+
+/// Answers `GET /<delay in ms>/<anything>` with the path, after the delay,
+/// or closes the connection without answering for `/drop/..`, and keeps
+/// the most connections it had at once.
+fn spawn_bulk_server() -> (std::net::SocketAddr, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+    use std::net;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let active = Arc::new(AtomicUsize::new(0));
+    let most = Arc::new(AtomicUsize::new(0));
+    let most_seen = most.clone();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            let active = active.clone();
+            let most = most.clone();
+            thread::spawn(move || {
+                let mut buf = [0; 4096];
+                let n = socket.read(&mut buf).unwrap();
+                let head = String::from_utf8_lossy(&buf[..n]).into_owned();
+                let path = head.split(' ').nth(1).unwrap().to_owned();
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                if now > most.load(Ordering::SeqCst) {
+                    most.store(now, Ordering::SeqCst);
+                }
+                let delay = path.split('/').nth(1).unwrap();
+                if delay == "drop" {
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    return;
+                }
+                thread::sleep(Duration::from_millis(delay.parse().unwrap()));
+                active.fetch_sub(1, Ordering::SeqCst);
+                let _ = write!(socket, "HTTP/1.1 200 OK\r\n\
+                                        Content-Length: {}\r\n\
+                                        Connection: close\r\n\
+                                        \r\n\
+                                        {}", path.len(), path);
+            });
+        }
+    });
+    (addr, most_seen)
+}
+
+#[test]
+fn test_get_many_keeps_order_and_bounds_concurrency() {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::Ordering;
+
+    let (addr, most) = spawn_bulk_server();
+    // the first ones are the slowest, so they finish last
+    let paths = (0..9).map(|i| format!("/{}/{}", 90 - i * 10, i)).collect::<Vec<_>>();
+    let urls = paths.iter().map(|path| format!("http://{}{}", addr, path)).collect::<Vec<_>>();
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let progress = calls.clone();
+
+    let client = reqwest::Client::new().unwrap();
+    let results = client.get_many(urls.iter().collect(), reqwest::BulkOptions {
+        workers: 3,
+        progress: Some(Arc::new(move |done, total| progress.lock().unwrap().push((done, total)))),
+        ..reqwest::BulkOptions::default()
+    });
+
+    assert_eq!(results.len(), 9);
+    for (i, done) in results.into_iter().enumerate() {
+        assert_eq!(done.index, i);
+        let mut body = String::new();
+        done.result.unwrap().read_to_string(&mut body).unwrap();
+        assert_eq!(body, paths[i]);
+    }
+    let most = most.load(Ordering::SeqCst);
+    assert!(most >= 2 && most <= 3, "{} requests at once", most);
+    assert_eq!(*calls.lock().unwrap(), (1..10).map(|done| (done, 9)).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_get_many_isolates_failures() {
+    use std::time::Duration;
+
+    let (addr, _) = spawn_bulk_server();
+    let urls = vec![
+        format!("http://{}/0/fast", addr),
+        format!("http://{}/drop/closed", addr),
+        format!("http://{}/1000/slow", addr),
+        "not a url".to_owned(),
+        format!("http://{}/10/fast-again", addr),
+    ];
+
+    let client = reqwest::Client::new().unwrap();
+    let results = client.get_many(urls.iter().collect(), reqwest::BulkOptions {
+        timeout: Some(Duration::from_millis(200)),
+        ..reqwest::BulkOptions::default()
+    });
+
+    assert_eq!(results.iter().map(|done| done.index).collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+    assert_eq!(results.iter().map(|done| done.is_ok()).collect::<Vec<_>>(),
+               vec![true, false, false, false, true]);
+    match results[2].result {
+        Err(ref err) => assert!(err.is_timeout(), "{:?}", err),
+        Ok(ref res) => panic!("unexpected response: {:?}", res),
+    }
+    assert!(results[2].elapsed < Duration::from_millis(900), "{:?}", results[2].elapsed);
+    match results[3].result {
+        Err(reqwest::Error::Http(reqwest::HyperError::Uri(_))) => (),
+        ref other => panic!("unexpected result: {:?}", other),
+    }
+
+    let results = client.get_many(urls.iter().collect(), reqwest::BulkOptions {
+        timeout: Some(Duration::from_secs(0)),
+        ..reqwest::BulkOptions::default()
+    });
+    assert_eq!(results.len(), 5);
+    for done in results {
+        match done.result {
+            Err(reqwest::Error::InvalidTimeout("BulkOptions::timeout", _)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    let results = client.get_many(urls.iter().collect(), reqwest::BulkOptions {
+        workers: 0,
+        ..reqwest::BulkOptions::default()
+    });
+    assert_eq!(results.len(), 5);
+    for (i, done) in results.into_iter().enumerate() {
+        assert_eq!(done.index, i);
+        match done.result {
+            Err(reqwest::Error::InvalidThreadCount("BulkOptions::workers", 0)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_get_many_per_host() {
+    use std::sync::atomic::Ordering;
+
+    let (first, first_most) = spawn_bulk_server();
+    let (second, second_most) = spawn_bulk_server();
+    let urls = (0..6).map(|i| {
+        let addr = if i % 2 == 0 { first } else { second };
+        format!("http://{}/50/{}", addr, i)
+    }).collect::<Vec<_>>();
+
+    let client = reqwest::Client::new().unwrap();
+    let results = client.get_many(urls.iter().collect(), reqwest::BulkOptions {
+        workers: 4,
+        per_host: Some(1),
+        ..reqwest::BulkOptions::default()
+    });
+    assert_eq!(results.len(), 6);
+    for (i, done) in results.into_iter().enumerate() {
+        assert_eq!(done.index, i);
+        let mut body = String::new();
+        done.result.unwrap().read_to_string(&mut body).unwrap();
+        assert_eq!(body, format!("/50/{}", i));
+    }
+    assert_eq!(first_most.load(Ordering::SeqCst), 1);
+    assert_eq!(second_most.load(Ordering::SeqCst), 1);
+
+    let results = client.get_many(urls.iter().collect(), reqwest::BulkOptions {
+        per_host: Some(0),
+        ..reqwest::BulkOptions::default()
+    });
+    for done in results {
+        match done.result {
+            Err(reqwest::Error::InvalidThreadCount("BulkOptions::per_host", 0)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn test_get_many_worker_panic() {
     let server = server! {
         request: b"\
-            GET /gzip HTTP/1.1\r\n\
+            GET /deprecated HTTP/1.1\r\n\
             Host: $HOST\r\n\
             User-Agent: $USERAGENT\r\n\
             Accept: */*\r\n\
-            Accept-Encoding: gzip\r\n\
+            Accept-Encoding: $ACCEPT_ENCODING\r\n\
             \r\n\
             ",
         response: b"\
             HTTP/1.1 200 OK\r\n\
-            Server: test-accept\r\n\
-            Content-Encoding: gzip\r\n\
-            Content-Length: 100\r\n\
+            Deprecation: true\r\n\
+            Content-Length: 0\r\n\
             \r\n\
-            0"
+            "
     };
-
-    let mut res = reqwest::get(&format!("http://{}/gzip", server.addr()))
+    let client = reqwest::Client::builder()
+        .on_deprecated_endpoint(|_, _| panic!("the hook panicked"))
+        .build()
         .unwrap();
-    // this tests that the request.send() didn't error, but that the error
-    // is in reading the body
-
-    let mut body = ::std::string::String::new();
-    res.read_to_string(&mut body).unwrap_err();
+    // the only worker panics in the first request, so the second one is
+    // never sent either
+    let urls = vec![format!("http://{}/deprecated", server.addr()), "not a url".to_owned()];
+    let results = client.get_many(urls.iter().collect(), reqwest::BulkOptions {
+        workers: 1,
+        ..reqwest::BulkOptions::default()
+    });
+    assert_eq!(results.len(), 2);
+    for (i, done) in results.into_iter().enumerate() {
+        assert_eq!(done.index, i);
+        match done.result {
+            Err(reqwest::Error::WorkerPanicked) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,88 @@
+//! The guards of `reqwest::test::deny_network` are process-wide, so these
+//! tests have their own binary, away from those that connect.
+#![cfg(feature = "test-util")]
+
+extern crate reqwest;
+
+use std::io::{self, Read, Write};
+use std::net;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Answers each connection with an empty `200 OK`.
+fn spawn_ok() -> net::SocketAddr {
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = socket.unwrap();
+            let mut buf = [0; 4096];
+            let _ = socket.read(&mut buf);
+            let _ = socket.write_all(b"\
+                HTTP/1.1 200 OK\r\n\
+                Content-Length: 0\r\n\
+                Connection: close\r\n\
+                \r\n\
+            ");
+        }
+    });
+    addr
+}
+
+/// A stream that discards the request, and answers with `response`.
+struct Canned {
+    response: io::Cursor<Vec<u8>>,
+}
+
+impl Read for Canned {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.response.read(buf)
+    }
+}
+
+impl Write for Canned {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// one test, so that no other test connects while a guard is alive
+#[test]
+fn test_deny_network() {
+    let addr = spawn_ok();
+    let url = format!("http://{}/guarded", addr);
+    let client = reqwest::Client::new().unwrap();
+
+    let guard = reqwest::test::deny_network();
+    let start = Instant::now();
+    match client.get("https://example.com/").send() {
+        Err(reqwest::Error::NetworkDenied(ref host, 443)) if host == "example.com" => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(start.elapsed() < Duration::from_secs(1), "{:?}", start.elapsed());
+    let err = client.get(&url).send().unwrap_err();
+    assert_eq!(err.to_string(),
+               format!("network access denied by test guard: tried {}", addr));
+
+    // a request over a given stream doesn't connect
+    let stream = Canned {
+        response: io::Cursor::new(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".to_vec()),
+    };
+    let mut res = client.request_over(stream, client.get("http://mock.local/")).unwrap();
+    let mut body = String::new();
+    res.read_to_string(&mut body).unwrap();
+    assert_eq!(body, "ok");
+
+    // nested guards keep denying until the last one is dropped
+    let inner = reqwest::test::deny_network();
+    drop(guard);
+    assert!(client.get(&url).send().is_err());
+    drop(inner);
+
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+}
@@ -1,7 +1,18 @@
 //! A server builder helper for the integration tests.
 
-use std::io::{Read, Write};
+// the servers use native-tls when it is built, for either TLS backend of
+// the client, and rustls otherwise
+#[cfg(feature = "default-tls")]
+extern crate native_tls;
+#[cfg(all(feature = "rustls-tls", not(feature = "default-tls")))]
+extern crate rustls;
+#[cfg(all(feature = "default-tls",
+          not(any(target_os = "windows", target_os = "macos", target_os = "ios"))))]
+extern crate openssl;
+
+use std::io::{self, Read, Write};
 use std::net;
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::thread;
 
 pub struct Server {
@@ -15,6 +26,11 @@ impl Server {
 }
 
 static DEFAULT_USER_AGENT: &'static str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+// the default `Accept-Encoding` asks for `br` too with the `brotli` feature
+#[cfg(not(feature = "brotli"))]
+static DEFAULT_ACCEPT_ENCODING: &'static str = "gzip, deflate";
+#[cfg(feature = "brotli")]
+static DEFAULT_ACCEPT_ENCODING: &'static str = "gzip, deflate, br";
 
 pub fn spawn(txns: Vec<(Vec<u8>, Vec<u8>)>) -> Server {
     let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
@@ -39,6 +55,374 @@ pub fn spawn(txns: Vec<(Vec<u8>, Vec<u8>)>) -> Server {
     }
 }
 
+/// Accepts one connection, reads the first bytes the client sends, and
+/// then resets the connection instead of closing it.
+#[cfg(unix)]
+pub fn spawn_reset() -> Server {
+    extern crate libc;
+    use std::os::unix::io::AsRawFd;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut socket, _addr) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        socket.read(&mut buf).unwrap();
+        // closing with a zero linger timeout sends a RST
+        let linger = libc::linger { l_onoff: 1, l_linger: 0 };
+        let ret = unsafe {
+            libc::setsockopt(socket.as_raw_fd(), libc::SOL_SOCKET, libc::SO_LINGER,
+                               &linger as *const _ as *const libc::c_void,
+                               ::std::mem::size_of::<libc::linger>() as libc::socklen_t)
+        };
+        assert_eq!(ret, 0);
+    });
+
+    Server {
+        addr: addr,
+    }
+}
+
+/// The reply of `spawn_tls`.
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+const TLS_OK: &'static [u8] = b"\
+    HTTP/1.1 200 OK\r\n\
+    Content-Length: 0\r\n\
+    Connection: close\r\n\
+    \r\n\
+    ";
+
+/// Accepts TLS connections with a certificate for `localhost`, signed by
+/// the test CA of `tls/ca.pem`, answering a request on each with an empty
+/// `200 OK`.
+///
+/// Connections whose handshake fails, such as when the client doesn't
+/// trust the certificate, are dropped.
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+pub fn spawn_tls() -> Server {
+    spawn_tls_reply(TLS_OK.to_vec())
+}
+
+/// Like `spawn_tls`, but answering each request with `reply`.
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+pub fn spawn_tls_reply(reply: Vec<u8>) -> Server {
+    serve_tls(tls_acceptor(), reply)
+}
+
+/// Like `spawn_tls`, but the client has to authenticate with a certificate
+/// signed by the test CA, such as the one of `tls/client.p12`.
+///
+/// Only the native-tls backend of the client has identities.
+#[cfg(feature = "default-tls")]
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+pub fn spawn_tls_client_auth() -> Server {
+    use self::native_tls::backend::openssl::TlsAcceptorBuilderExt;
+    use self::openssl::ssl::{SSL_VERIFY_FAIL_IF_NO_PEER_CERT, SSL_VERIFY_PEER};
+
+    let mut builder = tls_acceptor_builder();
+    {
+        let context = builder.builder_mut().builder_mut();
+        context.set_ca_file(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls/ca.pem")).unwrap();
+        context.set_verify(SSL_VERIFY_PEER | SSL_VERIFY_FAIL_IF_NO_PEER_CERT);
+    }
+    serve_tls(builder.build().unwrap(), TLS_OK.to_vec())
+}
+
+/// A stream of a server, such as a TLS one.
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+pub trait Stream: Read + Write {}
+
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+impl<T: Read + Write> Stream for T {}
+
+/// Like `spawn_tls`, but each connection is handled by `serve`, on a thread
+/// of its own, with how many connections were accepted before it.
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+pub fn spawn_tls_with<F>(serve: F) -> Server
+    where F: Fn(usize, &mut Stream) + Send + Sync + 'static
+{
+    use std::sync::Arc;
+
+    let acceptor = Arc::new(tls_acceptor());
+    let serve = Arc::new(serve);
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for (i, socket) in listener.incoming().enumerate() {
+            let (acceptor, serve) = (acceptor.clone(), serve.clone());
+            thread::spawn(move || {
+                if let Some(mut socket) = tls_accept(&acceptor, socket.unwrap()) {
+                    serve(i, &mut socket);
+                }
+            });
+        }
+    });
+
+    Server {
+        addr: addr,
+    }
+}
+
+/// What accepts the TLS connections of the servers.
+#[cfg(feature = "default-tls")]
+type TlsAcceptor = native_tls::TlsAcceptor;
+#[cfg(all(feature = "rustls-tls", not(feature = "default-tls")))]
+type TlsAcceptor = ::std::sync::Arc<rustls::ServerConfig>;
+
+/// An accepted TLS connection.
+#[cfg(feature = "default-tls")]
+type TlsStream = native_tls::TlsStream<net::TcpStream>;
+#[cfg(all(feature = "rustls-tls", not(feature = "default-tls")))]
+type TlsStream = RustlsStream;
+
+#[cfg(feature = "default-tls")]
+fn tls_acceptor_builder() -> native_tls::TlsAcceptorBuilder {
+    let identity = native_tls::Pkcs12::from_der(include_bytes!("tls/localhost.p12"), "reqwest")
+        .unwrap();
+    native_tls::TlsAcceptor::builder(identity).unwrap()
+}
+
+#[cfg(feature = "default-tls")]
+fn tls_acceptor() -> TlsAcceptor {
+    tls_acceptor_builder().build().unwrap()
+}
+
+/// The same certificate and key as `tls/localhost.p12`, which rustls can't
+/// read.
+#[cfg(all(feature = "rustls-tls", not(feature = "default-tls")))]
+fn tls_acceptor() -> TlsAcceptor {
+    use self::rustls::internal::pemfile;
+
+    let certs = pemfile::certs(&mut &include_bytes!("tls/localhost.pem")[..]).unwrap();
+    let mut keys = pemfile::rsa_private_keys(&mut &include_bytes!("tls/localhost.key")[..])
+        .unwrap();
+    let mut config = rustls::ServerConfig::new();
+    config.set_single_cert(certs, keys.remove(0));
+    ::std::sync::Arc::new(config)
+}
+
+/// Does the handshake of a connection, or `None` if it fails.
+#[cfg(feature = "default-tls")]
+fn tls_accept(acceptor: &TlsAcceptor, socket: net::TcpStream) -> Option<TlsStream> {
+    acceptor.accept(socket).ok()
+}
+
+/// Does the handshake of a connection, or `None` if it fails.
+#[cfg(all(feature = "rustls-tls", not(feature = "default-tls")))]
+fn tls_accept(acceptor: &TlsAcceptor, socket: net::TcpStream) -> Option<TlsStream> {
+    use self::rustls::Session;
+
+    let mut stream = RustlsStream {
+        session: rustls::ServerSession::new(acceptor),
+        socket: socket,
+    };
+    while stream.session.is_handshaking() {
+        if stream.write_tls().is_err() {
+            return None;
+        }
+        if stream.session.is_handshaking() && stream.session.wants_read() {
+            match stream.read_tls() {
+                Ok(true) => (),
+                _ => {
+                    // sends the alert of a failed handshake, if any
+                    let _ = stream.write_tls();
+                    return None;
+                },
+            }
+        }
+    }
+    stream.write_tls().ok().map(|()| stream)
+}
+
+/// A TLS connection of a server, with rustls.
+#[cfg(all(feature = "rustls-tls", not(feature = "default-tls")))]
+pub struct RustlsStream {
+    session: rustls::ServerSession,
+    socket: net::TcpStream,
+}
+
+#[cfg(all(feature = "rustls-tls", not(feature = "default-tls")))]
+impl RustlsStream {
+    fn write_tls(&mut self) -> io::Result<()> {
+        use self::rustls::Session;
+
+        while self.session.wants_write() {
+            try!(self.session.write_tls(&mut self.socket));
+        }
+        Ok(())
+    }
+
+    /// Reads more of the connection, returning whether there was any.
+    fn read_tls(&mut self) -> io::Result<bool> {
+        use self::rustls::Session;
+
+        if try!(self.session.read_tls(&mut self.socket)) == 0 {
+            return Ok(false);
+        }
+        try!(self.session.process_new_packets().map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+        }));
+        Ok(true)
+    }
+}
+
+#[cfg(all(feature = "rustls-tls", not(feature = "default-tls")))]
+impl Read for RustlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = try!(self.session.read(buf));
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            try!(self.write_tls());
+            if !try!(self.read_tls()) {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "rustls-tls", not(feature = "default-tls")))]
+impl Write for RustlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = try!(self.session.write(buf));
+        try!(self.write_tls());
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        try!(self.session.flush());
+        try!(self.write_tls());
+        self.socket.flush()
+    }
+}
+
+#[cfg(any(feature = "default-tls", feature = "rustls-tls"))]
+fn serve_tls(acceptor: TlsAcceptor, reply: Vec<u8>) -> Server {
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for socket in listener.incoming() {
+            let mut socket = match tls_accept(&acceptor, socket.unwrap()) {
+                Some(socket) => socket,
+                None => continue,
+            };
+            let mut buf = [0; 4096];
+            let _ = socket.read(&mut buf);
+            let _ = socket.write_all(&reply);
+        }
+    });
+
+    Server {
+        addr: addr,
+    }
+}
+
+/// A listener whose accept queue is full, so that connecting to it hangs
+/// until the client gives up.
+#[cfg(unix)]
+pub struct Backlogged {
+    addr: net::SocketAddr,
+    _listener: net::TcpListener,
+    _queued: Vec<net::TcpStream>,
+}
+
+#[cfg(unix)]
+impl Backlogged {
+    pub fn addr(&self) -> net::SocketAddr {
+        self.addr
+    }
+}
+
+/// Listens without ever accepting, and fills the accept queue, after which
+/// the SYN of each new connection is dropped.
+#[cfg(unix)]
+pub fn spawn_backlogged() -> Backlogged {
+    extern crate libc;
+    use std::os::unix::io::AsRawFd;
+    use std::time::Duration;
+
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    assert_eq!(unsafe { libc::listen(listener.as_raw_fd(), 0) }, 0);
+    let mut queued = Vec::new();
+    // a backlog of 0 still queues a connection or so, depending on the OS
+    while let Ok(stream) = net::TcpStream::connect_timeout(&addr, Duration::from_millis(100)) {
+        queued.push(stream);
+        assert!(queued.len() < 16, "the accept queue doesn't fill up");
+    }
+
+    Backlogged {
+        addr: addr,
+        _listener: listener,
+        _queued: queued,
+    }
+}
+
+/// One end of an in-memory duplex pipe, made with `pipe`.
+pub struct Pipe {
+    tx: Sender<Vec<u8>>,
+    rx: Receiver<Vec<u8>>,
+    buf: Vec<u8>,
+}
+
+/// Makes a duplex pipe, where what is written to one end is read from the
+/// other. Reading reaches the end once the other end is dropped.
+pub fn pipe() -> (Pipe, Pipe) {
+    let (a_tx, b_rx) = mpsc::channel();
+    let (b_tx, a_rx) = mpsc::channel();
+    let a = Pipe { tx: a_tx, rx: a_rx, buf: Vec::new() };
+    let b = Pipe { tx: b_tx, rx: b_rx, buf: Vec::new() };
+    (a, b)
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buf.is_empty() {
+            match self.rx.recv() {
+                Ok(bytes) => self.buf = bytes,
+                Err(_) => return Ok(0),
+            }
+        }
+        let n = ::std::cmp::min(buf.len(), self.buf.len());
+        buf[..n].copy_from_slice(&self.buf[..n]);
+        self.buf.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.tx.send(buf.to_vec()) {
+            Ok(()) => Ok(buf.len()),
+            Err(_) => Err(io::Error::new(io::ErrorKind::BrokenPipe, "other end dropped")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Like `spawn`, but all transactions are on the one `stream`, and `$HOST`
+/// is replaced with `host`.
+pub fn spawn_over<S>(mut stream: S, host: &'static str, txns: Vec<(Vec<u8>, Vec<u8>)>)
+where S: Read + Write + Send + 'static {
+    thread::spawn(move || {
+        for (mut expected, reply) in txns {
+            replace_expected_vars(&mut expected, host.as_ref(), DEFAULT_USER_AGENT.as_ref());
+            let mut received = vec![0; expected.len()];
+            stream.read_exact(&mut received).unwrap();
+
+            match (::std::str::from_utf8(&expected), ::std::str::from_utf8(&received)) {
+                (Ok(expected), Ok(received)) => assert_eq!(expected, received),
+                _ => assert_eq!(expected, received)
+            }
+            stream.write_all(&reply).unwrap();
+        }
+    });
+}
+
 fn replace_expected_vars(bytes: &mut Vec<u8>, host: &[u8], ua: &[u8]) {
     // plenty horrible, but these are just tests, and gets the job done
     let mut index = 0;
@@ -54,19 +438,18 @@ fn replace_expected_vars(bytes: &mut Vec<u8>, host: &[u8], ua: &[u8]) {
             }
         }
 
-        let has_host = (&bytes[index..]).starts_with(b"HOST");
-        if has_host {
-            bytes.drain(index - 1..index + 4);
-            for (i, b) in host.iter().enumerate() {
-                bytes.insert(index - 1 + i, *b);
-            }
-        } else {
-            let has_ua = (&bytes[index..]).starts_with(b"USERAGENT");
-            if has_ua {
-                bytes.drain(index - 1..index + 9);
-                for (i, b) in ua.iter().enumerate() {
+        let vars: [(&[u8], &[u8]); 3] = [
+            (b"HOST", host),
+            (b"USERAGENT", ua),
+            (b"ACCEPT_ENCODING", DEFAULT_ACCEPT_ENCODING.as_bytes()),
+        ];
+        for &(name, value) in vars.iter() {
+            if (&bytes[index..]).starts_with(name) {
+                bytes.drain(index - 1..index + name.len());
+                for (i, b) in value.iter().enumerate() {
                     bytes.insert(index - 1 + i, *b);
                 }
+                break;
             }
         }
     }
@@ -79,5 +462,11 @@ macro_rules! server {
             $(((&$req[..]).into(), (&$res[..]).into()),)*
         ];
         ::server::spawn(txns)
+    });
+    (over $stream:expr, host: $host:expr, $(request: $req:expr, response: $res:expr),*) => ({
+        let txns = vec![
+            $(((&$req[..]).into(), (&$res[..]).into()),)*
+        ];
+        ::server::spawn_over($stream, $host, txns)
     })
 }
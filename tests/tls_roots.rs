@@ -0,0 +1,39 @@
+//! The test CA is made one of the system's roots here, through
+//! `SSL_CERT_FILE`, which would change what the other tests trust, so
+//! these tests have their own binary.
+#![cfg(feature = "default-tls")]
+#![cfg(not(any(target_os = "windows", target_os = "macos", target_os = "ios")))]
+
+extern crate reqwest;
+
+#[allow(dead_code)]
+mod server;
+
+use std::env;
+
+// one test, so that the environment is only set once
+#[test]
+fn test_built_in_roots_disabled() {
+    env::set_var("SSL_CERT_FILE", concat!(env!("CARGO_MANIFEST_DIR"), "/tests/tls/ca.pem"));
+    // the server stands in for a public host, with a certificate from a
+    // root of the system
+    let server = server::spawn_tls();
+    let url = format!("https://localhost:{}/public", server.addr().port());
+    let builder = || reqwest::Client::builder().resolve("localhost", server.addr());
+
+    let res = builder().build().unwrap().get(&url).send().unwrap();
+    assert_eq!(res.status(), &reqwest::StatusCode::Ok);
+
+    // trusting only a private CA, the system's root isn't trusted anymore
+    let bundle = reqwest::Certificate::from_pem_bundle(include_bytes!("tls/bundle.pem")).unwrap();
+    let private = bundle.into_iter().next().unwrap();
+    let client = builder()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(private)
+        .build()
+        .unwrap();
+    match client.get(&url).send() {
+        Err(reqwest::Error::Http(reqwest::HyperError::Ssl(_))) => (),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}